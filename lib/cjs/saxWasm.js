@@ -1,6 +1,6 @@
 "use strict";
 Object.defineProperty(exports, "__esModule", { value: true });
-exports.readPosition = exports.readU32 = exports.readString = exports.SAXParser = exports.Tag = exports.Text = exports.ProcInst = exports.Attribute = exports.Position = exports.Reader = exports.AttributeType = exports.SaxEventType = void 0;
+exports.readPosition = exports.splitQName = exports.readU32 = exports.readString = exports.SAXParser = exports.XmlDecl = exports.Declaration = exports.AttributeDef = exports.ParseError = exports.Tag = exports.Text = exports.ProcInst = exports.Attribute = exports.Position = exports.Reader = exports.DefaultKind = exports.DeclarationKind = exports.ErrorCode = exports.AttributeType = exports.ParserOptions = exports.SaxEventType = void 0;
 /**
  * An enum representing the events that can be
  * subscribed to on the parser. Multiple events
@@ -28,6 +28,26 @@ exports.SaxEventType = {
     OpenTag: 0b10000000,
     CloseTag: 0b100000000,
     Cdata: 0b1000000000,
+    Error: 0b10000000000,
+    XmlDecl: 0b100000000000,
+};
+/**
+ * Options that change how the parser reads the document.
+ * Multiple options are enabled by using the bitwise or operator.
+ * All options are off by default.
+ *
+ * @example
+ * ```ts
+ *  // Decode entities and character references in text and attribute values.
+ *  const parser = new SAXParser(SaxEventType.Text, ParserOptions.DecodeEntities);
+ * ```
+ * Options can be updated between write operations.
+ */
+exports.ParserOptions = {
+    DecodeEntities: 0b1,
+    Namespaces: 0b10,
+    Strict: 0b100,
+    PreserveWhitespace: 0b1000,
 };
 /**
  * Represents the different types of attributes.
@@ -40,6 +60,42 @@ var AttributeType;
     AttributeType[AttributeType["SingleQuoted"] = 4] = "SingleQuoted";
     AttributeType[AttributeType["DoubleQuoted"] = 8] = "DoubleQuoted";
 })(AttributeType || (exports.AttributeType = AttributeType = {}));
+/**
+ * Represents the kinds of problems reported
+ * with the Error event.
+ */
+var ErrorCode;
+(function (ErrorCode) {
+    ErrorCode[ErrorCode["UnboundPrefix"] = 1] = "UnboundPrefix";
+    ErrorCode[ErrorCode["UnmatchedCloseTag"] = 2] = "UnmatchedCloseTag";
+    ErrorCode[ErrorCode["UnclosedTag"] = 3] = "UnclosedTag";
+    ErrorCode[ErrorCode["UnexpectedEof"] = 4] = "UnexpectedEof";
+    ErrorCode[ErrorCode["InvalidTagStart"] = 5] = "InvalidTagStart";
+    ErrorCode[ErrorCode["InvalidName"] = 6] = "InvalidName";
+    ErrorCode[ErrorCode["DoubleHyphenInComment"] = 7] = "DoubleHyphenInComment";
+    ErrorCode[ErrorCode["DuplicateAttribute"] = 8] = "DuplicateAttribute";
+    ErrorCode[ErrorCode["UnquotedAttributeValue"] = 9] = "UnquotedAttributeValue";
+    ErrorCode[ErrorCode["MissingAttributeValue"] = 10] = "MissingAttributeValue";
+    ErrorCode[ErrorCode["MultipleRoots"] = 11] = "MultipleRoots";
+    ErrorCode[ErrorCode["TextOutsideRoot"] = 12] = "TextOutsideRoot";
+    ErrorCode[ErrorCode["MismatchedCloseTag"] = 13] = "MismatchedCloseTag";
+    ErrorCode[ErrorCode["MisplacedXmlDecl"] = 14] = "MisplacedXmlDecl";
+})(ErrorCode || (exports.ErrorCode = ErrorCode = {}));
+var DeclarationKind;
+(function (DeclarationKind) {
+    DeclarationKind[DeclarationKind["Other"] = 0] = "Other";
+    DeclarationKind[DeclarationKind["Element"] = 1] = "Element";
+    DeclarationKind[DeclarationKind["Attlist"] = 2] = "Attlist";
+    DeclarationKind[DeclarationKind["Entity"] = 3] = "Entity";
+    DeclarationKind[DeclarationKind["Notation"] = 4] = "Notation";
+})(DeclarationKind || (exports.DeclarationKind = DeclarationKind = {}));
+var DefaultKind;
+(function (DefaultKind) {
+    DefaultKind[DefaultKind["Value"] = 0] = "Value";
+    DefaultKind[DefaultKind["Required"] = 1] = "Required";
+    DefaultKind[DefaultKind["Implied"] = 2] = "Implied";
+    DefaultKind[DefaultKind["Fixed"] = 3] = "Fixed";
+})(DefaultKind || (exports.DefaultKind = DefaultKind = {}));
 /**
  * Abstract class for decoding SAX event data.
  *
@@ -98,7 +154,7 @@ exports.Position = Position;
  * 4. 'value' bytes - byte position name_length-n (n bytes)
  */
 class Attribute extends Reader {
-    static LENGTH = 168;
+    static LENGTH = 216;
     type;
     name;
     value;
@@ -106,7 +162,7 @@ class Attribute extends Reader {
         super(data, memory);
         this.name = new Text(new Uint8Array(data.buffer, data.byteOffset, Text.LENGTH), memory);
         this.value = new Text(new Uint8Array(data.buffer, data.byteOffset + Text.LENGTH, Text.LENGTH), memory);
-        this.type = data[144];
+        this.type = data[176];
     }
     /**
     * Gets the byte offsets representing the
@@ -114,16 +170,41 @@ class Attribute extends Reader {
     */
     get byteOffsets() {
         return (this.cache.byteOffsets ??= {
-            start: readU64(this.data, 152),
-            end: readU64(this.data, 160)
+            start: readU64(this.data, 184),
+            end: readU64(this.data, 192)
         });
     }
+    /**
+     * Gets the resolved namespace URI of the attribute.
+     * Empty unless the parser runs in namespace mode
+     * and the attribute name is prefixed.
+     */
+    get uri() {
+        if (this.cache.uri !== undefined) {
+            return this.cache.uri;
+        }
+        const vecPtr = (0, exports.readU32)(this.data, 204);
+        const valueLen = (0, exports.readU32)(this.data, 208);
+        return (this.cache.uri = (0, exports.readString)(this.dataView, vecPtr, valueLen));
+    }
+    /**
+     * Gets the namespace prefix of the attribute name.
+     */
+    get prefix() {
+        return (0, exports.splitQName)(this.name.value)[0];
+    }
+    /**
+     * Gets the local part of the attribute name.
+     */
+    get localName() {
+        return (0, exports.splitQName)(this.name.value)[1];
+    }
     /**
      * @inheritDoc
      */
     toJSON() {
-        const { name, value, type, byteOffsets } = this;
-        return { name: name.toJSON(), value: value.toJSON(), type, byteOffsets };
+        const { name, value, type, uri, byteOffsets } = this;
+        return { name: name.toJSON(), value: value.toJSON(), type, uri, byteOffsets };
     }
     /**
      * Converts the attribute to a string representation.
@@ -165,7 +246,7 @@ exports.Attribute = Attribute;
  * * `ptr` - The initial pointer position.
  */
 class ProcInst extends Reader {
-    static LENGTH = 186;
+    static LENGTH = 224;
     target;
     content;
     constructor(data, memory) {
@@ -197,8 +278,8 @@ class ProcInst extends Reader {
      */
     get byteOffsets() {
         return (this.cache.byteOffsets ??= {
-            start: readU64(this.data, 16),
-            end: readU64(this.data, 24),
+            start: readU64(this.data, 208),
+            end: readU64(this.data, 216),
         });
     }
     /**
@@ -223,10 +304,10 @@ exports.ProcInst = ProcInst;
  * Represents a text node in the XML data.
  *
  * This class decodes the text node data sent across the FFI boundary
- * into its respective fields: `start`, `end`, and `value`.
+ * into its respective fields: `start`, `end`, `value` and `raw`.
  */
 class Text extends Reader {
-    static LENGTH = 72;
+    static LENGTH = 88;
     /**
      * Gets the start position of the text node.
      *
@@ -256,6 +337,24 @@ class Text extends Reader {
         const valueLen = (0, exports.readU32)(this.data, 16);
         return (this.cache.value = (0, exports.readString)(this.dataView, vecPtr, valueLen));
     }
+    /**
+     * Gets the text as written in the document. Differs from
+     * `value` when the parser decodes entities.
+     *
+     * @returns The source text of the text node.
+     */
+    get raw() {
+        if (this.cache.raw !== undefined) {
+            return this.cache.raw;
+        }
+        const rawPtr = (0, exports.readU32)(this.data, 76);
+        const rawLen = (0, exports.readU32)(this.data, 80);
+        // Nothing was decoded
+        if (rawLen === 0) {
+            return (this.cache.raw = this.value);
+        }
+        return (this.cache.raw = (0, exports.readString)(this.dataView, rawPtr, rawLen));
+    }
     /**
     * Gets the byte offsets representing the
     * start and end byte in the data
@@ -293,7 +392,7 @@ exports.Text = Text;
  * `closeEnd`, `selfClosing`, `name`, `attributes`, and `textNodes`.
  */
 class Tag extends Reader {
-    static LENGTH = 128;
+    static LENGTH = 160;
     /**
      * Gets the start position of the tag opening.
      *
@@ -338,6 +437,16 @@ class Tag extends Reader {
     get selfClosing() {
         return !!this.data[36];
     }
+    /**
+     * Gets whether the tag was closed by an ancestor's
+     * close tag or the end of the document rather than
+     * its own close tag.
+     *
+     * @returns The implicitly closed flag of the tag.
+     */
+    get implicitlyClosed() {
+        return !!this.data[152];
+    }
     /**
      * Gets the name of the tag.
      *
@@ -351,6 +460,30 @@ class Tag extends Reader {
         const valueLen = (0, exports.readU32)(this.data, 8);
         return (this.cache.name = (0, exports.readString)(this.dataView, vecPtr, valueLen));
     }
+    /**
+     * Gets the resolved namespace URI of the tag.
+     * Empty unless the parser runs in namespace mode.
+     */
+    get uri() {
+        if (this.cache.uri !== undefined) {
+            return this.cache.uri;
+        }
+        const vecPtr = (0, exports.readU32)(this.data, 132);
+        const valueLen = (0, exports.readU32)(this.data, 136);
+        return (this.cache.uri = (0, exports.readString)(this.dataView, vecPtr, valueLen));
+    }
+    /**
+     * Gets the namespace prefix of the tag name.
+     */
+    get prefix() {
+        return (0, exports.splitQName)(this.name)[0];
+    }
+    /**
+     * Gets the local part of the tag name.
+     */
+    get localName() {
+        return (0, exports.splitQName)(this.name)[1];
+    }
     /**
      * Gets the attributes of the tag.
      *
@@ -409,16 +542,18 @@ class Tag extends Reader {
      * @returns A JSON object representing the tag.
      */
     toJSON() {
-        const { openStart, openEnd, closeStart, closeEnd, name, attributes, textNodes, selfClosing, byteOffsets } = this;
+        const { openStart, openEnd, closeStart, closeEnd, name, uri, attributes, textNodes, selfClosing, implicitlyClosed, byteOffsets } = this;
         return {
             openStart,
             openEnd,
             closeStart,
             closeEnd,
             name,
+            uri,
             attributes: attributes.map(a => a.toJSON()),
             textNodes: textNodes.map(t => t.toJSON()),
             selfClosing,
+            implicitlyClosed,
             byteOffsets,
         };
     }
@@ -427,10 +562,247 @@ class Tag extends Reader {
     }
 }
 exports.Tag = Tag;
+/**
+ * Represents a problem found in the document.
+ *
+ * The parser does not stop on malformed input. These
+ * are reported using the Error event and parsing continues.
+ */
+class ParseError extends Reader {
+    static LENGTH = 64;
+    /**
+     * Gets the error code.
+     */
+    get code() {
+        return (0, exports.readU32)(this.data, 0);
+    }
+    /**
+     * Gets the human readable description of the problem.
+     */
+    get message() {
+        if (this.cache.message) {
+            return this.cache.message;
+        }
+        const vecPtr = (0, exports.readU32)(this.data, 8);
+        const valueLen = (0, exports.readU32)(this.data, 12);
+        return (this.cache.message = (0, exports.readString)(this.dataView, vecPtr, valueLen));
+    }
+    /**
+     * Gets the start position of the problem.
+     */
+    get start() {
+        return this.cache.start || (this.cache.start = (0, exports.readPosition)(this.data, 16));
+    }
+    /**
+     * Gets the end position of the problem.
+     */
+    get end() {
+        return this.cache.end || (this.cache.end = (0, exports.readPosition)(this.data, 32));
+    }
+    /**
+    * Gets the byte offsets representing the
+    * start and end byte in the data
+    */
+    get byteOffsets() {
+        return (this.cache.byteOffsets ??= {
+            start: readU64(this.data, 48),
+            end: readU64(this.data, 56)
+        });
+    }
+    /**
+     * @inheritDoc
+     */
+    toJSON() {
+        const { code, message, start, end, byteOffsets } = this;
+        return { code, message, start, end, byteOffsets };
+    }
+    toString() {
+        return this.message;
+    }
+}
+exports.ParseError = ParseError;
+/**
+ * Represents a single attribute definition of an ATTLIST declaration.
+ */
+class AttributeDef extends Reader {
+    static LENGTH = 272;
+    name;
+    attType;
+    defaultValue;
+    constructor(data, memory) {
+        super(data, memory);
+        this.name = new Text(new Uint8Array(data.buffer, data.byteOffset, Text.LENGTH), memory);
+        this.attType = new Text(new Uint8Array(data.buffer, data.byteOffset + 88, Text.LENGTH), memory);
+        this.defaultValue = new Text(new Uint8Array(data.buffer, data.byteOffset + 176, Text.LENGTH), memory);
+    }
+    /**
+     * Gets how the default value is declared
+     * e.g. #REQUIRED, #IMPLIED or #FIXED.
+     */
+    get defaultKind() {
+        return this.data[264];
+    }
+    /**
+     * @inheritDoc
+     */
+    toJSON() {
+        const { name, attType, defaultKind, defaultValue } = this;
+        return { name: name.toJSON(), attType: attType.toJSON(), defaultKind, defaultValue: defaultValue.toJSON() };
+    }
+}
+exports.AttributeDef = AttributeDef;
+/**
+ * Represents a markup declaration such as <!ELEMENT>,
+ * <!ATTLIST>, <!ENTITY> or <!NOTATION>.
+ *
+ * The whole declaration body is available using `value`, `start`,
+ * `end` and `byteOffsets` while each part of the declaration is
+ * decoded into its own Text. Parts which do not apply to the
+ * declaration kind are empty.
+ */
+class Declaration extends Reader {
+    static LENGTH = 544;
+    text;
+    name;
+    content;
+    publicId;
+    systemId;
+    notation;
+    constructor(data, memory) {
+        super(data, memory);
+        this.text = new Text(new Uint8Array(data.buffer, data.byteOffset, Text.LENGTH), memory);
+        this.name = new Text(new Uint8Array(data.buffer, data.byteOffset + 88, Text.LENGTH), memory);
+        this.content = new Text(new Uint8Array(data.buffer, data.byteOffset + 176, Text.LENGTH), memory);
+        this.publicId = new Text(new Uint8Array(data.buffer, data.byteOffset + 264, Text.LENGTH), memory);
+        this.systemId = new Text(new Uint8Array(data.buffer, data.byteOffset + 352, Text.LENGTH), memory);
+        this.notation = new Text(new Uint8Array(data.buffer, data.byteOffset + 440, Text.LENGTH), memory);
+    }
+    get start() {
+        return this.text.start;
+    }
+    get end() {
+        return this.text.end;
+    }
+    get value() {
+        return this.text.value;
+    }
+    get byteOffsets() {
+        return this.text.byteOffsets;
+    }
+    /**
+     * Gets the kind of declaration.
+     */
+    get kind() {
+        return this.data[540];
+    }
+    /**
+     * Gets whether this declares a parameter entity e.g. <!ENTITY % name ...>
+     */
+    get parameter() {
+        return !!this.data[541];
+    }
+    /**
+     * Gets the attribute definitions of an ATTLIST declaration.
+     */
+    get attributes() {
+        if (this.cache.attributes) {
+            return this.cache.attributes;
+        }
+        let ptr = (0, exports.readU32)(this.data, 532);
+        const numAttrs = (0, exports.readU32)(this.data, 536);
+        const attributes = [];
+        for (let i = 0; i < numAttrs; i++) {
+            attributes[i] = new AttributeDef(new Uint8Array(this.dataView.buffer, ptr, AttributeDef.LENGTH), this.memory);
+            ptr += AttributeDef.LENGTH;
+        }
+        return (this.cache.attributes = attributes);
+    }
+    /**
+     * @inheritDoc
+     */
+    toJSON() {
+        const { start, end, value, byteOffsets, kind, parameter, name, content, publicId, systemId, notation, attributes } = this;
+        return {
+            start,
+            end,
+            value,
+            byteOffsets,
+            kind,
+            parameter,
+            name: name.toJSON(),
+            content: content.toJSON(),
+            publicId: publicId.toJSON(),
+            systemId: systemId.toJSON(),
+            notation: notation.toJSON(),
+            attributes: attributes.map(a => a.toJSON()),
+        };
+    }
+    toString() {
+        return `<!${this.value}>`;
+    }
+}
+exports.Declaration = Declaration;
+/**
+ * Represents the XML declaration e.g. <?xml version="1.0" encoding="UTF-8"?>
+ *
+ * Each pseudo-attribute value is decoded into its own Text.
+ * Pseudo-attributes which are not present are empty.
+ */
+class XmlDecl extends Reader {
+    static LENGTH = 312;
+    version;
+    encoding;
+    standalone;
+    constructor(data, memory) {
+        super(data, memory);
+        this.version = new Text(new Uint8Array(data.buffer, data.byteOffset + 32, Text.LENGTH), memory);
+        this.encoding = new Text(new Uint8Array(data.buffer, data.byteOffset + 120, Text.LENGTH), memory);
+        this.standalone = new Text(new Uint8Array(data.buffer, data.byteOffset + 208, Text.LENGTH), memory);
+    }
+    /**
+     * Gets the start position of the XML declaration.
+     */
+    get start() {
+        return this.cache.start || (this.cache.start = (0, exports.readPosition)(this.data, 0));
+    }
+    /**
+     * Gets the end position of the XML declaration.
+     */
+    get end() {
+        return this.cache.end || (this.cache.end = (0, exports.readPosition)(this.data, 16));
+    }
+    /**
+    * Gets the byte offsets representing the
+    * start and end byte in the data
+    */
+    get byteOffsets() {
+        return (this.cache.byteOffsets ??= {
+            start: readU64(this.data, 296),
+            end: readU64(this.data, 304)
+        });
+    }
+    /**
+     * @inheritDoc
+     */
+    toJSON() {
+        const { version, encoding, standalone, start, end, byteOffsets } = this;
+        return { version: version.toJSON(), encoding: encoding.toJSON(), standalone: standalone.toJSON(), start, end, byteOffsets };
+    }
+}
+exports.XmlDecl = XmlDecl;
 class SAXParser {
     static textDecoder = new TextDecoder();
     events;
+    /**
+     * The `ParserOptions` bitmask. Like `events`,
+     * it can be changed between write operations.
+     */
+    options;
     wasmSaxParser;
+    /**
+     * The handle of this parser within the WASM instance.
+     */
+    handle = 0;
     eventHandler;
     createDetailConstructor(Constructor) {
         return (memoryBuffer, ptr) => {
@@ -439,7 +811,7 @@ class SAXParser {
     }
     eventConstructors = [];
     writeBuffer;
-    constructor(events = 0) {
+    constructor(events = 0, options = 0) {
         const self = this;
         // Initialize a fast lookup table for event constructors to avoid Map lookups per event.
         this.eventConstructors[exports.SaxEventType.Attribute] = this.createDetailConstructor(Attribute);
@@ -451,7 +823,9 @@ class SAXParser {
         this.eventConstructors[exports.SaxEventType.Cdata] = this.createDetailConstructor(Text);
         this.eventConstructors[exports.SaxEventType.Comment] = this.createDetailConstructor(Text);
         this.eventConstructors[exports.SaxEventType.Doctype] = this.createDetailConstructor(Text);
-        this.eventConstructors[exports.SaxEventType.Declaration] = this.createDetailConstructor(Text);
+        this.eventConstructors[exports.SaxEventType.Declaration] = this.createDetailConstructor(Declaration);
+        this.eventConstructors[exports.SaxEventType.Error] = this.createDetailConstructor(ParseError);
+        this.eventConstructors[exports.SaxEventType.XmlDecl] = this.createDetailConstructor(XmlDecl);
         Object.defineProperties(this, {
             events: {
                 get: () => ~~events,
@@ -461,7 +835,21 @@ class SAXParser {
                     }
                     events = ~~value;
                     if (self.wasmSaxParser) {
-                        self.wasmSaxParser.parser(events);
+                        self.wasmSaxParser.set_parser_events(self.handle, events);
+                    }
+                },
+                configurable: false,
+                enumerable: true,
+            },
+            options: {
+                get: () => ~~options,
+                set: (value) => {
+                    if (options === ~~value) {
+                        return;
+                    }
+                    options = ~~value;
+                    if (self.wasmSaxParser) {
+                        self.wasmSaxParser.set_parser_options(self.handle, options);
                     }
                 },
                 configurable: false,
@@ -589,7 +977,7 @@ class SAXParser {
         if (!this.wasmSaxParser) {
             return;
         }
-        const { write, memory: { buffer } } = this.wasmSaxParser;
+        const { write_parser, memory: { buffer } } = this.wasmSaxParser;
         // Allocations within the WASM process
         // invalidate reference to the memory buffer.
         // We check for this and create a new Uint8Array
@@ -602,7 +990,7 @@ class SAXParser {
             this.writeBuffer = new Uint8Array(buffer);
         }
         this.writeBuffer.set(chunk, 4);
-        write(4, chunk.byteLength);
+        write_parser(this.handle, 4, chunk.byteLength);
     }
     /**
      * Ends the parsing process.
@@ -612,7 +1000,7 @@ class SAXParser {
      */
     end() {
         this.writeBuffer = undefined;
-        this.wasmSaxParser?.end();
+        this.wasmSaxParser?.end_parser(this.handle);
     }
     async prepareWasm(saxWasm) {
         const env = {
@@ -633,8 +1021,9 @@ class SAXParser {
             instance = result?.instance;
         }
         if (instance && typeof this.events === 'number') {
-            const { parser } = this.wasmSaxParser = instance.exports;
-            parser(this.events);
+            const { create_parser, set_parser_options } = this.wasmSaxParser = instance.exports;
+            this.handle = create_parser(this.events);
+            set_parser_options(this.handle, this.options);
             return true;
         }
         throw new Error(`Failed to instantiate the parser.`);
@@ -694,6 +1083,17 @@ function readU64(uint8Array, ptr = 0) {
     const hi = view.getUint32(uint8Array.byteOffset + ptr + 4, true);
     return lo + hi * 0x1_0000_0000;
 }
+/**
+ * Splits a qualified name into its prefix and local part.
+ *
+ * @param name The qualified name e.g. `svg:rect`
+ * @returns The prefix (or an empty string) and local part
+ */
+const splitQName = (name) => {
+    const idx = name.indexOf(':');
+    return idx === -1 ? ['', name] : [name.slice(0, idx), name.slice(idx + 1)];
+};
+exports.splitQName = splitQName;
 const readPosition = (uint8Array, ptr = 0) => {
     const line = readU64(uint8Array, ptr);
     const character = readU64(uint8Array, ptr + 8);
@@ -25,9 +25,30 @@ export declare const SaxEventType: {
     readonly OpenTag: 128;
     readonly CloseTag: 256;
     readonly Cdata: 512;
+    readonly Error: 1024;
+    readonly XmlDecl: 2048;
 };
 export type SaxEventType = typeof SaxEventType[keyof typeof SaxEventType];
-export type SaxEvent = [typeof SaxEventType.Text, Text] | [typeof SaxEventType.ProcessingInstruction, ProcInst] | [typeof SaxEventType.Declaration, Text] | [typeof SaxEventType.Doctype, Text] | [typeof SaxEventType.Comment, Text] | [typeof SaxEventType.OpenTagStart, Tag] | [typeof SaxEventType.Attribute, Attribute] | [typeof SaxEventType.OpenTag, Tag] | [typeof SaxEventType.CloseTag, Tag] | [typeof SaxEventType.Cdata, Text];
+/**
+ * Options that change how the parser reads the document.
+ * Multiple options are enabled by using the bitwise or operator.
+ * All options are off by default.
+ *
+ * @example
+ * ```ts
+ *  // Decode entities and character references in text and attribute values.
+ *  const parser = new SAXParser(SaxEventType.Text, ParserOptions.DecodeEntities);
+ * ```
+ * Options can be updated between write operations.
+ */
+export declare const ParserOptions: {
+    readonly DecodeEntities: 1;
+    readonly Namespaces: 2;
+    readonly Strict: 4;
+    readonly PreserveWhitespace: 8;
+};
+export type ParserOptions = typeof ParserOptions[keyof typeof ParserOptions];
+export type SaxEvent = [typeof SaxEventType.Text, Text] | [typeof SaxEventType.ProcessingInstruction, ProcInst] | [typeof SaxEventType.Declaration, Declaration] | [typeof SaxEventType.Doctype, Text] | [typeof SaxEventType.Comment, Text] | [typeof SaxEventType.OpenTagStart, Tag] | [typeof SaxEventType.Attribute, Attribute] | [typeof SaxEventType.OpenTag, Tag] | [typeof SaxEventType.CloseTag, Tag] | [typeof SaxEventType.Cdata, Text] | [typeof SaxEventType.Error, ParseError] | [typeof SaxEventType.XmlDecl, XmlDecl];
 /**
  * Represents the different types of attributes.
  */
@@ -38,10 +59,44 @@ export declare enum AttributeType {
     SingleQuoted = 4,
     DoubleQuoted = 8
 }
+/**
+ * Represents the kinds of problems reported
+ * with the Error event.
+ */
+export declare enum ErrorCode {
+    UnboundPrefix = 1,
+    UnmatchedCloseTag = 2,
+    UnclosedTag = 3,
+    UnexpectedEof = 4,
+    InvalidTagStart = 5,
+    InvalidName = 6,
+    DoubleHyphenInComment = 7,
+    DuplicateAttribute = 8,
+    UnquotedAttributeValue = 9,
+    MissingAttributeValue = 10,
+    MultipleRoots = 11,
+    TextOutsideRoot = 12,
+    MismatchedCloseTag = 13,
+    MisplacedXmlDecl = 14
+}
+export declare enum DeclarationKind {
+    Other = 0,
+    Element = 1,
+    Attlist = 2,
+    Entity = 3,
+    Notation = 4
+}
+export declare enum DefaultKind {
+    Value = 0,
+    Required = 1,
+    Implied = 2,
+    Fixed = 3
+}
 export type AttributeDetail = {
     readonly type: AttributeType;
     readonly name: TextDetail;
     readonly value: TextDetail;
+    readonly uri: string;
     readonly byteOffsets: ByteOffsets;
 };
 export type TagDetail = {
@@ -52,7 +107,9 @@ export type TagDetail = {
     readonly closeStart: PositionDetail;
     readonly closeEnd: PositionDetail;
     readonly name: string;
+    readonly uri: string;
     readonly selfClosing: boolean;
+    readonly implicitlyClosed: boolean;
     readonly byteOffsets: ByteOffsets;
 };
 export type ProcInstDetail = {
@@ -62,12 +119,43 @@ export type ProcInstDetail = {
     readonly end: PositionDetail;
     readonly byteOffsets: ByteOffsets;
 };
+export type XmlDeclDetail = {
+    readonly version: TextDetail;
+    readonly encoding: TextDetail;
+    readonly standalone: TextDetail;
+    readonly start: PositionDetail;
+    readonly end: PositionDetail;
+    readonly byteOffsets: ByteOffsets;
+};
 export type TextDetail = {
     readonly start: PositionDetail;
     readonly end: PositionDetail;
     readonly value: string;
     readonly byteOffsets: ByteOffsets;
 };
+export type ParseErrorDetail = {
+    readonly code: ErrorCode;
+    readonly message: string;
+    readonly start: PositionDetail;
+    readonly end: PositionDetail;
+    readonly byteOffsets: ByteOffsets;
+};
+export type AttributeDefDetail = {
+    readonly name: TextDetail;
+    readonly attType: TextDetail;
+    readonly defaultKind: DefaultKind;
+    readonly defaultValue: TextDetail;
+};
+export type DeclarationDetail = TextDetail & {
+    readonly kind: DeclarationKind;
+    readonly parameter: boolean;
+    readonly name: TextDetail;
+    readonly content: TextDetail;
+    readonly publicId: TextDetail;
+    readonly systemId: TextDetail;
+    readonly notation: TextDetail;
+    readonly attributes: AttributeDefDetail[];
+};
 export type PositionDetail = {
     readonly line: number;
     readonly character: number;
@@ -79,7 +167,7 @@ export type ByteOffsets = {
 /**
  * Represents the detail of a SAX event.
  */
-export type Detail = AttributeDetail | TextDetail | TagDetail | ProcInstDetail;
+export type Detail = AttributeDetail | TextDetail | TagDetail | ProcInstDetail | ParseErrorDetail | DeclarationDetail | AttributeDefDetail | XmlDeclDetail;
 /**
  * Abstract class for decoding SAX event data.
  *
@@ -136,7 +224,7 @@ export declare class Position implements PositionDetail {
  * 4. 'value' bytes - byte position name_length-n (n bytes)
  */
 export declare class Attribute extends Reader<AttributeDetail> implements AttributeDetail {
-    static LENGTH: 168;
+    static LENGTH: 216;
     type: AttributeType;
     name: Text;
     value: Text;
@@ -146,6 +234,20 @@ export declare class Attribute extends Reader<AttributeDetail> implements Attrib
     * start and end byte in the data
     */
     get byteOffsets(): ByteOffsets;
+    /**
+     * Gets the resolved namespace URI of the attribute.
+     * Empty unless the parser runs in namespace mode
+     * and the attribute name is prefixed.
+     */
+    get uri(): string;
+    /**
+     * Gets the namespace prefix of the attribute name.
+     */
+    get prefix(): string;
+    /**
+     * Gets the local part of the attribute name.
+     */
+    get localName(): string;
     /**
      * @inheritDoc
      */
@@ -163,6 +265,7 @@ export declare class Attribute extends Reader<AttributeDetail> implements Attrib
             byteOffsets: ByteOffsets;
         };
         type: AttributeType;
+        uri: string;
         byteOffsets: ByteOffsets;
     };
     /**
@@ -199,7 +302,7 @@ export declare class Attribute extends Reader<AttributeDetail> implements Attrib
  * * `ptr` - The initial pointer position.
  */
 export declare class ProcInst extends Reader<ProcInstDetail> implements ProcInstDetail {
-    static LENGTH: 186;
+    static LENGTH: 224;
     target: Text;
     content: Text;
     constructor(data: Uint8Array, memory: WebAssembly.Memory);
@@ -254,7 +357,7 @@ export declare class ProcInst extends Reader<ProcInstDetail> implements ProcInst
  * into its respective fields: `start`, `end`, and `value`.
  */
 export declare class Text extends Reader<TextDetail> implements TextDetail {
-    static LENGTH: 72;
+    static LENGTH: 88;
     /**
      * Gets the start position of the text node.
      *
@@ -273,6 +376,13 @@ export declare class Text extends Reader<TextDetail> implements TextDetail {
      * @returns The value of the text node.
      */
     get value(): string;
+    /**
+     * Gets the text as written in the document. Differs from
+     * `value` when the parser decodes entities.
+     *
+     * @returns The source text of the text node.
+     */
+    get raw(): string;
     /**
     * Gets the byte offsets representing the
     * start and end byte in the data
@@ -304,7 +414,7 @@ export declare class Text extends Reader<TextDetail> implements TextDetail {
  * `closeEnd`, `selfClosing`, `name`, `attributes`, and `textNodes`.
  */
 export declare class Tag extends Reader<TagDetail> implements TagDetail {
-    static LENGTH: 128;
+    static LENGTH: 160;
     /**
      * Gets the start position of the tag opening.
      *
@@ -335,12 +445,33 @@ export declare class Tag extends Reader<TagDetail> implements TagDetail {
      * @returns The self-closing flag of the tag.
      */
     get selfClosing(): boolean;
+    /**
+     * Gets whether the tag was closed by an ancestor's
+     * close tag or the end of the document rather than
+     * its own close tag.
+     *
+     * @returns The implicitly closed flag of the tag.
+     */
+    get implicitlyClosed(): boolean;
     /**
      * Gets the name of the tag.
      *
      * @returns The name of the tag.
      */
     get name(): string;
+    /**
+     * Gets the resolved namespace URI of the tag.
+     * Empty unless the parser runs in namespace mode.
+     */
+    get uri(): string;
+    /**
+     * Gets the namespace prefix of the tag name.
+     */
+    get prefix(): string;
+    /**
+     * Gets the local part of the tag name.
+     */
+    get localName(): string;
     /**
      * Gets the attributes of the tag.
      *
@@ -371,6 +502,7 @@ export declare class Tag extends Reader<TagDetail> implements TagDetail {
         closeStart: PositionDetail;
         closeEnd: PositionDetail;
         name: string;
+        uri: string;
         attributes: {
             name: {
                 start: PositionDetail;
@@ -385,6 +517,7 @@ export declare class Tag extends Reader<TagDetail> implements TagDetail {
                 byteOffsets: ByteOffsets;
             };
             type: AttributeType;
+            uri: string;
             byteOffsets: ByteOffsets;
         }[];
         textNodes: {
@@ -394,15 +527,253 @@ export declare class Tag extends Reader<TagDetail> implements TagDetail {
             byteOffsets: ByteOffsets;
         }[];
         selfClosing: boolean;
+        implicitlyClosed: boolean;
         byteOffsets: ByteOffsets;
     };
     get value(): string;
 }
+/**
+ * Represents a problem found in the document.
+ *
+ * The parser does not stop on malformed input. These
+ * are reported using the Error event and parsing continues.
+ */
+export declare class ParseError extends Reader<ParseErrorDetail> implements ParseErrorDetail {
+    static LENGTH: 64;
+    /**
+     * Gets the error code.
+     */
+    get code(): ErrorCode;
+    /**
+     * Gets the human readable description of the problem.
+     */
+    get message(): string;
+    /**
+     * Gets the start position of the problem.
+     */
+    get start(): PositionDetail;
+    /**
+     * Gets the end position of the problem.
+     */
+    get end(): PositionDetail;
+    /**
+    * Gets the byte offsets representing the
+    * start and end byte in the data
+    */
+    get byteOffsets(): ByteOffsets;
+    /**
+     * @inheritDoc
+     */
+    toJSON(): {
+        code: ErrorCode;
+        message: string;
+        start: PositionDetail;
+        end: PositionDetail;
+        byteOffsets: ByteOffsets;
+    };
+    toString(): string;
+}
+/**
+ * Represents a single attribute definition of an ATTLIST declaration.
+ */
+export declare class AttributeDef extends Reader<AttributeDefDetail> implements AttributeDefDetail {
+    static LENGTH: 272;
+    name: Text;
+    attType: Text;
+    defaultValue: Text;
+    constructor(data: Uint8Array, memory: WebAssembly.Memory);
+    /**
+     * Gets how the default value is declared
+     * e.g. #REQUIRED, #IMPLIED or #FIXED.
+     */
+    get defaultKind(): DefaultKind;
+    /**
+     * @inheritDoc
+     */
+    toJSON(): {
+        name: {
+            start: PositionDetail;
+            end: PositionDetail;
+            value: string;
+            byteOffsets: ByteOffsets;
+        };
+        attType: {
+            start: PositionDetail;
+            end: PositionDetail;
+            value: string;
+            byteOffsets: ByteOffsets;
+        };
+        defaultKind: DefaultKind;
+        defaultValue: {
+            start: PositionDetail;
+            end: PositionDetail;
+            value: string;
+            byteOffsets: ByteOffsets;
+        };
+    };
+}
+/**
+ * Represents a markup declaration such as <!ELEMENT>,
+ * <!ATTLIST>, <!ENTITY> or <!NOTATION>.
+ *
+ * The whole declaration body is available using `value`, `start`,
+ * `end` and `byteOffsets` while each part of the declaration is
+ * decoded into its own Text. Parts which do not apply to the
+ * declaration kind are empty.
+ */
+export declare class Declaration extends Reader<DeclarationDetail> implements DeclarationDetail {
+    static LENGTH: 544;
+    text: Text;
+    name: Text;
+    content: Text;
+    publicId: Text;
+    systemId: Text;
+    notation: Text;
+    constructor(data: Uint8Array, memory: WebAssembly.Memory);
+    get start(): PositionDetail;
+    get end(): PositionDetail;
+    get value(): string;
+    get byteOffsets(): ByteOffsets;
+    /**
+     * Gets the kind of declaration.
+     */
+    get kind(): DeclarationKind;
+    /**
+     * Gets whether this declares a parameter entity e.g. <!ENTITY % name ...>
+     */
+    get parameter(): boolean;
+    /**
+     * Gets the attribute definitions of an ATTLIST declaration.
+     */
+    get attributes(): AttributeDef[];
+    /**
+     * @inheritDoc
+     */
+    toJSON(): {
+        start: PositionDetail;
+        end: PositionDetail;
+        value: string;
+        byteOffsets: ByteOffsets;
+        kind: DeclarationKind;
+        parameter: boolean;
+        name: {
+            start: PositionDetail;
+            end: PositionDetail;
+            value: string;
+            byteOffsets: ByteOffsets;
+        };
+        content: {
+            start: PositionDetail;
+            end: PositionDetail;
+            value: string;
+            byteOffsets: ByteOffsets;
+        };
+        publicId: {
+            start: PositionDetail;
+            end: PositionDetail;
+            value: string;
+            byteOffsets: ByteOffsets;
+        };
+        systemId: {
+            start: PositionDetail;
+            end: PositionDetail;
+            value: string;
+            byteOffsets: ByteOffsets;
+        };
+        notation: {
+            start: PositionDetail;
+            end: PositionDetail;
+            value: string;
+            byteOffsets: ByteOffsets;
+        };
+        attributes: {
+            name: {
+                start: PositionDetail;
+                end: PositionDetail;
+                value: string;
+                byteOffsets: ByteOffsets;
+            };
+            attType: {
+                start: PositionDetail;
+                end: PositionDetail;
+                value: string;
+                byteOffsets: ByteOffsets;
+            };
+            defaultKind: DefaultKind;
+            defaultValue: {
+                start: PositionDetail;
+                end: PositionDetail;
+                value: string;
+                byteOffsets: ByteOffsets;
+            };
+        }[];
+    };
+    toString(): string;
+}
+/**
+ * Represents the XML declaration e.g. <?xml version="1.0" encoding="UTF-8"?>
+ *
+ * Each pseudo-attribute value is decoded into its own Text.
+ * Pseudo-attributes which are not present are empty.
+ */
+export declare class XmlDecl extends Reader<XmlDeclDetail> implements XmlDeclDetail {
+    static LENGTH: 312;
+    version: Text;
+    encoding: Text;
+    standalone: Text;
+    constructor(data: Uint8Array, memory: WebAssembly.Memory);
+    /**
+     * Gets the start position of the XML declaration.
+     */
+    get start(): PositionDetail;
+    /**
+     * Gets the end position of the XML declaration.
+     */
+    get end(): PositionDetail;
+    /**
+    * Gets the byte offsets representing the
+    * start and end byte in the data
+    */
+    get byteOffsets(): ByteOffsets;
+    /**
+     * @inheritDoc
+     */
+    toJSON(): {
+        version: {
+            start: PositionDetail;
+            end: PositionDetail;
+            value: string;
+            byteOffsets: ByteOffsets;
+        };
+        encoding: {
+            start: PositionDetail;
+            end: PositionDetail;
+            value: string;
+            byteOffsets: ByteOffsets;
+        };
+        standalone: {
+            start: PositionDetail;
+            end: PositionDetail;
+            value: string;
+            byteOffsets: ByteOffsets;
+        };
+        start: PositionDetail;
+        end: PositionDetail;
+        byteOffsets: ByteOffsets;
+    };
+}
 interface WasmSaxParser extends WebAssembly.Exports {
     memory: WebAssembly.Memory;
     parser: (events: number) => void;
     write: (pointer: number, length: number) => void;
     end: () => void;
+    set_options: (options: number) => void;
+    create_parser: (events: number) => number;
+    set_parser_events: (handle: number, events: number) => void;
+    set_parser_options: (handle: number, options: number) => void;
+    write_parser: (handle: number, pointer: number, length: number) => void;
+    end_parser: (handle: number) => void;
+    destroy_parser: (handle: number) => void;
 }
 type TextDecoder = {
     decode: (input?: ArrayBufferView | ArrayBuffer, options?: {
@@ -412,12 +783,21 @@ type TextDecoder = {
 export declare class SAXParser {
     static textDecoder: TextDecoder;
     events?: number;
+    /**
+     * The `ParserOptions` bitmask. Like `events`,
+     * it can be changed between write operations.
+     */
+    options?: number;
     wasmSaxParser?: WasmSaxParser;
+    /**
+     * The handle of this parser within the WASM instance.
+     */
+    handle: number;
     eventHandler?: <T extends SaxEvent>(type: T[0], detail: T[1]) => void;
     private createDetailConstructor;
     private eventConstructors;
     private writeBuffer?;
-    constructor(events?: number);
+    constructor(events?: number, options?: number);
     /**
      * Parses the XML data from a readable stream.
      *
@@ -572,5 +952,12 @@ export declare class SAXParser {
 }
 export declare const readString: (data: Uint8Array, offset: number, length: number) => string;
 export declare const readU32: (uint8Array: Uint8Array, ptr: number) => number;
+/**
+ * Splits a qualified name into its prefix and local part.
+ *
+ * @param name The qualified name e.g. `svg:rect`
+ * @returns The prefix (or an empty string) and local part
+ */
+export declare const splitQName: (name: string) => [string, string];
 export declare const readPosition: (uint8Array: Uint8Array, ptr?: number) => Position;
 export {};
@@ -123,6 +123,7 @@ describe("SaxWasm", () => {
         },
       },
       type: 8,
+      uri: "",
       byteOffsets: {
         start: 8,
         end: 25,
@@ -0,0 +1,61 @@
+import { SaxEventType, Tag } from '../saxWasm';
+import { readFileSync } from 'fs';
+import { resolve } from 'path';
+import { deepEqual, notStrictEqual, strictEqual } from 'assert';
+
+const saxWasm = readFileSync(resolve(__dirname, '../../../lib/sax-wasm.wasm'));
+
+interface HandleExports {
+  memory: WebAssembly.Memory;
+  create_parser: (events: number) => number;
+  write_parser: (handle: number, pointer: number, length: number) => void;
+  end_parser: (handle: number) => void;
+  destroy_parser: (handle: number) => void;
+}
+
+describe('When hosting several parsers in one module instance, the SaxWasm', () => {
+  let exports: HandleExports;
+  let tags: Array<[number, string]>;
+
+  const write = (handle: number, xml: string) => {
+    const bytes = Buffer.from(xml);
+    new Uint8Array(exports.memory.buffer).set(bytes, 4);
+    exports.write_parser(handle, 4, bytes.byteLength);
+  };
+
+  beforeAll(async () => {
+    const event_listener = (event: number, ptr: number, handle: number) => {
+      const tag = new Tag(new Uint8Array(exports.memory.buffer, ptr, Tag.LENGTH), exports.memory);
+      tags.push([handle, tag.name]);
+    };
+    const { instance } = await WebAssembly.instantiate(saxWasm, { env: { event_listener } });
+    exports = instance.exports as unknown as HandleExports;
+  });
+
+  beforeEach(() => {
+    tags = [];
+  });
+
+  it('should route events to the parser that produced them', () => {
+    const a = exports.create_parser(SaxEventType.OpenTag);
+    const b = exports.create_parser(SaxEventType.OpenTag);
+    notStrictEqual(a, b);
+
+    write(a, '<first><nes');
+    write(b, '<second>');
+    write(a, 'ted/></first>');
+    exports.end_parser(a);
+    exports.end_parser(b);
+
+    deepEqual(tags, [[a, 'first'], [b, 'second'], [a, 'nested']]);
+    exports.destroy_parser(a);
+    exports.destroy_parser(b);
+  });
+
+  it('should ignore a destroyed handle', () => {
+    const a = exports.create_parser(SaxEventType.OpenTag);
+    exports.destroy_parser(a);
+    write(a, '<ignored/>');
+    strictEqual(tags.length, 0);
+  });
+});
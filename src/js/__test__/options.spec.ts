@@ -0,0 +1,88 @@
+import { Detail, ErrorCode, ParseError, ParserOptions, Reader, SaxEventType, SAXParser, Tag, Text } from '../saxWasm';
+import { readFileSync } from 'fs';
+import { resolve } from 'path';
+import { deepStrictEqual, strictEqual } from 'assert';
+
+const saxWasm = readFileSync(resolve(__dirname, '../../../lib/sax-wasm.wasm'));
+
+describe('When parser options are set, the SaxWasm', () => {
+  let parser: SAXParser;
+  let _events: [SaxEventType, Detail][];
+
+  beforeAll(async () => {
+    parser = new SAXParser(SaxEventType.OpenTag | SaxEventType.Text | SaxEventType.Error, ParserOptions.DecodeEntities);
+
+    parser.eventHandler = function (event: SaxEventType, data: Reader<Detail>) {
+      _events.push([event, data.toJSON() as Detail]);
+    };
+    return parser.prepareWasm(saxWasm);
+  });
+
+  beforeEach(() => {
+    _events = [];
+  });
+
+  afterEach(() => {
+    parser.options = ParserOptions.DecodeEntities;
+    parser.end();
+  });
+
+  it('should apply the options passed to the constructor', () => {
+    parser.write(Buffer.from('<a>&lt;&amp;</a>'));
+    const [, text] = _events.find(([event]) => event === SaxEventType.Text);
+    strictEqual((text as Text).value, '<&');
+  });
+
+  it('should keep the source of decoded text', () => {
+    const { eventHandler } = parser;
+    const raw: string[] = [];
+    parser.eventHandler = function (event: SaxEventType, data: Reader<Detail>) {
+      if (event === SaxEventType.Text) {
+        raw.push((data as Text).raw, (data as Text).value);
+      }
+    };
+    parser.write(Buffer.from('<a>&lt;&amp;</a><b>c</b>'));
+    parser.eventHandler = eventHandler;
+    deepStrictEqual(raw, ['&lt;&amp;', '<&', 'c', 'c']);
+  });
+
+  it('should apply options changed between writes', () => {
+    parser.options = 0;
+    parser.write(Buffer.from('<a>&lt;&amp;</a>'));
+    const [, text] = _events.find(([event]) => event === SaxEventType.Text);
+    strictEqual((text as Text).value, '&lt;&amp;');
+  });
+
+  it('should resolve namespaces when enabled', () => {
+    parser.write(Buffer.from('<svg:rect xmlns:svg="http://www.w3.org/2000/svg"/>'));
+    strictEqual((_events.find(([event]) => event === SaxEventType.OpenTag)[1] as Tag).uri, '');
+    parser.end();
+
+    parser.options = ParserOptions.Namespaces;
+    parser.write(Buffer.from('<svg:rect xmlns:svg="http://www.w3.org/2000/svg"/>'));
+    const [, tag] = _events.filter(([event]) => event === SaxEventType.OpenTag)[1];
+    strictEqual((tag as Tag).uri, 'http://www.w3.org/2000/svg');
+  });
+
+  it('should report well-formedness violations in strict mode', () => {
+    parser.write(Buffer.from('<a b="1" b="2"/>'));
+    strictEqual(_events.some(([event]) => event === SaxEventType.Error), false);
+    parser.end();
+
+    parser.options = ParserOptions.Strict;
+    parser.write(Buffer.from('<a b="1" b="2"/>'));
+    const [, error] = _events.find(([event]) => event === SaxEventType.Error);
+    strictEqual((error as ParseError).code, ErrorCode.DuplicateAttribute);
+  });
+
+  it('should report whitespace only text when preserving whitespace', () => {
+    parser.write(Buffer.from('<a>\n  <b/></a>'));
+    strictEqual(_events.some(([event]) => event === SaxEventType.Text), false);
+    parser.end();
+
+    parser.options = ParserOptions.PreserveWhitespace;
+    parser.write(Buffer.from('<a>\n  <b/></a>'));
+    const [, text] = _events.find(([event]) => event === SaxEventType.Text);
+    strictEqual((text as Text).value, '\n  ');
+  });
+});
@@ -1,4 +1,4 @@
-import { Detail, ProcInst, Reader, SaxEventType, SAXParser } from '../saxWasm';
+import { Detail, ProcInst, Reader, SaxEventType, SAXParser, XmlDecl } from '../saxWasm';
 import { readFileSync } from 'fs';
 import { resolve } from 'path';
 import { deepEqual, strictEqual } from 'assert';
@@ -29,6 +29,19 @@ describe('When parsing processing instructions, the SaxWasm', () => {
   });
 
   it('should recognize Processing Instructions', () => {
+    parser.write(Buffer.from('<?xml-stylesheet href="style.css" type="text/css"?>'));
+    strictEqual(_event, SaxEventType.ProcessingInstruction);
+    strictEqual(_data?.target.value, 'xml-stylesheet')
+    strictEqual(_data.content.value, 'href="style.css" type="text/css"');
+    deepEqual(_data.content.start, { character: 17, line: 0 })
+    deepEqual(_data.content.end, { character: 49, line: 0 });
+
+    deepEqual(_data.target.start, { character: 2, line: 0 })
+    deepEqual(_data.target.end, { character: 16, line: 0 });
+
+  });
+
+  it('should report the XML declaration as a Processing Instruction when XmlDecl is not subscribed', () => {
     parser.write(Buffer.from('<?xml version="1.0" encoding="utf-8"?>'));
     strictEqual(_event, SaxEventType.ProcessingInstruction);
     strictEqual(_data?.target.value, 'xml')
@@ -38,7 +51,19 @@ describe('When parsing processing instructions, the SaxWasm', () => {
 
     deepEqual(_data.target.start, { character: 2, line: 0 })
     deepEqual(_data.target.end, { character: 5, line: 0 });
+  });
 
+  it('should report the XML declaration using the XmlDecl event', () => {
+    parser.events = SaxEventType.ProcessingInstruction | SaxEventType.XmlDecl;
+    parser.write(Buffer.from('<?xml version="1.0" encoding="utf-8"?>'));
+    parser.events = SaxEventType.ProcessingInstruction;
+    strictEqual(_event, SaxEventType.XmlDecl);
+    const xmlDecl = _data as unknown as XmlDecl;
+    strictEqual(xmlDecl.version.value, '1.0');
+    deepEqual(xmlDecl.version.start, { character: 15, line: 0 });
+    strictEqual(xmlDecl.encoding.value, 'utf-8');
+    deepEqual(xmlDecl.encoding.byteOffsets, { start: 30, end: 35 });
+    strictEqual(xmlDecl.standalone.value, '');
   });
 
   it('should parse the unexpected question mark instead of tag name as a processing instruction', () => {
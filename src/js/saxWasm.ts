@@ -25,13 +25,36 @@ export const SaxEventType = {
   OpenTag: 0b10000000,
   CloseTag: 0b100000000,
   Cdata: 0b1000000000,
+  Error: 0b10000000000,
+  XmlDecl: 0b100000000000,
 } as const;
 
 export type SaxEventType = typeof SaxEventType[keyof typeof SaxEventType]
 
+/**
+ * Options that change how the parser reads the document.
+ * Multiple options are enabled by using the bitwise or operator.
+ * All options are off by default.
+ *
+ * @example
+ * ```ts
+ *  // Decode entities and character references in text and attribute values.
+ *  const parser = new SAXParser(SaxEventType.Text, ParserOptions.DecodeEntities);
+ * ```
+ * Options can be updated between write operations.
+ */
+export const ParserOptions = {
+  DecodeEntities: 0b1,
+  Namespaces: 0b10,
+  Strict: 0b100,
+  PreserveWhitespace: 0b1000,
+} as const;
+
+export type ParserOptions = typeof ParserOptions[keyof typeof ParserOptions]
+
 export type SaxEvent = [typeof SaxEventType.Text, Text]
   | [typeof SaxEventType.ProcessingInstruction, ProcInst]
-  | [typeof SaxEventType.Declaration, Text]
+  | [typeof SaxEventType.Declaration, Declaration]
   | [typeof SaxEventType.Doctype, Text]
   | [typeof SaxEventType.Comment, Text]
   | [typeof SaxEventType.OpenTagStart, Tag]
@@ -39,6 +62,8 @@ export type SaxEvent = [typeof SaxEventType.Text, Text]
   | [typeof SaxEventType.OpenTag, Tag]
   | [typeof SaxEventType.CloseTag, Tag]
   | [typeof SaxEventType.Cdata, Text]
+  | [typeof SaxEventType.Error, ParseError]
+  | [typeof SaxEventType.XmlDecl, XmlDecl]
 
 /**
  * Represents the different types of attributes.
@@ -51,10 +76,47 @@ export enum AttributeType {
   DoubleQuoted = 0b1000,
 }
 
+/**
+ * Represents the kinds of problems reported
+ * with the Error event.
+ */
+export enum ErrorCode {
+  UnboundPrefix = 1,
+  UnmatchedCloseTag = 2,
+  UnclosedTag = 3,
+  UnexpectedEof = 4,
+  InvalidTagStart = 5,
+  InvalidName = 6,
+  DoubleHyphenInComment = 7,
+  DuplicateAttribute = 8,
+  UnquotedAttributeValue = 9,
+  MissingAttributeValue = 10,
+  MultipleRoots = 11,
+  TextOutsideRoot = 12,
+  MismatchedCloseTag = 13,
+  MisplacedXmlDecl = 14,
+}
+
+export enum DeclarationKind {
+  Other = 0,
+  Element = 1,
+  Attlist = 2,
+  Entity = 3,
+  Notation = 4,
+}
+
+export enum DefaultKind {
+  Value = 0,
+  Required = 1,
+  Implied = 2,
+  Fixed = 3,
+}
+
 export type AttributeDetail = {
   readonly type: AttributeType;
   readonly name: TextDetail;
   readonly value: TextDetail;
+  readonly uri: string;
   readonly byteOffsets: ByteOffsets;
 }
 
@@ -68,7 +130,9 @@ export type TagDetail = {
   readonly closeEnd: PositionDetail;
 
   readonly name: string;
+  readonly uri: string;
   readonly selfClosing: boolean;
+  readonly implicitlyClosed: boolean;
   readonly byteOffsets: ByteOffsets;
 }
 
@@ -80,12 +144,47 @@ export type ProcInstDetail = {
   readonly byteOffsets: ByteOffsets;
 }
 
+export type XmlDeclDetail = {
+  readonly version: TextDetail;
+  readonly encoding: TextDetail;
+  readonly standalone: TextDetail;
+  readonly start: PositionDetail;
+  readonly end: PositionDetail;
+  readonly byteOffsets: ByteOffsets;
+}
+
 export type TextDetail = {
   readonly start: PositionDetail;
   readonly end: PositionDetail;
   readonly value: string;
   readonly byteOffsets: ByteOffsets;
 }
+
+export type ParseErrorDetail = {
+  readonly code: ErrorCode;
+  readonly message: string;
+  readonly start: PositionDetail;
+  readonly end: PositionDetail;
+  readonly byteOffsets: ByteOffsets;
+}
+
+export type AttributeDefDetail = {
+  readonly name: TextDetail;
+  readonly attType: TextDetail;
+  readonly defaultKind: DefaultKind;
+  readonly defaultValue: TextDetail;
+}
+
+export type DeclarationDetail = TextDetail & {
+  readonly kind: DeclarationKind;
+  readonly parameter: boolean;
+  readonly name: TextDetail;
+  readonly content: TextDetail;
+  readonly publicId: TextDetail;
+  readonly systemId: TextDetail;
+  readonly notation: TextDetail;
+  readonly attributes: AttributeDefDetail[];
+}
 export type PositionDetail = {
   readonly line: number;
   readonly character: number;
@@ -97,7 +196,7 @@ export type ByteOffsets = {
 /**
  * Represents the detail of a SAX event.
  */
-export type Detail = AttributeDetail | TextDetail | TagDetail | ProcInstDetail;
+export type Detail = AttributeDetail | TextDetail | TagDetail | ProcInstDetail | ParseErrorDetail | DeclarationDetail | AttributeDefDetail | XmlDeclDetail;
 
 /**
  * Abstract class for decoding SAX event data.
@@ -161,7 +260,7 @@ export class Position implements PositionDetail {
  * 4. 'value' bytes - byte position name_length-n (n bytes)
  */
 export class Attribute extends Reader<AttributeDetail> implements AttributeDetail {
-  public static LENGTH = 168 as const;
+  public static LENGTH = 216 as const;
 
   public type: AttributeType;
   public name: Text;
@@ -171,7 +270,7 @@ export class Attribute extends Reader<AttributeDetail> implements AttributeDetai
     super(data, memory);
     this.name = new Text(new Uint8Array(data.buffer, data.byteOffset, Text.LENGTH), memory);
     this.value = new Text(new Uint8Array(data.buffer, data.byteOffset + Text.LENGTH, Text.LENGTH), memory);
-    this.type = data[144];
+    this.type = data[176];
   }
 
   /**
@@ -180,17 +279,45 @@ export class Attribute extends Reader<AttributeDetail> implements AttributeDetai
   */
   public get byteOffsets(): ByteOffsets {
     return (this.cache.byteOffsets ??= {
-      start: readU64(this.data, 152),
-      end: readU64(this.data, 160)
+      start: readU64(this.data, 184),
+      end: readU64(this.data, 192)
     }) as ByteOffsets;
   }
 
+  /**
+   * Gets the resolved namespace URI of the attribute.
+   * Empty unless the parser runs in namespace mode
+   * and the attribute name is prefixed.
+   */
+  public get uri(): string {
+    if (this.cache.uri !== undefined) {
+      return this.cache.uri as string;
+    }
+    const vecPtr = readU32(this.data, 204);
+    const valueLen = readU32(this.data, 208);
+    return (this.cache.uri = readString(this.dataView, vecPtr, valueLen));
+  }
+
+  /**
+   * Gets the namespace prefix of the attribute name.
+   */
+  public get prefix(): string {
+    return splitQName(this.name.value)[0];
+  }
+
+  /**
+   * Gets the local part of the attribute name.
+   */
+  public get localName(): string {
+    return splitQName(this.name.value)[1];
+  }
+
   /**
    * @inheritDoc
    */
   public toJSON() {
-    const { name, value, type, byteOffsets } = this;
-    return { name: name.toJSON(), value: value.toJSON(), type, byteOffsets };
+    const { name, value, type, uri, byteOffsets } = this;
+    return { name: name.toJSON(), value: value.toJSON(), type, uri, byteOffsets };
   }
 
   /**
@@ -233,7 +360,7 @@ export class Attribute extends Reader<AttributeDetail> implements AttributeDetai
  * * `ptr` - The initial pointer position.
  */
 export class ProcInst extends Reader<ProcInstDetail> implements ProcInstDetail {
-  public static LENGTH = 186 as const;
+  public static LENGTH = 224 as const;
 
   public target: Text;
   public content: Text;
@@ -275,8 +402,8 @@ export class ProcInst extends Reader<ProcInstDetail> implements ProcInstDetail {
    */
   public get byteOffsets(): ByteOffsets {
     return (this.cache.byteOffsets ??= {
-      start: readU64(this.data, 16),
-      end: readU64(this.data, 24),
+      start: readU64(this.data, 208),
+      end: readU64(this.data, 216),
     }) as ByteOffsets;
   }
 
@@ -303,10 +430,10 @@ export class ProcInst extends Reader<ProcInstDetail> implements ProcInstDetail {
  * Represents a text node in the XML data.
  *
  * This class decodes the text node data sent across the FFI boundary
- * into its respective fields: `start`, `end`, and `value`.
+ * into its respective fields: `start`, `end`, `value` and `raw`.
  */
 export class Text extends Reader<TextDetail> implements TextDetail {
-  public static LENGTH = 72 as const;
+  public static LENGTH = 88 as const;
 
   /**
    * Gets the start position of the text node.
@@ -340,6 +467,25 @@ export class Text extends Reader<TextDetail> implements TextDetail {
     return (this.cache.value = readString(this.dataView, vecPtr, valueLen));
   }
 
+  /**
+   * Gets the text as written in the document. Differs from
+   * `value` when the parser decodes entities.
+   *
+   * @returns The source text of the text node.
+   */
+  public get raw(): string {
+    if (this.cache.raw !== undefined) {
+      return this.cache.raw as string;
+    }
+    const rawPtr = readU32(this.data, 76);
+    const rawLen = readU32(this.data, 80);
+    // Nothing was decoded
+    if (rawLen === 0) {
+      return (this.cache.raw = this.value);
+    }
+    return (this.cache.raw = readString(this.dataView, rawPtr, rawLen));
+  }
+
   /**
   * Gets the byte offsets representing the
   * start and end byte in the data
@@ -379,7 +525,7 @@ export class Text extends Reader<TextDetail> implements TextDetail {
  * `closeEnd`, `selfClosing`, `name`, `attributes`, and `textNodes`.
  */
 export class Tag extends Reader<TagDetail> implements TagDetail {
-  public static LENGTH = 128 as const;
+  public static LENGTH = 160 as const;
 
   /**
    * Gets the start position of the tag opening.
@@ -436,6 +582,17 @@ export class Tag extends Reader<TagDetail> implements TagDetail {
     return !!this.data[36];
   }
 
+  /**
+   * Gets whether the tag was closed by an ancestor's
+   * close tag or the end of the document rather than
+   * its own close tag.
+   *
+   * @returns The implicitly closed flag of the tag.
+   */
+  public get implicitlyClosed(): boolean {
+    return !!this.data[152];
+  }
+
   /**
    * Gets the name of the tag.
    *
@@ -450,6 +607,33 @@ export class Tag extends Reader<TagDetail> implements TagDetail {
     return (this.cache.name = readString(this.dataView, vecPtr, valueLen));
   }
 
+  /**
+   * Gets the resolved namespace URI of the tag.
+   * Empty unless the parser runs in namespace mode.
+   */
+  public get uri(): string {
+    if (this.cache.uri !== undefined) {
+      return this.cache.uri as string;
+    }
+    const vecPtr = readU32(this.data, 132);
+    const valueLen = readU32(this.data, 136);
+    return (this.cache.uri = readString(this.dataView, vecPtr, valueLen));
+  }
+
+  /**
+   * Gets the namespace prefix of the tag name.
+   */
+  public get prefix(): string {
+    return splitQName(this.name)[0];
+  }
+
+  /**
+   * Gets the local part of the tag name.
+   */
+  public get localName(): string {
+    return splitQName(this.name)[1];
+  }
+
   /**
    * Gets the attributes of the tag.
    *
@@ -512,16 +696,18 @@ export class Tag extends Reader<TagDetail> implements TagDetail {
    * @returns A JSON object representing the tag.
    */
   public toJSON() {
-    const { openStart, openEnd, closeStart, closeEnd, name, attributes, textNodes, selfClosing, byteOffsets } = this;
+    const { openStart, openEnd, closeStart, closeEnd, name, uri, attributes, textNodes, selfClosing, implicitlyClosed, byteOffsets } = this;
     return {
       openStart,
       openEnd,
       closeStart,
       closeEnd,
       name,
+      uri,
       attributes: attributes.map(a => a.toJSON()),
       textNodes: textNodes.map(t => t.toJSON()),
       selfClosing,
+      implicitlyClosed,
       byteOffsets,
     };
   }
@@ -531,11 +717,274 @@ export class Tag extends Reader<TagDetail> implements TagDetail {
   }
 }
 
+/**
+ * Represents a problem found in the document.
+ *
+ * The parser does not stop on malformed input. These
+ * are reported using the Error event and parsing continues.
+ */
+export class ParseError extends Reader<ParseErrorDetail> implements ParseErrorDetail {
+  public static LENGTH = 64 as const;
+
+  /**
+   * Gets the error code.
+   */
+  public get code(): ErrorCode {
+    return readU32(this.data, 0);
+  }
+
+  /**
+   * Gets the human readable description of the problem.
+   */
+  public get message(): string {
+    if (this.cache.message) {
+      return this.cache.message as string;
+    }
+    const vecPtr = readU32(this.data, 8);
+    const valueLen = readU32(this.data, 12);
+    return (this.cache.message = readString(this.dataView, vecPtr, valueLen));
+  }
+
+  /**
+   * Gets the start position of the problem.
+   */
+  public get start(): PositionDetail {
+    return this.cache.start as PositionDetail || (this.cache.start = readPosition(this.data, 16));
+  }
+
+  /**
+   * Gets the end position of the problem.
+   */
+  public get end(): PositionDetail {
+    return this.cache.end as PositionDetail || (this.cache.end = readPosition(this.data, 32));
+  }
+
+  /**
+  * Gets the byte offsets representing the
+  * start and end byte in the data
+  */
+  public get byteOffsets(): ByteOffsets {
+    return (this.cache.byteOffsets ??= {
+      start: readU64(this.data, 48),
+      end: readU64(this.data, 56)
+    }) as ByteOffsets;
+  }
+
+  /**
+   * @inheritDoc
+   */
+  public toJSON() {
+    const { code, message, start, end, byteOffsets } = this;
+    return { code, message, start, end, byteOffsets };
+  }
+
+  public toString(): string {
+    return this.message;
+  }
+}
+
+/**
+ * Represents a single attribute definition of an ATTLIST declaration.
+ */
+export class AttributeDef extends Reader<AttributeDefDetail> implements AttributeDefDetail {
+  public static LENGTH = 272 as const;
+
+  public name: Text;
+  public attType: Text;
+  public defaultValue: Text;
+
+  constructor(data: Uint8Array, memory: WebAssembly.Memory) {
+    super(data, memory);
+    this.name = new Text(new Uint8Array(data.buffer, data.byteOffset, Text.LENGTH), memory);
+    this.attType = new Text(new Uint8Array(data.buffer, data.byteOffset + 88, Text.LENGTH), memory);
+    this.defaultValue = new Text(new Uint8Array(data.buffer, data.byteOffset + 176, Text.LENGTH), memory);
+  }
+
+  /**
+   * Gets how the default value is declared
+   * e.g. #REQUIRED, #IMPLIED or #FIXED.
+   */
+  public get defaultKind(): DefaultKind {
+    return this.data[264];
+  }
+
+  /**
+   * @inheritDoc
+   */
+  public toJSON() {
+    const { name, attType, defaultKind, defaultValue } = this;
+    return { name: name.toJSON(), attType: attType.toJSON(), defaultKind, defaultValue: defaultValue.toJSON() };
+  }
+}
+
+/**
+ * Represents a markup declaration such as <!ELEMENT>,
+ * <!ATTLIST>, <!ENTITY> or <!NOTATION>.
+ *
+ * The whole declaration body is available using `value`, `start`,
+ * `end` and `byteOffsets` while each part of the declaration is
+ * decoded into its own Text. Parts which do not apply to the
+ * declaration kind are empty.
+ */
+export class Declaration extends Reader<DeclarationDetail> implements DeclarationDetail {
+  public static LENGTH = 544 as const;
+
+  public text: Text;
+  public name: Text;
+  public content: Text;
+  public publicId: Text;
+  public systemId: Text;
+  public notation: Text;
+
+  constructor(data: Uint8Array, memory: WebAssembly.Memory) {
+    super(data, memory);
+    this.text = new Text(new Uint8Array(data.buffer, data.byteOffset, Text.LENGTH), memory);
+    this.name = new Text(new Uint8Array(data.buffer, data.byteOffset + 88, Text.LENGTH), memory);
+    this.content = new Text(new Uint8Array(data.buffer, data.byteOffset + 176, Text.LENGTH), memory);
+    this.publicId = new Text(new Uint8Array(data.buffer, data.byteOffset + 264, Text.LENGTH), memory);
+    this.systemId = new Text(new Uint8Array(data.buffer, data.byteOffset + 352, Text.LENGTH), memory);
+    this.notation = new Text(new Uint8Array(data.buffer, data.byteOffset + 440, Text.LENGTH), memory);
+  }
+
+  public get start(): PositionDetail {
+    return this.text.start;
+  }
+
+  public get end(): PositionDetail {
+    return this.text.end;
+  }
+
+  public get value(): string {
+    return this.text.value;
+  }
+
+  public get byteOffsets(): ByteOffsets {
+    return this.text.byteOffsets;
+  }
+
+  /**
+   * Gets the kind of declaration.
+   */
+  public get kind(): DeclarationKind {
+    return this.data[540];
+  }
+
+  /**
+   * Gets whether this declares a parameter entity e.g. <!ENTITY % name ...>
+   */
+  public get parameter(): boolean {
+    return !!this.data[541];
+  }
+
+  /**
+   * Gets the attribute definitions of an ATTLIST declaration.
+   */
+  public get attributes(): AttributeDef[] {
+    if (this.cache.attributes) {
+      return this.cache.attributes as AttributeDef[];
+    }
+    let ptr = readU32(this.data, 532);
+    const numAttrs = readU32(this.data, 536);
+    const attributes = [] as AttributeDef[];
+    for (let i = 0; i < numAttrs; i++) {
+      attributes[i] = new AttributeDef(new Uint8Array(this.dataView.buffer, ptr, AttributeDef.LENGTH), this.memory);
+      ptr += AttributeDef.LENGTH;
+    }
+    return (this.cache.attributes = attributes);
+  }
+
+  /**
+   * @inheritDoc
+   */
+  public toJSON() {
+    const { start, end, value, byteOffsets, kind, parameter, name, content, publicId, systemId, notation, attributes } = this;
+    return {
+      start,
+      end,
+      value,
+      byteOffsets,
+      kind,
+      parameter,
+      name: name.toJSON(),
+      content: content.toJSON(),
+      publicId: publicId.toJSON(),
+      systemId: systemId.toJSON(),
+      notation: notation.toJSON(),
+      attributes: attributes.map(a => a.toJSON()),
+    };
+  }
+
+  public toString(): string {
+    return `<!${this.value}>`;
+  }
+}
+
+/**
+ * Represents the XML declaration e.g. <?xml version="1.0" encoding="UTF-8"?>
+ *
+ * Each pseudo-attribute value is decoded into its own Text.
+ * Pseudo-attributes which are not present are empty.
+ */
+export class XmlDecl extends Reader<XmlDeclDetail> implements XmlDeclDetail {
+  public static LENGTH = 312 as const;
+
+  public version: Text;
+  public encoding: Text;
+  public standalone: Text;
+
+  constructor(data: Uint8Array, memory: WebAssembly.Memory) {
+    super(data, memory);
+    this.version = new Text(new Uint8Array(data.buffer, data.byteOffset + 32, Text.LENGTH), memory);
+    this.encoding = new Text(new Uint8Array(data.buffer, data.byteOffset + 120, Text.LENGTH), memory);
+    this.standalone = new Text(new Uint8Array(data.buffer, data.byteOffset + 208, Text.LENGTH), memory);
+  }
+
+  /**
+   * Gets the start position of the XML declaration.
+   */
+  public get start(): PositionDetail {
+    return this.cache.start as PositionDetail || (this.cache.start = readPosition(this.data, 0));
+  }
+
+  /**
+   * Gets the end position of the XML declaration.
+   */
+  public get end(): PositionDetail {
+    return this.cache.end as PositionDetail || (this.cache.end = readPosition(this.data, 16));
+  }
+
+  /**
+  * Gets the byte offsets representing the
+  * start and end byte in the data
+  */
+  public get byteOffsets(): ByteOffsets {
+    return (this.cache.byteOffsets ??= {
+      start: readU64(this.data, 296),
+      end: readU64(this.data, 304)
+    }) as ByteOffsets;
+  }
+
+  /**
+   * @inheritDoc
+   */
+  public toJSON() {
+    const { version, encoding, standalone, start, end, byteOffsets } = this;
+    return { version: version.toJSON(), encoding: encoding.toJSON(), standalone: standalone.toJSON(), start, end, byteOffsets };
+  }
+}
+
 interface WasmSaxParser extends WebAssembly.Exports {
   memory: WebAssembly.Memory;
   parser: (events: number) => void;
   write: (pointer: number, length: number) => void;
   end: () => void;
+  set_options: (options: number) => void;
+  create_parser: (events: number) => number;
+  set_parser_events: (handle: number, events: number) => void;
+  set_parser_options: (handle: number, options: number) => void;
+  write_parser: (handle: number, pointer: number, length: number) => void;
+  end_parser: (handle: number) => void;
+  destroy_parser: (handle: number) => void;
 }
 
 type TextDecoder = {
@@ -548,7 +997,16 @@ export class SAXParser {
   public static textDecoder: TextDecoder = new TextDecoder();
 
   public events?: number;
+  /**
+   * The `ParserOptions` bitmask. Like `events`,
+   * it can be changed between write operations.
+   */
+  public options?: number;
   public wasmSaxParser?: WasmSaxParser;
+  /**
+   * The handle of this parser within the WASM instance.
+   */
+  public handle = 0;
 
   public eventHandler?: <T extends SaxEvent>(type: T[0], detail: T[1]) => void;
 
@@ -562,7 +1020,7 @@ export class SAXParser {
 
   private writeBuffer?: Uint8Array;
 
-  constructor(events = 0) {
+  constructor(events = 0, options = 0) {
     const self = this;
     // Initialize a fast lookup table for event constructors to avoid Map lookups per event.
     this.eventConstructors[SaxEventType.Attribute] = this.createDetailConstructor(Attribute);
@@ -574,7 +1032,9 @@ export class SAXParser {
     this.eventConstructors[SaxEventType.Cdata] = this.createDetailConstructor(Text);
     this.eventConstructors[SaxEventType.Comment] = this.createDetailConstructor(Text);
     this.eventConstructors[SaxEventType.Doctype] = this.createDetailConstructor(Text);
-    this.eventConstructors[SaxEventType.Declaration] = this.createDetailConstructor(Text);
+    this.eventConstructors[SaxEventType.Declaration] = this.createDetailConstructor(Declaration);
+    this.eventConstructors[SaxEventType.Error] = this.createDetailConstructor(ParseError);
+    this.eventConstructors[SaxEventType.XmlDecl] = this.createDetailConstructor(XmlDecl);
 
     Object.defineProperties(this, {
       events: {
@@ -585,7 +1045,21 @@ export class SAXParser {
           }
           events = ~~value;
           if (self.wasmSaxParser) {
-            self.wasmSaxParser.parser(events);
+            self.wasmSaxParser.set_parser_events(self.handle, events);
+          }
+        },
+        configurable: false,
+        enumerable: true,
+      },
+      options: {
+        get: () => ~~options,
+        set: (value: number) => {
+          if (options === ~~value) {
+            return;
+          }
+          options = ~~value;
+          if (self.wasmSaxParser) {
+            self.wasmSaxParser.set_parser_options(self.handle, options);
           }
         },
         configurable: false,
@@ -717,7 +1191,7 @@ export class SAXParser {
       return;
     }
 
-    const { write, memory: { buffer } } = this.wasmSaxParser;
+    const { write_parser, memory: { buffer } } = this.wasmSaxParser;
 
     // Allocations within the WASM process
     // invalidate reference to the memory buffer.
@@ -731,7 +1205,7 @@ export class SAXParser {
       this.writeBuffer = new Uint8Array(buffer);
     }
     this.writeBuffer.set(chunk, 4);
-    write(4, chunk.byteLength);
+    write_parser(this.handle, 4, chunk.byteLength);
   }
 
   /**
@@ -742,7 +1216,7 @@ export class SAXParser {
    */
   public end(): void {
     this.writeBuffer = undefined;
-    this.wasmSaxParser?.end();
+    this.wasmSaxParser?.end_parser(this.handle);
   }
 
   /**
@@ -807,8 +1281,9 @@ export class SAXParser {
       instance = result?.instance;
     }
     if (instance && typeof this.events === 'number') {
-      const { parser } = this.wasmSaxParser = instance.exports as unknown as WasmSaxParser;
-      parser(this.events);
+      const { create_parser, set_parser_options } = this.wasmSaxParser = instance.exports as unknown as WasmSaxParser;
+      this.handle = create_parser(this.events);
+      set_parser_options(this.handle, this.options);
       return true;
     }
     throw new Error(`Failed to instantiate the parser.`);
@@ -819,11 +1294,11 @@ export class SAXParser {
       return;
     }
     const memoryBuffer = this.wasmSaxParser.memory.buffer;
-    let detail: Attribute | Text | Tag | ProcInst;
+    let detail: Attribute | Text | Tag | ProcInst | ParseError;
 
     const ctor = this.eventConstructors[event];
     if (ctor) {
-      detail = ctor(memoryBuffer, ptr) as Attribute | Text | Tag | ProcInst
+      detail = ctor(memoryBuffer, ptr) as Attribute | Text | Tag | ProcInst | ParseError
     } else {
       throw new Error("No reader for this event type");
     }
@@ -872,6 +1347,17 @@ function readU64(uint8Array: Uint8Array, ptr = 0): number {
   return lo + hi * 0x1_0000_0000;
 }
 
+/**
+ * Splits a qualified name into its prefix and local part.
+ *
+ * @param name The qualified name e.g. `svg:rect`
+ * @returns The prefix (or an empty string) and local part
+ */
+export const splitQName = (name: string): [string, string] => {
+  const idx = name.indexOf(':');
+  return idx === -1 ? ['', name] : [name.slice(0, idx), name.slice(idx + 1)];
+};
+
 export const readPosition = (uint8Array: Uint8Array, ptr = 0): Position => {
   const line = readU64(uint8Array, ptr);
   const character = readU64(uint8Array, ptr + 8);
@@ -0,0 +1,276 @@
+//! Borrowed views of the dispatched values.
+//!
+//! A [`BorrowedEventHandler`](super::parser::BorrowedEventHandler) receives these
+//! instead of the owned [`Entity`] values. Names and values point straight into
+//! the chunk being parsed when the token lies entirely within it. Only a token
+//! spanning a chunk boundary is read from the buffer the parser accumulates it in.
+//! Nothing is cloned or kept until the next write, so a view is only valid for
+//! the duration of the callback.
+
+use super::dtd::Declaration;
+use super::tag::{split_qname, AttrType, Attribute, Entity, ParseError, ProcInst, Tag, Text, XmlDecl};
+
+/// A borrowed [`Text`].
+#[derive(Clone, Copy)]
+pub struct TextRef<'a> {
+    pub value: &'a [u8],
+    // The undecoded source bytes when entity
+    // decoding replaced references in `value`
+    pub raw: &'a [u8],
+    pub start: [u64; 2],
+    pub end: [u64; 2],
+    pub byte_range: (u64, u64),
+}
+
+impl<'a> TextRef<'a> {
+    /// Returns the value exactly as it appeared in the source.
+    pub fn raw_value(&self) -> &'a [u8] {
+        if self.raw.is_empty() {
+            self.value
+        } else {
+            self.raw
+        }
+    }
+
+    /// Copies the view into an owned [`Text`].
+    pub fn to_owned(&self) -> Text {
+        let mut text = Text::new(self.start);
+        text.value = self.value.to_vec();
+        text.raw = self.raw.to_vec();
+        text.end = self.end;
+        text.byte_range = self.byte_range;
+        text
+    }
+}
+
+/// A borrowed [`Attribute`].
+#[derive(Clone, Copy)]
+pub struct AttributeRef<'a> {
+    pub name: TextRef<'a>,
+    pub value: TextRef<'a>,
+    pub attr_type: AttrType,
+    pub byte_range: (u64, u64),
+    // Resolved namespace URI (namespace mode only)
+    pub uri: &'a [u8],
+}
+
+impl<'a> AttributeRef<'a> {
+    /// Returns the namespace prefix of the attribute name.
+    pub fn prefix(&self) -> &'a [u8] {
+        split_qname(self.name.value).0
+    }
+
+    /// Returns the local part of the attribute name.
+    pub fn local_name(&self) -> &'a [u8] {
+        split_qname(self.name.value).1
+    }
+
+    /// Copies the view into an owned [`Attribute`].
+    pub fn to_owned(&self) -> Attribute {
+        Attribute {
+            name: self.name.to_owned(),
+            value: self.value.to_owned(),
+            attr_type: self.attr_type,
+            byte_range: self.byte_range,
+            uri: self.uri.to_vec(),
+        }
+    }
+}
+
+/// A borrowed [`Tag`].
+#[derive(Clone, Copy)]
+pub struct TagRef<'a> {
+    pub name: &'a [u8],
+    // Resolved namespace URI (namespace mode only)
+    pub uri: &'a [u8],
+    pub self_closing: bool,
+    // Closed by an ancestor's close tag or the end of the input
+    pub implicitly_closed: bool,
+    pub open_start: [u64; 2],
+    pub open_end: [u64; 2],
+    pub close_start: [u64; 2],
+    pub close_end: [u64; 2],
+    pub byte_range: (u64, u64),
+    // xmlns declarations made on this element
+    // as (prefix, uri) pairs (namespace mode only)
+    pub ns_bindings: &'a [(Vec<u8>, Vec<u8>)],
+    pub(super) attributes: &'a [Attribute],
+    pub(super) text_nodes: &'a [Text],
+    pub(super) source: &'a [u8],
+}
+
+impl<'a> TagRef<'a> {
+    /// Returns the namespace prefix of the tag name.
+    pub fn prefix(&self) -> &'a [u8] {
+        split_qname(self.name).0
+    }
+
+    /// Returns the local part of the tag name.
+    pub fn local_name(&self) -> &'a [u8] {
+        split_qname(self.name).1
+    }
+
+    /// The attributes of the tag. Collected for `OpenTag` and `CloseTag` events only.
+    pub fn attributes(&self) -> impl ExactSizeIterator<Item = AttributeRef<'a>> + 'a {
+        let source = self.source;
+        self.attributes.iter().map(move |a| a.view(source))
+    }
+
+    /// The text nodes directly within the tag. Collected for `CloseTag` events only.
+    pub fn text_nodes(&self) -> impl ExactSizeIterator<Item = TextRef<'a>> + 'a {
+        let source = self.source;
+        self.text_nodes.iter().map(move |t| t.view(source))
+    }
+
+    /// Copies the view into an owned [`Tag`].
+    pub fn to_owned(&self) -> Tag {
+        let mut tag = Tag::new(self.open_start);
+        tag.name = self.name.to_vec();
+        tag.uri = self.uri.to_vec();
+        tag.self_closing = self.self_closing;
+        tag.implicitly_closed = self.implicitly_closed;
+        tag.open_end = self.open_end;
+        tag.close_start = self.close_start;
+        tag.close_end = self.close_end;
+        tag.byte_range = self.byte_range;
+        tag.ns_bindings = self.ns_bindings.to_vec();
+        tag.attributes = self.attributes().map(|a| a.to_owned()).collect();
+        tag.text_nodes = self.text_nodes().map(|t| t.to_owned()).collect();
+        tag
+    }
+}
+
+/// The borrowed counterpart of [`Entity`].
+#[derive(Clone, Copy)]
+pub enum EntityRef<'a> {
+    Attribute(AttributeRef<'a>),
+    ProcInst(&'a ProcInst),
+    Tag(TagRef<'a>),
+    Text(TextRef<'a>),
+    Error(&'a ParseError),
+    Declaration(&'a Declaration),
+    XmlDecl(&'a XmlDecl),
+}
+
+impl<'a> From<Entity<'a>> for EntityRef<'a> {
+    /// Views an owned, fully hydrated entity.
+    fn from(entity: Entity<'a>) -> Self {
+        match entity {
+            Entity::Attribute(attribute) => EntityRef::Attribute(attribute.view(&[])),
+            Entity::ProcInst(proc_inst) => EntityRef::ProcInst(proc_inst),
+            Entity::Tag(tag) => EntityRef::Tag(tag.view(&[])),
+            Entity::Text(text) => EntityRef::Text(text.view(&[])),
+            Entity::Error(error) => EntityRef::Error(error),
+            Entity::Declaration(declaration) => EntityRef::Declaration(declaration),
+            Entity::XmlDecl(xml_decl) => EntityRef::XmlDecl(xml_decl),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sax::parser::{Event, SAXParser};
+
+    fn describe_text(text: TextRef) -> String {
+        format!("{}{:?}{:?}{:?}", String::from_utf8_lossy(text.value), text.start, text.end, text.byte_range)
+    }
+
+    fn describe_attribute(attribute: AttributeRef) -> String {
+        let uri = String::from_utf8_lossy(attribute.uri);
+        format!("{}={}{}", describe_text(attribute.name), describe_text(attribute.value), uri)
+    }
+
+    fn describe(data: EntityRef) -> String {
+        match data {
+            EntityRef::Tag(tag) => {
+                let mut description = format!(
+                    "{}{}{:?}{}",
+                    String::from_utf8_lossy(tag.name),
+                    tag.self_closing,
+                    tag.byte_range,
+                    String::from_utf8_lossy(tag.uri)
+                );
+                tag.attributes().for_each(|a| description += &describe_attribute(a));
+                tag.text_nodes().for_each(|t| description += &describe_text(t));
+                description
+            }
+            EntityRef::Attribute(attribute) => describe_attribute(attribute),
+            EntityRef::Text(text) => describe_text(text),
+            EntityRef::ProcInst(proc_inst) => String::from_utf8_lossy(&proc_inst.target.value).into_owned(),
+            EntityRef::Error(error) => String::from_utf8_lossy(&error.message).into_owned(),
+            EntityRef::Declaration(declaration) => String::from_utf8_lossy(&declaration.name.value).into_owned(),
+            EntityRef::XmlDecl(xml_decl) => String::from_utf8_lossy(&xml_decl.version.value).into_owned(),
+        }
+    }
+
+    fn parse(doc: &[u8], chunk_size: usize, configure: fn(&mut SAXParser), borrowed: bool) -> Vec<(Event, String)> {
+        let mut events = Vec::new();
+        let mut owned_handler = |event: Event, data: Entity| events.push((event, describe(EntityRef::from(data))));
+        let mut events_borrowed = Vec::new();
+        let mut borrowed_handler = |event: Event, data: EntityRef| events_borrowed.push((event, describe(data)));
+        let mut parser = if borrowed {
+            SAXParser::new_borrowed(&mut borrowed_handler)
+        } else {
+            SAXParser::new_mut(&mut owned_handler)
+        };
+        parser.events = [true; Event::COUNT];
+        configure(&mut parser);
+        for chunk in doc.chunks(chunk_size) {
+            parser.write(chunk);
+        }
+        parser.identity();
+        drop(parser);
+        if borrowed {
+            events_borrowed
+        } else {
+            events
+        }
+    }
+
+    #[test]
+    fn test_matches_owned_events() {
+        let doc = "<?xml version=\"1.0\"?>\n<r xmlns:p=\"urn:p\" a=\"1\" b='' p:c=\"tw&amp;o\"><!-- c -->🚀 some longer text &lt; here<p:e/><![CDATA[x]]><f g=\"h\">i</f><?pi d?></r><oops>".as_bytes();
+        let configurations: [fn(&mut SAXParser); 3] = [
+            |_| {},
+            |parser| parser.decode_entities = true,
+            |parser| {
+                parser.namespaces = true;
+                parser.strict = true;
+            },
+        ];
+        for configure in configurations {
+            // Split everywhere so tokens span chunks
+            for chunk_size in 1..=doc.len() {
+                let expected = parse(doc, chunk_size, configure, false);
+                assert!(expected.len() > 15);
+                assert_eq!(parse(doc, chunk_size, configure, true), expected, "chunk size {}", chunk_size);
+            }
+        }
+    }
+
+    #[test]
+    fn test_borrows_from_chunk() {
+        let doc = b"<root attr=\"value\">text</root>";
+        let range = doc.as_ptr_range();
+        let mut borrowed = 0;
+        let mut handler = |_event: Event, data: EntityRef| {
+            let values = match data {
+                EntityRef::Tag(tag) => vec![tag.name],
+                EntityRef::Attribute(attribute) => vec![attribute.name.value, attribute.value.value],
+                EntityRef::Text(text) => vec![text.value],
+                _ => vec![],
+            };
+            borrowed += values.iter().filter(|value| range.contains(&value.as_ptr())).count();
+        };
+        let mut parser = SAXParser::new_borrowed(&mut handler);
+        parser.events[Event::OpenTag] = true;
+        parser.events[Event::Attribute] = true;
+        parser.events[Event::Text] = true;
+        // The first chunk is copied while the encoding is detected
+        parser.write(b"<doc>");
+        parser.write(doc);
+        drop(parser);
+        assert_eq!(borrowed, 4);
+    }
+}
@@ -0,0 +1,622 @@
+//! Canonical XML output.
+//!
+//! The [`Canonicalizer`] writes the events of a parser as
+//! [Canonical XML 1.0](https://www.w3.org/TR/xml-c14n) or
+//! [Exclusive XML Canonicalization](https://www.w3.org/TR/xml-exc-c14n/),
+//! the form digests of XML signatures are computed over:
+//!
+//! * The XML declaration and the DOCTYPE are dropped. Processing
+//!   instructions and comments outside the document element are separated
+//!   from it by a line break. Comments are only kept when
+//!   [`C14nOptions::with_comments`] is set.
+//! * Line breaks are normalized to `\n`, references are resolved, CDATA
+//!   sections are replaced by their text and whitespace in attribute values
+//!   is normalized to spaces.
+//! * Empty elements are written with a start and an end tag. Namespace
+//!   declarations are sorted by prefix and written where they are first
+//!   needed, followed by the attributes sorted by namespace URI and local name.
+//! * Text and attribute values are escaped the same way every time.
+//!
+//! Default attributes declared in the DTD are not added. The parser must
+//! preserve whitespace, see `SAXParser::preserve_whitespace`.
+//!
+//! # Examples
+//!
+//! ```
+//! use sax_wasm::sax::c14n::{canonicalize, C14nOptions};
+//!
+//! let doc = b"<?xml version=\"1.0\"?>\r\n<doc b='2' a=\"1\" xmlns=\"urn:x\"><e/><![CDATA[1 < 2]]></doc>";
+//! let canonical = canonicalize(doc, &C14nOptions::default()).unwrap();
+//! assert_eq!(canonical, b"<doc xmlns=\"urn:x\" a=\"1\" b=\"2\"><e></e>1 &lt; 2</doc>");
+//! ```
+
+use std::io::{self, Write};
+
+use super::dtd::Declaration;
+use super::entities::EntityTable;
+use super::parser::{subscribe, Event, SAXParser, TypedEventHandler, XML_NAMESPACE};
+use super::path::PathPattern;
+use super::tag::{split_qname, ParseError, ProcInst, Tag, Text};
+use super::writer::{escape_attribute, escape_text};
+
+/// The canonicalization algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Canonical XML 1.0. Every namespace in scope is declared on the
+    /// top element of the output, which also inherits the `xml:`
+    /// attributes of its ancestors.
+    Canonical,
+    /// Exclusive XML Canonicalization 1.0. Namespaces are declared on
+    /// the elements using them.
+    Exclusive,
+}
+
+/// The options of a [`Canonicalizer`].
+#[derive(Debug, Clone)]
+pub struct C14nOptions {
+    pub algorithm: Algorithm,
+    pub with_comments: bool,
+    /// The prefixes of the `InclusiveNamespaces PrefixList` of exclusive
+    /// canonicalization, declared as by Canonical XML 1.0. The default
+    /// namespace is given as `#default`.
+    pub inclusive_prefixes: Vec<Vec<u8>>,
+    /// Only the elements matching the pattern and their content are written.
+    pub subtree: Option<PathPattern>,
+}
+
+impl Default for C14nOptions {
+    fn default() -> Self {
+        C14nOptions {
+            algorithm: Algorithm::Canonical,
+            with_comments: false,
+            inclusive_prefixes: Vec::new(),
+            subtree: None,
+        }
+    }
+}
+
+/// An open element.
+struct Frame {
+    // The namespaces declared on the element as (prefix, URI) pairs
+    namespaces: Vec<(Vec<u8>, Vec<u8>)>,
+    // The `xml:` attributes of the element as (name, value) pairs
+    xml_attributes: Vec<(Vec<u8>, Vec<u8>)>,
+    // The namespaces declared in the output at the element
+    rendered: Vec<(Vec<u8>, Vec<u8>)>,
+    // The steps of the subtree pattern matched within the element
+    states: u64,
+    // The element is written
+    inside: bool,
+}
+
+// The namespace URI, local name, name and value of an attribute
+type OutputAttribute<'a> = (&'a [u8], &'a [u8], &'a [u8], Vec<u8>);
+
+/// The URI bound to `prefix` within the innermost of `frames`,
+/// empty when it is unbound.
+fn lookup<'f>(frames: &'f [Frame], prefix: &[u8]) -> &'f [u8] {
+    if prefix == b"xml" {
+        return XML_NAMESPACE;
+    }
+    frames.iter().rev().find_map(|frame| frame.namespaces.iter().find(|(p, _)| p == prefix)).map_or(&[], |(_, uri)| uri.as_slice())
+}
+
+/// Replaces `\r\n` and `\r` by `\n`.
+fn normalize_line_endings(bytes: &[u8]) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(bytes.len());
+    for (i, &byte) in bytes.iter().enumerate() {
+        match byte {
+            b'\r' if bytes.get(i + 1) == Some(&b'\n') => {}
+            b'\r' => normalized.push(b'\n'),
+            _ => normalized.push(byte),
+        }
+    }
+    normalized
+}
+
+/// Appends `bytes` replacing the bytes for which `escape` has a replacement.
+fn escape(bytes: &[u8], out: &mut Vec<u8>, escape: fn(u8) -> Option<&'static [u8]>) {
+    for &byte in bytes {
+        match escape(byte) {
+            Some(replacement) => out.extend_from_slice(replacement),
+            None => out.push(byte),
+        }
+    }
+}
+
+/// The events a [`Canonicalizer`] needs. `XmlDecl` is subscribed only so
+/// the XML declaration, which has no canonical form, is not delivered as
+/// a processing instruction.
+pub const EVENTS: [Event; 9] = [
+    Event::Text,
+    Event::ProcessingInstruction,
+    Event::XmlDecl,
+    Event::Declaration,
+    Event::Comment,
+    Event::OpenTag,
+    Event::CloseTag,
+    Event::Cdata,
+    Event::Error,
+];
+
+/// Writes the canonical form of a document from the events of a parser.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::c14n::{Algorithm, C14nOptions, Canonicalizer, EVENTS};
+/// use sax_wasm::sax::parser::{subscribe, SAXParser};
+/// use sax_wasm::sax::path::PathPattern;
+///
+/// let options = C14nOptions {
+///     algorithm: Algorithm::Exclusive,
+///     subtree: Some(PathPattern::parse("//ds:SignedInfo").unwrap()),
+///     ..C14nOptions::default()
+/// };
+/// let mut canonicalizer = Canonicalizer::new(Vec::new(), options);
+/// let mut parser = SAXParser::new_typed(&mut canonicalizer);
+/// subscribe(&mut parser.events, &EVENTS);
+/// parser.preserve_whitespace = true;
+/// parser.write(b"<doc xmlns:ds=\"http://www.w3.org/2000/09/xmldsig#\" xmlns:x=\"urn:x\">");
+/// parser.write(b"<ds:Signature><ds:SignedInfo Id=\"a\"/></ds:Signature></doc>");
+/// parser.identity();
+/// drop(parser);
+/// assert!(canonicalizer.errors().is_empty());
+/// assert_eq!(
+///     canonicalizer.finish().unwrap(),
+///     b"<ds:SignedInfo xmlns:ds=\"http://www.w3.org/2000/09/xmldsig#\" Id=\"a\"></ds:SignedInfo>"
+/// );
+/// ```
+pub struct Canonicalizer<W: Write> {
+    sink: W,
+    options: C14nOptions,
+    // The first error writing to the sink
+    io_error: Option<io::Error>,
+    errors: Vec<ParseError>,
+    // The entities declared in the DTD
+    entities: EntityTable,
+    frames: Vec<Frame>,
+    // The document element was closed
+    after_root: bool,
+}
+
+impl<W: Write> Canonicalizer<W> {
+    pub fn new(sink: W, options: C14nOptions) -> Canonicalizer<W> {
+        Canonicalizer {
+            sink,
+            options,
+            io_error: None,
+            errors: Vec::new(),
+            entities: EntityTable::new(),
+            frames: Vec::new(),
+            after_root: false,
+        }
+    }
+
+    /// The errors reported by the parser. The output of
+    /// a document which is not well-formed is not canonical.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// Returns the sink, or the first error writing to it.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.io_error {
+            return Err(error);
+        }
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if self.io_error.is_none() {
+            if let Err(error) = self.sink.write_all(bytes) {
+                self.io_error = Some(error);
+            }
+        }
+    }
+
+    /// Whether content at the current position is written.
+    fn inside(&self) -> bool {
+        match self.frames.last() {
+            Some(frame) => frame.inside,
+            None => self.options.subtree.is_none(),
+        }
+    }
+
+    /// Writes a comment or processing instruction, separated from
+    /// the document element when outside of it.
+    fn write_node(&mut self, node: &[u8]) {
+        if !self.inside() {
+            return;
+        }
+        let outside = self.frames.is_empty();
+        if outside && self.after_root {
+            self.write(b"\n");
+        }
+        self.write(node);
+        if outside && !self.after_root {
+            self.write(b"\n");
+        }
+    }
+
+    /// Resolves the references in `raw` after normalizing its line endings.
+    fn text_value(&self, raw: &[u8]) -> Vec<u8> {
+        let normalized = normalize_line_endings(raw);
+        self.entities.decode(&normalized).unwrap_or(normalized)
+    }
+
+    /// Resolves the references in `raw` after normalizing its whitespace.
+    fn attribute_value(&self, raw: &[u8]) -> Vec<u8> {
+        let normalized: Vec<u8> = normalize_line_endings(raw)
+            .into_iter()
+            .map(|byte| {
+                if matches!(byte, b'\t' | b'\n') {
+                    b' '
+                } else {
+                    byte
+                }
+            })
+            .collect();
+        self.entities.decode(&normalized).unwrap_or(normalized)
+    }
+
+    /// The namespace declarations of the start tag of the innermost element.
+    fn namespace_declarations(&self, tag: &Tag, apex: bool) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let frames = &self.frames;
+        let mut prefixes: Vec<&[u8]> = Vec::new();
+        match self.options.algorithm {
+            Algorithm::Canonical => {
+                for frame in frames {
+                    prefixes.extend(frame.namespaces.iter().map(|(prefix, _)| prefix.as_slice()));
+                }
+            }
+            Algorithm::Exclusive => {
+                prefixes.push(tag.prefix());
+                for attribute in &tag.attributes {
+                    let name = attribute.name.value.as_slice();
+                    if name != b"xmlns" && !name.starts_with(b"xmlns:") && !attribute.prefix().is_empty() {
+                        prefixes.push(attribute.prefix());
+                    }
+                }
+                for prefix in &self.options.inclusive_prefixes {
+                    prefixes.push(if prefix == b"#default" {
+                        b""
+                    } else {
+                        prefix
+                    });
+                }
+            }
+        }
+        prefixes.sort();
+        prefixes.dedup();
+        let parent = frames.len().checked_sub(2).filter(|_| !apex).map(|i| frames[i].rendered.as_slice());
+        let rendered = |prefix: &[u8]| {
+            let rendered = parent.unwrap_or_default().iter().find(|(p, _)| p == prefix);
+            rendered.map_or(&[][..], |(_, uri)| uri.as_slice())
+        };
+        prefixes
+            .into_iter()
+            .filter(|&prefix| prefix != b"xml")
+            .filter_map(|prefix| {
+                let uri = lookup(frames, prefix);
+                // Prefixes can not be undeclared
+                let declared = uri != rendered(prefix) && (!uri.is_empty() || prefix.is_empty());
+                declared.then(|| (prefix.to_vec(), uri.to_vec()))
+            })
+            .collect()
+    }
+
+    /// Writes the start tag of the innermost element.
+    fn write_start_tag(&mut self, tag: &Tag, apex: bool) {
+        let declarations = self.namespace_declarations(tag, apex);
+        let frames = &self.frames;
+        let mut attributes: Vec<OutputAttribute> = Vec::new();
+        for attribute in &tag.attributes {
+            let name = attribute.name.value.as_slice();
+            if name == b"xmlns" || name.starts_with(b"xmlns:") {
+                continue;
+            }
+            let prefix = attribute.prefix();
+            let uri = if prefix.is_empty() {
+                &[][..]
+            } else {
+                lookup(frames, prefix)
+            };
+            attributes.push((uri, attribute.local_name(), name, self.attribute_value(attribute.value.raw_value())));
+        }
+        if apex && self.options.algorithm == Algorithm::Canonical {
+            for frame in frames.iter().rev().skip(1) {
+                for (name, value) in &frame.xml_attributes {
+                    if attributes.iter().all(|(_, _, n, _)| n != name) {
+                        attributes.push((XML_NAMESPACE, split_qname(name).1, name, value.clone()));
+                    }
+                }
+            }
+        }
+        attributes.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+
+        let mut bytes = Vec::new();
+        bytes.push(b'<');
+        bytes.extend_from_slice(&tag.name);
+        for (prefix, uri) in &declarations {
+            bytes.extend_from_slice(b" xmlns");
+            if !prefix.is_empty() {
+                bytes.push(b':');
+                bytes.extend_from_slice(prefix);
+            }
+            bytes.extend_from_slice(b"=\"");
+            escape(uri, &mut bytes, escape_attribute);
+            bytes.push(b'"');
+        }
+        for (_, _, name, value) in &attributes {
+            bytes.push(b' ');
+            bytes.extend_from_slice(name);
+            bytes.extend_from_slice(b"=\"");
+            escape(value, &mut bytes, escape_attribute);
+            bytes.push(b'"');
+        }
+        bytes.push(b'>');
+
+        let len = self.frames.len();
+        let mut rendered = match apex {
+            true => Vec::new(),
+            false => self.frames[len - 2].rendered.clone(),
+        };
+        for (prefix, uri) in declarations {
+            rendered.retain(|(p, _)| *p != prefix);
+            rendered.push((prefix, uri));
+        }
+        self.frames[len - 1].rendered = rendered;
+        self.write(&bytes);
+    }
+}
+
+impl<W: Write> TypedEventHandler for Canonicalizer<W> {
+    fn text(&mut self, text: &Text) {
+        if self.frames.is_empty() || !self.inside() {
+            return;
+        }
+        let mut bytes = Vec::new();
+        escape(&self.text_value(text.raw_value()), &mut bytes, escape_text);
+        self.write(&bytes);
+    }
+
+    fn processing_instruction(&mut self, proc_inst: &ProcInst) {
+        let content = proc_inst.content.value.trim_ascii_start();
+        let mut bytes = b"<?".to_vec();
+        bytes.extend_from_slice(&proc_inst.target.value);
+        if !content.is_empty() {
+            bytes.push(b' ');
+            bytes.extend_from_slice(&normalize_line_endings(content));
+        }
+        bytes.extend_from_slice(b"?>");
+        self.write_node(&bytes);
+    }
+
+    fn declaration(&mut self, declaration: &Declaration) {
+        self.entities.declare_entity(declaration);
+    }
+
+    fn comment(&mut self, comment: &Text) {
+        if !self.options.with_comments {
+            return;
+        }
+        let mut bytes = b"<!--".to_vec();
+        bytes.extend_from_slice(&normalize_line_endings(&comment.value));
+        bytes.extend_from_slice(b"-->");
+        self.write_node(&bytes);
+    }
+
+    fn open_tag(&mut self, tag: &Tag) {
+        let (parent_states, parent_inside) = match self.frames.last() {
+            Some(frame) => (frame.states, frame.inside),
+            None => (1, self.options.subtree.is_none()),
+        };
+        let (states, matched) = match &self.options.subtree {
+            Some(pattern) => {
+                let states = pattern.step(parent_states, &tag.name);
+                (states, pattern.is_match(states))
+            }
+            None => (0, false),
+        };
+        let mut frame = Frame {
+            namespaces: Vec::new(),
+            xml_attributes: Vec::new(),
+            rendered: Vec::new(),
+            states,
+            inside: parent_inside || matched,
+        };
+        for attribute in &tag.attributes {
+            let name = attribute.name.value.as_slice();
+            let value = self.attribute_value(attribute.value.raw_value());
+            if name == b"xmlns" {
+                frame.namespaces.push((Vec::new(), value));
+            } else if let Some(prefix) = name.strip_prefix(b"xmlns:") {
+                frame.namespaces.push((prefix.to_vec(), value));
+            } else if name.starts_with(b"xml:") {
+                frame.xml_attributes.push((name.to_vec(), value));
+            }
+        }
+        let inside = frame.inside;
+        self.frames.push(frame);
+        if inside {
+            self.write_start_tag(tag, !parent_inside || self.frames.len() == 1);
+        }
+    }
+
+    fn close_tag(&mut self, tag: &Tag) {
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        if frame.inside {
+            let mut bytes = b"</".to_vec();
+            bytes.extend_from_slice(&tag.name);
+            bytes.push(b'>');
+            self.write(&bytes);
+        }
+        self.after_root |= self.frames.is_empty();
+    }
+
+    fn cdata(&mut self, cdata: &Text) {
+        if !self.inside() {
+            return;
+        }
+        let mut bytes = Vec::new();
+        escape(&normalize_line_endings(&cdata.value), &mut bytes, escape_text);
+        self.write(&bytes);
+    }
+
+    fn error(&mut self, error: &ParseError) {
+        self.errors.push(error.clone());
+    }
+}
+
+/// Canonicalizes a complete document, parsed in strict mode.
+/// Returns the first error when it is not well-formed.
+pub fn canonicalize(source: &[u8], options: &C14nOptions) -> Result<Vec<u8>, ParseError> {
+    let mut canonicalizer = Canonicalizer::new(Vec::new(), options.clone());
+    let mut parser = SAXParser::new_typed(&mut canonicalizer);
+    subscribe(&mut parser.events, &EVENTS);
+    parser.preserve_whitespace = true;
+    parser.strict = true;
+    parser.write(source);
+    parser.identity();
+    drop(parser);
+    if let Some(error) = canonicalizer.errors.first() {
+        return Err(error.clone());
+    }
+    // Writing to a Vec does not fail
+    Ok(canonicalizer.finish().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sax::tag::ErrorCode;
+
+    fn check(options: &C14nOptions, doc: &str, expected: &str) {
+        let canonical = canonicalize(doc.as_bytes(), options).unwrap();
+        assert_eq!(String::from_utf8_lossy(&canonical), expected);
+        for chunk_size in [5, 13] {
+            let mut canonicalizer = Canonicalizer::new(Vec::new(), options.clone());
+            let mut parser = SAXParser::new_typed(&mut canonicalizer);
+            subscribe(&mut parser.events, &EVENTS);
+            parser.preserve_whitespace = true;
+            for chunk in doc.as_bytes().chunks(chunk_size) {
+                parser.write(chunk);
+            }
+            parser.identity();
+            drop(parser);
+            assert_eq!(canonicalizer.finish().unwrap(), canonical, "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn test_document() {
+        let doc = "<?xml version=\"1.0\"?>\n\n<?xml-stylesheet   href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n\n<!DOCTYPE doc SYSTEM \"doc.dtd\">\n\n<doc>Hello, world!<!-- Comment 1 --></doc>\n\n<?pi-without-data     ?>\n\n<!-- Comment 2 -->\n\n<!-- Comment 3 -->\n";
+        check(
+            &C14nOptions::default(),
+            doc,
+            "<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n<doc>Hello, world!</doc>\n<?pi-without-data?>",
+        );
+        let options = C14nOptions {
+            with_comments: true,
+            ..C14nOptions::default()
+        };
+        check(
+            &options,
+            doc,
+            "<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n<doc>Hello, world!<!-- Comment 1 --></doc>\n<?pi-without-data?>\n<!-- Comment 2 -->\n<!-- Comment 3 -->",
+        );
+    }
+
+    #[test]
+    fn test_tags_and_namespaces() {
+        let doc = r#"<doc>
+   <e1   />
+   <e2   ></e2>
+   <e3   name = "elem3"   id="elem3"   />
+   <e4   name="elem4"   id="elem4"   ></e4>
+   <e5 a:attr="out" b:attr="sorted" attr2="all" attr="I'm"
+      xmlns:b="http://www.ietf.org"
+      xmlns:a="http://www.w3.org"
+      xmlns="http://example.org"/>
+   <e6 xmlns="" xmlns:a="http://www.w3.org">
+      <e7 xmlns="http://www.ietf.org">
+         <e8 xmlns="" xmlns:a="http://www.w3.org">
+            <e9 xmlns="" xmlns:a="http://www.ietf.org"/>
+         </e8>
+      </e7>
+   </e6>
+</doc>"#;
+        let expected = r#"<doc>
+   <e1></e1>
+   <e2></e2>
+   <e3 id="elem3" name="elem3"></e3>
+   <e4 id="elem4" name="elem4"></e4>
+   <e5 xmlns="http://example.org" xmlns:a="http://www.w3.org" xmlns:b="http://www.ietf.org" attr="I'm" attr2="all" b:attr="sorted" a:attr="out"></e5>
+   <e6 xmlns:a="http://www.w3.org">
+      <e7 xmlns="http://www.ietf.org">
+         <e8 xmlns="">
+            <e9 xmlns:a="http://www.ietf.org"></e9>
+         </e8>
+      </e7>
+   </e6>
+</doc>"#;
+        check(&C14nOptions::default(), doc, expected);
+        let options = C14nOptions {
+            algorithm: Algorithm::Exclusive,
+            ..C14nOptions::default()
+        };
+        check(
+            &options,
+            doc,
+            &expected.replace("<e6 xmlns:a=\"http://www.w3.org\">", "<e6>").replace("<e9 xmlns:a=\"http://www.ietf.org\">", "<e9>"),
+        );
+    }
+
+    #[test]
+    fn test_characters() {
+        let doc = "<!DOCTYPE doc [<!ENTITY e \"a &amp; b\">]>\r\n<doc>\r\n   <text>First line&#x0d;&#10;Second line</text>\r\n   <value>&#x32;&e;</value>\r\n   <compute><![CDATA[value>\"0\" && value<\"10\" ?\"valid\":\"error\"]]></compute>\r\n   <compute expr='value>\"0\" &amp;&amp; value&lt;\"10\" ?\"valid\":\"error\"'>valid</compute>\r\n   <norm attr=' &apos;   &#x20;&#13;&#xa;&#9;   &apos; '/>\r\n</doc>";
+        check(
+            &C14nOptions::default(),
+            doc,
+            "<doc>\n   <text>First line&#xD;\nSecond line</text>\n   <value>2a &amp; b</value>\n   <compute>value&gt;\"0\" &amp;&amp; value&lt;\"10\" ?\"valid\":\"error\"</compute>\n   <compute expr=\"value>&quot;0&quot; &amp;&amp; value&lt;&quot;10&quot; ?&quot;valid&quot;:&quot;error&quot;\">valid</compute>\n   <norm attr=\" '    &#xD;&#xA;&#x9;   ' \"></norm>\n</doc>",
+        );
+    }
+
+    #[test]
+    fn test_subtree() {
+        let doc = "<n0:local xmlns:n0=\"foo:bar\" xmlns:n3=\"ftp://example.org\" xml:space=\"preserve\"><!-- c -->\n  <n1:elem2 xmlns:n1=\"http://example.net\" xml:lang=\"en\">\n    <n3:stuff xmlns:n3=\"ftp://example.org\"/>\n  </n1:elem2>\n</n0:local>";
+        let mut options = C14nOptions {
+            with_comments: true,
+            subtree: Some(PathPattern::parse("//n1:elem2").unwrap()),
+            ..C14nOptions::default()
+        };
+        check(
+            &options,
+            doc,
+            "<n1:elem2 xmlns:n0=\"foo:bar\" xmlns:n1=\"http://example.net\" xmlns:n3=\"ftp://example.org\" xml:lang=\"en\" xml:space=\"preserve\">\n    <n3:stuff></n3:stuff>\n  </n1:elem2>",
+        );
+        options.algorithm = Algorithm::Exclusive;
+        check(
+            &options,
+            doc,
+            "<n1:elem2 xmlns:n1=\"http://example.net\" xml:lang=\"en\">\n    <n3:stuff xmlns:n3=\"ftp://example.org\"></n3:stuff>\n  </n1:elem2>",
+        );
+        options.inclusive_prefixes = vec![b"n0".to_vec(), b"#default".to_vec()];
+        check(
+            &options,
+            doc,
+            "<n1:elem2 xmlns:n0=\"foo:bar\" xmlns:n1=\"http://example.net\" xml:lang=\"en\">\n    <n3:stuff xmlns:n3=\"ftp://example.org\"></n3:stuff>\n  </n1:elem2>",
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = canonicalize(b"<a><b></a>", &C14nOptions::default()).unwrap_err();
+        assert_eq!(error.code, ErrorCode::MismatchedCloseTag);
+        assert_eq!(error.to_string(), "Expected '</b>' but found '</a>' at line 1, character 7");
+    }
+}
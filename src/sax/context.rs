@@ -0,0 +1,165 @@
+//! Where in the document an event occurs.
+//!
+//! A [`ContextEventHandler`](super::parser::ContextEventHandler) receives a
+//! [`Context`] with each event describing the elements open around it and
+//! the position of the parser, so handlers can make path dependent decisions
+//! without keeping a stack of their own.
+
+use super::borrowed::TagRef;
+use super::tag::Tag;
+
+/// The open elements and position of the parser at an event.
+///
+/// The element of an `OpenTag` or `CloseTag` event is the innermost open
+/// element. Earlier events of a start tag (`OpenTagStart`, `Attribute`) see
+/// the enclosing element as the innermost one.
+pub struct Context<'a> {
+    tags: &'a [Tag],
+    // The tag of an OpenTag or CloseTag event
+    current: Option<&'a Tag>,
+    // Number of elements opened so far within each open
+    // element. The first entry counts the top level elements
+    children: &'a [usize],
+    source: &'a [u8],
+    /// Line of the end of the event.
+    pub line: u64,
+    /// Character of the end of the event within its line.
+    pub character: u64,
+    /// Byte offset of the end of the event in the input.
+    pub byte_offset: u64,
+}
+
+impl<'a> Context<'a> {
+    pub(crate) fn new(
+        tags: &'a [Tag],
+        current: Option<&'a Tag>,
+        children: &'a [usize],
+        source: &'a [u8],
+        end: [u64; 2],
+        byte_offset: u64,
+    ) -> Context<'a> {
+        Context {
+            tags,
+            current,
+            children,
+            source,
+            line: end[0],
+            character: end[1],
+            byte_offset,
+        }
+    }
+
+    /// The number of open elements.
+    pub fn depth(&self) -> usize {
+        self.tags.len() + self.current.is_some() as usize
+    }
+
+    /// The open elements starting with the root element. Attributes are
+    /// only collected when `OpenTag` or `CloseTag` events are subscribed to.
+    pub fn open_elements(&self) -> impl DoubleEndedIterator<Item = TagRef<'a>> + 'a {
+        let source = self.source;
+        self.tags.iter().chain(self.current).map(move |tag| tag.view(source))
+    }
+
+    /// The names of the open elements starting with the root element.
+    pub fn names(&self) -> impl DoubleEndedIterator<Item = &'a [u8]> + 'a {
+        self.open_elements().map(|tag| tag.name)
+    }
+
+    /// The index of each open element among the elements of its parent
+    /// (or the top level elements), starting with the root element.
+    pub fn indexes(&self) -> impl DoubleEndedIterator<Item = usize> + ExactSizeIterator + 'a {
+        self.children[..self.depth()].iter().map(|count| count - 1)
+    }
+
+    /// The index of the innermost open element among its sibling
+    /// elements, `None` outside of the root element.
+    pub fn index(&self) -> Option<usize> {
+        self.indexes().next_back()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sax::parser::{Event, SAXParser};
+    use crate::sax::tag::Entity;
+
+    use super::*;
+
+    fn path(context: &Context) -> String {
+        let steps: Vec<_> =
+            context.names().zip(context.indexes()).map(|(name, index)| format!("{}[{}]", String::from_utf8_lossy(name), index)).collect();
+        format!("/{}", steps.join("/"))
+    }
+
+    #[test]
+    fn test_paths() {
+        let doc = b"<root><a/><b>one<c x=\"1\"/><c/></b><a>two</a></root>";
+        for chunk_size in [2, 3, 5, doc.len()] {
+            let mut paths = Vec::new();
+            let mut handler = |event: Event, data: Entity, context: &Context| {
+                let description = match data {
+                    Entity::Text(text) => String::from_utf8_lossy(&text.value).into_owned(),
+                    _ => String::new(),
+                };
+                paths.push(format!("{:?} {} {}", event, path(context), description));
+            };
+            let mut parser = SAXParser::new_with_context(&mut handler);
+            parser.events[Event::OpenTag] = true;
+            parser.events[Event::CloseTag] = true;
+            parser.events[Event::Text] = true;
+            for chunk in doc.chunks(chunk_size) {
+                parser.write(chunk);
+            }
+            parser.identity();
+            drop(parser);
+            assert_eq!(
+                paths,
+                [
+                    "OpenTag /root[0] ",
+                    "OpenTag /root[0]/a[0] ",
+                    "CloseTag /root[0]/a[0] ",
+                    "OpenTag /root[0]/b[1] ",
+                    "Text /root[0]/b[1] one",
+                    "OpenTag /root[0]/b[1]/c[0] ",
+                    "CloseTag /root[0]/b[1]/c[0] ",
+                    "OpenTag /root[0]/b[1]/c[1] ",
+                    "CloseTag /root[0]/b[1]/c[1] ",
+                    "CloseTag /root[0]/b[1] ",
+                    "OpenTag /root[0]/a[2] ",
+                    "Text /root[0]/a[2] two",
+                    "CloseTag /root[0]/a[2] ",
+                    "CloseTag /root[0] ",
+                ],
+                "chunk size {}",
+                chunk_size
+            );
+        }
+    }
+
+    #[test]
+    fn test_attributes_and_position() {
+        let mut seen = Vec::new();
+        let mut handler = |event: Event, _data: Entity, context: &Context| {
+            let attributes: Vec<_> = context.open_elements().flat_map(|tag| tag.attributes().map(|a| a.value.value.to_vec())).collect();
+            seen.push((event, context.depth(), attributes, context.line, context.character, context.byte_offset));
+        };
+        let mut parser = SAXParser::new_with_context(&mut handler);
+        parser.events[Event::OpenTag] = true;
+        parser.events[Event::Text] = true;
+        parser.events[Event::Attribute] = true;
+        parser.write(b"<a id=\"1\">\n<b id=\"2\">x</b></a>");
+        parser.identity();
+        drop(parser);
+        assert_eq!(
+            seen,
+            [
+                (Event::Attribute, 0, vec![], 0, 8, 8),
+                (Event::OpenTag, 1, vec![b"1".to_vec()], 0, 10, 10),
+                (Event::Attribute, 1, vec![b"1".to_vec()], 1, 8, 19),
+                (Event::OpenTag, 2, vec![b"1".to_vec(), b"2".to_vec()], 1, 10, 21),
+                (Event::Text, 2, vec![b"1".to_vec(), b"2".to_vec()], 1, 11, 22),
+            ]
+        );
+    }
+}
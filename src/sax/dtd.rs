@@ -0,0 +1,605 @@
+use super::tag::Text;
+use super::utils::advance_position;
+
+/// A markup declaration such as `<!ELEMENT ...>`, `<!ATTLIST ...>`,
+/// `<!ENTITY ...>` or `<!NOTATION ...>` broken into its parts.
+///
+/// `text` holds the whole declaration body (everything between `<!` and `>`)
+/// and comes first so the declaration can still be read as a `Text`
+/// across the FFI boundary. Each part is a `Text` with its own positions.
+/// Parts that do not apply to the declaration kind are left empty.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::dtd::{Declaration, DeclarationKind, DefaultKind};
+/// use sax_wasm::sax::tag::Text;
+///
+/// let mut text = Text::new([0, 2]);
+/// text.value = br#"ATTLIST img src CDATA #REQUIRED align (left|right) "left""#.to_vec();
+/// let decl = Declaration::parse(text);
+///
+/// assert_eq!(decl.kind, DeclarationKind::Attlist);
+/// assert_eq!(decl.name.value, b"img");
+/// assert_eq!(decl.attributes[1].att_type.value, b"(left|right)");
+/// assert_eq!(decl.attributes[1].default_kind, DefaultKind::Value);
+/// assert_eq!(decl.attributes[1].default_value.value, b"left");
+/// assert_eq!(decl.attributes[1].default_value.start, [0, 54]);
+/// ```
+#[repr(C)]
+#[derive(Clone)]
+pub struct Declaration {
+    pub text: Text,
+    // Element, attribute list, entity or notation name
+    pub name: Text,
+    // ELEMENT content spec or the ENTITY literal value
+    pub content: Text,
+    pub public_id: Text,
+    pub system_id: Text,
+    // ENTITY NDATA notation name
+    pub notation: Text,
+    // ATTLIST attribute definitions
+    pub attributes: Vec<AttributeDef>,
+    pub kind: DeclarationKind,
+    // <!ENTITY % name ...>
+    pub parameter: bool,
+}
+
+/// A single attribute definition of an `ATTLIST` declaration.
+#[repr(C)]
+#[derive(Clone)]
+pub struct AttributeDef {
+    pub name: Text,
+    // CDATA, ID, NMTOKENS, (a|b), NOTATION (a|b) etc.
+    pub att_type: Text,
+    pub default_value: Text,
+    pub default_kind: DefaultKind,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DeclarationKind {
+    // <!FOO ...> or anything not recognized
+    Other = 0,
+    Element = 1,
+    Attlist = 2,
+    Entity = 3,
+    Notation = 4,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DefaultKind {
+    // "value"
+    Value = 0,
+    // #REQUIRED
+    Required = 1,
+    // #IMPLIED
+    Implied = 2,
+    // #FIXED "value"
+    Fixed = 3,
+}
+
+/// The parsed content spec of an `ELEMENT` declaration.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ContentModel {
+    Empty,
+    Any,
+    // (#PCDATA|a|b)* with the names allowed next to text
+    Mixed(Vec<Vec<u8>>),
+    Children(ContentParticle),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ContentParticle {
+    pub kind: ParticleKind,
+    pub occurrence: Occurrence,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ParticleKind {
+    Name(Vec<u8>),
+    // (a|b|c)
+    Choice(Vec<ContentParticle>),
+    // (a,b,c)
+    Seq(Vec<ContentParticle>),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Occurrence {
+    Once,
+    // ?
+    Optional,
+    // *
+    ZeroOrMore,
+    // +
+    OneOrMore,
+}
+
+impl Declaration {
+    /// Parses the (hydrated) body of a markup declaration.
+    ///
+    /// Malformed declarations never fail. Whatever could be
+    /// recognized is kept and the remaining parts are left empty.
+    pub fn parse(text: Text) -> Declaration {
+        let mut decl = Declaration {
+            name: Text::new([0; 2]),
+            content: Text::new([0; 2]),
+            public_id: Text::new([0; 2]),
+            system_id: Text::new([0; 2]),
+            notation: Text::new([0; 2]),
+            attributes: Vec::new(),
+            kind: DeclarationKind::Other,
+            parameter: false,
+            text,
+        };
+        let body = decl.text.value.clone();
+        let mut scanner = Scanner {
+            text: &decl.text,
+            body: &body,
+            idx: 0,
+        };
+
+        let keyword = scanner.name();
+        decl.kind = match &body[keyword.0..keyword.1] {
+            b"ELEMENT" => DeclarationKind::Element,
+            b"ATTLIST" => DeclarationKind::Attlist,
+            b"ENTITY" => DeclarationKind::Entity,
+            b"NOTATION" => DeclarationKind::Notation,
+            _ => return decl,
+        };
+
+        if decl.kind == DeclarationKind::Entity {
+            scanner.skip_whitespace();
+            if body[scanner.idx..].starts_with(b"%") && body.get(scanner.idx + 1).is_some_and(u8::is_ascii_whitespace) {
+                decl.parameter = true;
+                scanner.idx += 1;
+            }
+        }
+        let name = scanner.name();
+        decl.name = scanner.text(name);
+
+        match decl.kind {
+            DeclarationKind::Element => {
+                scanner.skip_whitespace();
+                let rest = scanner.rest();
+                decl.content = scanner.text(rest);
+            }
+            DeclarationKind::Attlist => {
+                while let Some(attribute) = scanner.attribute_def() {
+                    decl.attributes.push(attribute);
+                }
+            }
+            DeclarationKind::Entity | DeclarationKind::Notation => {
+                scanner.skip_whitespace();
+                if scanner.peek_quote().is_some() {
+                    decl.content = scanner.literal();
+                    return decl;
+                }
+                match &body[scanner.name_range()] {
+                    b"SYSTEM" => {
+                        scanner.name();
+                        decl.system_id = scanner.literal();
+                    }
+                    b"PUBLIC" => {
+                        scanner.name();
+                        decl.public_id = scanner.literal();
+                        // The system literal is optional for notations
+                        scanner.skip_whitespace();
+                        if scanner.peek_quote().is_some() {
+                            decl.system_id = scanner.literal();
+                        }
+                    }
+                    _ => return decl,
+                }
+                if decl.kind == DeclarationKind::Entity && &body[scanner.name_range()] == b"NDATA" {
+                    scanner.name();
+                    let notation = scanner.name();
+                    decl.notation = scanner.text(notation);
+                }
+            }
+            DeclarationKind::Other => {}
+        }
+        decl
+    }
+
+    pub fn map_byte_ranges(&mut self, map: &dyn Fn(u64) -> u64) {
+        for text in [&mut self.text, &mut self.name, &mut self.content, &mut self.public_id, &mut self.system_id, &mut self.notation] {
+            text.map_byte_ranges(map);
+        }
+        for attribute in &mut self.attributes {
+            attribute.name.map_byte_ranges(map);
+            attribute.att_type.map_byte_ranges(map);
+            attribute.default_value.map_byte_ranges(map);
+        }
+    }
+
+    /// Checks if this declares an internal entity (one with a literal value).
+    pub fn is_internal_entity(&self) -> bool {
+        // Parts are never found at the very start of the body since the
+        // keyword comes first, so an unset end means there was no literal.
+        self.kind == DeclarationKind::Entity && self.content.byte_range.1 > self.text.byte_range.0
+    }
+
+    /// Parses the content spec of an `ELEMENT` declaration.
+    ///
+    /// # Returns
+    ///
+    /// * `None` when this is not an element declaration
+    ///   or the content spec is malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sax_wasm::sax::dtd::{ContentModel, Declaration, Occurrence, ParticleKind};
+    /// use sax_wasm::sax::tag::Text;
+    ///
+    /// let mut text = Text::new([0, 0]);
+    /// text.value = b"ELEMENT note (to+, (body | summary)?)".to_vec();
+    /// let Some(ContentModel::Children(particle)) = Declaration::parse(text).content_model() else {
+    ///     panic!("expected element content");
+    /// };
+    /// let ParticleKind::Seq(items) = particle.kind else { panic!("expected a sequence") };
+    /// assert_eq!(items[0].kind, ParticleKind::Name(b"to".to_vec()));
+    /// assert_eq!(items[0].occurrence, Occurrence::OneOrMore);
+    /// assert!(matches!(items[1].kind, ParticleKind::Choice(_)));
+    /// assert_eq!(items[1].occurrence, Occurrence::Optional);
+    /// ```
+    pub fn content_model(&self) -> Option<ContentModel> {
+        if self.kind != DeclarationKind::Element {
+            return None;
+        }
+        let spec = self.content.value.as_slice();
+        match spec {
+            b"EMPTY" => return Some(ContentModel::Empty),
+            b"ANY" => return Some(ContentModel::Any),
+            _ => {}
+        }
+        let mut model = ModelParser {
+            spec,
+            idx: 0,
+        };
+        if model.is_mixed() {
+            return model.mixed();
+        }
+        let particle = model.particle()?;
+        model.end().then_some(ContentModel::Children(particle))
+    }
+}
+
+/// Walks a declaration body handing out (start, end) byte ranges.
+struct Scanner<'s> {
+    text: &'s Text,
+    body: &'s [u8],
+    idx: usize,
+}
+
+impl Scanner<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.body.get(self.idx).is_some_and(u8::is_ascii_whitespace) {
+            self.idx += 1;
+        }
+    }
+
+    fn peek_quote(&self) -> Option<u8> {
+        self.body.get(self.idx).copied().filter(|&b| b == b'"' || b == b'\'')
+    }
+
+    /// The range of the next whitespace delimited token without consuming it.
+    fn name_range(&mut self) -> std::ops::Range<usize> {
+        self.skip_whitespace();
+        let len = self.body[self.idx..].iter().position(|b| b.is_ascii_whitespace()).unwrap_or(self.body.len() - self.idx);
+        self.idx..self.idx + len
+    }
+
+    fn name(&mut self) -> (usize, usize) {
+        let range = self.name_range();
+        self.idx = range.end;
+        (range.start, range.end)
+    }
+
+    /// A quoted literal. The range excludes the quotes.
+    /// Unterminated literals consume the rest of the body.
+    fn quoted(&mut self) -> Option<(usize, usize)> {
+        self.skip_whitespace();
+        let quote = self.peek_quote()?;
+        let start = self.idx + 1;
+        let Some(len) = self.body[start..].iter().position(|&b| b == quote) else {
+            self.idx = self.body.len();
+            return None;
+        };
+        self.idx = start + len + 1;
+        Some((start, start + len))
+    }
+
+    fn literal(&mut self) -> Text {
+        match self.quoted() {
+            Some(range) => self.text(range),
+            None => Text::new([0; 2]),
+        }
+    }
+
+    /// A parenthesized group e.g. `(a|b)` including any nested groups.
+    fn group(&mut self) -> (usize, usize) {
+        let start = self.idx;
+        let mut depth = 0;
+        while let Some(&byte) = self.body.get(self.idx) {
+            self.idx += 1;
+            match byte {
+                b'(' => depth += 1,
+                b')' => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+        }
+        (start, self.idx)
+    }
+
+    fn rest(&mut self) -> (usize, usize) {
+        let start = self.idx;
+        let end = self.body.len() - self.body.iter().rev().take_while(|b| b.is_ascii_whitespace()).count();
+        self.idx = self.body.len();
+        (start, end.max(start))
+    }
+
+    fn attribute_def(&mut self) -> Option<AttributeDef> {
+        self.skip_whitespace();
+        if self.idx >= self.body.len() {
+            return None;
+        }
+        let name = self.name();
+        let name = self.text(name);
+        self.skip_whitespace();
+        let att_type = if self.body.get(self.idx) == Some(&b'(') {
+            self.group()
+        } else {
+            let (start, end) = self.name();
+            if &self.body[start..end] == b"NOTATION" {
+                self.skip_whitespace();
+                (start, self.group().1)
+            } else {
+                (start, end)
+            }
+        };
+        let att_type = self.text(att_type);
+
+        self.skip_whitespace();
+        let mut default_kind = DefaultKind::Value;
+        if self.peek_quote().is_none() {
+            default_kind = match &self.body[self.name_range()] {
+                b"#REQUIRED" => DefaultKind::Required,
+                b"#IMPLIED" => DefaultKind::Implied,
+                b"#FIXED" => DefaultKind::Fixed,
+                // Not a default declaration. Give up on the rest.
+                _ => {
+                    self.idx = self.body.len();
+                    return Some(AttributeDef {
+                        name,
+                        att_type,
+                        default_value: Text::new([0; 2]),
+                        default_kind,
+                    });
+                }
+            };
+            self.name();
+        }
+        let default_value = match default_kind {
+            DefaultKind::Value | DefaultKind::Fixed => self.literal(),
+            _ => Text::new([0; 2]),
+        };
+        Some(AttributeDef {
+            name,
+            att_type,
+            default_value,
+            default_kind,
+        })
+    }
+
+    /// Builds a `Text` for a part of the body positioned
+    /// relative to the start of the declaration.
+    fn text(&self, (start, end): (usize, usize)) -> Text {
+        let mut text = Text::new(advance_position(self.text.start, &self.body[..start]));
+        text.end = advance_position(text.start, &self.body[start..end]);
+        text.byte_range = (self.text.byte_range.0 + start as u64, self.text.byte_range.0 + end as u64);
+        text.value = self.body[start..end].to_vec();
+        text
+    }
+}
+
+/// Recursive descent over an element content spec.
+struct ModelParser<'s> {
+    spec: &'s [u8],
+    idx: usize,
+}
+
+impl ModelParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.spec.get(self.idx).is_some_and(u8::is_ascii_whitespace) {
+            self.idx += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.spec.get(self.idx) == Some(&byte) {
+            self.idx += 1;
+            return true;
+        }
+        false
+    }
+
+    fn is_mixed(&mut self) -> bool {
+        let idx = self.idx;
+        let mixed = self.eat(b'(') && {
+            self.skip_whitespace();
+            self.spec[self.idx..].starts_with(b"#PCDATA")
+        };
+        self.idx = idx;
+        mixed
+    }
+
+    fn name(&mut self) -> Option<Vec<u8>> {
+        self.skip_whitespace();
+        let len = self.spec[self.idx..]
+            .iter()
+            .position(|b| b.is_ascii_whitespace() || b"|,()?*+".contains(b))
+            .unwrap_or(self.spec.len() - self.idx);
+        if len == 0 {
+            return None;
+        }
+        self.idx += len;
+        Some(self.spec[self.idx - len..self.idx].to_vec())
+    }
+
+    fn mixed(&mut self) -> Option<ContentModel> {
+        self.eat(b'(');
+        self.name(); // #PCDATA
+        let mut names = Vec::new();
+        while self.eat(b'|') {
+            names.push(self.name()?);
+        }
+        if !self.eat(b')') {
+            return None;
+        }
+        // ')*' is required when names are listed
+        if !self.eat(b'*') && !names.is_empty() {
+            return None;
+        }
+        self.end().then_some(ContentModel::Mixed(names))
+    }
+
+    fn particle(&mut self) -> Option<ContentParticle> {
+        let kind = if self.eat(b'(') {
+            let first = self.particle()?;
+            let separator = match self.spec.get(self.idx) {
+                Some(&b) if b == b'|' || b == b',' => b,
+                _ => 0,
+            };
+            let mut items = vec![first];
+            while separator != 0 && self.eat(separator) {
+                items.push(self.particle()?);
+            }
+            if !self.eat(b')') {
+                return None;
+            }
+            if separator == b'|' {
+                ParticleKind::Choice(items)
+            } else {
+                ParticleKind::Seq(items)
+            }
+        } else {
+            ParticleKind::Name(self.name()?)
+        };
+        let occurrence = match self.spec.get(self.idx) {
+            Some(b'?') => Occurrence::Optional,
+            Some(b'*') => Occurrence::ZeroOrMore,
+            Some(b'+') => Occurrence::OneOrMore,
+            _ => Occurrence::Once,
+        };
+        if occurrence != Occurrence::Once {
+            self.idx += 1;
+        }
+        self.skip_whitespace();
+        Some(ContentParticle {
+            kind,
+            occurrence,
+        })
+    }
+
+    fn end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.idx == self.spec.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(body: &str) -> Declaration {
+        let mut text = Text::new([3, 4]);
+        text.byte_range.0 = 100;
+        text.value = body.as_bytes().to_vec();
+        Declaration::parse(text)
+    }
+
+    #[test]
+    fn parses_element_declarations() {
+        let decl = parse("ELEMENT  p (#PCDATA | b | i)*");
+        assert_eq!(decl.kind, DeclarationKind::Element);
+        assert_eq!(decl.name.value, b"p");
+        assert_eq!(decl.name.start, [3, 13]);
+        assert_eq!(decl.name.byte_range, (109, 110));
+        assert_eq!(decl.content.value, b"(#PCDATA | b | i)*");
+        assert_eq!(decl.content_model(), Some(ContentModel::Mixed(vec![b"b".to_vec(), b"i".to_vec()])));
+
+        assert_eq!(parse("ELEMENT br EMPTY").content_model(), Some(ContentModel::Empty));
+        assert_eq!(parse("ELEMENT x ANY").content_model(), Some(ContentModel::Any));
+        assert_eq!(parse("ELEMENT t (#PCDATA)").content_model(), Some(ContentModel::Mixed(Vec::new())));
+        assert_eq!(parse("ELEMENT t (#PCDATA|b)").content_model(), None);
+        assert_eq!(parse("ELEMENT t (a,b").content_model(), None);
+
+        let Some(ContentModel::Children(particle)) = parse("ELEMENT doc (head, (p | list)+)").content_model() else {
+            panic!("expected element content");
+        };
+        let ParticleKind::Seq(items) = particle.kind else {
+            panic!("expected a sequence");
+        };
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].occurrence, Occurrence::OneOrMore);
+        assert_eq!(
+            items[1].kind,
+            ParticleKind::Choice(vec![
+                ContentParticle {
+                    kind: ParticleKind::Name(b"p".to_vec()),
+                    occurrence: Occurrence::Once
+                },
+                ContentParticle {
+                    kind: ParticleKind::Name(b"list".to_vec()),
+                    occurrence: Occurrence::Once
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_attlist_declarations() {
+        let decl = parse("ATTLIST a\n  href CDATA #REQUIRED\n  type NOTATION (x|y) #IMPLIED\n  lang NMTOKEN #FIXED 'en'");
+        assert_eq!(decl.kind, DeclarationKind::Attlist);
+        assert_eq!(decl.attributes.len(), 3);
+        assert_eq!(decl.attributes[0].default_kind, DefaultKind::Required);
+        assert_eq!(decl.attributes[1].att_type.value, b"NOTATION (x|y)");
+        assert_eq!(decl.attributes[1].default_kind, DefaultKind::Implied);
+        let lang = &decl.attributes[2];
+        assert_eq!(lang.name.start, [6, 2]);
+        assert_eq!(lang.default_kind, DefaultKind::Fixed);
+        assert_eq!(lang.default_value.value, b"en");
+        assert_eq!(lang.default_value.start, [6, 23]);
+    }
+
+    #[test]
+    fn parses_entity_and_notation_declarations() {
+        let decl = parse(r#"ENTITY % common "a | b""#);
+        assert!(decl.parameter);
+        assert_eq!(decl.name.value, b"common");
+        assert_eq!(decl.content.value, b"a | b");
+
+        let decl = parse(r#"ENTITY logo PUBLIC "-//ACME//Logo" 'logo.gif' NDATA gif"#);
+        assert!(!decl.parameter);
+        assert_eq!(decl.public_id.value, b"-//ACME//Logo");
+        assert_eq!(decl.system_id.value, b"logo.gif");
+        assert_eq!(decl.notation.value, b"gif");
+        assert_eq!(decl.notation.byte_range, (152, 155));
+
+        let decl = parse(r#"NOTATION gif PUBLIC "image/gif""#);
+        assert_eq!(decl.kind, DeclarationKind::Notation);
+        assert_eq!(decl.public_id.value, b"image/gif");
+        assert!(decl.system_id.value.is_empty());
+
+        let decl = parse("FOO bar");
+        assert_eq!(decl.kind, DeclarationKind::Other);
+        assert!(decl.name.value.is_empty());
+    }
+}
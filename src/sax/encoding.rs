@@ -0,0 +1,376 @@
+/// The input encodings the parser transcodes to UTF-8.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    // ISO-8859-1
+    Latin1,
+    Windows1252,
+}
+
+impl Encoding {
+    /// Looks up an encoding by the label used in the
+    /// `encoding` pseudo-attribute of the XML declaration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sax_wasm::sax::encoding::Encoding;
+    ///
+    /// assert_eq!(Encoding::from_label(b"ISO-8859-1"), Some(Encoding::Latin1));
+    /// assert_eq!(Encoding::from_label(b"cp1252"), Some(Encoding::Windows1252));
+    /// assert_eq!(Encoding::from_label(b"Shift_JIS"), None);
+    /// ```
+    pub fn from_label(label: &[u8]) -> Option<Encoding> {
+        let label = label.trim_ascii().to_ascii_lowercase();
+        let encoding = match label.as_slice() {
+            b"utf-8" | b"utf8" => Encoding::Utf8,
+            b"utf-16le" => Encoding::Utf16Le,
+            // Without a BOM, UTF-16 is big endian
+            b"utf-16" | b"utf-16be" => Encoding::Utf16Be,
+            b"iso-8859-1" | b"iso8859-1" | b"iso_8859-1" | b"latin1" | b"l1" | b"us-ascii" | b"ascii" => Encoding::Latin1,
+            b"windows-1252" | b"cp1252" | b"x-cp1252" => Encoding::Windows1252,
+            _ => return None,
+        };
+        Some(encoding)
+    }
+
+    fn is_utf16(self) -> bool {
+        self == Encoding::Utf16Le || self == Encoding::Utf16Be
+    }
+}
+
+/// The longest XML declaration that is buffered while
+/// looking for its `encoding` pseudo-attribute.
+pub const MAX_SNIFF_LEN: usize = 1024;
+
+/// Detects the encoding from the first bytes of a document.
+///
+/// A byte order mark wins. Otherwise UTF-16 is recognized by the
+/// `<?` of the XML declaration and ASCII compatible documents use the
+/// `encoding` pseudo-attribute of the XML declaration when present.
+///
+/// # Returns
+///
+/// * `None` when more bytes are needed to decide, otherwise the
+///   encoding and the length of the byte order mark to skip.
+///   `bytes` is considered complete when `eof` is true.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::encoding::{sniff, Encoding};
+///
+/// assert_eq!(sniff(b"\xFF\xFE<\0", false), Some((Encoding::Utf16Le, 2)));
+/// assert_eq!(sniff(b"<?xml version='1.0' encoding='windows-1252'?>", false), Some((Encoding::Windows1252, 0)));
+/// assert_eq!(sniff(b"<?xml version='1.0'", false), None);
+/// assert_eq!(sniff(b"<root/>", false), Some((Encoding::Utf8, 0)));
+/// ```
+pub fn sniff(bytes: &[u8], eof: bool) -> Option<(Encoding, usize)> {
+    match bytes {
+        [0xEF, 0xBB, 0xBF, ..] => return Some((Encoding::Utf8, 3)),
+        [0xFF, 0xFE, ..] => return Some((Encoding::Utf16Le, 2)),
+        [0xFE, 0xFF, ..] => return Some((Encoding::Utf16Be, 2)),
+        [b'<', 0, b'?', 0, ..] => return Some((Encoding::Utf16Le, 0)),
+        [0, b'<', 0, b'?', ..] => return Some((Encoding::Utf16Be, 0)),
+        _ => {}
+    }
+    // A prefix of any of the above or of '<?xml '
+    let undecided = bytes.len() < 6
+        && (b"<?xml ".starts_with(bytes)
+            || b"\xEF\xBB\xBF".starts_with(bytes)
+            || bytes == [0xFF]
+            || bytes == [0xFE]
+            || b"<\0?".starts_with(bytes)
+            || b"\0<\0".starts_with(bytes));
+    if undecided && !eof {
+        return None;
+    }
+    // '<?xml-stylesheet' and the like are processing instructions
+    if !matches!(bytes, [b'<', b'?', b'x', b'm', b'l', b' ' | b'\t' | b'\r' | b'\n', ..]) {
+        return Some((Encoding::Utf8, 0));
+    }
+    let Some(end) = bytes.iter().position(|&b| b == b'>') else {
+        return if eof || bytes.len() >= MAX_SNIFF_LEN {
+            Some((Encoding::Utf8, 0))
+        } else {
+            None
+        };
+    };
+    let encoding = declared_encoding(&bytes[..end])
+        .and_then(Encoding::from_label)
+        // The declaration was readable as ASCII so it cannot be UTF-16
+        .filter(|encoding| !encoding.is_utf16())
+        .unwrap_or(Encoding::Utf8);
+    Some((encoding, 0))
+}
+
+/// Finds the value of the `encoding` pseudo-attribute.
+fn declared_encoding(xml_decl: &[u8]) -> Option<&[u8]> {
+    let idx = xml_decl.windows(8).position(|w| w == b"encoding")?;
+    let rest = xml_decl[idx + 8..].trim_ascii_start().strip_prefix(b"=")?.trim_ascii_start();
+    let quote = *rest.first().filter(|&&b| b == b'"' || b == b'\'')?;
+    let len = rest[1..].iter().position(|&b| b == quote)?;
+    Some(&rest[1..1 + len])
+}
+
+/// Transcodes chunks of a document to UTF-8.
+///
+/// Code units split across chunks are carried over to the next call.
+/// The decoder also remembers where each run of characters started
+/// in the input so offsets into the UTF-8 output can be mapped back
+/// to offsets into the original bytes.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::encoding::{Decoder, Encoding};
+///
+/// let mut decoder = Decoder::new(Encoding::Utf16Le, 2);
+/// let mut out = Vec::new();
+/// decoder.decode(b"<\0\xE9", &mut out);
+/// decoder.decode(b"\0/\0", &mut out);
+/// assert_eq!(out, "<é/".as_bytes());
+///
+/// // '/' starts at UTF-8 offset 3 and at input offset 6 (after the BOM)
+/// assert_eq!(decoder.input_offset(3), 6);
+/// ```
+pub struct Decoder {
+    encoding: Encoding,
+    // Bytes of a code unit (or the high surrogate
+    // of a pair) cut off at the end of the last chunk
+    pending: Vec<u8>,
+    // Runs of characters with the same UTF-8 and input width
+    pub(super) runs: Vec<Run>,
+    output_len: u64,
+    input_len: u64,
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct Run {
+    output_start: u64,
+    input_start: u64,
+    output_width: u8,
+    input_width: u8,
+}
+
+impl Decoder {
+    /// Creates a decoder for input that starts `bom_len` bytes into the document.
+    pub fn new(encoding: Encoding, bom_len: usize) -> Decoder {
+        Decoder {
+            encoding,
+            pending: Vec::new(),
+            runs: Vec::new(),
+            output_len: 0,
+            input_len: bom_len as u64,
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Appends the UTF-8 encoding of `input` to `out`.
+    pub fn decode(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        out.reserve(input.len() * 2);
+        let mut bytes = input;
+        let joined;
+        if !self.pending.is_empty() {
+            let mut pending = std::mem::take(&mut self.pending);
+            pending.extend_from_slice(input);
+            joined = pending;
+            bytes = &joined;
+        }
+        match self.encoding {
+            Encoding::Utf8 => {
+                self.push(out, bytes, 1, 1);
+            }
+            Encoding::Latin1 | Encoding::Windows1252 => {
+                for &byte in bytes {
+                    let ch = if self.encoding == Encoding::Windows1252 {
+                        windows_1252_char(byte)
+                    } else {
+                        byte as char
+                    };
+                    self.push_char(out, ch, 1);
+                }
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let mut idx = 0;
+                while idx + 1 < bytes.len() {
+                    let unit = self.code_unit(bytes, idx);
+                    if (0xD800..0xDC00).contains(&unit) {
+                        if idx + 3 >= bytes.len() {
+                            break;
+                        }
+                        let low = self.code_unit(bytes, idx + 2);
+                        if (0xDC00..0xE000).contains(&low) {
+                            let code = 0x10000 + ((unit as u32 - 0xD800) << 10) + (low as u32 - 0xDC00);
+                            self.push_char(out, char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER), 4);
+                            idx += 4;
+                            continue;
+                        }
+                    }
+                    self.push_char(out, char::from_u32(unit as u32).unwrap_or(char::REPLACEMENT_CHARACTER), 2);
+                    idx += 2;
+                }
+                self.pending.extend_from_slice(&bytes[idx..]);
+            }
+        }
+    }
+
+    /// Appends a U+FFFD for a code unit cut off at the end of the input.
+    /// Call it once the last chunk was decoded.
+    pub fn finish(&mut self, out: &mut Vec<u8>) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let input_width = self.pending.len() as u8;
+        self.pending.clear();
+        self.push_char(out, char::REPLACEMENT_CHARACTER, input_width);
+    }
+
+    /// Maps an offset into the UTF-8 output back to
+    /// the offset into the original input.
+    pub fn input_offset(&self, output_offset: u64) -> u64 {
+        let idx = self.runs.partition_point(|run| run.output_start <= output_offset);
+        let Some(run) = idx.checked_sub(1).map(|idx| self.runs[idx]) else {
+            return output_offset + self.input_len - self.output_len;
+        };
+        let chars = (output_offset - run.output_start) / run.output_width as u64;
+        run.input_start + chars * run.input_width as u64
+    }
+
+    /// Drops the runs that end before `output_offset`. Offsets
+    /// before it can no longer be mapped to the input.
+    pub fn discard_before(&mut self, output_offset: u64) {
+        let idx = self.runs.partition_point(|run| run.output_start <= output_offset);
+        self.runs.drain(..idx.saturating_sub(1));
+    }
+
+    fn code_unit(&self, bytes: &[u8], idx: usize) -> u16 {
+        let pair = [bytes[idx], bytes[idx + 1]];
+        if self.encoding == Encoding::Utf16Le {
+            u16::from_le_bytes(pair)
+        } else {
+            u16::from_be_bytes(pair)
+        }
+    }
+
+    fn push_char(&mut self, out: &mut Vec<u8>, ch: char, input_width: u8) {
+        let mut buf = [0u8; 4];
+        self.push(out, ch.encode_utf8(&mut buf).as_bytes(), ch.len_utf8() as u8, input_width);
+    }
+
+    fn push(&mut self, out: &mut Vec<u8>, utf8: &[u8], output_width: u8, input_width: u8) {
+        let same_run = self.runs.last().is_some_and(|run| run.output_width == output_width && run.input_width == input_width);
+        if !same_run {
+            self.runs.push(Run {
+                output_start: self.output_len,
+                input_start: self.input_len,
+                output_width,
+                input_width,
+            });
+        }
+        out.extend_from_slice(utf8);
+        let chars = (utf8.len() / output_width as usize) as u64;
+        self.output_len += utf8.len() as u64;
+        self.input_len += chars * input_width as u64;
+    }
+}
+
+/// Maps a Windows-1252 byte to its character. The five
+/// undefined bytes map to the C1 control of the same value.
+fn windows_1252_char(byte: u8) -> char {
+    const HIGH: [u16; 32] = [
+        0x20AC, 0x81, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0x8D, 0x017D, 0x8F, //
+        0x90, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014, 0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0x9D, 0x017E, 0x0178,
+    ];
+    match byte {
+        0x80..=0x9F => char::from_u32(HIGH[byte as usize - 0x80] as u32).unwrap_or(char::REPLACEMENT_CHARACTER),
+        _ => byte as char,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sniff, Decoder, Encoding};
+
+    fn decode_split(encoding: Encoding, input: &[u8]) -> Vec<u8> {
+        let mut whole = Vec::new();
+        Decoder::new(encoding, 0).decode(input, &mut whole);
+        for i in 1..input.len() {
+            let mut decoder = Decoder::new(encoding, 0);
+            let mut out = Vec::new();
+            decoder.decode(&input[..i], &mut out);
+            decoder.decode(&input[i..], &mut out);
+            assert_eq!(out, whole, "At iteration i={}", i);
+        }
+        whole
+    }
+
+    #[test]
+    fn decodes_utf16_with_surrogates_split_across_chunks() {
+        let text = "<a>😀é</a>";
+        let le: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(decode_split(Encoding::Utf16Le, &le), text.as_bytes());
+        assert_eq!(decode_split(Encoding::Utf16Be, &be), text.as_bytes());
+
+        // unpaired surrogate
+        assert_eq!(decode_split(Encoding::Utf16Le, b"\x00\xD8a\x00"), "\u{FFFD}a".as_bytes());
+
+        // truncated input
+        let mut decoder = Decoder::new(Encoding::Utf16Le, 0);
+        let mut out = Vec::new();
+        decoder.decode(b"a\0\x3D\xD8\0", &mut out);
+        assert_eq!(out, b"a");
+        decoder.finish(&mut out);
+        assert_eq!(out, "a\u{FFFD}".as_bytes());
+        assert_eq!(decoder.input_offset(4), 5);
+    }
+
+    #[test]
+    fn decodes_single_byte_encodings() {
+        assert_eq!(decode_split(Encoding::Latin1, b"caf\xE9 \x80"), "café \u{80}".as_bytes());
+        assert_eq!(decode_split(Encoding::Windows1252, b"caf\xE9 \x80\x96\x81"), "café €–\u{81}".as_bytes());
+    }
+
+    #[test]
+    fn maps_output_offsets_to_input_offsets() {
+        let mut decoder = Decoder::new(Encoding::Windows1252, 0);
+        let mut out = Vec::new();
+        decoder.decode(b"ab\x80cd\xE9", &mut out);
+        // a b € c d é
+        assert_eq!(out.len(), 9);
+        assert_eq!(decoder.input_offset(0), 0);
+        assert_eq!(decoder.input_offset(2), 2);
+        assert_eq!(decoder.input_offset(5), 3);
+        assert_eq!(decoder.input_offset(7), 5);
+        assert_eq!(decoder.input_offset(9), 6);
+
+        let mut decoder = Decoder::new(Encoding::Utf16Be, 2);
+        let mut out = Vec::new();
+        let input: Vec<u8> = "x😀y".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        decoder.decode(&input, &mut out);
+        assert_eq!(decoder.input_offset(1), 4);
+        assert_eq!(decoder.input_offset(5), 8);
+        assert_eq!(decoder.input_offset(6), 10);
+    }
+
+    #[test]
+    fn sniffs_the_encoding() {
+        assert_eq!(sniff(b"\xEF\xBB\xBF<a/>", false), Some((Encoding::Utf8, 3)));
+        assert_eq!(sniff(b"\xFE\xFF\0<", false), Some((Encoding::Utf16Be, 2)));
+        assert_eq!(sniff(b"\0<\0?\0x", false), Some((Encoding::Utf16Be, 0)));
+        assert_eq!(sniff(b"<?x", false), None);
+        assert_eq!(sniff(b"<?x", true), Some((Encoding::Utf8, 0)));
+        assert_eq!(sniff(b"<?xml-stylesheet?>", false), Some((Encoding::Utf8, 0)));
+        assert_eq!(sniff(br#"<?xml-stylesheet encoding="ISO-8859-1"?>"#, false), Some((Encoding::Utf8, 0)));
+        assert_eq!(sniff(b"<?xml\tencoding='latin1'?>", false), Some((Encoding::Latin1, 0)));
+        assert_eq!(sniff(br#"<?xml version="1.0" encoding = "ISO-8859-1"?><a/>"#, false), Some((Encoding::Latin1, 0)));
+        assert_eq!(sniff(br#"<?xml version="1.0" encoding="UTF-16"?>"#, false), Some((Encoding::Utf8, 0)));
+        assert_eq!(sniff(br#"<?xml version="1.0" encoding="bogus"?>"#, false), Some((Encoding::Utf8, 0)));
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+
+use super::dtd::Declaration;
+use super::tag::Text;
+
+/// Decodes the predefined XML entities and numeric character references
+/// found in the given bytes.
+///
+/// The five predefined entities (`&amp;`, `&lt;`, `&gt;`, `&quot;` and `&apos;`)
+/// as well as decimal (`&#60;`) and hexadecimal (`&#x3C;`) character references
+/// are resolved. References that are unknown, unterminated or that point to
+/// a code point which is not a valid XML character are left untouched so the
+/// lenient parsing behavior is preserved.
+///
+/// # Arguments
+///
+/// * `bytes` - The raw bytes of a text node or attribute value.
+///
+/// # Returns
+///
+/// * `Some` containing the decoded bytes if at least one reference was resolved,
+///   `None` if the bytes contain nothing to decode.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::entities::decode;
+///
+/// assert_eq!(decode(b"1 &lt; 2 &amp;&amp; 3 &gt; 2").unwrap(), b"1 < 2 && 3 > 2");
+/// assert_eq!(decode("&#x1F600;&#65;".as_bytes()).unwrap(), "😀A".as_bytes());
+/// assert!(decode(b"nothing to see here").is_none());
+/// assert!(decode(b"&unknown;").is_none());
+/// ```
+pub fn decode(bytes: &[u8]) -> Option<Vec<u8>> {
+    decode_with(bytes, None)
+}
+
+fn decode_with(bytes: &[u8], table: Option<&EntityTable>) -> Option<Vec<u8>> {
+    if !bytes.contains(&b'&') {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(bytes.len());
+    if expand(bytes, table, &mut Vec::new(), &mut decoded) {
+        Some(decoded)
+    } else {
+        None
+    }
+}
+
+/// Appends `bytes` to `out` with every reference that can be resolved
+/// replaced by its value. `stack` holds the names of the general entities
+/// currently being expanded and is used to detect recursive references.
+fn expand<'t>(bytes: &[u8], table: Option<&'t EntityTable>, stack: &mut Vec<&'t [u8]>, out: &mut Vec<u8>) -> bool {
+    let mut changed = false;
+    let mut idx = 0;
+    let mut copied_to = 0;
+
+    while let Some(offset) = bytes[idx..].iter().position(|&b| b == b'&') {
+        idx += offset;
+        let Some(len) = bytes[idx + 1..].iter().take(MAX_REFERENCE_LEN).position(|&b| b == b';') else {
+            idx += 1;
+            continue;
+        };
+        let name = &bytes[idx + 1..idx + 1 + len];
+        out.extend_from_slice(&bytes[copied_to..idx]);
+        copied_to = idx;
+        if resolve_reference(name, out) || table.is_some_and(|table| table.expand_general(name, stack, out)) {
+            changed = true;
+            idx += len + 2;
+            copied_to = idx;
+        } else {
+            idx += 1;
+        }
+    }
+
+    out.extend_from_slice(&bytes[copied_to..]);
+    changed
+}
+
+/// Holds the general and parameter entities declared in the
+/// internal subset of a DOCTYPE.
+///
+/// Declarations are registered using the body of the markup declaration
+/// as it is handed out by the parser (everything between `<!` and `>`).
+/// Only internal entities (those with a literal value) are stored; external
+/// entities are never fetched. As required by the XML spec, the first
+/// declaration of a name is binding and later ones are ignored.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::entities::EntityTable;
+///
+/// let mut entities = EntityTable::new();
+/// entities.declare(br#"ENTITY % brand "Acme""#);
+/// entities.declare(br#"ENTITY product "%brand; Rocket &#8482;""#);
+/// entities.declare(br#"ENTITY tagline "&product; &amp; more""#);
+///
+/// assert_eq!(entities.get(b"product").unwrap(), "Acme Rocket ™".as_bytes());
+/// assert_eq!(entities.decode(b"Buy &tagline;").unwrap(), "Buy Acme Rocket ™ & more".as_bytes());
+/// ```
+#[derive(Default)]
+pub struct EntityTable {
+    general: HashMap<Vec<u8>, Vec<u8>>,
+    parameter: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl EntityTable {
+    pub fn new() -> EntityTable {
+        EntityTable::default()
+    }
+
+    /// Registers an `ENTITY` markup declaration.
+    ///
+    /// Parameter entity references and character references in the literal
+    /// value are expanded immediately while general entity references are
+    /// kept and resolved when the entity is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `declaration` - The declaration body, e.g. `ENTITY name "value"`.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if a new internal entity was registered, `false` otherwise.
+    pub fn declare(&mut self, declaration: &[u8]) -> bool {
+        let mut text = Text::new([0; 2]);
+        text.value = declaration.to_vec();
+        self.declare_entity(&Declaration::parse(text))
+    }
+
+    /// Registers a parsed `ENTITY` declaration.
+    /// See [`EntityTable::declare`].
+    pub fn declare_entity(&mut self, declaration: &Declaration) -> bool {
+        let name = declaration.name.value.as_slice();
+        if !declaration.is_internal_entity() || name.is_empty() {
+            return false;
+        }
+        let value = self.replacement_text(&declaration.content.value);
+        let entities = if declaration.parameter {
+            &mut self.parameter
+        } else {
+            &mut self.general
+        };
+        if entities.contains_key(name) {
+            return false;
+        }
+        entities.insert(name.to_vec(), value);
+        true
+    }
+
+    /// Returns the replacement text of a declared general entity.
+    pub fn get(&self, name: &[u8]) -> Option<&[u8]> {
+        self.general.get(name).map(Vec::as_slice)
+    }
+
+    /// Returns the replacement text of a declared parameter entity.
+    pub fn get_parameter(&self, name: &[u8]) -> Option<&[u8]> {
+        self.parameter.get(name).map(Vec::as_slice)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.general.is_empty() && self.parameter.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.general.clear();
+        self.parameter.clear();
+    }
+
+    /// Decodes the predefined entities, character references and
+    /// the general entities declared in this table.
+    ///
+    /// Entity values are expanded recursively. A reference to an entity
+    /// that is already being expanded is recursive and is left untouched.
+    ///
+    /// # Returns
+    ///
+    /// * `Some` containing the decoded bytes if at least one reference was resolved,
+    ///   `None` otherwise.
+    pub fn decode(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        decode_with(bytes, Some(self))
+    }
+
+    fn expand_general<'t>(&'t self, name: &[u8], stack: &mut Vec<&'t [u8]>, out: &mut Vec<u8>) -> bool {
+        let Some((name, value)) = self.general.get_key_value(name) else {
+            return false;
+        };
+        if stack.contains(&name.as_slice()) || out.len() > MAX_EXPANDED_LEN {
+            return false;
+        }
+        stack.push(name);
+        expand(value, Some(self), stack, out);
+        stack.pop();
+        true
+    }
+
+    /// Builds the replacement text for an entity value literal by
+    /// expanding parameter entity and character references.
+    fn replacement_text(&self, literal: &[u8]) -> Vec<u8> {
+        let mut value = Vec::with_capacity(literal.len());
+        let mut idx = 0;
+        while idx < literal.len() {
+            let byte = literal[idx];
+            if byte == b'%' || (byte == b'&' && literal.get(idx + 1) == Some(&b'#')) {
+                if let Some(len) = literal[idx + 1..].iter().take(MAX_REFERENCE_LEN).position(|&b| b == b';') {
+                    let name = &literal[idx + 1..idx + 1 + len];
+                    let resolved = if byte == b'%' {
+                        self.parameter.get(name).map(|pe| value.extend_from_slice(pe)).is_some()
+                    } else {
+                        resolve_reference(name, &mut value)
+                    };
+                    if resolved {
+                        idx += len + 2;
+                        continue;
+                    }
+                }
+            }
+            value.push(byte);
+            idx += 1;
+        }
+        value
+    }
+}
+
+/// Expansion stops once a decoded value grows past this size
+/// which guards against exponential "billion laughs" entities.
+const MAX_EXPANDED_LEN: usize = 1 << 24;
+
+/// The longest reference body considered when searching for the
+/// terminating `;`. `&#x10FFFF;` and the predefined entity names
+/// comfortably fit, anything longer is treated as literal text.
+const MAX_REFERENCE_LEN: usize = 32;
+
+/// Resolves a single reference body (the bytes between `&` and `;`)
+/// and appends its UTF-8 encoded value to `out`.
+///
+/// # Returns
+///
+/// * `true` if the reference was resolved, `false` otherwise.
+pub fn resolve_reference(name: &[u8], out: &mut Vec<u8>) -> bool {
+    let value: &[u8] = match name {
+        b"amp" => b"&",
+        b"lt" => b"<",
+        b"gt" => b">",
+        b"quot" => b"\"",
+        b"apos" => b"'",
+        [b'#', b'x', digits @ ..] => return push_char_code(parse_code_point(digits, 16), out),
+        [b'#', digits @ ..] => return push_char_code(parse_code_point(digits, 10), out),
+        _ => return false,
+    };
+    out.extend_from_slice(value);
+    true
+}
+
+fn parse_code_point(digits: &[u8], radix: u32) -> Option<u32> {
+    if digits.is_empty() {
+        return None;
+    }
+    let mut code: u32 = 0;
+    for &digit in digits {
+        let value = (digit as char).to_digit(radix)?;
+        code = code.checked_mul(radix)?.checked_add(value)?;
+    }
+    Some(code)
+}
+
+fn push_char_code(code: Option<u32>, out: &mut Vec<u8>) -> bool {
+    let Some(ch) = code.filter(|&c| is_xml_char(c)).and_then(char::from_u32) else {
+        return false;
+    };
+    let mut buf = [0u8; 4];
+    out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+    true
+}
+
+/// Checks if a code point matches the XML 1.0 `Char` production.
+fn is_xml_char(code: u32) -> bool {
+    matches!(code, 0x9 | 0xA | 0xD | 0x20..=0xD7FF | 0xE000..=0xFFFD | 0x10000..=0x10FFFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, EntityTable};
+
+    #[test]
+    fn decodes_predefined_entities() {
+        let decoded = decode(b"&lt;a href=&quot;x&quot;&gt; &amp; &apos;").unwrap();
+        assert_eq!(decoded, b"<a href=\"x\"> & '");
+    }
+
+    #[test]
+    fn decodes_numeric_references() {
+        assert_eq!(decode(b"&#60;&#x3c;&#x3C;").unwrap(), b"<<<");
+        assert_eq!(decode("x&#128512;y".as_bytes()).unwrap(), "x😀y".as_bytes());
+    }
+
+    #[test]
+    fn expands_declared_entities() {
+        let mut entities = EntityTable::new();
+        assert!(entities.declare(br#"ENTITY copy "&#169; 2024""#));
+        assert!(entities.declare(b"ENTITY\n  note\t'<b>&copy;</b>'"));
+        assert!(!entities.declare(br#"ENTITY copy "ignored""#));
+        assert!(!entities.declare(br#"ENTITY logo SYSTEM "logo.gif" NDATA gif"#));
+        assert!(!entities.declare(br#"ELEMENT note (#PCDATA)"#));
+
+        assert_eq!(entities.get(b"copy").unwrap(), "© 2024".as_bytes());
+        assert_eq!(entities.decode(b"&note; &lt;&copy;&gt; &logo;").unwrap(), "<b>© 2024</b> <© 2024> &logo;".as_bytes());
+    }
+
+    #[test]
+    fn expands_parameter_entities_in_values() {
+        let mut entities = EntityTable::new();
+        assert!(entities.declare(br#"ENTITY % year "2024""#));
+        assert!(entities.declare(br#"ENTITY year "%year;/%unknown;""#));
+        assert_eq!(entities.get_parameter(b"year").unwrap(), b"2024");
+        assert_eq!(entities.get(b"year").unwrap(), b"2024/%unknown;");
+    }
+
+    #[test]
+    fn leaves_recursive_references_untouched() {
+        let mut entities = EntityTable::new();
+        entities.declare(br#"ENTITY a "a[&b;]""#);
+        entities.declare(br#"ENTITY b "b[&a;]""#);
+        entities.declare(br#"ENTITY self "&self;""#);
+        assert_eq!(entities.decode(b"&a;").unwrap(), b"a[b[&a;]]");
+        assert_eq!(entities.decode(b"&self;|&amp;").unwrap(), b"&self;|&");
+    }
+
+    #[test]
+    fn leaves_invalid_references_untouched() {
+        assert!(decode(b"AT&T").is_none());
+        assert!(decode(b"&#0; &#xD800; &#x110000; &#xZZ; &#; &;").is_none());
+        assert!(decode(b"&#99999999999999999999;").is_none());
+        assert!(decode(b"&bogus; &amp").is_none());
+        assert_eq!(decode(b"&& &amp;").unwrap(), b"&& &");
+    }
+}
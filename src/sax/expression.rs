@@ -0,0 +1,72 @@
+//! The reader shared by the expression languages of [`xpath`](super::xpath)
+//! and [`selector`](super::selector).
+
+use std::marker::PhantomData;
+
+/// An invalid expression of one of the languages.
+pub(super) trait ExpressionError {
+    fn new(expression: &str, message: &str) -> Self;
+}
+
+/// Reads an expression from left to right. Each language adds
+/// its own productions in an `impl Reader<'_, ItsError>` block.
+pub(super) struct Reader<'a, E> {
+    expression: &'a str,
+    pub(super) rest: &'a [u8],
+    error: PhantomData<E>,
+}
+
+impl<'a, E: ExpressionError> Reader<'a, E> {
+    pub(super) fn new(expression: &'a str) -> Reader<'a, E> {
+        Reader {
+            expression,
+            rest: expression.as_bytes(),
+            error: PhantomData,
+        }
+    }
+
+    pub(super) fn error<T>(&self, message: &str) -> Result<T, E> {
+        Err(E::new(self.expression, message))
+    }
+
+    pub(super) fn eat(&mut self, prefix: &[u8]) -> bool {
+        match self.rest.strip_prefix(prefix) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Skips whitespace and returns whether there was any.
+    pub(super) fn whitespace(&mut self) -> bool {
+        let end = self.rest.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(self.rest.len());
+        self.rest = &self.rest[end..];
+        end > 0
+    }
+
+    /// Reads the bytes up to the first one `is_name` rejects.
+    pub(super) fn name_while(&mut self, is_name: impl Fn(u8) -> bool) -> Result<Vec<u8>, E> {
+        let end = self.rest.iter().position(|&b| !is_name(b)).unwrap_or(self.rest.len());
+        if end == 0 {
+            return self.error("a name is missing");
+        }
+        let name = self.rest[..end].to_vec();
+        self.rest = &self.rest[end..];
+        Ok(name)
+    }
+
+    /// Reads a value in single or double quotes, `None` when there is no quote.
+    pub(super) fn quoted(&mut self) -> Result<Option<Vec<u8>>, E> {
+        let Some(&quote) = self.rest.first().filter(|&&b| b == b'\'' || b == b'"') else {
+            return Ok(None);
+        };
+        let Some(end) = self.rest[1..].iter().position(|&b| b == quote) else {
+            return self.error("a quoted value is not closed");
+        };
+        let value = self.rest[1..end + 1].to_vec();
+        self.rest = &self.rest[end + 2..];
+        Ok(Some(value))
+    }
+}
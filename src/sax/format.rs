@@ -0,0 +1,444 @@
+//! Re-indents markup.
+//!
+//! The [`Formatter`] reads a document from the [lossless tokens](super::lossless)
+//! of its chunks and writes it to a sink as they come, so documents larger
+//! than memory can be formatted. Only whitespace which does not change the
+//! meaning of the document is changed:
+//!
+//! * An element whose content starts with markup has each child on a line
+//!   of its own, indented one level deeper than the element.
+//! * An element whose content starts with text has mixed content. Its
+//!   content is written with its whitespace unchanged. An element found to
+//!   have text after child elements is written unchanged from that text on.
+//! * The content of elements with `xml:space="preserve"` and of the elements
+//!   in [`FormatOptions::preserve`] is written unchanged, tags included.
+//! * Whitespace between attributes is normalized to a single space. Start
+//!   tags reaching past [`FormatOptions::max_width`] have an attribute per line.
+//!
+//! Unlike the other handlers of this crate but like the [minifier](super::minify),
+//! the formatter is not built on the [`SAXParser`](super::parser::SAXParser):
+//! it needs the whitespace and the spelling of tags the parser drops, and
+//! writes malformed input out as is rather than reporting it. Its tests check that the parser reads the same
+//! elements, attributes and text from the output as from the input.
+//!
+//! # Examples
+//!
+//! ```
+//! use sax_wasm::sax::format::{format, FormatOptions};
+//!
+//! let doc = b"<catalog><product   id=\"1\"><name>Tea</name>\n<note>Keep <b>dry</b></note></product></catalog>";
+//! let formatted = format(doc, &FormatOptions::default());
+//! assert_eq!(
+//!     String::from_utf8(formatted).unwrap(),
+//!     "<catalog>\n  <product id=\"1\">\n    <name>Tea</name>\n    <note>Keep <b>dry</b></note>\n  </product>\n</catalog>\n"
+//! );
+//! ```
+
+use std::io::{self, Write};
+
+use super::lossless::{PullToken, Token, TokenKind, Tokenizer};
+use super::markup::{contains_name, names, CloseTag, Frames, Markup, StartTag, TagReader, HTML_PRESERVE, HTML_VOID_ELEMENTS};
+
+/// How elements written as self-closing in the input are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfClosing {
+    /// `<a/>` or `<a />` as in the input.
+    Preserve,
+    /// `<a/>`
+    Compact,
+    /// `<a />`
+    Spaced,
+    /// `<a></a>`
+    Expanded,
+}
+
+/// The options of a [`Formatter`].
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// The number of spaces per level of indentation.
+    pub indent: usize,
+    /// Start tags longer than this many characters from the start
+    /// of their line are written with an attribute per line.
+    pub max_width: usize,
+    /// How self-closing elements are written.
+    pub self_closing: SelfClosing,
+    /// Elements whose content is written unchanged, compared ignoring ASCII case.
+    pub preserve: Vec<Vec<u8>>,
+    /// Elements without content or close tag, compared ignoring ASCII case.
+    pub void_elements: Vec<Vec<u8>>,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent: 2,
+            max_width: 100,
+            self_closing: SelfClosing::Preserve,
+            preserve: Vec::new(),
+            void_elements: Vec::new(),
+        }
+    }
+}
+
+impl FormatOptions {
+    /// The options for HTML, preserving the content of `pre`, `textarea`,
+    /// `script` and `style` elements and knowing the void elements.
+    pub fn html() -> FormatOptions {
+        FormatOptions {
+            preserve: names(HTML_PRESERVE),
+            void_elements: names(HTML_VOID_ELEMENTS),
+            ..FormatOptions::default()
+        }
+    }
+}
+
+/// How the content of an element is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    // Nothing but whitespace was read in the element yet
+    Pending,
+    // A child per line
+    Block,
+    // Whitespace unchanged
+    Mixed,
+    // Everything unchanged
+    Preserve,
+}
+
+/// Writes tokens in their new layout.
+struct Printer<W: Write> {
+    sink: W,
+    options: FormatOptions,
+    // The column of the output
+    column: usize,
+    // The document followed by the open elements
+    frames: Frames<Mode>,
+    tags: TagReader,
+    // Whitespace in content read since the last output
+    whitespace: Vec<u8>,
+    // Anything was written
+    started: bool,
+}
+
+impl<W: Write> Printer<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let characters = |bytes: &[u8]| bytes.iter().filter(|&&b| b & 0b1100_0000 != 0b1000_0000).count();
+        match bytes.iter().rposition(|&b| b == b'\n') {
+            Some(i) => self.column = characters(&bytes[i + 1..]),
+            None => self.column += characters(bytes),
+        }
+        self.started |= !bytes.is_empty();
+        self.sink.write_all(bytes)
+    }
+
+    /// Starts a new line indented to `level`.
+    fn new_line(&mut self, level: usize) -> io::Result<()> {
+        if self.started {
+            self.write(b"\n")?;
+        }
+        self.write(&b" ".repeat(level * self.options.indent))
+    }
+
+    fn level(&self) -> usize {
+        self.frames.depth()
+    }
+
+    /// Readies the output for text or markup in the innermost element.
+    fn before_content(&mut self, is_text: bool) -> io::Result<()> {
+        let level = self.level();
+        let mode = self.frames.last_mut();
+        *mode = match *mode {
+            Mode::Pending | Mode::Block if is_text => Mode::Mixed,
+            Mode::Pending => Mode::Block,
+            mode => mode,
+        };
+        let whitespace = std::mem::take(&mut self.whitespace);
+        match *mode {
+            Mode::Block => self.new_line(level),
+            _ => self.write(&whitespace),
+        }
+    }
+
+    fn token(&mut self, token: Token) -> io::Result<()> {
+        match self.tags.read(token, false) {
+            Markup::Content(token) if token.kind == TokenKind::Whitespace => {
+                self.whitespace.extend_from_slice(token.raw);
+                Ok(())
+            }
+            Markup::Content(token) => {
+                self.before_content(matches!(token.kind, TokenKind::Text | TokenKind::Cdata))?;
+                self.write(token.raw)
+            }
+            Markup::StartTag(tag, self_closing) => self.open_element(tag, self_closing),
+            Markup::CloseTag(tag) => self.close_element(tag),
+            Markup::Partial => Ok(()),
+        }
+    }
+
+    /// Writes the start tag read and opens its element.
+    fn open_element(&mut self, tag: StartTag, self_closing: bool) -> io::Result<()> {
+        let parent = *self.frames.last();
+        let mode = match tag.xml_space() {
+            Some(true) => Mode::Preserve,
+            Some(false) => Mode::Pending,
+            None if contains_name(&self.options.preserve, &tag.name) || parent == Mode::Preserve => Mode::Preserve,
+            None => Mode::Pending,
+        };
+        self.before_content(false)?;
+        if parent == Mode::Preserve {
+            self.write(&tag.raw)?;
+        } else {
+            self.write_start_tag(&tag, self_closing)?;
+        }
+        if !self_closing && !contains_name(&self.options.void_elements, &tag.name) {
+            self.frames.open(tag.name, mode);
+        }
+        Ok(())
+    }
+
+    fn write_start_tag(&mut self, tag: &StartTag, self_closing: bool) -> io::Result<()> {
+        let end: &[u8] = match (self_closing, self.options.self_closing) {
+            (false, _) => b">",
+            (true, SelfClosing::Preserve) if tag.raw.len() > 2 && tag.raw[tag.raw.len() - 3].is_ascii_whitespace() => b" />",
+            (true, SelfClosing::Preserve | SelfClosing::Compact) => b"/>",
+            (true, SelfClosing::Spaced) => b" />",
+            (true, SelfClosing::Expanded) => b">",
+        };
+        let normalized = tag.normalized();
+        if tag.attributes.is_empty() || self.column + normalized.len() + end.len() <= self.options.max_width {
+            self.write(&normalized)?;
+        } else {
+            let level = self.level() + 1;
+            self.write(b"<")?;
+            self.write(&tag.name)?;
+            for attribute in &tag.attributes {
+                self.new_line(level)?;
+                self.write(attribute)?;
+            }
+        }
+        self.write(end)?;
+        if self_closing && self.options.self_closing == SelfClosing::Expanded {
+            self.write(b"</")?;
+            self.write(&tag.name)?;
+            self.write(b">")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the close tag read and closes its element, and the elements
+    /// left open within it. A close tag without open element is content.
+    fn close_element(&mut self, tag: CloseTag) -> io::Result<()> {
+        let Some(depth) = self.frames.find(&tag.name) else {
+            self.before_content(false)?;
+            return self.write(&tag.raw);
+        };
+        let mode = self.frames.close(depth);
+        let whitespace = std::mem::take(&mut self.whitespace);
+        match mode {
+            Mode::Pending => {}
+            Mode::Block => self.new_line(self.level())?,
+            Mode::Mixed | Mode::Preserve => self.write(&whitespace)?,
+        }
+        if mode == Mode::Preserve {
+            return self.write(&tag.raw);
+        }
+        self.write(b"</")?;
+        self.write(&tag.name)?;
+        self.write(b">")
+    }
+
+    /// Writes what is left at the end of the document.
+    fn finish(&mut self) -> io::Result<()> {
+        let rest = self.tags.finish();
+        if !rest.is_empty() {
+            self.before_content(false)?;
+            self.write(&rest)?;
+        } else if self.frames.states().any(|&mode| mode == Mode::Mixed || mode == Mode::Preserve) {
+            let whitespace = std::mem::take(&mut self.whitespace);
+            self.write(&whitespace)?;
+        } else if self.started {
+            self.write(b"\n")?;
+        }
+        self.sink.flush()
+    }
+}
+
+/// Formats a document written to it in chunks. See the [module documentation](self).
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Write;
+/// use sax_wasm::sax::format::{FormatOptions, Formatter, SelfClosing};
+///
+/// let options = FormatOptions { indent: 4, self_closing: SelfClosing::Spaced, ..FormatOptions::default() };
+/// let mut formatter = Formatter::new(Vec::new(), options);
+/// formatter.write_all(b"<a><b/>").unwrap();
+/// formatter.write_all(b"<c xml:space=\"preserve\"> <d/> </c></a>").unwrap();
+/// let formatted = formatter.finish().unwrap();
+/// assert_eq!(formatted, b"<a>\n    <b />\n    <c xml:space=\"preserve\"> <d/> </c>\n</a>\n");
+/// ```
+pub struct Formatter<W: Write> {
+    tokenizer: Tokenizer,
+    printer: Printer<W>,
+}
+
+impl<W: Write> Formatter<W> {
+    pub fn new(sink: W, options: FormatOptions) -> Formatter<W> {
+        Formatter {
+            tokenizer: Tokenizer::new(),
+            printer: Printer {
+                sink,
+                options,
+                column: 0,
+                frames: Frames::new(Mode::Block),
+                tags: TagReader::default(),
+                whitespace: Vec::new(),
+                started: false,
+            },
+        }
+    }
+
+    fn print_tokens(&mut self) -> io::Result<()> {
+        while let PullToken::Token(token) = self.tokenizer.next_token() {
+            self.printer.token(token)?;
+        }
+        Ok(())
+    }
+
+    /// Formats the rest of the document and returns the sink.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.tokenizer.finish();
+        self.print_tokens()?;
+        self.printer.finish()?;
+        Ok(self.printer.sink)
+    }
+}
+
+impl<W: Write> Write for Formatter<W> {
+    /// Formats the next chunk of the document. Output is written
+    /// as soon as the tokens and whitespace around it are known.
+    fn write(&mut self, chunk: &[u8]) -> io::Result<usize> {
+        self.tokenizer.feed(chunk);
+        self.print_tokens()?;
+        Ok(chunk.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.printer.sink.flush()
+    }
+}
+
+/// Formats a complete document.
+pub fn format(source: &[u8], options: &FormatOptions) -> Vec<u8> {
+    let mut formatter = Formatter::new(Vec::new(), options.clone());
+    // Writing to a Vec does not fail
+    formatter.write_all(source).unwrap();
+    formatter.finish().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sax::parser::{Event, SAXParser};
+    use crate::sax::tag::Entity;
+
+    /// The events the parser reads from a document, leaving out whitespace only text.
+    fn parse(doc: &[u8]) -> Vec<(Event, String)> {
+        let mut events = Vec::new();
+        let lossy = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+        let mut handler = |event: Event, data: Entity| {
+            let value = match data {
+                Entity::Tag(tag) if event == Event::OpenTag => tag
+                    .attributes
+                    .iter()
+                    .fold(lossy(&tag.name), |tag, a| format!("{} {}={}", tag, lossy(&a.name.value), lossy(&a.value.value))),
+                Entity::Tag(tag) => lossy(&tag.name),
+                Entity::Text(text) if text.value.iter().all(u8::is_ascii_whitespace) => return,
+                Entity::Text(text) => lossy(&text.value),
+                Entity::ProcInst(proc_inst) => lossy(&proc_inst.content.value),
+                _ => return,
+            };
+            events.push((event, value));
+        };
+        let mut parser = SAXParser::new_mut(&mut handler);
+        for event in
+            [Event::OpenTag, Event::CloseTag, Event::Text, Event::Comment, Event::Cdata, Event::ProcessingInstruction, Event::Doctype]
+        {
+            parser.events[event] = true;
+        }
+        parser.preserve_whitespace = true;
+        parser.write(doc);
+        parser.identity();
+        drop(parser);
+        events
+    }
+
+    fn check(options: &FormatOptions, doc: &str, expected: &str) {
+        let formatted = format(doc.as_bytes(), options);
+        assert_eq!(String::from_utf8_lossy(&formatted), expected);
+        // The parser reads the same document
+        assert_eq!(parse(&formatted), parse(doc.as_bytes()));
+        // Formatting is stable
+        assert_eq!(String::from_utf8_lossy(&format(&formatted, options)), expected);
+        for chunk_size in [1, 3, 7] {
+            let mut formatter = Formatter::new(Vec::new(), options.clone());
+            for chunk in doc.as_bytes().chunks(chunk_size) {
+                formatter.write_all(chunk).unwrap();
+            }
+            assert_eq!(formatter.finish().unwrap(), formatted, "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn test_indentation() {
+        check(
+            &FormatOptions::default(),
+            "<?xml version=\"1.0\"?><!DOCTYPE r><!-- top --><r>\n\n<a>  </a><b>text &amp; more</b>\t<c><d/><?pi x?><!-- c --></c><e><![CDATA[ x ]]></e></r>",
+            "<?xml version=\"1.0\"?>\n<!DOCTYPE r>\n<!-- top -->\n<r>\n  <a></a>\n  <b>text &amp; more</b>\n  <c>\n    <d/>\n    <?pi x?>\n    <!-- c -->\n  </c>\n  <e><![CDATA[ x ]]></e>\n</r>\n",
+        );
+    }
+
+    #[test]
+    fn test_preserves_whitespace() {
+        let options = FormatOptions::html();
+        check(
+            &options,
+            "<div><p> Some <b>bold</b> <i>text</i>\n  here </p><pre>\n  <b>x</b>\n</pre><br><img src=a.png><p xml:space=\"preserve\"><a>\n</a></p></div>",
+            "<div>\n  <p> Some <b>bold</b> <i>text</i>\n  here </p>\n  <pre>\n  <b>x</b>\n</pre>\n  <br>\n  <img src=a.png>\n  <p xml:space=\"preserve\"><a>\n</a></p>\n</div>\n",
+        );
+        // Text after child elements
+        check(&options, "<div>\n  <span>a</span> b <span>c</span>\n</div>", "<div>\n  <span>a</span> b <span>c</span>\n</div>\n");
+    }
+
+    #[test]
+    fn test_attributes() {
+        let options = FormatOptions {
+            max_width: 30,
+            ..FormatOptions::default()
+        };
+        check(
+            &options,
+            "<root><item   id = \"1\"\n name='a' /><item id=\"2\" name=\"a long name\" enabled/><jsx value={ a > b }/></root>",
+            "<root>\n  <item id=\"1\" name='a' />\n  <item\n    id=\"2\"\n    name=\"a long name\"\n    enabled/>\n  <jsx value={ a > b }/>\n</root>\n",
+        );
+        for (self_closing, expected) in [
+            (SelfClosing::Compact, "<a>\n  <b/>\n  <c/>\n</a>\n"),
+            (SelfClosing::Spaced, "<a>\n  <b />\n  <c />\n</a>\n"),
+            (SelfClosing::Expanded, "<a>\n  <b></b>\n  <c></c>\n</a>\n"),
+        ] {
+            let options = FormatOptions {
+                self_closing,
+                ..FormatOptions::default()
+            };
+            check(&options, "<a><b/><c  /></a>", expected);
+        }
+    }
+
+    #[test]
+    fn test_malformed() {
+        let options = FormatOptions::default();
+        check(&options, "<a><b></a></c>text", "<a>\n  <b>\n</a>\n</c>text");
+        check(&options, "<a><b x=\"1", "<a>\n  <b x=\"1");
+    }
+}
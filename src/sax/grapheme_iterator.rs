@@ -1,6 +1,6 @@
+use super::simd::{self, is_whitespace, BLOCK};
 use super::utils::{ascii_contains, grapheme_len};
-use core::arch::wasm32::{i8x16_bitmask, i8x16_eq, i8x16_splat, v128_load, v128_or};
-use std::{mem, ptr};
+use std::{mem, ptr, slice};
 
 /// Represents an iterator over grapheme clusters in a byte slice.
 ///
@@ -160,8 +160,27 @@ impl GraphemeClusters<'_> {
         let mut matched_byte = b'0';
         let mut found = false;
         let mut len = 0;
+        let mut scalar_end = cursor;
 
         while cursor < max_index {
+            // Scan whole blocks while a sequence straddling the block still ends before
+            // the last byte, so the walk below settles `len` when the input runs out.
+            if cursor >= scalar_end && cursor + BLOCK + 3 < max_index {
+                match scan_block(&self.bytes[cursor..cursor + BLOCK], haystack, &mut line, &mut character) {
+                    BlockScan::Found(offset) => {
+                        cursor += offset;
+                        found = true;
+                        matched_byte = self.bytes[cursor];
+                        len = grapheme_len(matched_byte);
+                        break;
+                    }
+                    BlockScan::Passed(advance) => {
+                        cursor += advance;
+                        continue;
+                    }
+                    BlockScan::Irregular => scalar_end = cursor + BLOCK,
+                }
+            }
             let next_byte = unsafe { *ptr.add(cursor) };
 
             if ascii_contains(haystack, next_byte) {
@@ -230,8 +249,25 @@ impl GraphemeClusters<'_> {
         let mut character = self.character;
         let mut found = false;
         let mut len = 0;
+        let mut scalar_end = cursor;
 
         while cursor < max_index {
+            // Same block scan as in `take_until_one_found`.
+            if cursor >= scalar_end && cursor + BLOCK + 3 < max_index {
+                match scan_block(&self.bytes[cursor..cursor + BLOCK], slice::from_ref(&match_byte), &mut line, &mut character) {
+                    BlockScan::Found(offset) => {
+                        cursor += offset;
+                        found = true;
+                        len = grapheme_len(match_byte);
+                        break;
+                    }
+                    BlockScan::Passed(advance) => {
+                        cursor += advance;
+                        continue;
+                    }
+                    BlockScan::Irregular => scalar_end = cursor + BLOCK,
+                }
+            }
             let next_byte = unsafe { *ptr.add(cursor) };
             len = grapheme_len(next_byte);
 
@@ -284,68 +320,55 @@ impl GraphemeClusters<'_> {
         let mut character = self.character;
         let mut done = false;
         let max_index = self.byte_len;
-        let ptr = self.bytes.as_ptr();
-
-        unsafe {
-            // Fast path: scan 16 bytes at a time for non-whitespace. Whitespace set
-            // matches the ASCII characters the parser expects: space, tab, CR, NL.
-            let sp = i8x16_splat(b' ' as i8);
-            let tab = i8x16_splat(b'\t' as i8);
-            let nl = i8x16_splat(b'\n' as i8);
-            let cr = i8x16_splat(b'\r' as i8);
-
-            while cursor + 16 <= max_index {
-                let chunk = v128_load(ptr.add(cursor) as *const _);
-                let is_space = i8x16_eq(chunk, sp);
-                let is_tab = i8x16_eq(chunk, tab);
-                let is_nl = i8x16_eq(chunk, nl);
-                let is_cr = i8x16_eq(chunk, cr);
-                let ws_mask_bits = i8x16_bitmask(v128_or(v128_or(is_space, is_tab), v128_or(is_nl, is_cr))) as u32;
-                let nl_mask_bits = i8x16_bitmask(is_nl) as u32;
-                let non_ws_mask_bits = !ws_mask_bits & 0xFFFF;
-
-                if non_ws_mask_bits == 0 {
-                    // The entire chunk is whitespace.
-                    if nl_mask_bits != 0 {
-                        let nl_count = nl_mask_bits.count_ones();
-                        line += nl_count as u64;
-                        // After the last newline, the character counts the bytes that follow.
-                        let last_nl_idx = 31u32.saturating_sub(nl_mask_bits.leading_zeros()) as usize;
-                        character = 15usize.saturating_sub(last_nl_idx) as u64;
-                    } else {
-                        character += 16;
-                    }
-                    cursor += 16;
-                    continue;
-                }
+        let bytes = self.bytes;
 
-                // Found a non-whitespace byte within this chunk. Process only the prefix.
-                let first_non_ws = non_ws_mask_bits.trailing_zeros() as usize;
-                let prefix_mask = if first_non_ws == 0 {
-                    0
-                } else {
-                    (1u32 << first_non_ws) - 1
-                };
-                let nl_prefix = nl_mask_bits & prefix_mask;
-                let nl_count = nl_prefix.count_ones();
+        // Fast path: classify 16 bytes at a time. The whitespace set matches the
+        // ASCII characters the parser expects: space, tab, CR, NL.
+        while let Some(block) = bytes.get(cursor..cursor + BLOCK) {
+            let (ws_mask_bits, nl_mask_bits) = simd::whitespace_masks(block.try_into().unwrap());
+            let non_ws_mask_bits = !ws_mask_bits & 0xFFFF;
 
-                if nl_count == 0 {
-                    character += first_non_ws as u64;
-                } else {
+            if non_ws_mask_bits == 0 {
+                // The entire chunk is whitespace.
+                if nl_mask_bits != 0 {
+                    let nl_count = nl_mask_bits.count_ones();
                     line += nl_count as u64;
-                    let last_nl_idx = 31u32.saturating_sub(nl_prefix.leading_zeros()) as usize;
-                    character = first_non_ws.saturating_sub(last_nl_idx + 1) as u64;
+                    // After the last newline, the character counts the bytes that follow.
+                    let last_nl_idx = 31u32.saturating_sub(nl_mask_bits.leading_zeros()) as usize;
+                    character = 15usize.saturating_sub(last_nl_idx) as u64;
+                } else {
+                    character += 16;
                 }
+                cursor += BLOCK;
+                continue;
+            }
 
-                cursor += first_non_ws;
-                done = true;
-                break;
+            // Found a non-whitespace byte within this chunk. Process only the prefix.
+            let first_non_ws = non_ws_mask_bits.trailing_zeros() as usize;
+            let prefix_mask = if first_non_ws == 0 {
+                0
+            } else {
+                (1u32 << first_non_ws) - 1
+            };
+            let nl_prefix = nl_mask_bits & prefix_mask;
+            let nl_count = nl_prefix.count_ones();
+
+            if nl_count == 0 {
+                character += first_non_ws as u64;
+            } else {
+                line += nl_count as u64;
+                let last_nl_idx = 31u32.saturating_sub(nl_prefix.leading_zeros()) as usize;
+                character = first_non_ws.saturating_sub(last_nl_idx + 1) as u64;
             }
+
+            cursor += first_non_ws;
+            done = true;
+            break;
         }
 
-        while cursor < max_index {
-            let next_byte = unsafe { *ptr.add(cursor) };
-            if next_byte > 32 {
+        while !done && cursor < max_index {
+            let next_byte = bytes[cursor];
+            if !is_whitespace(next_byte) {
                 done = true;
                 break;
             }
@@ -430,9 +453,80 @@ impl<'a> AsRef<Self> for GraphemeClusters<'a> {
     }
 }
 
+/// The outcome of scanning one block in [`scan_block`].
+enum BlockScan {
+    /// A delimiter sits at this offset. Line and character are advanced up to it.
+    Found(usize),
+    /// No delimiter in the block. The cursor advances by this many bytes, which
+    /// includes the tail of a multi-byte sequence that straddles the block end.
+    Passed(usize),
+    /// The block holds malformed UTF-8 and must be walked byte by byte.
+    Irregular,
+}
+
+/// Searches a block for the first delimiter, advancing `line` and `character`
+/// in bulk exactly as the byte-by-byte walks in `take_until_one_found` and
+/// `take_until` would.
+///
+/// The block must start at a code point boundary. Newlines reset the character
+/// count, code points count as one UTF-16 unit and 4-byte sequences as two.
+#[inline(always)]
+fn scan_block(block: &[u8], delimiters: &[u8], line: &mut u64, character: &mut u64) -> BlockScan {
+    let block: &[u8; BLOCK] = block.try_into().unwrap();
+    let masks = simd::delimiter_masks(block, delimiters);
+
+    // Locate continuation bytes and check that they are exactly the ones the
+    // lead bytes announce. Bits past the block end belong to a sequence that
+    // straddles it.
+    let mut continuation = 0u32;
+    let mut expected = 0u32;
+    let mut astral = 0u32;
+    let mut non_ascii = masks.non_ascii;
+    while non_ascii != 0 {
+        let i = non_ascii.trailing_zeros();
+        non_ascii &= non_ascii - 1;
+        match block[i as usize] {
+            0x80..=0xBF => continuation |= 1 << i,
+            0xC0..=0xDF => expected |= 0b1 << (i + 1),
+            0xE0..=0xEF => expected |= 0b11 << (i + 1),
+            0xF0..=0xF7 => {
+                expected |= 0b111 << (i + 1);
+                astral |= 1 << i;
+            }
+            _ => {}
+        }
+    }
+    if continuation != expected & 0xFFFF || masks.delimiters & continuation != 0 {
+        return BlockScan::Irregular;
+    }
+
+    let end = if masks.delimiters == 0 {
+        BLOCK
+    } else {
+        masks.delimiters.trailing_zeros() as usize
+    };
+    let prefix = (1u32 << end) - 1;
+    let newlines = masks.newlines & prefix;
+    let mut counted = prefix & !continuation;
+    if newlines != 0 {
+        *line += newlines.count_ones() as u64;
+        let last_nl_idx = 31 - newlines.leading_zeros();
+        counted &= !((2u32 << last_nl_idx) - 1);
+        *character = 0;
+    }
+    *character += (counted.count_ones() + (counted & astral).count_ones()) as u64;
+
+    if end < BLOCK {
+        BlockScan::Found(end)
+    } else {
+        BlockScan::Passed(BLOCK + (expected >> BLOCK).count_ones() as usize)
+    }
+}
+
 #[cfg(test)]
 mod grapheme_iterator_tests {
     use crate::sax::grapheme_iterator::GraphemeClusters;
+    use crate::sax::utils::grapheme_len;
 
     #[test]
     fn iterator_test() {
@@ -488,4 +582,101 @@ mod grapheme_iterator_tests {
         let (unwrapped, _) = result.unwrap();
         assert_eq!(unwrapped, "this is 🐉 a te".as_bytes());
     }
+
+    /// The byte-by-byte walk the block scan must agree with.
+    fn walk(bytes: &[u8], delimiters: &[u8]) -> (usize, u64, u64, bool) {
+        let (mut cursor, mut line, mut character, mut len) = (0, 0, 0, 0);
+        while cursor < bytes.len() {
+            let byte = bytes[cursor];
+            if delimiters.contains(&byte) {
+                return (cursor, line, character, true);
+            }
+            len = grapheme_len(byte);
+            if byte == b'\n' {
+                line += 1;
+                character = 0;
+            } else {
+                character += if len != 4 {
+                    1
+                } else {
+                    2
+                };
+            }
+            cursor += len;
+        }
+        if cursor > bytes.len() {
+            cursor -= len;
+        }
+        (cursor, line, character, false)
+    }
+
+    #[test]
+    fn take_until_block_scan_matches_byte_walk() {
+        let pieces: [&[u8]; 10] =
+            [b"text ", b"\n", "é".as_bytes(), "€".as_bytes(), "🐉".as_bytes(), b"<", b">", b"=", b"\xA9", b"\xF0\x9F"];
+        let mut seed = 0x2545_F491_4F6C_DD1Du64;
+        for _ in 0..500 {
+            let mut input = b"text ".to_vec();
+            while input.len() < 80 {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                // Favour plain text so delimiters sit at varying depths.
+                let pick = (seed % 24) as usize;
+                input.extend_from_slice(
+                    pieces[if pick < 10 {
+                        pick
+                    } else {
+                        0
+                    }],
+                );
+            }
+            for delimiters in [&b"<"[..], b"<\n", b"=>", b"\xA9"] {
+                let (cursor, line, character, found) = walk(&input, delimiters);
+                let mut gc = GraphemeClusters::new(&input);
+                let found_one = gc.take_until_one_found(delimiters, false).is_some_and(|(_, found)| found);
+                assert_eq!(
+                    (gc.cursor, gc.line, gc.character, found_one),
+                    (cursor, line, character, found),
+                    "{input:?} until {delimiters:?}"
+                );
+
+                if let [delimiter] = delimiters {
+                    let mut gc = GraphemeClusters::new(&input);
+                    let found_one = gc.take_until(*delimiter, false).is_some_and(|(_, found)| found);
+                    assert_eq!(
+                        (gc.cursor, gc.line, gc.character, found_one),
+                        (cursor, line, character, found),
+                        "{input:?} until {delimiter}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn skip_whitespace_tracks_lines_across_blocks() {
+        // Walks every prefix length so the first non-whitespace byte lands in each
+        // lane of the block scan as well as in the scalar tail.
+        let pattern = b" \t\n\r  \n\n \t";
+        for len in 0..48 {
+            let mut input: Vec<u8> = pattern.iter().cycle().take(len).copied().collect();
+            input.extend_from_slice(b"\x0C<a/>");
+
+            let mut line = 0;
+            let mut character = 0;
+            for &byte in &input[..len] {
+                if byte == b'\n' {
+                    line += 1;
+                    character = 0;
+                } else {
+                    character += 1;
+                }
+            }
+
+            let mut gc = GraphemeClusters::new(&input);
+            assert!(gc.skip_whitespace());
+            assert_eq!((gc.cursor, gc.line, gc.character), (len, line, character), "prefix length {len}");
+        }
+    }
 }
@@ -0,0 +1,603 @@
+//! A lossless token stream of a document.
+//!
+//! The parser drops what does not change the meaning of a document: the
+//! whitespace between attributes and inside of close tags, whitespace only
+//! text between tags, the quotes around attribute values and the spelling
+//! of close tags. Tools editing documents in place need all of it. The
+//! tokens of this module cover every byte of the input exactly once, in
+//! order, so concatenating their `raw` bytes gives back the original
+//! document and the `byte_range` of a token is where an edit applies.
+//!
+//! Markup is split the same way the parser reads it in its default lenient
+//! mode. Start tags are split into their name, attribute names, `=`,
+//! attribute values (with their quotes or braces) and the closing `>` or
+//! `/>`, with [`TokenKind::Whitespace`] trivia between them. Bytes which
+//! are not markup where markup is expected are returned as
+//! [`TokenKind::Unknown`] tokens rather than dropped.
+//!
+//! # Examples
+//!
+//! ```
+//! use sax_wasm::sax::lossless::{tokens, TokenKind};
+//!
+//! let doc = b"<a  href = 'x' >\n  <b/>\n</a >";
+//! let kinds: Vec<_> = tokens(doc).map(|token| token.kind).collect();
+//! assert_eq!(
+//!     kinds,
+//!     [
+//!         TokenKind::OpenTagStart,
+//!         TokenKind::Whitespace,
+//!         TokenKind::AttributeName,
+//!         TokenKind::Whitespace,
+//!         TokenKind::Equals,
+//!         TokenKind::Whitespace,
+//!         TokenKind::AttributeValue,
+//!         TokenKind::Whitespace,
+//!         TokenKind::TagEnd,
+//!         TokenKind::Whitespace,
+//!         TokenKind::OpenTagStart,
+//!         TokenKind::SelfClosingTagEnd,
+//!         TokenKind::Whitespace,
+//!         TokenKind::CloseTagStart,
+//!         TokenKind::Whitespace,
+//!         TokenKind::TagEnd,
+//!     ]
+//! );
+//! // Replace the attribute value in place
+//! let value = tokens(doc).find(|token| token.kind == TokenKind::AttributeValue).unwrap();
+//! let (start, end) = (value.byte_range.0 as usize, value.byte_range.1 as usize);
+//! let edited = [&doc[..start], b"\"y\"", &doc[end..]].concat();
+//! assert_eq!(edited, b"<a  href = \"y\" >\n  <b/>\n</a >");
+//! ```
+
+use super::utils::{advance_position, ascii_compare, grapheme_len};
+
+/// What a token is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Character data including its references, `a &amp; b`.
+    Text,
+    /// Whitespace between markup, within tags and whitespace only text.
+    Whitespace,
+    /// `<?xml ... ?>` at the start of the document.
+    XmlDecl,
+    /// `<?target ... ?>`
+    ProcessingInstruction,
+    /// `<!DOCTYPE ... >` including its internal subset.
+    Doctype,
+    /// `<!-- ... -->`
+    Comment,
+    /// `<![CDATA[ ... ]]>`
+    Cdata,
+    /// `<` and the element name, `<` alone for a JSX fragment.
+    OpenTagStart,
+    /// `</` and the element name.
+    CloseTagStart,
+    AttributeName,
+    /// `=` between an attribute name and value.
+    Equals,
+    /// An attribute value with its quotes or braces.
+    AttributeValue,
+    /// `>` ending a start or close tag.
+    TagEnd,
+    /// `/>` ending a self-closing tag.
+    SelfClosingTagEnd,
+    /// Bytes the parser does not read as markup, e.g. `<!x>` or a stray `"` within a tag.
+    Unknown,
+}
+
+impl TokenKind {
+    /// Whether the token can be removed or changed
+    /// without changing the meaning of the document.
+    pub fn is_trivia(self) -> bool {
+        self == TokenKind::Whitespace
+    }
+}
+
+/// A span of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    /// The bytes of the token exactly as they are in the input.
+    pub raw: &'a [u8],
+    pub start: [u64; 2],
+    pub end: [u64; 2],
+    pub byte_range: (u64, u64),
+}
+
+/// Where the lexer is between tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Content,
+    // Within a start tag
+    StartTag,
+    // After an attribute name, an `=` may follow
+    AttributeName,
+    // After the `=` of an attribute
+    Equals,
+    // Within a close tag
+    CloseTag,
+}
+
+/// Splits bytes into tokens. It keeps the state in between
+/// tokens so the input can be given in pieces.
+#[derive(Debug, Clone)]
+struct Lexer {
+    state: State,
+    position: [u64; 2],
+    offset: u64,
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    byte.is_ascii_whitespace()
+}
+
+/// The length of the token ending after the first `needle` found from
+/// `from`. Without one the token may end in the input to come.
+fn through(bytes: &[u8], from: usize, needle: &[u8], finished: bool) -> Option<usize> {
+    match bytes.get(from..).and_then(|rest| rest.windows(needle.len()).position(|window| window == needle)) {
+        Some(i) => Some(from + i + needle.len()),
+        None if finished => Some(bytes.len()),
+        None => None,
+    }
+}
+
+/// The length of the run of bytes from `from` for which `accept` holds.
+/// A run reaching the end of the input may continue in the input to come.
+fn run(bytes: &[u8], from: usize, finished: bool, accept: impl Fn(u8) -> bool) -> Option<usize> {
+    match bytes[from..].iter().position(|&b| !accept(b)) {
+        Some(i) => Some(from + i),
+        None if finished => Some(bytes.len()),
+        None => None,
+    }
+}
+
+/// Whether `bytes` starts with `prefix`, `None` when it is too short to tell.
+fn starts_with(bytes: &[u8], prefix: &[u8], finished: bool) -> Option<bool> {
+    let len = bytes.len().min(prefix.len());
+    if !ascii_compare(&prefix[..len], &bytes[..len]) {
+        return Some(false);
+    }
+    if len < prefix.len() && !finished {
+        return None;
+    }
+    Some(len == prefix.len())
+}
+
+/// The length of a DOCTYPE, skipping quoted strings and the internal subset.
+fn doctype(bytes: &[u8], finished: bool) -> Option<usize> {
+    let mut quote = 0;
+    let mut depth = 0;
+    for (i, &byte) in bytes.iter().enumerate().skip(2) {
+        match byte {
+            _ if byte == quote => quote = 0,
+            _ if quote != 0 => {}
+            b'"' | b'\'' => quote = byte,
+            b'[' => depth += 1,
+            b']' => depth -= 1,
+            b'>' if depth <= 0 => return Some(i + 1),
+            _ => {}
+        }
+    }
+    finished.then_some(bytes.len())
+}
+
+/// The length of a JSX attribute expression from its `{`.
+fn braces(bytes: &[u8], finished: bool) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &byte) in bytes.iter().enumerate() {
+        match byte {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    finished.then_some(bytes.len())
+}
+
+impl Lexer {
+    fn new() -> Lexer {
+        Lexer {
+            state: State::Content,
+            position: [0; 2],
+            offset: 0,
+        }
+    }
+
+    /// The kind and length of the token at the start of `bytes` and the
+    /// state after it. `None` when the token may continue past `bytes`.
+    fn lex(&self, bytes: &[u8], finished: bool) -> Option<(TokenKind, usize, State)> {
+        let first = bytes[0];
+        let state = self.state;
+        if is_whitespace(first) && state != State::Content {
+            return Some((TokenKind::Whitespace, run(bytes, 0, finished, is_whitespace)?, state));
+        }
+        let name_end = |from| run(bytes, from, finished, |b| !is_whitespace(b) && !b"/>=\"'<".contains(&b));
+        match state {
+            State::Content if first == b'<' => self.markup(bytes, finished),
+            State::Content => {
+                let len = run(bytes, 0, finished, |b| b != b'<')?;
+                let kind = match bytes[..len].iter().all(|&b| is_whitespace(b)) {
+                    true => TokenKind::Whitespace,
+                    false => TokenKind::Text,
+                };
+                Some((kind, len, State::Content))
+            }
+            State::StartTag | State::AttributeName | State::Equals | State::CloseTag if first == b'>' => {
+                Some((TokenKind::TagEnd, 1, State::Content))
+            }
+            State::StartTag | State::AttributeName if first == b'/' => match bytes.get(1) {
+                Some(b'>') => Some((TokenKind::SelfClosingTagEnd, 2, State::Content)),
+                None if !finished => None,
+                _ => Some((TokenKind::Unknown, 1, State::StartTag)),
+            },
+            State::AttributeName if first == b'=' => Some((TokenKind::Equals, 1, State::Equals)),
+            State::StartTag | State::AttributeName if !b"=\"'<".contains(&first) => {
+                Some((TokenKind::AttributeName, name_end(0)?, State::AttributeName))
+            }
+            State::Equals => {
+                let len = match first {
+                    b'"' | b'\'' => through(bytes, 1, &[first], finished)?,
+                    b'{' => braces(bytes, finished)?,
+                    _ => run(bytes, 0, finished, |b| !is_whitespace(b) && b != b'>')?,
+                };
+                Some((TokenKind::AttributeValue, len, State::StartTag))
+            }
+            State::CloseTag => Some((TokenKind::Unknown, name_end(0)?.max(1), State::CloseTag)),
+            _ => Some((TokenKind::Unknown, grapheme_len(first).min(bytes.len()), State::StartTag)),
+        }
+    }
+
+    /// Lexes markup starting with `<`.
+    fn markup(&self, bytes: &[u8], finished: bool) -> Option<(TokenKind, usize, State)> {
+        let name_end = |from| run(bytes, from, finished, |b| !is_whitespace(b) && b != b'/' && b != b'>');
+        let content = State::Content;
+        if starts_with(bytes, b"<!--", finished)? {
+            return Some((TokenKind::Comment, through(bytes, 4, b"-->", finished)?, content));
+        }
+        if starts_with(bytes, b"<![CDATA[", finished)? {
+            return Some((TokenKind::Cdata, through(bytes, 9, b"]]>", finished)?, content));
+        }
+        if starts_with(bytes, b"<!DOCTYPE", finished)? {
+            return Some((TokenKind::Doctype, doctype(bytes, finished)?, content));
+        }
+        if starts_with(bytes, b"<?", finished)? {
+            let len = through(bytes, 2, b"?>", finished)?;
+            // The target is `xml` followed by whitespace
+            let is_xml_decl = self.offset == 0 && bytes.len() > 5 && ascii_compare(b"<?xml", &bytes[..5]) && is_whitespace(bytes[5]);
+            let kind = match is_xml_decl {
+                true => TokenKind::XmlDecl,
+                false => TokenKind::ProcessingInstruction,
+            };
+            return Some((kind, len, content));
+        }
+        if starts_with(bytes, b"<!", finished)? {
+            return Some((TokenKind::Unknown, through(bytes, 2, b">", finished)?, content));
+        }
+        if starts_with(bytes, b"</", finished)? {
+            return Some((TokenKind::CloseTagStart, name_end(2)?, State::CloseTag));
+        }
+        match bytes.get(1) {
+            None if !finished => None,
+            // '< name' is text
+            None => Some((TokenKind::Text, 1, content)),
+            Some(&b) if is_whitespace(b) => Some((TokenKind::Text, run(bytes, 1, finished, |b| b != b'<')?, content)),
+            Some(_) => Some((TokenKind::OpenTagStart, name_end(1)?, State::StartTag)),
+        }
+    }
+
+    /// The next token of `bytes` which continue the input lexed so far.
+    fn next<'a>(&mut self, bytes: &'a [u8], finished: bool) -> Option<Token<'a>> {
+        if bytes.is_empty() {
+            return None;
+        }
+        let (kind, len, state) = self.lex(bytes, finished)?;
+        let raw = &bytes[..len];
+        let start = self.position;
+        self.position = advance_position(start, raw);
+        self.state = state;
+        let byte_range = (self.offset, self.offset + len as u64);
+        self.offset = byte_range.1;
+        Some(Token {
+            kind,
+            raw,
+            start,
+            end: self.position,
+            byte_range,
+        })
+    }
+}
+
+/// The tokens of a complete document.
+pub fn tokens(source: &[u8]) -> Tokens<'_> {
+    Tokens {
+        lexer: Lexer::new(),
+        rest: source,
+    }
+}
+
+/// An iterator over the tokens of a complete document, see [`tokens`].
+pub struct Tokens<'a> {
+    lexer: Lexer,
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.lexer.next(self.rest, true)?;
+        self.rest = &self.rest[token.raw.len()..];
+        Some(token)
+    }
+}
+
+/// The result of [`Tokenizer::next_token`].
+pub enum PullToken<'a> {
+    /// The next token, borrowed until the following call.
+    Token(Token<'a>),
+    /// The chunks fed so far end within the next token.
+    NeedMoreData,
+    /// The document was finished and every token was returned.
+    End,
+}
+
+/// Splits a document given in chunks into tokens.
+///
+/// A token is returned once it is complete so the tokens do not depend on
+/// where the chunks end. Only the bytes of the token being read are kept.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::lossless::{PullToken, Tokenizer};
+///
+/// let mut tokenizer = Tokenizer::new();
+/// let mut output = Vec::new();
+/// for chunk in [&b"<a x='1'"[..], b"> text </a", b">"] {
+///     tokenizer.feed(chunk);
+///     while let PullToken::Token(token) = tokenizer.next_token() {
+///         output.extend_from_slice(token.raw);
+///     }
+/// }
+/// tokenizer.finish();
+/// while let PullToken::Token(token) = tokenizer.next_token() {
+///     output.extend_from_slice(token.raw);
+/// }
+/// assert_eq!(output, b"<a x='1'> text </a>");
+/// ```
+pub struct Tokenizer {
+    lexer: Lexer,
+    // The bytes from the start of the next token
+    buffer: Vec<u8>,
+    // The bytes of the buffer returned as the last token
+    returned: usize,
+    finished: bool,
+}
+
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Tokenizer::new()
+    }
+}
+
+impl Tokenizer {
+    pub fn new() -> Tokenizer {
+        Tokenizer {
+            lexer: Lexer::new(),
+            buffer: Vec::new(),
+            returned: 0,
+            finished: false,
+        }
+    }
+
+    /// Adds the next chunk of the document. Feeding a chunk
+    /// after [`finish`](Self::finish) starts a new document.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        if self.finished {
+            *self = Tokenizer::new();
+        }
+        self.buffer.drain(..self.returned);
+        self.returned = 0;
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Ends the document. The bytes left form the last tokens
+    /// even when they end within a comment, tag etc.
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    /// Returns the next token.
+    pub fn next_token(&mut self) -> PullToken<'_> {
+        let rest = &self.buffer[self.returned..];
+        match self.lexer.next(rest, self.finished) {
+            Some(token) => {
+                self.returned += token.raw.len();
+                PullToken::Token(token)
+            }
+            None if self.finished => PullToken::End,
+            None => PullToken::NeedMoreData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sax::parser::{Event, SAXParser};
+    use crate::sax::tag::Entity;
+
+    const DOC: &[u8] = b"<?xml version=\"1.0\" ?>\n<!DOCTYPE r [\n <!ENTITY e \"x>\">\n]>\n<r  a = '1'\n b=\"2\" c=3 d e={ {x} }>t &amp; u<!-- c --> <![CDATA[x]]><?pi d ?><e\t/>< f<x/ ><></>\xF0\x9F\x9A\x80</r  >\n<!-- end -->";
+
+    fn describe(token: &Token) -> String {
+        format!("{:?} {}", token.kind, String::from_utf8_lossy(token.raw))
+    }
+
+    #[test]
+    fn test_tokens() {
+        let found: Vec<_> = tokens(DOC).map(|token| describe(&token)).collect();
+        assert_eq!(
+            found,
+            [
+                "XmlDecl <?xml version=\"1.0\" ?>",
+                "Whitespace \n",
+                "Doctype <!DOCTYPE r [\n <!ENTITY e \"x>\">\n]>",
+                "Whitespace \n",
+                "OpenTagStart <r",
+                "Whitespace   ",
+                "AttributeName a",
+                "Whitespace  ",
+                "Equals =",
+                "Whitespace  ",
+                "AttributeValue '1'",
+                "Whitespace \n ",
+                "AttributeName b",
+                "Equals =",
+                "AttributeValue \"2\"",
+                "Whitespace  ",
+                "AttributeName c",
+                "Equals =",
+                "AttributeValue 3",
+                "Whitespace  ",
+                "AttributeName d",
+                "Whitespace  ",
+                "AttributeName e",
+                "Equals =",
+                "AttributeValue { {x} }",
+                "TagEnd >",
+                "Text t &amp; u",
+                "Comment <!-- c -->",
+                "Whitespace  ",
+                "Cdata <![CDATA[x]]>",
+                "ProcessingInstruction <?pi d ?>",
+                "OpenTagStart <e",
+                "Whitespace \t",
+                "SelfClosingTagEnd />",
+                "Text < f",
+                "OpenTagStart <x",
+                "Unknown /",
+                "Whitespace  ",
+                "TagEnd >",
+                "OpenTagStart <",
+                "TagEnd >",
+                "CloseTagStart </",
+                "TagEnd >",
+                "Text 🚀",
+                "CloseTagStart </r",
+                "Whitespace   ",
+                "TagEnd >",
+                "Whitespace \n",
+                "Comment <!-- end -->",
+            ]
+        );
+        let tokens: Vec<_> = tokens(DOC).collect();
+        let last = tokens.last().unwrap();
+        assert_eq!(last.end, [6, 12]);
+        assert_eq!(last.byte_range.1, DOC.len() as u64);
+        let rocket = tokens.iter().find(|token| token.raw == "🚀".as_bytes()).unwrap();
+        assert_eq!(rocket.end[1] - rocket.start[1], 2);
+        for pair in tokens.windows(2) {
+            assert_eq!(pair[0].byte_range.1, pair[1].byte_range.0);
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+    }
+
+    #[test]
+    fn test_chunks() {
+        let expected: Vec<_> = tokens(DOC).map(|token| (token.kind, token.raw.to_vec(), token.start, token.byte_range)).collect();
+        for chunk_size in 1..=DOC.len() {
+            let mut tokenizer = Tokenizer::new();
+            let mut found = Vec::new();
+            for chunk in DOC.chunks(chunk_size) {
+                tokenizer.feed(chunk);
+                while let PullToken::Token(token) = tokenizer.next_token() {
+                    found.push((token.kind, token.raw.to_vec(), token.start, token.byte_range));
+                }
+            }
+            tokenizer.finish();
+            while let PullToken::Token(token) = tokenizer.next_token() {
+                found.push((token.kind, token.raw.to_vec(), token.start, token.byte_range));
+            }
+            assert!(matches!(tokenizer.next_token(), PullToken::End));
+            assert_eq!(found, expected, "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn test_unterminated() {
+        for doc in [&b"<a b='1"[..], b"<!-- c", b"<![CDATA[x]", b"<?pi", b"<!DOCTYPE r [", b"<a b={x", b"text", b"<", b"<!", b"</a"] {
+            let raw: Vec<u8> = tokens(doc).flat_map(|token| token.raw.to_vec()).collect();
+            assert_eq!(raw, doc, "{}", String::from_utf8_lossy(doc));
+        }
+        let kinds: Vec<_> = tokens(b"<!-- c").map(|token| token.kind).collect();
+        assert_eq!(kinds, [TokenKind::Comment]);
+        let kinds: Vec<_> = tokens(b"<!doctype html><!doc>").map(|token| token.kind).collect();
+        assert_eq!(kinds, [TokenKind::Doctype, TokenKind::Unknown]);
+    }
+
+    #[test]
+    fn test_parser_boundaries() {
+        let doc = b"<?xml version=\"1.0\" ?>\n<!DOCTYPE r>\n<r  a = '1'\n b=\"2\">t &amp; u<!-- c --> <![CDATA[x]]><?pi d ?><e\t/>\n  <f g='>'>\xF0\x9F\x9A\x80</f\n>x\ny</r  >\n<!-- end -->";
+
+        // The events the parser would dispatch for the tokens
+        let mut expected = Vec::new();
+        let mut tag_start = None;
+        let mut open = Vec::new();
+        for token in tokens(doc) {
+            let (start, end) = token.byte_range;
+            match token.kind {
+                TokenKind::XmlDecl | TokenKind::ProcessingInstruction => expected.push((Event::ProcessingInstruction, token.byte_range)),
+                TokenKind::Doctype => expected.push((Event::Doctype, token.byte_range)),
+                TokenKind::Comment => expected.push((Event::Comment, token.byte_range)),
+                TokenKind::Cdata => expected.push((Event::Cdata, token.byte_range)),
+                TokenKind::Text => expected.push((Event::Text, token.byte_range)),
+                TokenKind::Whitespace if tag_start.is_none() => expected.push((Event::Text, token.byte_range)),
+                TokenKind::OpenTagStart | TokenKind::CloseTagStart => tag_start = Some((token.kind, start)),
+                TokenKind::SelfClosingTagEnd => {
+                    let (_, open_start) = tag_start.take().unwrap();
+                    expected.push((Event::OpenTag, (open_start, end)));
+                    expected.push((Event::CloseTag, (open_start, end)));
+                }
+                TokenKind::TagEnd => match tag_start.take().unwrap() {
+                    (TokenKind::OpenTagStart, open_start) => {
+                        expected.push((Event::OpenTag, (open_start, end)));
+                        open.push(open_start);
+                    }
+                    _ => expected.push((Event::CloseTag, (open.pop().unwrap(), end))),
+                },
+                _ => {}
+            }
+        }
+
+        for i in 1..=doc.len() {
+            let mut found = Vec::new();
+            let mut handler = |event: Event, data: Entity| {
+                let byte_range = match data {
+                    Entity::Tag(tag) => tag.byte_range,
+                    Entity::Text(text) => text.byte_range,
+                    Entity::ProcInst(proc_inst) => proc_inst.byte_range,
+                    _ => return,
+                };
+                found.push((event, byte_range));
+            };
+            let mut parser = SAXParser::new_mut(&mut handler);
+            for event in
+                [Event::OpenTag, Event::CloseTag, Event::Text, Event::Comment, Event::Cdata, Event::ProcessingInstruction, Event::Doctype]
+            {
+                parser.events[event] = true;
+            }
+            parser.preserve_whitespace = true;
+            parser.write(&doc[..i]);
+            parser.write(&doc[i..]);
+            parser.identity();
+            drop(parser);
+            assert_eq!(found, expected, "At iteration i={}", i);
+        }
+    }
+}
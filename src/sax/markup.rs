@@ -0,0 +1,218 @@
+//! The tags and open elements shared by the [`format`](super::format) and
+//! [`minify`](super::minify) printers.
+//!
+//! Like the printers this reads the [lossless tokens](super::lossless) rather
+//! than the events of the [`SAXParser`](super::parser::SAXParser), as the
+//! printers need the spelling of tags and write malformed tags out as is.
+
+use super::lossless::{Token, TokenKind};
+use super::utils::ascii_compare;
+
+/// The HTML elements whose whitespace is content.
+pub(super) const HTML_PRESERVE: &[&str] = &["pre", "textarea", "script", "style"];
+
+/// The HTML elements without content or close tag.
+pub(super) const HTML_VOID_ELEMENTS: &[&str] =
+    &["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr"];
+
+pub(super) fn names(names: &[&str]) -> Vec<Vec<u8>> {
+    names.iter().map(|name| name.as_bytes().to_vec()).collect()
+}
+
+pub(super) fn contains_name(names: &[Vec<u8>], name: &[u8]) -> bool {
+    names.iter().any(|n| ascii_compare(n, name))
+}
+
+/// The value within the quotes of `value` if HTML allows it without them.
+fn unquoted(value: &[u8]) -> Option<&[u8]> {
+    let inner = match value {
+        [b'"', inner @ .., b'"'] | [b'\'', inner @ .., b'\''] => inner,
+        _ => return None,
+    };
+    let allowed = !inner.is_empty() && inner.iter().all(|b| !b.is_ascii_whitespace() && !b"\"'=<>`".contains(b));
+    allowed.then_some(inner)
+}
+
+/// A start tag.
+#[derive(Default)]
+pub(super) struct StartTag {
+    pub(super) name: Vec<u8>,
+    // The tag as it is in the input
+    pub(super) raw: Vec<u8>,
+    // Each attribute without the whitespace around its `=`
+    pub(super) attributes: Vec<Vec<u8>>,
+    // The last attribute may still get an `=` and a value
+    attribute_open: bool,
+    // The last attribute has a value without quotes
+    pub(super) unquoted: bool,
+}
+
+impl StartTag {
+    fn push(&mut self, token: Token, unquote: bool) {
+        self.raw.extend_from_slice(token.raw);
+        match token.kind {
+            TokenKind::Equals if self.attribute_open => self.attributes.last_mut().unwrap().extend_from_slice(token.raw),
+            TokenKind::AttributeValue if self.attribute_open && self.attributes.last().unwrap().ends_with(b"=") => {
+                let value = match unquoted(token.raw) {
+                    Some(value) if unquote => value,
+                    _ => token.raw,
+                };
+                self.unquoted = !matches!(value.first(), Some(b'"' | b'\'' | b'{'));
+                self.attributes.last_mut().unwrap().extend_from_slice(value);
+                self.attribute_open = false;
+            }
+            TokenKind::AttributeName | TokenKind::Equals | TokenKind::AttributeValue | TokenKind::Unknown => {
+                self.attributes.push(token.raw.to_vec());
+                self.attribute_open = token.kind == TokenKind::AttributeName;
+                self.unquoted = false;
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether `xml:space` asks to preserve whitespace, `None` without it.
+    pub(super) fn xml_space(&self) -> Option<bool> {
+        self.attributes.iter().find_map(|attribute| {
+            let value = attribute.strip_prefix(b"xml:space=")?;
+            Some(matches!(value, b"\"preserve\"" | b"'preserve'" | b"preserve"))
+        })
+    }
+
+    /// The tag with a space before each attribute, without its end.
+    pub(super) fn normalized(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.raw.len());
+        bytes.push(b'<');
+        bytes.extend_from_slice(&self.name);
+        for attribute in &self.attributes {
+            bytes.push(b' ');
+            bytes.extend_from_slice(attribute);
+        }
+        bytes
+    }
+}
+
+/// A close tag.
+#[derive(Default)]
+pub(super) struct CloseTag {
+    pub(super) name: Vec<u8>,
+    pub(super) raw: Vec<u8>,
+}
+
+/// What a token completes.
+pub(super) enum Markup<'a> {
+    /// A token outside of tags.
+    Content(Token<'a>),
+    /// A start tag, and whether it is self-closing.
+    StartTag(StartTag, bool),
+    CloseTag(CloseTag),
+    /// A token within a tag not read to its end yet.
+    Partial,
+}
+
+/// Reads tags from tokens.
+#[derive(Default)]
+pub(super) struct TagReader {
+    start_tag: Option<StartTag>,
+    close_tag: Option<CloseTag>,
+}
+
+impl TagReader {
+    /// Reads the next token. `unquote` removes the quotes of
+    /// attribute values where HTML allows it.
+    pub(super) fn read<'a>(&mut self, token: Token<'a>, unquote: bool) -> Markup<'a> {
+        if let Some(tag) = &mut self.start_tag {
+            tag.push(token, unquote);
+            return match token.kind {
+                TokenKind::TagEnd => Markup::StartTag(self.start_tag.take().unwrap(), false),
+                TokenKind::SelfClosingTagEnd => Markup::StartTag(self.start_tag.take().unwrap(), true),
+                _ => Markup::Partial,
+            };
+        }
+        if let Some(tag) = &mut self.close_tag {
+            tag.raw.extend_from_slice(token.raw);
+            return match token.kind {
+                TokenKind::TagEnd => Markup::CloseTag(self.close_tag.take().unwrap()),
+                _ => Markup::Partial,
+            };
+        }
+        match token.kind {
+            TokenKind::OpenTagStart => {
+                self.start_tag = Some(StartTag {
+                    name: token.raw[1..].to_vec(),
+                    raw: token.raw.to_vec(),
+                    ..StartTag::default()
+                });
+                Markup::Partial
+            }
+            TokenKind::CloseTagStart => {
+                self.close_tag = Some(CloseTag {
+                    name: token.raw[2..].to_vec(),
+                    raw: token.raw.to_vec(),
+                });
+                Markup::Partial
+            }
+            _ => Markup::Content(token),
+        }
+    }
+
+    /// The tag cut off at the end of the document as it is in the input.
+    pub(super) fn finish(&mut self) -> Vec<u8> {
+        match (self.start_tag.take(), self.close_tag.take()) {
+            (Some(tag), _) => tag.raw,
+            (_, Some(tag)) => tag.raw,
+            _ => Vec::new(),
+        }
+    }
+}
+
+struct Frame<S> {
+    name: Vec<u8>,
+    state: S,
+}
+
+/// The document followed by the open elements, each with the state of its printer.
+pub(super) struct Frames<S>(Vec<Frame<S>>);
+
+impl<S> Frames<S> {
+    pub(super) fn new(document: S) -> Frames<S> {
+        Frames(vec![Frame {
+            name: Vec::new(),
+            state: document,
+        }])
+    }
+
+    /// The number of open elements.
+    pub(super) fn depth(&self) -> usize {
+        self.0.len() - 1
+    }
+
+    pub(super) fn last(&self) -> &S {
+        &self.0.last().unwrap().state
+    }
+
+    pub(super) fn last_mut(&mut self) -> &mut S {
+        &mut self.0.last_mut().unwrap().state
+    }
+
+    pub(super) fn states(&self) -> impl Iterator<Item = &S> {
+        self.0.iter().map(|frame| &frame.state)
+    }
+
+    pub(super) fn open(&mut self, name: Vec<u8>, state: S) {
+        self.0.push(Frame {
+            name,
+            state,
+        });
+    }
+
+    /// The depth of the innermost open element named `name`.
+    pub(super) fn find(&self, name: &[u8]) -> Option<usize> {
+        self.0.iter().skip(1).rposition(|frame| ascii_compare(&frame.name, name)).map(|position| position + 1)
+    }
+
+    /// Closes the element at `depth` and the elements left open within it.
+    pub(super) fn close(&mut self, depth: usize) -> S {
+        self.0.truncate(depth + 1);
+        self.0.pop().unwrap().state
+    }
+}
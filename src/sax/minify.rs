@@ -0,0 +1,407 @@
+//! Minifies markup.
+//!
+//! The [`Minifier`] reads a document from the [lossless tokens](super::lossless)
+//! of its chunks and writes it to a sink as they come, so documents larger
+//! than memory can be minified:
+//!
+//! * Comments are dropped, except comments starting with `<!--!` when
+//!   [`MinifyOptions::keep_license_comments`] is set.
+//! * Whitespace only text is dropped, unless there was text in its element
+//!   before. Every other run of whitespace becomes a single space. The
+//!   content of elements with `xml:space="preserve"` and of the elements in
+//!   [`MinifyOptions::preserve`] keeps its whitespace.
+//! * Whitespace in tags is dropped where it is not needed. Attribute values
+//!   lose their quotes when [`MinifyOptions::unquote_attributes`] is set and
+//!   the value allows it.
+//! * Elements without content are written self-closing when
+//!   [`MinifyOptions::self_close_empty`] is set.
+//!
+//! [`MinifyStats`] tells how many bytes were saved.
+//!
+//! Like the [formatter](super::format) the minifier is not built on the
+//! [`SAXParser`](super::parser::SAXParser), which drops the whitespace within
+//! tags and the quotes of attribute values the minifier decides on, and would
+//! report malformed input the minifier writes out as is.
+//!
+//! # Examples
+//!
+//! ```
+//! use sax_wasm::sax::minify::{minify, MinifyOptions};
+//!
+//! let svg = b"<svg  width=\"10\">\n  <!-- icon -->\n  <g></g>\n  <text>a   b</text>\n</svg>\n";
+//! let (minified, stats) = minify(svg, &MinifyOptions::default());
+//! assert_eq!(minified, b"<svg width=\"10\"><g/><text>a b</text></svg>");
+//! assert_eq!(stats.saved(), svg.len() - minified.len());
+//! ```
+
+use std::io::{self, Write};
+
+use super::lossless::{PullToken, Token, TokenKind, Tokenizer};
+use super::markup::{contains_name, names, CloseTag, Frames, Markup, StartTag, TagReader, HTML_PRESERVE, HTML_VOID_ELEMENTS};
+
+/// The options of a [`Minifier`].
+#[derive(Debug, Clone)]
+pub struct MinifyOptions {
+    /// Keeps comments starting with `<!--!`, such as license headers.
+    pub keep_license_comments: bool,
+    /// Writes attribute values without quotes where HTML allows it.
+    /// XML requires the quotes.
+    pub unquote_attributes: bool,
+    /// Writes `<a></a>` as `<a/>`. HTML only allows it for void elements.
+    pub self_close_empty: bool,
+    /// Elements whose whitespace is written unchanged, compared ignoring ASCII case.
+    pub preserve: Vec<Vec<u8>>,
+    /// Elements without content or close tag, compared ignoring ASCII case.
+    pub void_elements: Vec<Vec<u8>>,
+}
+
+impl Default for MinifyOptions {
+    fn default() -> Self {
+        MinifyOptions {
+            keep_license_comments: false,
+            unquote_attributes: false,
+            self_close_empty: true,
+            preserve: Vec::new(),
+            void_elements: Vec::new(),
+        }
+    }
+}
+
+impl MinifyOptions {
+    /// The options for HTML, removing quotes, keeping empty elements,
+    /// preserving the whitespace of `pre`, `textarea`, `script` and `style`
+    /// elements and knowing the void elements.
+    pub fn html() -> MinifyOptions {
+        MinifyOptions {
+            unquote_attributes: true,
+            self_close_empty: false,
+            preserve: names(HTML_PRESERVE),
+            void_elements: names(HTML_VOID_ELEMENTS),
+            ..MinifyOptions::default()
+        }
+    }
+}
+
+/// The bytes read and written by a [`Minifier`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MinifyStats {
+    pub input_bytes: usize,
+    pub output_bytes: usize,
+}
+
+impl MinifyStats {
+    /// The bytes saved, zero when the output is longer than the input.
+    pub fn saved(&self) -> usize {
+        self.input_bytes.saturating_sub(self.output_bytes)
+    }
+}
+
+/// How the content of an element is written.
+struct Content {
+    // Whitespace is content
+    preserve: bool,
+    // Text was read in the element
+    mixed: bool,
+}
+
+/// Writes tokens in their minified form.
+struct Printer<W: Write> {
+    sink: W,
+    options: MinifyOptions,
+    stats: MinifyStats,
+    // The document followed by the open elements
+    frames: Frames<Content>,
+    tags: TagReader,
+    // The start tag of the innermost element without its `>` while the
+    // element is empty, and whether its last attribute value is unquoted
+    open_tag: Option<(Vec<u8>, bool)>,
+    // Whitespace in content was read since the last output
+    space: bool,
+}
+
+impl<W: Write> Printer<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.stats.output_bytes += bytes.len();
+        self.sink.write_all(bytes)
+    }
+
+    /// Writes the start tag held back, its element is not empty.
+    fn flush_open_tag(&mut self) -> io::Result<()> {
+        match self.open_tag.take() {
+            Some((tag, _)) => {
+                self.write(&tag)?;
+                self.write(b">")
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Readies the output for markup in the innermost element.
+    fn before_markup(&mut self) -> io::Result<()> {
+        self.flush_open_tag()?;
+        if std::mem::take(&mut self.space) && self.frames.last().mixed {
+            self.write(b" ")?;
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.flush_open_tag()?;
+        let content = self.frames.last_mut();
+        if content.preserve {
+            return self.write(bytes);
+        }
+        content.mixed = true;
+        let mut collapsed = Vec::with_capacity(bytes.len());
+        for &byte in bytes {
+            if byte.is_ascii_whitespace() {
+                self.space = true;
+                continue;
+            }
+            if std::mem::take(&mut self.space) {
+                collapsed.push(b' ');
+            }
+            collapsed.push(byte);
+        }
+        self.write(&collapsed)
+    }
+
+    fn token(&mut self, token: Token) -> io::Result<()> {
+        let token = match self.tags.read(token, self.options.unquote_attributes) {
+            Markup::Content(token) => token,
+            Markup::StartTag(tag, self_closing) => return self.open_element(tag, self_closing),
+            Markup::CloseTag(tag) => return self.close_element(tag),
+            Markup::Partial => return Ok(()),
+        };
+        match token.kind {
+            TokenKind::Whitespace if !self.frames.last().preserve => {
+                self.space = true;
+                Ok(())
+            }
+            TokenKind::Whitespace | TokenKind::Text => self.text(token.raw),
+            TokenKind::Comment if !(self.options.keep_license_comments && token.raw.starts_with(b"<!--!")) => Ok(()),
+            kind => {
+                if kind == TokenKind::Cdata {
+                    self.frames.last_mut().mixed = true;
+                }
+                self.before_markup()?;
+                self.write(token.raw)
+            }
+        }
+    }
+
+    /// Writes the start tag read and opens its element.
+    fn open_element(&mut self, tag: StartTag, self_closing: bool) -> io::Result<()> {
+        self.before_markup()?;
+        let preserve = tag.xml_space().unwrap_or_else(|| self.frames.last().preserve || contains_name(&self.options.preserve, &tag.name));
+        let bytes = tag.normalized();
+        if self_closing || contains_name(&self.options.void_elements, &tag.name) {
+            self.write(&bytes)?;
+            // `/` would be part of an unquoted value
+            return self.write(match (self_closing, tag.unquoted) {
+                (false, _) => b">",
+                (true, false) => b"/>",
+                (true, true) => b" />",
+            });
+        }
+        self.frames.open(
+            tag.name,
+            Content {
+                preserve,
+                mixed: false,
+            },
+        );
+        if self.options.self_close_empty {
+            self.open_tag = Some((bytes, tag.unquoted));
+            return Ok(());
+        }
+        self.write(&bytes)?;
+        self.write(b">")
+    }
+
+    /// Writes the close tag read and closes its element, and the elements
+    /// left open within it. A close tag without open element is content.
+    fn close_element(&mut self, tag: CloseTag) -> io::Result<()> {
+        let Some(depth) = self.frames.find(&tag.name) else {
+            self.before_markup()?;
+            return self.write(&tag.raw);
+        };
+        let empty = depth == self.frames.depth();
+        if empty && self.open_tag.is_some() {
+            let (open_tag, unquoted) = self.open_tag.take().unwrap();
+            self.frames.close(depth);
+            self.space = false;
+            self.write(&open_tag)?;
+            return self.write(if unquoted {
+                b" />"
+            } else {
+                b"/>"
+            });
+        }
+        self.flush_open_tag()?;
+        let content = self.frames.close(depth);
+        if std::mem::take(&mut self.space) && content.mixed {
+            self.write(b" ")?;
+        }
+        self.write(b"</")?;
+        self.write(&tag.name)?;
+        self.write(b">")
+    }
+
+    /// Writes what is left at the end of the document.
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush_open_tag()?;
+        let rest = self.tags.finish();
+        if !rest.is_empty() {
+            self.before_markup()?;
+            self.write(&rest)?;
+        }
+        self.sink.flush()
+    }
+}
+
+/// Minifies a document written to it in chunks. See the [module documentation](self).
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Write;
+/// use sax_wasm::sax::minify::{Minifier, MinifyOptions};
+///
+/// let mut minifier = Minifier::new(Vec::new(), MinifyOptions::html());
+/// minifier.write_all(b"<p  class=\"note\">\n  Hello,\n").unwrap();
+/// minifier.write_all(b"  <b>world</b>\n</p>\n").unwrap();
+/// let (minified, stats) = minifier.finish().unwrap();
+/// assert_eq!(minified, b"<p class=note> Hello, <b>world</b> </p>");
+/// assert_eq!(stats.saved(), 8);
+/// ```
+pub struct Minifier<W: Write> {
+    tokenizer: Tokenizer,
+    printer: Printer<W>,
+}
+
+impl<W: Write> Minifier<W> {
+    pub fn new(sink: W, options: MinifyOptions) -> Minifier<W> {
+        Minifier {
+            tokenizer: Tokenizer::new(),
+            printer: Printer {
+                sink,
+                options,
+                stats: MinifyStats::default(),
+                frames: Frames::new(Content {
+                    preserve: false,
+                    mixed: false,
+                }),
+                tags: TagReader::default(),
+                open_tag: None,
+                space: false,
+            },
+        }
+    }
+
+    /// The bytes read and written so far.
+    pub fn stats(&self) -> MinifyStats {
+        self.printer.stats
+    }
+
+    fn print_tokens(&mut self) -> io::Result<()> {
+        while let PullToken::Token(token) = self.tokenizer.next_token() {
+            self.printer.token(token)?;
+        }
+        Ok(())
+    }
+
+    /// Minifies the rest of the document and returns the sink.
+    pub fn finish(mut self) -> io::Result<(W, MinifyStats)> {
+        self.tokenizer.finish();
+        self.print_tokens()?;
+        self.printer.finish()?;
+        Ok((self.printer.sink, self.printer.stats))
+    }
+}
+
+impl<W: Write> Write for Minifier<W> {
+    /// Minifies the next chunk of the document. Output is written
+    /// as soon as the tokens and whitespace around it are known.
+    fn write(&mut self, chunk: &[u8]) -> io::Result<usize> {
+        self.printer.stats.input_bytes += chunk.len();
+        self.tokenizer.feed(chunk);
+        self.print_tokens()?;
+        Ok(chunk.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.printer.sink.flush()
+    }
+}
+
+/// Minifies a complete document.
+pub fn minify(source: &[u8], options: &MinifyOptions) -> (Vec<u8>, MinifyStats) {
+    let mut minifier = Minifier::new(Vec::new(), options.clone());
+    // Writing to a Vec does not fail
+    minifier.write_all(source).unwrap();
+    minifier.finish().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(options: &MinifyOptions, doc: &str, expected: &str) {
+        let (minified, stats) = minify(doc.as_bytes(), options);
+        assert_eq!(String::from_utf8_lossy(&minified), expected);
+        assert_eq!(stats.input_bytes, doc.len());
+        assert_eq!(stats.output_bytes, minified.len());
+        // Minifying is stable
+        assert_eq!(String::from_utf8_lossy(&minify(&minified, options).0), expected);
+        for chunk_size in [1, 3, 7] {
+            let mut minifier = Minifier::new(Vec::new(), options.clone());
+            for chunk in doc.as_bytes().chunks(chunk_size) {
+                minifier.write_all(chunk).unwrap();
+            }
+            assert_eq!(minifier.finish().unwrap(), (minified.clone(), stats), "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn test_whitespace_and_comments() {
+        let doc = "<?xml version=\"1.0\"?>\n<!-- generated -->\n<!--! MIT License -->\n<config>\n  <name>  My   app </name>\n  <items>\n    <item/>\n  </items>\n  <p>a <b>b</b>  <!-- x -->  <i>c</i>\n</p>\n  <s xml:space=\"preserve\">  x  <t> </t></s>\n</config>\n";
+        let body = "<config><name> My app </name><items><item/></items><p>a <b>b</b> <i>c</i> </p><s xml:space=\"preserve\">  x  <t> </t></s></config>";
+        check(&MinifyOptions::default(), doc, &format!("<?xml version=\"1.0\"?>{}", body));
+        let options = MinifyOptions {
+            keep_license_comments: true,
+            ..MinifyOptions::default()
+        };
+        check(&options, doc, &format!("<?xml version=\"1.0\"?><!--! MIT License -->{}", body));
+        check(
+            &MinifyOptions::html(),
+            "<div>\n  <pre>\n  a  b\n</pre>\n  <textarea> x </textarea>\n</div>",
+            "<div><pre>\n  a  b\n</pre><textarea> x </textarea></div>",
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        check(
+            &MinifyOptions::default(),
+            "<svg  viewBox = \"0 0 10 10\"   fill='none' ><path d=\"M0 0\"  /></svg>",
+            "<svg viewBox=\"0 0 10 10\" fill='none'><path d=\"M0 0\"/></svg>",
+        );
+        check(
+            &MinifyOptions::html(),
+            "<div  class=\"a\"  id='main' title=\"a b\" data-x=\"\" hidden><input value=\"x\"/><img src=\"a.png\"><br/></div>",
+            "<div class=a id=main title=\"a b\" data-x=\"\" hidden><input value=x /><img src=a.png><br/></div>",
+        );
+    }
+
+    #[test]
+    fn test_empty_elements() {
+        let options = MinifyOptions::default();
+        check(&options, "<a><b></b><c> </c><d><!-- x --></d><e>text</e><f x=\"1\"></F ></a>", "<a><b/><c/><d/><e>text</e><f x=\"1\"/></a>");
+        check(&MinifyOptions::html(), "<div></div><p> </p>", "<div></div><p></p>");
+        // Malformed documents
+        check(&options, "<a><b></a></c>", "<a><b></a></c>");
+        check(&options, "<a><b x=\"1", "<a><b x=\"1");
+        assert_eq!(minify(b"<a>  </a>", &options).1.saved(), 5);
+    }
+}
@@ -1,5 +1,23 @@
+pub mod borrowed;
+pub mod c14n;
+pub mod context;
+pub mod dtd;
+pub mod encoding;
+pub mod entities;
+mod expression;
+pub mod format;
 pub mod grapheme_iterator;
+pub mod lossless;
+mod markup;
+pub mod minify;
 pub mod names;
 pub mod parser;
+pub mod path;
+pub mod pull;
+pub mod selector;
+pub mod simd;
 pub mod tag;
+pub mod tree;
 pub mod utils;
+pub mod writer;
+pub mod xpath;
@@ -1,4 +1,4 @@
-use super::utils::to_char_code;
+use super::utils::{grapheme_len, to_char_code};
 
 // Checks if a grapheme cluster is a valid XML name start character.
 ///
@@ -94,7 +94,7 @@ pub fn is_name_char(grapheme: &[u8]) -> bool {
     // Fast ASCII path avoids UTF-8 decode on the hot case.
     let b0 = grapheme[0];
     if b0 <= 0x7F {
-        return matches!(b0, 0x61..=0x7A | 0x41..=0x5A | 0x30..=0x39 | 0x2D | 0x2E | 0x5F);
+        return matches!(b0, 0x61..=0x7A | 0x41..=0x5A | 0x30..=0x39 | 0x2D | 0x2E | 0x3A | 0x5F);
     }
 
     let c = to_char_code(grapheme);
@@ -119,3 +119,36 @@ pub fn is_name_char(grapheme: &[u8]) -> bool {
         0x10000..=0xEFFFF
     )
 }
+
+/// Checks if the bytes form a valid XML `Name`.
+///
+/// The first grapheme must be a name start character
+/// and every following grapheme a name character.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::names::is_name;
+///
+/// assert!(is_name("svg:rect".as_bytes()));
+/// assert!(is_name("état-1".as_bytes()));
+/// assert!(!is_name("1st".as_bytes()));
+/// assert!(!is_name("a$b".as_bytes()));
+/// ```
+pub fn is_name(bytes: &[u8]) -> bool {
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let end = (idx + grapheme_len(bytes[idx])).min(bytes.len());
+        let grapheme = &bytes[idx..end];
+        let valid = if idx == 0 {
+            is_name_start_char(grapheme)
+        } else {
+            is_name_char(grapheme)
+        };
+        if !valid {
+            return false;
+        }
+        idx = end;
+    }
+    !bytes.is_empty()
+}
@@ -1,12 +1,20 @@
+use std::collections::VecDeque;
 use std::mem;
 use std::ops::Index;
 use std::ops::IndexMut;
 use std::ptr;
+use std::slice;
 
+use super::borrowed::EntityRef;
+use super::context::Context;
+use super::dtd::Declaration;
+use super::encoding::{sniff, Decoder, Encoding};
+use super::entities::EntityTable;
 use super::grapheme_iterator::GraphemeClusters;
-use super::names::is_name_start_char;
+use super::names::{is_name, is_name_start_char};
+use super::path::PathFilter;
 use super::tag::*;
-use super::utils::{ascii_compare, ascii_contains};
+use super::utils::{advance_position, ascii_compare, ascii_contains, utf16_len};
 
 /// Byte Order Mark (BOM) for UTF-8 encoded files.
 static BOM: [u8; 3] = [0xef, 0xbb, 0xbf];
@@ -35,12 +43,151 @@ static DOCTYPE_VALUE_END: &[u8] = &[b' ', b'\n', b'\t', b'\r', b'>'];
 
 static DOCTYPE_END: &[u8] = &[b'!', b'>'];
 
+/// Characters that indicate the end of an entity
+/// or the start of a quoted literal within it.
+static ENTITY_END: &[u8] = &[b'>', b'"', b'\''];
+
+/// Namespace bound to the reserved `xml` prefix
+pub(crate) static XML_NAMESPACE: &[u8] = b"http://www.w3.org/XML/1998/namespace";
+
+/// Namespace bound to the reserved `xmlns` prefix
+static XMLNS_NAMESPACE: &[u8] = b"http://www.w3.org/2000/xmlns/";
+
 // Trait for implementing an event handler struct
 // to pass to the parser for receiving events
 pub trait EventHandler {
     fn handle_event(&self, event: Event, data: Entity);
 }
 
+// Same as `EventHandler` for handlers that keep state
+// without interior mutability. Every `FnMut(Event, Entity)`
+// closure is an `EventHandlerMut`
+pub trait EventHandlerMut {
+    fn handle_event(&mut self, event: Event, data: Entity);
+}
+
+impl<F: FnMut(Event, Entity)> EventHandlerMut for F {
+    fn handle_event(&mut self, event: Event, data: Entity) {
+        self(event, data)
+    }
+}
+
+// Same as `EventHandlerMut` for handlers receiving views borrowed
+// from the chunk being parsed instead of owned values, see
+// `sax::borrowed`. Every `FnMut(Event, EntityRef)` closure is a
+// `BorrowedEventHandler`
+pub trait BorrowedEventHandler {
+    fn handle_event(&mut self, event: Event, data: EntityRef);
+}
+
+impl<F: FnMut(Event, EntityRef)> BorrowedEventHandler for F {
+    fn handle_event(&mut self, event: Event, data: EntityRef) {
+        self(event, data)
+    }
+}
+
+// Same as `EventHandlerMut` for handlers that also need to know
+// where in the document an event occurs. Every
+// `FnMut(Event, Entity, &Context)` closure is a `ContextEventHandler`
+pub trait ContextEventHandler {
+    fn handle_event(&mut self, event: Event, data: Entity, context: &Context);
+}
+
+impl<F: FnMut(Event, Entity, &Context)> ContextEventHandler for F {
+    fn handle_event(&mut self, event: Event, data: Entity, context: &Context) {
+        self(event, data, context)
+    }
+}
+
+/// Receives each event through a method of its own.
+///
+/// Every method does nothing by default so only the events of interest
+/// need implementing. Events are still subscribed to using `SAXParser::events`.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::parser::{Event, SAXParser, TypedEventHandler};
+/// use sax_wasm::sax::tag::Tag;
+///
+/// #[derive(Default)]
+/// struct Depth {
+///     current: usize,
+///     max: usize,
+/// }
+///
+/// impl TypedEventHandler for Depth {
+///     fn open_tag(&mut self, _tag: &Tag) {
+///         self.current += 1;
+///         self.max = self.max.max(self.current);
+///     }
+///
+///     fn close_tag(&mut self, _tag: &Tag) {
+///         self.current -= 1;
+///     }
+/// }
+///
+/// let mut depth = Depth::default();
+/// let mut parser = SAXParser::new_typed(&mut depth);
+/// parser.events[Event::OpenTag] = true;
+/// parser.events[Event::CloseTag] = true;
+/// parser.write(b"<a><b><c/></b><b/></a>");
+/// parser.identity();
+/// drop(parser);
+/// assert_eq!(depth.max, 3);
+/// ```
+pub trait TypedEventHandler {
+    fn text(&mut self, _text: &Text) {}
+    fn processing_instruction(&mut self, _proc_inst: &ProcInst) {}
+    fn declaration(&mut self, _declaration: &Declaration) {}
+    fn doctype(&mut self, _doctype: &Text) {}
+    fn comment(&mut self, _comment: &Text) {}
+    fn open_tag_start(&mut self, _tag: &Tag) {}
+    fn attribute(&mut self, _attribute: &Attribute) {}
+    fn open_tag(&mut self, _tag: &Tag) {}
+    fn close_tag(&mut self, _tag: &Tag) {}
+    fn cdata(&mut self, _cdata: &Text) {}
+    fn error(&mut self, _error: &ParseError) {}
+    fn xml_decl(&mut self, _xml_decl: &XmlDecl) {}
+}
+
+/// The event handler a parser was created with.
+enum Handler<'a> {
+    Shared(&'a dyn EventHandler),
+    Mut(&'a mut dyn EventHandlerMut),
+    Typed(&'a mut dyn TypedEventHandler),
+    Borrowed(&'a mut dyn BorrowedEventHandler),
+    Context(&'a mut dyn ContextEventHandler),
+}
+
+impl Handler<'_> {
+    fn handle_event(&mut self, event: Event, data: Entity) {
+        let handler = match self {
+            Handler::Shared(handler) => return handler.handle_event(event, data),
+            Handler::Mut(handler) => return handler.handle_event(event, data),
+            Handler::Borrowed(handler) => return handler.handle_event(event, EntityRef::from(data)),
+            // Passed a context by `SAXParser::dispatch`
+            Handler::Context(_) => return,
+            Handler::Typed(handler) => handler,
+        };
+        match (event, data) {
+            (Event::Text, Entity::Text(text)) => handler.text(text),
+            (Event::ProcessingInstruction, Entity::ProcInst(proc_inst)) => handler.processing_instruction(proc_inst),
+            (Event::Declaration, Entity::Declaration(declaration)) => handler.declaration(declaration),
+            (Event::Doctype, Entity::Text(doctype)) => handler.doctype(doctype),
+            (Event::Comment, Entity::Text(comment)) => handler.comment(comment),
+            (Event::OpenTagStart, Entity::Tag(tag)) => handler.open_tag_start(tag),
+            (Event::Attribute, Entity::Attribute(attribute)) => handler.attribute(attribute),
+            (Event::OpenTag, Entity::Tag(tag)) => handler.open_tag(tag),
+            (Event::CloseTag, Entity::Tag(tag)) => handler.close_tag(tag),
+            (Event::Cdata, Entity::Text(cdata)) => handler.cdata(cdata),
+            (Event::Error, Entity::Error(error)) => handler.error(error),
+            (Event::XmlDecl, Entity::XmlDecl(xml_decl)) => handler.xml_decl(xml_decl),
+            _ => {}
+        }
+    }
+}
+
 /// Represents a SAX (Simple API for XML) parser.
 ///
 /// This struct provides functionality to parse XML data using the SAX approach,
@@ -49,6 +196,11 @@ pub trait EventHandler {
 /// # Fields
 ///
 /// * `events` - A bitmask representing the events to be generated.
+/// * `decode_entities` - Whether entity and character references are decoded.
+/// * `entities` - The entities declared in the internal subset of the DOCTYPE.
+/// * `namespaces` - Whether `xmlns` declarations are tracked and names resolved.
+/// * `strict` - Whether XML 1.0 well-formedness violations are reported.
+/// * `preserve_whitespace` - Whether text is reported with all of its whitespace.
 /// * `tags` - A vector of tags encountered during parsing.
 /// * `state` - The current state of the parser.
 /// * `cdata` - The current CDATA section being parsed.
@@ -64,25 +216,56 @@ pub trait EventHandler {
 /// * `brace_ct` - The current brace count.
 /// * `event_handler` - The event handler function.
 /// * `leftover_bytes` - Bytes left over from the previous parse.
+/// * `sniffed` - The first bytes of the document buffered until the encoding is known.
+/// * `decoder` - Transcodes UTF-16, ISO-8859-1 and Windows-1252 input to UTF-8.
 /// * `end_pos` - The end position of the current parse.
 pub struct SAXParser<'a> {
     // Configuration and State
-    pub events: [bool; 10],
+    pub events: Events,
+    // Opt-in decoding of the predefined entities and
+    // numeric character references in text and
+    // attribute values. Source bytes are kept in `raw`
+    pub decode_entities: bool,
+    // Opt-in namespace mode. Prefixes are resolved against
+    // the xmlns declarations in scope and the resolved URI
+    // is set on each Tag and Attribute
+    pub namespaces: bool,
+    // Opt-in strict mode. Anything that is not well-formed
+    // XML 1.0 is reported using the Error event
+    pub strict: bool,
+    // Opt-in whitespace preserving mode. Text is reported
+    // unsplit up to the next markup, including its newlines
+    // and whitespace only text
+    pub preserve_whitespace: bool,
+    // Opt-in path filter. Only events within elements
+    // matching one of its patterns are generated
+    pub path_filter: Option<PathFilter>,
     state: State,
     brace_ct: u32,
     quote: u8,
 
     // Event Handling
-    event_handler: &'a dyn EventHandler,
+    event_handler: Handler<'a>,
     // Used to make sure dispatched objects
     // stick around until the next write
     dispatched: Vec<Dispatched>,
+    // Events held for a pull reader instead
+    // of being passed to the event handler
+    queue: Option<VecDeque<(Event, Dispatched)>>,
 
     // Parsing Buffers
     tags: Vec<Tag>,
+    // Number of elements opened so far within each of `tags`.
+    // The first entry counts the top level elements
+    children: Vec<usize>,
+    seen_root: bool,
+    // Byte offset where the XML declaration
+    // may start (past any BOM)
+    xml_decl_offset: u64,
     text: Option<Text>,
     markup_decl: Option<Text>,
     markup_entity: Option<Text>,
+    entities: EntityTable,
 
     proc_inst: Option<ProcInst>,
     attribute: Attribute,
@@ -90,9 +273,18 @@ pub struct SAXParser<'a> {
     close_tag: Text,
     fragment: Vec<u8>,
 
+    // Encoding
+    // The first bytes of the document buffered
+    // until the encoding is known
+    sniffed: Option<Vec<u8>>,
+    // Transcodes non UTF-8 input
+    decoder: Option<Decoder>,
+
     // Position Tracking
     end_pos: [u64; 2],
     source_ptr: *const u8,
+    // Length of the chunk at `source_ptr` while it is parsed
+    source_len: usize,
     end_offset: usize,
     chunk_offset: u64,
 }
@@ -138,7 +330,7 @@ impl<'a> SAXParser<'a> {
     /// let tags = Rc::new(RefCell::new(Vec::new()));
     /// let event_handler = SaxEventHandler::new(Rc::clone(&tags));
     /// let mut parser = SAXParser::new(&event_handler);
-    /// let mut events = [false;10];
+    /// let mut events = [false; Event::COUNT];
     /// events[Event::OpenTag as usize] = true;
     /// parser.events = events;
     /// parser.write(b"<tag>content</tag>");
@@ -149,9 +341,106 @@ impl<'a> SAXParser<'a> {
     /// }
     /// ```
     pub fn new(event_handler: &'a dyn EventHandler) -> SAXParser<'a> {
+        SAXParser::with_handler(Handler::Shared(event_handler))
+    }
+
+    /// Creates a new `SAXParser` with an event handler taking `&mut self`,
+    /// e.g. a closure.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sax_wasm::sax::parser::{Event, SAXParser};
+    /// use sax_wasm::sax::tag::Entity;
+    ///
+    /// let mut names = Vec::new();
+    /// let mut handler = |_event: Event, data: Entity| {
+    ///     if let Entity::Tag(tag) = data {
+    ///         names.push(tag.name.clone());
+    ///     }
+    /// };
+    /// let mut parser = SAXParser::new_mut(&mut handler);
+    /// parser.events[Event::OpenTag] = true;
+    /// parser.write(b"<a><b/></a>");
+    /// drop(parser);
+    /// assert_eq!(names, [b"a", b"b"]);
+    /// ```
+    pub fn new_mut(event_handler: &'a mut dyn EventHandlerMut) -> SAXParser<'a> {
+        SAXParser::with_handler(Handler::Mut(event_handler))
+    }
+
+    /// Creates a new `SAXParser` calling the method of a
+    /// [`TypedEventHandler`] matching each event.
+    pub fn new_typed(event_handler: &'a mut dyn TypedEventHandler) -> SAXParser<'a> {
+        SAXParser::with_handler(Handler::Typed(event_handler))
+    }
+
+    /// Creates a new `SAXParser` passing views of the event data to a
+    /// [`BorrowedEventHandler`]. Names and values are borrowed from the
+    /// chunk being parsed so most events are dispatched without allocating.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sax_wasm::sax::borrowed::EntityRef;
+    /// use sax_wasm::sax::parser::{Event, SAXParser};
+    ///
+    /// let mut names = Vec::new();
+    /// let mut handler = |_event: Event, data: EntityRef| {
+    ///     if let EntityRef::Tag(tag) = data {
+    ///         names.push(tag.name.to_vec());
+    ///     }
+    /// };
+    /// let mut parser = SAXParser::new_borrowed(&mut handler);
+    /// parser.events[Event::OpenTag] = true;
+    /// parser.write(b"<a><b/></a>");
+    /// drop(parser);
+    /// assert_eq!(names, [b"a", b"b"]);
+    /// ```
+    pub fn new_borrowed(event_handler: &'a mut dyn BorrowedEventHandler) -> SAXParser<'a> {
+        SAXParser::with_handler(Handler::Borrowed(event_handler))
+    }
+
+    /// Creates a new `SAXParser` passing a [`Context`] along with each event
+    /// to a [`ContextEventHandler`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sax_wasm::sax::context::Context;
+    /// use sax_wasm::sax::parser::{Event, SAXParser};
+    /// use sax_wasm::sax::tag::Entity;
+    ///
+    /// let mut texts = Vec::new();
+    /// let mut handler = |_event: Event, data: Entity, context: &Context| {
+    ///     // Only the text of <title> elements within <book>
+    ///     if let Entity::Text(text) = data {
+    ///         if context.names().eq([&b"book"[..], b"title"]) {
+    ///             texts.push(text.value.clone());
+    ///         }
+    ///     }
+    /// };
+    /// let mut parser = SAXParser::new_with_context(&mut handler);
+    /// parser.events[Event::Text] = true;
+    /// parser.write(b"<book><title>SAX</title><chapter><title>Events</title></chapter></book>");
+    /// drop(parser);
+    /// assert_eq!(texts, [b"SAX"]);
+    /// ```
+    pub fn new_with_context(event_handler: &'a mut dyn ContextEventHandler) -> SAXParser<'a> {
+        SAXParser::with_handler(Handler::Context(event_handler))
+    }
+
+    fn with_handler(event_handler: Handler<'a>) -> SAXParser<'a> {
         SAXParser {
             // Configuration and State
-            events: [false; 10],
+            events: [false; Event::COUNT],
+            decode_entities: false,
+            namespaces: false,
+            strict: false,
+            preserve_whitespace: false,
+            path_filter: None,
+            seen_root: false,
+            xml_decl_offset: 0,
             state: State::Begin,
             brace_ct: 0,
             quote: 0,
@@ -159,12 +448,15 @@ impl<'a> SAXParser<'a> {
             // Event Handling
             event_handler,
             dispatched: Vec::new(),
+            queue: None,
 
             // Parsing Buffers
             text: None,
             tags: Vec::new(),
+            children: vec![0],
             markup_decl: None,
             markup_entity: None,
+            entities: EntityTable::new(),
 
             attribute: Attribute::new(),
             proc_inst: None,
@@ -172,10 +464,15 @@ impl<'a> SAXParser<'a> {
             close_tag: Text::new([0, 0]),
             fragment: Vec::new(),
 
+            // Encoding
+            sniffed: Some(Vec::new()),
+            decoder: None,
+
             // Position Tracking
             end_pos: [0, 0],
             end_offset: 0,
             source_ptr: ptr::null(),
+            source_len: 0,
             chunk_offset: 0,
         }
     }
@@ -230,6 +527,57 @@ impl<'a> SAXParser<'a> {
     /// ```
     pub fn write(&mut self, source: &[u8]) {
         self.dispatched.clear();
+        // Nothing is parsed until the encoding is known
+        if let Some(mut sniffed) = self.sniffed.take() {
+            sniffed.extend_from_slice(source);
+            match sniff(&sniffed, false) {
+                Some((encoding, bom_len)) => self.start_decoding(encoding, bom_len, &sniffed),
+                None => self.sniffed = Some(sniffed),
+            }
+            return;
+        }
+        match &mut self.decoder {
+            Some(decoder) => {
+                let mut utf8 = Vec::new();
+                decoder.decode(source, &mut utf8);
+                self.parse(&utf8);
+                self.discard_runs();
+            }
+            None => self.parse(source),
+        }
+    }
+
+    fn start_decoding(&mut self, encoding: Encoding, bom_len: usize, bytes: &[u8]) {
+        // UTF-8 is parsed as is. A BOM is skipped in begin()
+        if encoding == Encoding::Utf8 {
+            self.parse(bytes);
+            return;
+        }
+        let mut decoder = Decoder::new(encoding, bom_len);
+        let mut utf8 = Vec::new();
+        decoder.decode(&bytes[bom_len..], &mut utf8);
+        self.decoder = Some(decoder);
+        self.parse(&utf8);
+    }
+
+    /// Lets the decoder forget the offsets no event will be mapped from.
+    /// Open elements already hold input offsets, so these are the offsets
+    /// before the construct still being parsed or else before the next chunk.
+    fn discard_runs(&mut self) {
+        let text_start = self.text.as_ref().map(|text| text.byte_range.0);
+        let pending_start = self.unterminated().map(|(_, _, byte_start)| byte_start);
+        let oldest = text_start.into_iter().chain(pending_start).fold(self.chunk_offset, u64::min);
+        if let Some(decoder) = &mut self.decoder {
+            decoder.discard_before(oldest);
+        }
+    }
+
+    /// Maps an offset into the parsed UTF-8 back to the input.
+    fn input_offset(&self, offset: u64) -> u64 {
+        self.decoder.as_ref().map_or(offset, |decoder| decoder.input_offset(offset))
+    }
+
+    fn parse(&mut self, source: &[u8]) {
         let mut bytes = source;
 
         let frag_len = self.fragment.len();
@@ -243,6 +591,7 @@ impl<'a> SAXParser<'a> {
         }
 
         self.source_ptr = bytes.as_ptr();
+        self.source_len = bytes.len();
 
         let mut gc = GraphemeClusters::new(bytes);
         gc.line = self.end_pos[0];
@@ -255,12 +604,17 @@ impl<'a> SAXParser<'a> {
         self.end_pos = [gc.line, gc.character];
         self.end_offset = gc.cursor;
 
+        // Bytes of a grapheme cut off at the end of the chunk are
+        // processed again on the next write and must not be counted yet
+        let mut consumed = bytes.len();
         if let Some(fragment) = gc.get_remaining_bytes() {
+            consumed -= fragment.len();
             self.fragment.extend_from_slice(fragment);
         }
 
         self.hydrate();
-        self.chunk_offset += source.len() as u64;
+        self.source_len = 0;
+        self.chunk_offset += consumed as u64;
     }
 
     fn hydrate(&mut self) {
@@ -330,8 +684,25 @@ impl<'a> SAXParser<'a> {
     /// parser.identity();
     /// ```
     pub fn identity(&mut self) {
+        // a document too short to tell its encoding
+        if let Some(sniffed) = self.sniffed.take() {
+            if let Some((encoding, bom_len)) = sniff(&sniffed, true) {
+                self.start_decoding(encoding, bom_len, &sniffed);
+            }
+        }
+        // a code unit cut off at the EOF
+        if let Some(decoder) = &mut self.decoder {
+            let mut utf8 = Vec::new();
+            decoder.finish(&mut utf8);
+            if !utf8.is_empty() {
+                self.parse(&utf8);
+            }
+        }
         // flush text at the EOF
-        self.flush_text(self.end_pos[0], self.end_pos[1], 0);
+        self.flush_text(self.end_pos[0], self.end_pos[1], 0, self.chunk_offset);
+        self.report_eof();
+        self.flush_unterminated();
+        self.close_open_tags();
         // Reset Configuration and State
         self.state = State::Begin;
         self.brace_ct = 0;
@@ -343,8 +714,16 @@ impl<'a> SAXParser<'a> {
         // Reset Parsing Buffers
         self.text = None;
         self.tags.clear();
+        self.children.clear();
+        self.children.push(0);
+        if let Some(filter) = &mut self.path_filter {
+            filter.clear();
+        }
+        self.seen_root = false;
+        self.xml_decl_offset = 0;
         self.markup_decl = None;
         self.markup_entity = None;
+        self.entities.clear();
 
         self.attribute = Attribute::new();
         self.proc_inst = None;
@@ -352,6 +731,10 @@ impl<'a> SAXParser<'a> {
         self.close_tag = Text::new([0, 0]);
         self.fragment.clear();
 
+        // Reset Encoding
+        self.sniffed = Some(Vec::new());
+        self.decoder = None;
+
         // Reset Position Tracking
         self.end_pos = [0, 0];
         self.end_offset = 0;
@@ -402,6 +785,7 @@ impl<'a> SAXParser<'a> {
         self.state = State::BeginWhitespace;
         // BOM
         if current == BOM {
+            self.xml_decl_offset = self.chunk_offset + gc.cursor as u64;
             return;
         }
 
@@ -427,7 +811,7 @@ impl<'a> SAXParser<'a> {
     fn begin_white_space(&mut self, gc: &mut GraphemeClusters, current: &[u8]) {
         let byte = current[0];
 
-        if byte == b'\n' {
+        if byte == b'\n' && !self.preserve_whitespace {
             self.state = State::SkipWhitespace;
             return;
         }
@@ -438,17 +822,23 @@ impl<'a> SAXParser<'a> {
             return;
         }
 
-        self.new_text(gc.line, gc.last_character, gc.last_cursor_pos);
+        self.new_text(gc.last_line, gc.last_character, gc.last_cursor_pos);
     }
 
     fn less_than(&mut self, gc: &mut GraphemeClusters, current: &[u8]) {
         let mut should_flush_text = true;
         let character = gc.character.saturating_sub(2);
         let offset = gc.last_cursor_pos.saturating_sub(1);
+        // the '<' may have been in the previous chunk
+        let byte_end = (self.chunk_offset + gc.last_cursor_pos as u64).saturating_sub(1);
         match current[0] {
             _ if is_name_start_char(current) == true => {
                 should_flush_text = false;
                 self.tag.header = (gc.last_cursor_pos, gc.cursor);
+                // Set here since open_tag() is re-entered
+                // when the name spans multiple chunks
+                self.tag.open_start = [gc.line, gc.character.saturating_sub(2)];
+                self.tag.byte_range.0 = (self.chunk_offset + gc.cursor as u64).saturating_sub(2);
 
                 self.state = State::OpenTag;
                 // since calling open_tag advances
@@ -456,7 +846,7 @@ impl<'a> SAXParser<'a> {
                 // the stack, we need to flush_text
                 // now to prevent text nodes from
                 // being added to the wrong tag
-                self.flush_text(gc.line, character, offset);
+                self.flush_text(gc.line, character, offset, byte_end);
                 self.open_tag(gc, current);
             }
 
@@ -477,6 +867,7 @@ impl<'a> SAXParser<'a> {
 
                 self.tag.close_start = [gc.line, gc.last_character.saturating_sub(1)];
                 self.close_tag.header.0 = gc.last_cursor_pos;
+                self.close_tag.byte_range.0 = (self.chunk_offset + gc.cursor as u64).saturating_sub(2);
             }
 
             b'?' => {
@@ -485,8 +876,11 @@ impl<'a> SAXParser<'a> {
                 let mut proc_inst = ProcInst::new();
                 proc_inst.start = [gc.line, gc.character.saturating_sub(2)];
                 proc_inst.target.start = [gc.line, gc.character];
-                proc_inst.target.header = (gc.last_cursor_pos.saturating_sub(1), gc.cursor);
+                // the '<' may have been in the previous chunk
+                proc_inst.target.header = (gc.last_cursor_pos, gc.cursor);
+                proc_inst.target.value.push(b'<');
                 proc_inst.byte_range.0 = (self.chunk_offset + gc.cursor as u64).saturating_sub(2);
+                proc_inst.target.byte_range.0 = proc_inst.byte_range.0 + 2;
                 self.proc_inst = Some(proc_inst);
             }
 
@@ -496,7 +890,7 @@ impl<'a> SAXParser<'a> {
                 // a tag onto the stack, we need to
                 // flush_text now to prevent text nodes
                 // from being added to the wrong tag
-                self.flush_text(gc.line, character, offset);
+                self.flush_text(gc.line, character, offset, byte_end);
                 self.process_open_tag(false, gc); // JSX fragment
             }
 
@@ -507,17 +901,20 @@ impl<'a> SAXParser<'a> {
                 // backup 2 graphemes (not bytes) since we might have gotten
                 // something like '< ' or '< *multi-bytes-grapheme*'
                 self.new_text(gc.line, gc.character, gc.last_cursor_pos);
+
+                let start = [gc.last_line, gc.last_character.saturating_sub(1)];
+                let byte_start = (self.chunk_offset + gc.last_cursor_pos as u64).saturating_sub(1);
+                let message = "'<' is not followed by a tag name".to_string();
+                self.report(ErrorCode::InvalidTagStart, message, start, [gc.last_line, gc.last_character], (byte_start, byte_start + 1));
             }
         }
 
         if should_flush_text && self.text.is_some() {
-            self.flush_text(gc.line, character, offset);
+            self.flush_text(gc.line, character, offset, byte_end);
         }
     }
 
     fn open_tag(&mut self, gc: &mut GraphemeClusters, current: &[u8]) {
-        self.tag.open_start = [gc.line, gc.character.saturating_sub(2)];
-        self.tag.byte_range.0 = (self.chunk_offset + gc.cursor as u64).saturating_sub(2);
         let mut byte = current[0];
         if !ascii_contains(TAG_NAME_END, byte) {
             if let Some((span, found)) = gc.take_until_one_found(TAG_NAME_END, true) {
@@ -531,13 +928,26 @@ impl<'a> SAXParser<'a> {
                 self.tag.header.1 = gc.last_cursor_pos;
             }
         }
+        if let Some(filter) = &mut self.path_filter {
+            // The name is complete
+            if ascii_contains(TAG_NAME_END, byte) {
+                filter.start_element(self.tag.get_name_slice(self.source_ptr));
+            }
+        }
 
-        if self.events[Event::OpenTagStart] {
-            let mut tag = Box::new(self.tag.clone());
-            tag.hydrate(self.source_ptr);
+        if self.wants(Event::OpenTagStart) {
+            if self.borrows_events() {
+                self.tag.hydrate_spanning(self.source_ptr);
+                let source = self.source();
+                if let Handler::Borrowed(handler) = &mut self.event_handler {
+                    handler.handle_event(Event::OpenTagStart, EntityRef::Tag(self.tag.view(source)));
+                }
+            } else {
+                let mut tag = Box::new(self.tag.clone());
+                tag.hydrate(self.source_ptr);
 
-            self.event_handler.handle_event(Event::OpenTagStart, Entity::Tag(&*tag));
-            self.dispatched.push(Dispatched::Tag(tag));
+                self.dispatch(Event::OpenTagStart, Dispatched::Tag(tag));
+            }
         }
 
         match byte {
@@ -558,7 +968,7 @@ impl<'a> SAXParser<'a> {
             // Try to take the entire close tag name
             let mut offset: usize = 0;
             let start = gc.last_cursor_pos;
-            if let Some((span, found)) = gc.take_until_one_found(&[b'>', b' '], true) {
+            if let Some((span, found)) = gc.take_until_one_found(b"> \t\n\r", true) {
                 byte = span[span.len() - 1];
                 offset = found as usize;
             }
@@ -571,7 +981,7 @@ impl<'a> SAXParser<'a> {
             // We've hit a close tag - process it
             b'>' => self.process_close_tag(gc),
             // skip and catch the next iteration
-            b' ' => {
+            b' ' | b'\t' | b'\n' | b'\r' => {
                 gc.skip_whitespace();
             }
             _ => {}
@@ -588,9 +998,9 @@ impl<'a> SAXParser<'a> {
             return;
         }
 
-        if byte == b'\n' {
-            // Newlines flush text always
-            self.flush_text(gc.last_line, gc.last_character, gc.last_cursor_pos);
+        if byte == b'\n' && !self.preserve_whitespace {
+            // Newlines flush text unless whitespace is preserved
+            self.flush_text(gc.last_line, gc.last_character, gc.last_cursor_pos, self.chunk_offset + gc.last_cursor_pos as u64);
             self.state = State::SkipWhitespace
         } else {
             gc.take_until_one_found(TEXT_END, false);
@@ -601,11 +1011,11 @@ impl<'a> SAXParser<'a> {
         }
     }
 
-    fn flush_text(&mut self, line: u64, character: u64, offset: usize) {
+    fn flush_text(&mut self, line: u64, character: u64, offset: usize, byte_end: u64) {
         if self.text.is_none() {
             return;
         }
-        let mut text = Box::new(unsafe { self.text.take().unwrap_unchecked() });
+        let mut text = unsafe { self.text.take().unwrap_unchecked() };
         text.end = [line, character];
         text.header.1 = offset;
 
@@ -613,17 +1023,37 @@ impl<'a> SAXParser<'a> {
         if text.header.0 == text.header.1 && text.value.is_empty() {
             return;
         }
-        text.byte_range.1 = self.chunk_offset + offset as u64;
+        text.byte_range.1 = byte_end;
+
+        if self.strict && self.tags.is_empty() && text.hydrate(self.source_ptr) && text.value.iter().any(|b| !b.is_ascii_whitespace()) {
+            let message = "Text is not allowed outside the root element".to_string();
+            self.report(ErrorCode::TextOutsideRoot, message, text.start, text.end, text.byte_range);
+        }
+
+        if self.decode_entities {
+            text.hydrate(self.source_ptr);
+            text.decode(&self.entities);
+        }
 
         let len = self.tags.len();
         // Store these only if we're interested in CloseTag events
-        if len != 0 && self.events[Event::CloseTag] {
-            self.tags[len - 1].text_nodes.push(*text.clone());
+        if len != 0 && self.wants(Event::CloseTag) {
+            let mut text_node = text.clone();
+            if let Some(decoder) = &self.decoder {
+                text_node.map_byte_ranges(&|offset| decoder.input_offset(offset));
+            }
+            self.tags[len - 1].text_nodes.push(text_node);
         }
 
-        if self.events[Event::Text] && text.hydrate(self.source_ptr) {
-            self.event_handler.handle_event(Event::Text, Entity::Text(&text));
-            self.dispatched.push(Dispatched::Text(text));
+        if !self.wants(Event::Text) {
+            return;
+        }
+        if self.borrows_events() {
+            if text.hydrate_spanning(self.source_ptr) {
+                self.dispatch_borrowed(Event::Text, EntityRef::Text(text.view(self.source())));
+            }
+        } else if text.hydrate(self.source_ptr) {
+            self.dispatch(Event::Text, Dispatched::Text(Box::new(text)));
         }
     }
 
@@ -676,10 +1106,19 @@ impl<'a> SAXParser<'a> {
             md_slice
         };
         if bytes_to_check != b"<!-" && bytes_to_check != b"<![" && !ascii_compare(b"<!D", bytes_to_check) {
-            let mut markup_entity = Text::new([gc.line, gc.character.saturating_sub(2)]);
-            markup_entity.byte_range.1 = (self.chunk_offset + gc.cursor as u64).saturating_sub(2);
-            // skip over the <! and any whitespace afterwards
-            gc.skip_whitespace();
+            // The keyword was already consumed while looking for
+            // the end of the '<!' prefix so it seeds the value
+            let keyword = md_slice[2..].to_vec();
+            let mut markup_entity = Text::new([markup_decl.start[0], markup_decl.start[1] + 1]);
+            markup_entity.byte_range.0 = markup_decl.byte_range.0 + 2;
+            if keyword.is_empty() {
+                // skip over any whitespace after the <!
+                let whitespace_start = gc.cursor;
+                gc.skip_whitespace();
+                markup_entity.start = [gc.line, gc.character];
+                markup_entity.byte_range.0 += (gc.cursor - whitespace_start) as u64;
+            }
+            markup_entity.value = keyword;
             markup_entity.header = (gc.cursor, 0);
 
             self.markup_entity = Some(markup_entity);
@@ -701,18 +1140,31 @@ impl<'a> SAXParser<'a> {
 
         markup_decl.header.1 = gc.cursor;
         markup_decl.byte_range.1 = self.chunk_offset + gc.cursor as u64;
+        let (comment_start, comment_byte_start) = (markup_decl.start, markup_decl.byte_range.0);
 
         let markup_slice = markup_decl.get_value_slice(self.source_ptr, gc.byte_len);
         let len = markup_slice.len();
 
         // We're looking for exactly '-->'
         if len > 2 && &markup_slice[(len - 3)..] == b"-->" {
+            // '--' must not occur in the comment (this includes '--->')
+            let double_hyphen = match self.strict {
+                true => markup_slice[..len - 2].windows(2).position(|w| w == b"--"),
+                false => None,
+            };
+            if let Some(idx) = double_hyphen {
+                let start = advance_position(advance_position(comment_start, b"<!--"), &markup_slice[..idx]);
+                let byte_start = comment_byte_start + 4 + idx as u64;
+                let message = "'--' is not allowed in a comment".to_string();
+                self.report(ErrorCode::DoubleHyphenInComment, message, start, [start[0], start[1] + 2], (byte_start, byte_start + 2));
+            }
+            let wanted = self.wants(Event::Comment);
+            let markup_decl = self.markup_decl.as_mut().unwrap();
             markup_decl.end = [gc.line, gc.character];
-            if self.events[Event::Comment] && markup_decl.hydrate(self.source_ptr) {
+            if wanted && markup_decl.hydrate(self.source_ptr) {
                 let mut markup_decl = Box::new(self.markup_decl.take().unwrap());
                 markup_decl.value.truncate(markup_decl.value.len() - 3); // remove '-->'
-                self.event_handler.handle_event(Event::Comment, Entity::Text(&markup_decl));
-                self.dispatched.push(Dispatched::Text(markup_decl));
+                self.dispatch(Event::Comment, Dispatched::Text(markup_decl));
             }
             self.markup_decl = None;
             self.state = State::BeginWhitespace;
@@ -726,6 +1178,7 @@ impl<'a> SAXParser<'a> {
             gc.take_until(b'>', true);
         }
 
+        let wanted = self.wants(Event::Cdata);
         let markup_decl = self.markup_decl.as_mut().unwrap();
         markup_decl.header.1 = gc.cursor;
         markup_decl.byte_range.1 = self.chunk_offset + gc.cursor as u64;
@@ -735,11 +1188,10 @@ impl<'a> SAXParser<'a> {
         // We're looking for exactly ']]>'
         if len > 2 && &markup_slice[(len - 3)..] == b"]]>" {
             markup_decl.end = [gc.line, gc.character];
-            if self.events[Event::Cdata] && markup_decl.hydrate(self.source_ptr) {
+            if wanted && markup_decl.hydrate(self.source_ptr) {
                 let mut markup_decl = Box::new(self.markup_decl.take().unwrap());
                 markup_decl.value.truncate(markup_decl.value.len() - 3); // remove ]]>
-                self.event_handler.handle_event(Event::Cdata, Entity::Text(&markup_decl));
-                self.dispatched.push(Dispatched::Text(markup_decl));
+                self.dispatch(Event::Cdata, Dispatched::Text(markup_decl));
             }
             self.state = State::BeginWhitespace;
         } else {
@@ -762,11 +1214,28 @@ impl<'a> SAXParser<'a> {
     fn doctype(&mut self, gc: &mut GraphemeClusters, current: &[u8]) {
         let mut byte = current[0];
 
+        // A write ended before or right after the value
+        if self.state == State::Doctype && DOCTYPE_VALUE_END.contains(&byte) {
+            let markup_decl = self.markup_decl.as_mut().unwrap();
+            if !markup_decl.value.is_empty() {
+                self.state = State::DoctypeEntity;
+            } else if byte != b'>' {
+                gc.skip_whitespace();
+                markup_decl.header = (gc.cursor, 0);
+                return;
+            }
+        }
+
         // determine where to stop taking bytes for
         // for the doctype value. e.g. '<!DOCTYPE movie ' <----- take 'movie' but not 'movie '
         if self.state != State::DoctypeEntity && !DOCTYPE_VALUE_END.contains(&byte) {
-            if let Some((span, _)) = gc.take_until_one_found(DOCTYPE_VALUE_END, true) {
+            if let Some((span, found)) = gc.take_until_one_found(DOCTYPE_VALUE_END, true) {
                 byte = span[span.len() - 1];
+                // The value is complete. Don't take it again
+                // if the rest of the doctype is in the next write
+                if found {
+                    self.state = State::DoctypeEntity;
+                }
             }
             let markup_decl = self.markup_decl.as_mut().unwrap();
             markup_decl.header.1 = gc.cursor;
@@ -793,11 +1262,15 @@ impl<'a> SAXParser<'a> {
         if byte == b'>' {
             let mut markup_decl = Box::new(self.markup_decl.take().unwrap());
             markup_decl.end = [gc.line, gc.character];
-            if self.events[Event::Doctype] && markup_decl.hydrate(self.source_ptr) {
-                markup_decl.value.truncate(markup_decl.value.len() - 1); // remove '>' or '['
+            markup_decl.byte_range.1 = self.chunk_offset + gc.cursor as u64;
+            if self.wants(Event::Doctype) && markup_decl.hydrate(self.source_ptr) {
+                // remove '>', '[' or the whitespace after the value
+                // unless a write ended right after the value
+                if markup_decl.value.last().is_some_and(|b| DOCTYPE_VALUE_END.contains(b) || *b == b'[') {
+                    markup_decl.value.pop();
+                }
 
-                self.event_handler.handle_event(Event::Doctype, Entity::Text(&markup_decl));
-                self.dispatched.push(Dispatched::Text(markup_decl));
+                self.dispatch(Event::Doctype, Dispatched::Text(markup_decl));
             }
             self.state = State::BeginWhitespace;
         }
@@ -806,34 +1279,61 @@ impl<'a> SAXParser<'a> {
     fn entity(&mut self, gc: &mut GraphemeClusters, current: &[u8]) {
         let mut byte = current[0];
 
-        if byte != b'>' {
-            if let Some((span, _)) = gc.take_until(b'>', true) {
+        // Quoted literals may contain '>' e.g. <!ENTITY arrow "->">
+        if self.quote != 0 {
+            if byte != self.quote {
+                if let Some((span, _)) = gc.take_until(self.quote, true) {
+                    byte = span[span.len() - 1];
+                }
+            }
+            if byte == self.quote {
+                self.quote = 0;
+            }
+        } else if byte == b'"' || byte == b'\'' {
+            self.quote = byte;
+        } else if byte != b'>' {
+            if let Some((span, _)) = gc.take_until_one_found(ENTITY_END, true) {
                 byte = span[span.len() - 1];
+                if byte == b'"' || byte == b'\'' {
+                    self.quote = byte;
+                }
             }
         }
 
-        if byte == b'>' {
-            let mut markup_entity = Box::new(self.markup_entity.take().unwrap());
-            markup_entity.header.1 = gc.cursor.saturating_sub(1);
-            markup_entity.byte_range.1 = (self.chunk_offset + gc.cursor as u64).saturating_sub(1);
+        let markup_entity = self.markup_entity.as_mut().unwrap();
+        markup_entity.header.1 = gc.cursor;
+        if self.quote != 0 || byte != b'>' {
+            return;
+        }
+
+        let mut markup_entity = Box::new(self.markup_entity.take().unwrap());
+        markup_entity.header.1 = gc.cursor.saturating_sub(1);
+        markup_entity.byte_range.1 = (self.chunk_offset + gc.cursor as u64).saturating_sub(1);
 
-            markup_entity.end = [gc.line, gc.character.saturating_sub(1)];
+        markup_entity.end = [gc.line, gc.character.saturating_sub(1)];
 
-            if self.events[Event::Declaration] && markup_entity.hydrate(self.source_ptr) {
-                self.event_handler.handle_event(Event::Cdata, Entity::Text(&markup_entity));
-                self.dispatched.push(Dispatched::Text(markup_entity));
+        // if we have a markup_decl, we're inside
+        // the internal subset of a doctype and
+        // need to collect the declared entities
+        let in_doctype = self.markup_decl.is_some();
+        if (in_doctype || self.wants(Event::Declaration)) && markup_entity.hydrate(self.source_ptr) {
+            let declaration = Box::new(Declaration::parse(*markup_entity));
+            if in_doctype {
+                self.entities.declare_entity(&declaration);
+            }
+            if self.wants(Event::Declaration) {
+                self.dispatch(Event::Declaration, Dispatched::Declaration(declaration));
             }
-            // if we have a markup_decl, we previously
-            // were processing a doctype and encountered
-            // entities and now need to complete the doctype
-            self.state = if self.markup_decl.is_some() {
-                State::DoctypeEntity
-            } else {
-                State::BeginWhitespace
-            };
-            gc.skip_whitespace();
-            return;
         }
+        // if we have a markup_decl, we previously
+        // were processing a doctype and encountered
+        // entities and now need to complete the doctype
+        self.state = if in_doctype {
+            State::DoctypeEntity
+        } else {
+            State::BeginWhitespace
+        };
+        gc.skip_whitespace();
     }
 
     fn proc_inst(&mut self, gc: &mut GraphemeClusters, current: &[u8]) {
@@ -857,11 +1357,13 @@ impl<'a> SAXParser<'a> {
             b if b < 33 => {
                 proc_inst.target.header.1 = gc.cursor.saturating_sub(1);
                 proc_inst.target.end = [gc.line, gc.character.saturating_sub(1)];
+                proc_inst.target.byte_range.1 -= 1;
                 // we could have something like this before the content starts:
                 // <?process-div           \n   instruction?>
                 gc.skip_whitespace();
                 proc_inst.content.start = [gc.line, gc.character];
                 proc_inst.content.header = (gc.cursor, 0);
+                proc_inst.content.byte_range.0 = self.chunk_offset + gc.cursor as u64;
                 self.state = State::ProcInstValue;
             }
             _ => {}
@@ -891,17 +1393,42 @@ impl<'a> SAXParser<'a> {
     fn process_proc_inst(&mut self, gc: &mut GraphemeClusters) {
         self.state = State::BeginWhitespace;
         let mut proc_inst = Box::new(self.proc_inst.take().unwrap());
+        let is_xml_decl = proc_inst.target.get_value_slice(self.source_ptr, gc.byte_len) == b"<?xml";
+        let misplaced = is_xml_decl && proc_inst.byte_range.0 != self.xml_decl_offset;
+        let wanted = if is_xml_decl {
+            self.wants(Event::XmlDecl) || self.wants(Event::ProcessingInstruction) || (misplaced && self.events[Event::Error])
+        } else {
+            self.wants(Event::ProcessingInstruction)
+        };
+        if !wanted {
+            return;
+        }
         proc_inst.hydrate(self.source_ptr);
+        proc_inst.end = [gc.line, gc.character];
+        proc_inst.content.end = [gc.line, gc.character.saturating_sub(2)];
+        proc_inst.content.byte_range.1 = proc_inst.content.byte_range.1.saturating_sub(2);
+        proc_inst.byte_range.1 = self.chunk_offset + gc.cursor as u64;
+
+        proc_inst.target.value.drain(..2); // remove '<?'
+        proc_inst.content.value.truncate(proc_inst.content.value.len().saturating_sub(2)); // remove '?>'
+
+        if is_xml_decl {
+            if !misplaced {
+                // Handlers not subscribed to XmlDecl still
+                // receive the declaration as a processing instruction
+                if self.wants(Event::XmlDecl) {
+                    let xml_decl = Box::new(XmlDecl::from_proc_inst(&proc_inst));
+                    self.dispatch(Event::XmlDecl, Dispatched::XmlDecl(xml_decl));
+                    return;
+                }
+            } else {
+                let message = "The XML declaration is only allowed at the very start of the document".to_string();
+                self.report(ErrorCode::MisplacedXmlDecl, message, proc_inst.start, proc_inst.end, proc_inst.byte_range);
+            }
+        }
 
-        if self.events[Event::ProcessingInstruction] {
-            proc_inst.end = [gc.line, gc.character];
-            proc_inst.content.end = [gc.line, gc.character.saturating_sub(2)];
-            proc_inst.byte_range.1 = self.chunk_offset + gc.cursor as u64;
-
-            proc_inst.target.value.drain(..2); // remove '<?'
-            proc_inst.content.value.truncate(proc_inst.content.value.len().saturating_sub(2)); // remove '?>'
-            self.event_handler.handle_event(Event::ProcessingInstruction, Entity::ProcInst(&proc_inst));
-            self.dispatched.push(Dispatched::ProcInst(proc_inst));
+        if self.wants(Event::ProcessingInstruction) {
+            self.dispatch(Event::ProcessingInstruction, Dispatched::ProcInst(proc_inst));
         }
     }
 
@@ -1084,7 +1611,7 @@ impl<'a> SAXParser<'a> {
                 attr_end = found;
             }
             self.attribute.value.header.1 = gc.cursor;
-            self.attribute.value.byte_range.1 = gc.cursor as u64;
+            self.attribute.value.byte_range.1 = self.chunk_offset + gc.cursor as u64;
             self.attribute.value.end = [gc.line, gc.character];
 
             if !attr_end && current[0] != byte {
@@ -1103,125 +1630,605 @@ impl<'a> SAXParser<'a> {
     fn process_attribute(&mut self, gc: &mut GraphemeClusters) {
         let mut attr = mem::replace(&mut self.attribute, Attribute::new());
         attr.byte_range.1 = self.chunk_offset + gc.cursor as u64;
-        if self.events[Event::Attribute] && attr.hydrate(self.source_ptr) {
-            let attr_box = Box::new(attr.clone());
-            self.event_handler.handle_event(Event::Attribute, Entity::Attribute(&attr_box));
-            self.dispatched.push(Dispatched::Attribute(attr_box));
+        self.commit_attribute(attr);
+    }
+
+    fn commit_attribute(&mut self, mut attr: Attribute) {
+        if self.decode_entities && !matches!(attr.attr_type, AttrType::JSX) {
+            attr.hydrate(self.source_ptr);
+            attr.value.decode(&self.entities);
         }
-        // Store them only if we're interested in Open and Close tag events
-        if self.events[Event::OpenTag] || self.events[Event::CloseTag] {
+        if self.strict {
+            attr.hydrate(self.source_ptr);
+            self.check_attribute(&attr);
+        }
+        // In namespace mode the prefix may be declared by a later
+        // attribute so these are dispatched once the tag is open
+        if self.namespaces {
+            attr.hydrate(self.source_ptr);
+            self.tag.attributes.push(attr);
+            return;
+        }
+        if self.wants(Event::Attribute) {
+            if self.borrows_events() {
+                if attr.hydrate_spanning(self.source_ptr) {
+                    self.dispatch_borrowed(Event::Attribute, EntityRef::Attribute(attr.view(self.source())));
+                }
+            } else if attr.hydrate(self.source_ptr) {
+                let attr_box = Box::new(attr.clone());
+                self.dispatch(Event::Attribute, Dispatched::Attribute(attr_box));
+            }
+        }
+        // Store them only if we're interested in Open and Close tag
+        // events or need them to find duplicates in strict mode
+        if self.wants(Event::OpenTag) || self.wants(Event::CloseTag) || self.strict {
             self.tag.attributes.push(attr);
         }
     }
 
+    fn check_attribute(&mut self, attr: &Attribute) {
+        let name = &attr.name;
+        let display = String::from_utf8_lossy(&name.value);
+        let mut errors = Vec::new();
+        if !is_name(&name.value) {
+            errors.push((ErrorCode::InvalidName, format!("Invalid attribute name '{}'", display), name));
+        }
+        if self.tag.attributes.iter().any(|a| a.name.value == name.value) {
+            errors.push((ErrorCode::DuplicateAttribute, format!("Attribute '{}' is repeated", display), name));
+        }
+        match attr.attr_type {
+            AttrType::NoValue => {
+                errors.push((ErrorCode::MissingAttributeValue, format!("Attribute '{}' has no value", display), name));
+            }
+            AttrType::NoQuotes | AttrType::JSX => {
+                let message = format!("Value of attribute '{}' is not quoted", display);
+                errors.push((ErrorCode::UnquotedAttributeValue, message, &attr.value));
+            }
+            _ => {}
+        }
+        for (code, message, text) in errors {
+            self.report(code, message, text.start, text.end, text.byte_range);
+        }
+    }
+
+    fn check_element(&mut self, tag: &mut Tag) {
+        tag.hydrate(self.source_ptr);
+        let start = tag.open_start;
+        let end = [start[0], start[1] + 1 + utf16_len(&tag.name)];
+        let byte_range = (tag.byte_range.0, tag.byte_range.0 + 1 + tag.name.len() as u64);
+        if !is_name(&tag.name) {
+            let message = format!("Invalid tag name '{}'", String::from_utf8_lossy(&tag.name));
+            self.report(ErrorCode::InvalidName, message, start, end, byte_range);
+        }
+        if self.tags.is_empty() {
+            if self.seen_root {
+                let message = "Only one root element is allowed".to_string();
+                self.report(ErrorCode::MultipleRoots, message, start, end, byte_range);
+            }
+            self.seen_root = true;
+        }
+    }
+
     fn process_open_tag(&mut self, self_closing: bool, gc: &mut GraphemeClusters) {
         let mut tag = mem::replace(&mut self.tag, Tag::new([0, 0]));
         tag.self_closing = self_closing;
         tag.open_end = [gc.line, gc.character];
         tag.byte_range.1 = self.chunk_offset + gc.cursor as u64;
+        self.commit_open_tag(tag);
+    }
 
-        if self.events[Event::OpenTag] {
-            tag.hydrate(self.source_ptr);
-            let tag_box = Box::new(tag.clone());
-            self.event_handler.handle_event(Event::OpenTag, Entity::Tag(&tag_box));
-            self.dispatched.push(Dispatched::Tag(tag_box));
+    fn commit_open_tag(&mut self, mut tag: Tag) {
+        let self_closing = tag.self_closing;
+        *unsafe { self.children.last_mut().unwrap_unchecked() } += 1;
+        if let Some(filter) = &mut self.path_filter {
+            // Also when the start tag is cut off by the end of the input
+            filter.start_element(tag.get_name_slice(self.source_ptr));
+        }
+        if self.strict {
+            self.check_element(&mut tag);
+            if !self.wants(Event::OpenTag) && !self.wants(Event::CloseTag) {
+                tag.attributes.clear();
+            }
         }
 
-        if self.events[Event::CloseTag] && self_closing {
-            tag.hydrate(self.source_ptr);
-            let tag_box = Box::new(tag.clone());
-            self.event_handler.handle_event(Event::CloseTag, Entity::Tag(&tag_box));
-            self.dispatched.push(Dispatched::Tag(tag_box));
+        if self.namespaces {
+            self.resolve_namespaces(&mut tag);
+        }
+
+        if self.wants(Event::OpenTag) {
+            self.dispatch_tag(Event::OpenTag, &mut tag);
+        }
+
+        if self.wants(Event::CloseTag) && self_closing {
+            self.dispatch_tag(Event::CloseTag, &mut tag);
         }
 
         if !self_closing {
+            // Open elements are viewed by a `Context` while later chunks are parsed
+            tag.hydrate_spanning(self.source_ptr);
+            if let Some(filter) = &mut self.path_filter {
+                filter.push(tag.get_name_slice(self.source_ptr));
+            }
+            // Open elements hold input offsets so the
+            // decoder does not keep the runs they start in
+            if let Some(decoder) = &self.decoder {
+                tag.map_byte_ranges(&|offset| decoder.input_offset(offset));
+            }
             self.tags.push(tag);
+            self.children.push(0);
+        } else if let Some(filter) = &mut self.path_filter {
+            filter.reselect();
         }
 
         self.state = State::BeginWhitespace;
     }
 
-    fn process_close_tag(&mut self, gc: &mut GraphemeClusters) {
-        self.state = State::BeginWhitespace;
-        let mut close_tag = mem::replace(&mut self.close_tag, Text::new([0, 0]));
-        let close_tag_name = close_tag.get_value_slice(self.source_ptr, gc.byte_len);
-
-        let mut found = false;
-        let mut tag_index = 0;
-
-        for (i, tag) in self.tags.iter_mut().enumerate().rev() {
-            let tag_name = tag.get_name_slice(self.source_ptr);
-            if tag_name == close_tag_name {
-                tag.close_start = self.tag.close_start;
-                tag.close_end = [gc.line, gc.character];
-                tag.byte_range.1 = self.chunk_offset + gc.cursor as u64;
-                found = true;
-                tag_index = i;
-                break;
+    /// Collects the xmlns declarations made on the tag and resolves
+    /// the namespace URI of the tag and its attributes against them
+    /// and the declarations of the open elements.
+    fn resolve_namespaces(&mut self, tag: &mut Tag) {
+        tag.hydrate(self.source_ptr);
+        for attr in &tag.attributes {
+            let name = attr.name.value.as_slice();
+            if name == b"xmlns" {
+                tag.ns_bindings.push((Vec::new(), attr.value.value.clone()));
+            } else if let Some(prefix) = name.strip_prefix(b"xmlns:") {
+                tag.ns_bindings.push((prefix.to_vec(), attr.value.value.clone()));
             }
         }
 
-        // Rare encounter of an </orphan> tag
-        if !found {
-            let text = self.text.get_or_insert(Text::new([0, 0]));
-
-            let mut value = Vec::from("</");
-            value.extend_from_slice(close_tag_name);
-            value.extend_from_slice(&[b'>']);
-
-            text.value = value;
-            text.start = self.tag.close_start;
-            text.header = (0, 0);
+        let mut unbound = Vec::new();
+        let prefix = tag.prefix();
+        match self.lookup_namespace(prefix, &tag.ns_bindings) {
+            Some(uri) => tag.uri = uri.to_vec(),
+            None if !prefix.is_empty() => {
+                let start = tag.open_start;
+                let name_len = tag.name.len() as u64;
+                let end = [start[0], start[1] + 1 + utf16_len(&tag.name)];
+                unbound.push((prefix.to_vec(), start, end, (tag.byte_range.0, tag.byte_range.0 + 1 + name_len)));
+            }
+            None => {}
+        }
 
-            self.flush_text(gc.line, gc.character, 0);
-            self.state = State::BeginWhitespace;
-            return;
+        for i in 0..tag.attributes.len() {
+            let attr = &tag.attributes[i];
+            let prefix = attr.prefix();
+            // Unprefixed attributes are in no namespace
+            let uri = if prefix.is_empty() {
+                if attr.name.value == b"xmlns" {
+                    Some(XMLNS_NAMESPACE)
+                } else {
+                    continue;
+                }
+            } else {
+                self.lookup_namespace(prefix, &tag.ns_bindings)
+            };
+            match uri {
+                Some(uri) => tag.attributes[i].uri = uri.to_vec(),
+                None => unbound.push((prefix.to_vec(), attr.name.start, attr.name.end, attr.name.byte_range)),
+            }
         }
 
-        if !self.events[Event::CloseTag] {
-            self.tags.truncate(tag_index.max(1));
-            return;
+        for (prefix, start, end, byte_range) in unbound {
+            let message = format!("Unbound namespace prefix \'{}\'", String::from_utf8_lossy(&prefix));
+            self.report(ErrorCode::UnboundPrefix, message, start, end, byte_range);
         }
 
-        let mut i = self.tags.len();
-        while i > tag_index {
-            let mut tag = Box::new(unsafe { self.tags.pop().unwrap_unchecked() });
-            tag.hydrate(self.source_ptr);
-            self.event_handler.handle_event(Event::CloseTag, Entity::Tag(&tag));
-            self.dispatched.push(Dispatched::Tag(tag));
-            i -= 1;
+        if self.wants(Event::Attribute) {
+            for attr in &tag.attributes {
+                // Hydrated by now
+                if self.borrows_events() {
+                    self.dispatch_borrowed(Event::Attribute, EntityRef::Attribute(attr.view(&[])));
+                    continue;
+                }
+                let attr_box = Box::new(attr.clone());
+                self.dispatch(Event::Attribute, Dispatched::Attribute(attr_box));
+            }
+        }
+        // Store them only if we're interested in Open and Close tag events
+        if !self.wants(Event::OpenTag) && !self.wants(Event::CloseTag) {
+            tag.attributes.clear();
         }
     }
 
-    fn jsx_attribute_expression(&mut self, gc: &mut GraphemeClusters, current: &[u8]) {
-        if current[0] == b'}' {
-            self.brace_ct -= 1;
-        } else if current[0] == b'{' {
-            self.brace_ct += 1;
+    /// Finds the namespace URI bound to `prefix` starting with the
+    /// `bindings` of the current tag and then walking the open elements.
+    /// An empty prefix looks up the default namespace.
+    fn lookup_namespace<'b>(&'b self, prefix: &[u8], bindings: &'b [(Vec<u8>, Vec<u8>)]) -> Option<&'b [u8]> {
+        match prefix {
+            b"xml" => return Some(XML_NAMESPACE),
+            b"xmlns" => return Some(XMLNS_NAMESPACE),
+            _ => {}
+        }
+        let scopes = std::iter::once(bindings).chain(self.tags.iter().rev().map(|tag| tag.ns_bindings.as_slice()));
+        for scope in scopes {
+            if let Some((_, uri)) = scope.iter().rev().find(|(p, _)| p == prefix) {
+                // xmlns="" removes the default namespace
+                return if uri.is_empty() {
+                    None
+                } else {
+                    Some(uri)
+                };
+            }
         }
+        None
+    }
 
-        if self.brace_ct == 0 {
-            self.attribute.value.end = [gc.line, gc.character.saturating_sub(1)];
-            self.attribute.value.header.1 = gc.last_cursor_pos;
-            self.process_attribute(gc);
-            self.state = State::AttribValueClosed;
+    /// Reports the open tags from `from` to the top of the stack as unclosed.
+    fn report_unclosed(&mut self, from: usize) {
+        if !self.events[Event::Error] {
             return;
         }
-        gc.take_until_one_found(&[b'{', b'}'], false);
+        for i in from..self.tags.len() {
+            let tag = &mut self.tags[i];
+            let message = format!("Tag '{}' was not closed", String::from_utf8_lossy(tag.get_name_slice(self.source_ptr)));
+            let mut error = Box::new(ParseError::new(ErrorCode::UnclosedTag, message));
+            error.start = tag.open_start;
+            error.end = tag.open_end;
+            // Already an input offset, see `commit_open_tag`
+            error.byte_range = tag.byte_range;
+            self.deliver(Event::Error, Dispatched::Error(error));
+        }
     }
 
-    fn new_text(&mut self, line: u64, character: u64, offset: usize) {
-        if self.text.is_none() && (self.events[Event::Text] || self.events[Event::CloseTag]) {
-            let mut text = Text::new([line, character]);
-            text.header = (offset, offset);
-            text.byte_range.0 = self.chunk_offset + offset as u64;
-            self.text = Some(text);
+    /// Reports the tags left open and any construct
+    /// still in progress when the end of the input is reached.
+    fn report_eof(&mut self) {
+        if !self.events[Event::Error] {
+            return;
         }
+        self.report_unclosed(0);
 
-        self.state = State::Text;
+        if let Some((kind, start, byte_start)) = self.unterminated() {
+            let message = format!("Unexpected end of input in {}", kind);
+            self.report(ErrorCode::UnexpectedEof, message, start, self.end_pos, (byte_start, self.chunk_offset));
+        }
     }
-}
-#[derive(Clone, Copy)]
+
+    /// The construct still being parsed, if any, as its
+    /// kind, start position and start byte offset.
+    fn unterminated(&self) -> Option<(&'static str, [u64; 2], u64)> {
+        match self.state {
+            State::Comment => self.markup_decl.as_ref().map(|md| ("comment", md.start, md.byte_range.0)),
+            State::Cdata => self.markup_decl.as_ref().map(|md| ("CDATA section", md.start, md.byte_range.0)),
+            State::MarkupDecl => self.markup_decl.as_ref().map(|md| ("markup declaration", md.start, md.byte_range.0)),
+            State::Doctype | State::DoctypeEntity => self.markup_decl.as_ref().map(|md| ("DOCTYPE", md.start, md.byte_range.0)),
+            State::Entity => match (&self.markup_decl, &self.markup_entity) {
+                (Some(md), _) => Some(("DOCTYPE", md.start, md.byte_range.0)),
+                (None, Some(me)) => Some(("declaration", me.start, me.byte_range.0)),
+                _ => None,
+            },
+            State::ProcInst | State::ProcInstValue => {
+                self.proc_inst.as_ref().map(|pi| ("processing instruction", pi.start, pi.byte_range.0))
+            }
+            State::OpenTag
+            | State::Attrib
+            | State::AttribName
+            | State::AttribNameSawWhite
+            | State::AttribValue
+            | State::AttribValueQuoted
+            | State::AttribValueClosed
+            | State::AttribValueUnquoted
+            | State::OpenTagSlash
+            | State::JSXAttributeExpression => Some(("tag", self.tag.open_start, self.tag.byte_range.0)),
+            State::CloseTag => Some(("close tag", self.tag.close_start, self.close_tag.byte_range.0)),
+            State::LT => Some(("tag", [self.end_pos[0], self.end_pos[1].saturating_sub(1)], self.chunk_offset.saturating_sub(1))),
+            _ => None,
+        }
+    }
+
+    /// Dispatches whatever was parsed of a comment, CDATA section,
+    /// processing instruction or open tag cut off by the end of the input.
+    fn flush_unterminated(&mut self) {
+        let end = self.end_pos;
+        let byte_end = self.chunk_offset;
+        match self.state {
+            State::Comment | State::Cdata => {
+                let event = if self.state == State::Comment {
+                    Event::Comment
+                } else {
+                    Event::Cdata
+                };
+                if !self.wants(event) {
+                    return;
+                }
+                if let Some(markup_decl) = self.markup_decl.take() {
+                    let mut markup_decl = Box::new(markup_decl);
+                    markup_decl.end = end;
+                    markup_decl.byte_range.1 = byte_end;
+                    self.dispatch(event, Dispatched::Text(markup_decl));
+                }
+            }
+            State::ProcInst | State::ProcInstValue => {
+                if !self.wants(Event::ProcessingInstruction) {
+                    return;
+                }
+                if let Some(proc_inst) = self.proc_inst.take() {
+                    let mut proc_inst = Box::new(proc_inst);
+                    proc_inst.end = end;
+                    proc_inst.byte_range.1 = byte_end;
+                    if self.state == State::ProcInst {
+                        proc_inst.target.end = end;
+                    } else {
+                        proc_inst.content.end = end;
+                    }
+                    if proc_inst.target.value.starts_with(b"<?") {
+                        proc_inst.target.value.drain(..2); // remove '<?'
+                    }
+                    self.dispatch(Event::ProcessingInstruction, Dispatched::ProcInst(proc_inst));
+                }
+            }
+            State::OpenTag
+            | State::Attrib
+            | State::AttribName
+            | State::AttribNameSawWhite
+            | State::AttribValue
+            | State::AttribValueQuoted
+            | State::AttribValueClosed
+            | State::AttribValueUnquoted
+            | State::OpenTagSlash
+            | State::JSXAttributeExpression => {
+                // Keep the attribute being written if it has a name
+                let mut attr = mem::replace(&mut self.attribute, Attribute::new());
+                if !attr.name.value.is_empty() {
+                    if attr.name.end == [0, 0] {
+                        attr.name.end = end;
+                        attr.name.byte_range.1 = byte_end;
+                    }
+                    if self.state != State::AttribName && self.state != State::AttribNameSawWhite {
+                        attr.value.end = end;
+                        attr.value.byte_range.1 = byte_end;
+                    }
+                    attr.byte_range.1 = byte_end;
+                    self.commit_attribute(attr);
+                }
+                let mut tag = mem::replace(&mut self.tag, Tag::new([0, 0]));
+                tag.open_end = end;
+                tag.byte_range.1 = byte_end;
+                self.commit_open_tag(tag);
+            }
+            _ => {}
+        }
+    }
+
+    /// Dispatches a CloseTag event for each element still open at
+    /// the end of the input, starting with the innermost.
+    fn close_open_tags(&mut self) {
+        let end = self.end_pos;
+        while let Some(tag) = self.tags.pop() {
+            self.children.pop();
+            if self.wants(Event::CloseTag) {
+                let mut tag = Box::new(tag);
+                tag.implicitly_closed = true;
+                tag.close_start = end;
+                tag.close_end = end;
+                tag.byte_range.1 = self.input_offset(self.chunk_offset);
+                self.deliver(Event::CloseTag, Dispatched::Tag(tag));
+            }
+            if let Some(filter) = &mut self.path_filter {
+                filter.pop();
+            }
+        }
+    }
+
+    /// Holds events for [`next_queued`](Self::next_queued)
+    /// instead of passing them to the event handler.
+    pub(crate) fn queue_events(&mut self) {
+        self.queue.get_or_insert_with(VecDeque::new);
+    }
+
+    /// Takes the oldest event held since [`queue_events`](Self::queue_events).
+    pub(crate) fn next_queued(&mut self) -> Option<(Event, Dispatched)> {
+        self.queue.as_mut()?.pop_front()
+    }
+
+    fn dispatch(&mut self, event: Event, mut dispatched: Dispatched) {
+        if let Some(decoder) = &self.decoder {
+            dispatched.map_byte_ranges(&|offset| decoder.input_offset(offset));
+        }
+        self.deliver(event, dispatched);
+    }
+
+    /// Passes an event whose byte ranges are input offsets to the handler.
+    fn deliver(&mut self, event: Event, dispatched: Dispatched) {
+        if let Some(queue) = &mut self.queue {
+            queue.push_back((event, dispatched));
+            return;
+        }
+        let source = self.source();
+        if let Handler::Context(handler) = &mut self.event_handler {
+            // The tag of these events is not among the open elements
+            let current = match (&dispatched, event) {
+                (Dispatched::Tag(tag), Event::OpenTag | Event::CloseTag) => Some(tag.as_ref()),
+                _ => None,
+            };
+            let (end, byte_offset) = event_end(event, &dispatched);
+            let context = Context::new(&self.tags, current, &self.children, source, end, byte_offset);
+            handler.handle_event(event, dispatched.as_entity(), &context);
+        } else {
+            self.event_handler.handle_event(event, dispatched.as_entity());
+        }
+        // Views handed to a `BorrowedEventHandler` do not outlive the call
+        if !matches!(self.event_handler, Handler::Borrowed(_)) {
+            self.dispatched.push(dispatched);
+        }
+    }
+
+    /// Whether the event is subscribed to and passes the path filter.
+    fn wants(&self, event: Event) -> bool {
+        self.events[event] && self.path_filter.as_ref().is_none_or(PathFilter::selected)
+    }
+
+    /// Whether events are passed to a [`BorrowedEventHandler`] as views of the
+    /// chunk being parsed. Transcoded input is dispatched as owned values since
+    /// their byte ranges are mapped to the input.
+    fn borrows_events(&self) -> bool {
+        matches!(self.event_handler, Handler::Borrowed(_)) && self.decoder.is_none()
+    }
+
+    /// The chunk being parsed, empty outside of `parse`. Not tied
+    /// to `self` so it can be viewed while the handler is called.
+    fn source<'s>(&self) -> &'s [u8] {
+        if self.source_len == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.source_ptr, self.source_len) }
+    }
+
+    fn dispatch_borrowed(&mut self, event: Event, data: EntityRef) {
+        if let Handler::Borrowed(handler) = &mut self.event_handler {
+            handler.handle_event(event, data);
+        }
+    }
+
+    /// Dispatches a copy of the tag, or a view of it when events are borrowed.
+    fn dispatch_tag(&mut self, event: Event, tag: &mut Tag) {
+        if self.borrows_events() {
+            tag.hydrate_spanning(self.source_ptr);
+            self.dispatch_borrowed(event, EntityRef::Tag(tag.view(self.source())));
+        } else {
+            tag.hydrate(self.source_ptr);
+            let tag_box = Box::new(tag.clone());
+            self.dispatch(event, Dispatched::Tag(tag_box));
+        }
+    }
+
+    fn report(&mut self, code: ErrorCode, message: String, start: [u64; 2], end: [u64; 2], byte_range: (u64, u64)) {
+        if !self.events[Event::Error] {
+            return;
+        }
+        let mut error = Box::new(ParseError::new(code, message));
+        error.start = start;
+        error.end = end;
+        error.byte_range = byte_range;
+        self.dispatch(Event::Error, Dispatched::Error(error));
+    }
+
+    fn process_close_tag(&mut self, gc: &mut GraphemeClusters) {
+        self.state = State::BeginWhitespace;
+        let mut close_tag = mem::replace(&mut self.close_tag, Text::new([0, 0]));
+        let close_tag_start = close_tag.byte_range.0;
+        let close_tag_name = close_tag.get_value_slice(self.source_ptr, gc.byte_len);
+
+        let mut found = false;
+        let mut tag_index = 0;
+        let byte_end = self.input_offset(self.chunk_offset + gc.cursor as u64);
+
+        for (i, tag) in self.tags.iter_mut().enumerate().rev() {
+            let tag_name = tag.get_name_slice(self.source_ptr);
+            if tag_name == close_tag_name {
+                tag.close_start = self.tag.close_start;
+                tag.close_end = [gc.line, gc.character];
+                tag.byte_range.1 = byte_end;
+                found = true;
+                tag_index = i;
+                break;
+            }
+        }
+
+        let byte_range = (close_tag_start, self.chunk_offset + gc.cursor as u64);
+        // Rare encounter of an </orphan> tag
+        if !found {
+            if self.events[Event::Error] {
+                let message = format!("Close tag '{}' has no matching open tag", String::from_utf8_lossy(close_tag_name));
+                self.report(ErrorCode::UnmatchedCloseTag, message, self.tag.close_start, [gc.line, gc.character], byte_range);
+            }
+            // Strict mode drops the orphan instead of recovering it as text
+            if self.strict {
+                return;
+            }
+
+            let text = self.text.get_or_insert(Text::new([0, 0]));
+
+            let mut value = Vec::from("</");
+            value.extend_from_slice(close_tag_name);
+            value.extend_from_slice(&[b'>']);
+
+            text.value = value;
+            text.start = self.tag.close_start;
+            text.header = (0, 0);
+
+            self.flush_text(gc.line, gc.character, 0, self.chunk_offset + gc.cursor as u64);
+            self.state = State::BeginWhitespace;
+            return;
+        }
+
+        if self.strict && tag_index + 1 != self.tags.len() {
+            let last = self.tags.len() - 1;
+            let expected = String::from_utf8_lossy(self.tags[last].get_name_slice(self.source_ptr)).into_owned();
+            let message = format!("Expected '</{}>' but found '</{}>'", expected, String::from_utf8_lossy(close_tag_name));
+            self.report(ErrorCode::MismatchedCloseTag, message, self.tag.close_start, [gc.line, gc.character], byte_range);
+        }
+        // Anything opened after the matching tag is closed implicitly
+        self.report_unclosed(tag_index + 1);
+
+        if !self.events[Event::CloseTag] {
+            self.tags.truncate(tag_index);
+            self.children.truncate(tag_index + 1);
+            if let Some(filter) = &mut self.path_filter {
+                filter.truncate(tag_index);
+            }
+            return;
+        }
+
+        let mut i = self.tags.len();
+        while i > tag_index {
+            let mut tag = unsafe { self.tags.pop().unwrap_unchecked() };
+            self.children.pop();
+            // Closed by an ancestor's close tag
+            if i - 1 > tag_index {
+                tag.implicitly_closed = true;
+                tag.close_start = self.tag.close_start;
+                tag.close_end = self.tag.close_start;
+                tag.byte_range.1 = self.input_offset(close_tag_start);
+            }
+            i -= 1;
+            let wanted = self.wants(Event::CloseTag);
+            if let Some(filter) = &mut self.path_filter {
+                filter.pop();
+            }
+            if !wanted {
+                continue;
+            }
+            if self.borrows_events() {
+                tag.hydrate_spanning(self.source_ptr);
+                self.dispatch_borrowed(Event::CloseTag, EntityRef::Tag(tag.view(self.source())));
+            } else {
+                tag.hydrate(self.source_ptr);
+                self.deliver(Event::CloseTag, Dispatched::Tag(Box::new(tag)));
+            }
+        }
+    }
+
+    fn jsx_attribute_expression(&mut self, gc: &mut GraphemeClusters, current: &[u8]) {
+        if current[0] == b'}' {
+            self.brace_ct -= 1;
+        } else if current[0] == b'{' {
+            self.brace_ct += 1;
+        }
+
+        if self.brace_ct == 0 {
+            self.attribute.value.end = [gc.line, gc.character.saturating_sub(1)];
+            self.attribute.value.header.1 = gc.last_cursor_pos;
+            self.process_attribute(gc);
+            self.state = State::AttribValueClosed;
+            return;
+        }
+        gc.take_until_one_found(&[b'{', b'}'], false);
+    }
+
+    fn new_text(&mut self, line: u64, character: u64, offset: usize) {
+        if self.text.is_none() && (self.wants(Event::Text) || self.wants(Event::CloseTag) || self.strict) {
+            let mut text = Text::new([line, character]);
+            text.header = (offset, offset);
+            text.byte_range.0 = self.chunk_offset + offset as u64;
+            self.text = Some(text);
+        }
+
+        self.state = State::Text;
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Event {
     // 1
     Text = 0,
@@ -1243,9 +2250,79 @@ pub enum Event {
     CloseTag = 8,
     // 512
     Cdata = 9,
+    // 1024
+    Error = 10,
+    // 2048
+    XmlDecl = 11,
+}
+
+impl Event {
+    /// The number of events a parser can subscribe to.
+    pub const COUNT: usize = 12;
 }
 
-impl Index<Event> for [bool; 10] {
+/// Which events a parser reports, indexed by [`Event`].
+pub type Events = [bool; Event::COUNT];
+
+/// Subscribes to each event of `list`, such as the `EVENTS` an event
+/// handler of this crate needs, leaving the other subscriptions as they are.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::parser::{subscribe, Event, SAXParser};
+/// use sax_wasm::sax::tree::{TreeBuilder, EVENTS};
+///
+/// let mut builder = TreeBuilder::new();
+/// let mut parser = SAXParser::new_typed(&mut builder);
+/// parser.events[Event::Attribute] = true;
+/// subscribe(&mut parser.events, &EVENTS);
+/// assert!(parser.events[Event::Attribute] && parser.events[Event::OpenTag]);
+/// ```
+pub fn subscribe(events: &mut Events, list: &[Event]) {
+    for &event in list {
+        events[event] = true;
+    }
+}
+
+/// Parses `doc` written in chunks of `chunk_len` bytes with a parser
+/// subscribed to `events`, then drops `event_handler` so whatever its
+/// callback borrowed can be inspected.
+#[cfg(test)]
+pub(crate) fn parse_in_chunks(mut event_handler: impl ContextEventHandler, events: &[Event], doc: &[u8], chunk_len: usize) {
+    let mut parser = SAXParser::new_with_context(&mut event_handler);
+    subscribe(&mut parser.events, events);
+    for chunk in doc.chunks(chunk_len) {
+        parser.write(chunk);
+    }
+    parser.identity();
+}
+
+/// Where the token of an event ends. This is
+/// where the parser is when dispatching it.
+fn event_end(event: Event, dispatched: &Dispatched) -> ([u64; 2], u64) {
+    match dispatched {
+        Dispatched::Tag(tag) => match event {
+            Event::OpenTagStart => {
+                let end = [tag.open_start[0], tag.open_start[1] + 1 + utf16_len(&tag.name)];
+                (end, tag.byte_range.0 + 1 + tag.name.len() as u64)
+            }
+            Event::OpenTag => (tag.open_end, tag.byte_range.1),
+            _ => (tag.close_end, tag.byte_range.1),
+        },
+        Dispatched::Attribute(attribute) => match attribute.attr_type {
+            AttrType::NoValue => (attribute.name.end, attribute.name.byte_range.1),
+            _ => (attribute.value.end, attribute.value.byte_range.1),
+        },
+        Dispatched::Text(text) => (text.end, text.byte_range.1),
+        Dispatched::ProcInst(proc_inst) => (proc_inst.end, proc_inst.byte_range.1),
+        Dispatched::Error(error) => (error.end, error.byte_range.1),
+        Dispatched::Declaration(declaration) => (declaration.text.end, declaration.text.byte_range.1),
+        Dispatched::XmlDecl(xml_decl) => (xml_decl.end, xml_decl.byte_range.1),
+    }
+}
+
+impl Index<Event> for Events {
     type Output = bool;
 
     fn index(&self, event: Event) -> &Self::Output {
@@ -1254,7 +2331,7 @@ impl Index<Event> for [bool; 10] {
     }
 }
 
-impl IndexMut<Event> for [bool; 10] {
+impl IndexMut<Event> for Events {
     fn index_mut(&mut self, event: Event) -> &mut Self::Output {
         unsafe { self.get_unchecked_mut(event as usize) }
     }
@@ -1317,15 +2394,19 @@ mod tests {
     use std::fs::File;
     use std::io::{BufReader, Read, Result};
 
-    use crate::sax::parser::{Event, EventHandler, SAXParser};
+    use crate::sax::dtd::{DeclarationKind, DefaultKind};
+    use crate::sax::parser::{Event, EventHandler, SAXParser, TypedEventHandler};
     use crate::sax::tag::Entity;
 
-    use super::{Attribute, ProcInst, Tag, Text};
+    use super::{Attribute, Declaration, ErrorCode, ParseError, ProcInst, Tag, Text, XmlDecl, XMLNS_NAMESPACE, XML_NAMESPACE};
     pub struct TextEventHandler {
         pub attributes: RefCell<Vec<Attribute>>,
         pub texts: RefCell<Vec<Text>>,
         pub tags: RefCell<Vec<Tag>>,
         pub proc_insts: RefCell<Vec<ProcInst>>,
+        pub errors: RefCell<Vec<ParseError>>,
+        pub declarations: RefCell<Vec<Declaration>>,
+        pub xml_decls: RefCell<Vec<XmlDecl>>,
     }
 
     impl TextEventHandler {
@@ -1335,6 +2416,9 @@ mod tests {
                 texts: RefCell::new(Vec::new()),
                 tags: RefCell::new(Vec::new()),
                 proc_insts: RefCell::new(Vec::new()),
+                errors: RefCell::new(Vec::new()),
+                declarations: RefCell::new(Vec::new()),
+                xml_decls: RefCell::new(Vec::new()),
             }
         }
     }
@@ -1346,6 +2430,9 @@ mod tests {
                 Entity::ProcInst(proc_inst) => self.proc_insts.borrow_mut().push(proc_inst.clone()),
                 Entity::Tag(tag) => self.tags.borrow_mut().push(tag.clone()),
                 Entity::Text(text) => self.texts.borrow_mut().push(text.clone()),
+                Entity::Error(error) => self.errors.borrow_mut().push(error.clone()),
+                Entity::Declaration(declaration) => self.declarations.borrow_mut().push(declaration.clone()),
+                Entity::XmlDecl(xml_decl) => self.xml_decls.borrow_mut().push(xml_decl.clone()),
             }
         }
     }
@@ -1353,7 +2440,7 @@ mod tests {
     fn test_attribute_position() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Attribute] = true;
         events[Event::CloseTag] = true;
         sax.events = events;
@@ -1395,7 +2482,7 @@ mod tests {
     fn test_attribute_position_1() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Attribute] = true;
         events[Event::CloseTag] = true;
         sax.events = events;
@@ -1433,7 +2520,7 @@ mod tests {
     fn test_attribute_position_2() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Attribute] = true;
         events[Event::CloseTag] = true;
         events[Event::Text] = true;
@@ -1458,7 +2545,7 @@ mod tests {
     fn test_attribute() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Attribute] = true;
         events[Event::CloseTag] = true;
         events[Event::Text] = true;
@@ -1518,7 +2605,7 @@ mod tests {
     fn test_attribute_single_character_boolean() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Attribute] = true;
         events[Event::CloseTag] = true;
         events[Event::Text] = true;
@@ -1545,7 +2632,7 @@ mod tests {
     fn test_attribute_unquoted() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Attribute] = true;
         events[Event::CloseTag] = true;
         events[Event::Text] = true;
@@ -1570,7 +2657,7 @@ mod tests {
     fn test_attribute_single_character() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Attribute] = true;
         events[Event::CloseTag] = true;
         events[Event::Text] = true;
@@ -1600,7 +2687,7 @@ mod tests {
     fn test_empty_tag() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::CloseTag] = true;
         events[Event::Text] = true;
         sax.events = events;
@@ -1631,7 +2718,7 @@ mod tests {
     fn test_tag() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::CloseTag] = true;
         events[Event::Text] = true;
         sax.events = events;
@@ -1667,7 +2754,7 @@ mod tests {
         for i in 1..bytes.len() {
             let event_handler = TextEventHandler::new();
             let mut sax = SAXParser::new(&event_handler);
-            let mut events = [false; 10];
+            let mut events = [false; Event::COUNT];
             events[Event::CloseTag] = true;
             events[Event::Text] = true;
             events[Event::Attribute] = true;
@@ -1714,7 +2801,7 @@ mod tests {
     fn test_whitespace() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::CloseTag] = true;
         events[Event::Text] = true;
         sax.events = events;
@@ -1742,7 +2829,7 @@ the plugin
     fn test_comment() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Comment] = true;
         events[Event::Text] = true;
         sax.events = events;
@@ -1770,7 +2857,7 @@ the plugin
 
     #[test]
     fn test_comment_write_boundary_2() -> Result<()> {
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Comment] = true;
         let str = r#"<!--lit-part cI7PGs8mxHY=-->
         <p><!--lit-part-->hello<!--/lit-part--></p>
@@ -1805,7 +2892,7 @@ the plugin
     fn stream_large_xml() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        sax.events = [true; 10];
+        sax.events = [true; Event::COUNT];
         let f = File::open("src/js/__test__/xml.xml")?;
         let mut reader = BufReader::new(f);
         const BUFFER_LEN: usize = 64 * 1024;
@@ -1825,7 +2912,7 @@ the plugin
     fn test_4_bytes() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Text] = true;
         sax.events = events;
         let str = "🏴📚📚🏴📚📚🏴📚📚🏴📚📚🏴📚📚🏴📚📚🏴📚📚🏴📚📚🏴📚📚🏴📚📚";
@@ -1848,7 +2935,7 @@ the plugin
     fn test_cdata_write_boundary() -> Result<()> {
         let str = "<div><![CDATA[something]]>";
         let bytes = str.as_bytes();
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Cdata] = true;
         for i in 1..bytes.len() {
             let event_handler = TextEventHandler::new();
@@ -1878,7 +2965,7 @@ the plugin
     fn count_grapheme_length() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Text] = true;
         sax.events = events;
         let str = "🏴📚📚<div href=\"./123/123\">hey there</div>";
@@ -1898,7 +2985,7 @@ the plugin
     fn parse_jsx_expression() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Text] = true;
         events[Event::CloseTag] = true;
         sax.events = events;
@@ -1925,7 +3012,7 @@ the plugin
     fn test_doctype() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Doctype] = true;
         events[Event::Declaration] = true;
         sax.events = events;
@@ -1943,11 +3030,32 @@ the plugin
         sax.identity();
 
         let doctypes = event_handler.texts.borrow();
-        assert_eq!(doctypes.len(), 8);
-        assert_eq!(doctypes[0].value, r#"ENTITY COM "Comedy""#.as_bytes());
-        assert_eq!(doctypes[1].value, r#"LIST title xml:lang TOKEN "EN" id ID #IMPLIED"#.as_bytes());
-        assert_eq!(doctypes[7].value, b"movie");
-
+        assert_eq!(doctypes.len(), 1);
+        assert_eq!(doctypes[0].value, b"movie");
+
+        let declarations = event_handler.declarations.borrow();
+        assert_eq!(declarations.len(), 7);
+        assert_eq!(declarations[0].text.value, r#"ENTITY COM "Comedy""#.as_bytes());
+        assert_eq!(declarations[1].text.value, r#"LIST title xml:lang TOKEN "EN" id ID #IMPLIED"#.as_bytes());
+        assert_eq!(declarations[1].kind, DeclarationKind::Other);
+        assert_eq!(declarations[3].kind, DeclarationKind::Element);
+        assert_eq!(declarations[3].content.value, b"(title+,genre,year)");
+
+        for str in ["<!DOCTYPE  movie SYSTEM \"movie.dtd\"><movie/>", "<!DOCTYPE movie><movie/>"] {
+            let end = str.find("<movie").unwrap() as u64;
+            for i in 1..str.len() {
+                let event_handler = TextEventHandler::new();
+                let mut sax = SAXParser::new(&event_handler);
+                sax.events[Event::Doctype] = true;
+                sax.write(&str.as_bytes()[..i]);
+                sax.write(&str.as_bytes()[i..]);
+                sax.identity();
+
+                let doctypes = event_handler.texts.borrow();
+                assert_eq!(doctypes[0].value, b"movie", "At iteration i={}", i);
+                assert_eq!(doctypes[0].byte_range, (0, end), "At iteration i={}", i);
+            }
+        }
         Ok(())
     }
 
@@ -1955,7 +3063,7 @@ the plugin
     fn test_empty_cdata() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Cdata] = true;
         sax.events = events;
         let str = "<div>
@@ -1982,7 +3090,7 @@ the plugin
     fn test_proc_inst() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::ProcessingInstruction] = true;
         sax.events = events;
         let str = r#"<?xml-stylesheet
@@ -2008,7 +3116,7 @@ the plugin
     fn test_jsx() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::CloseTag] = true;
         sax.events = events;
         let str = r#"
@@ -2040,7 +3148,7 @@ the plugin
     fn test_self_closing_tag() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::CloseTag] = true;
         sax.events = events;
         let str = r#"
@@ -2077,7 +3185,7 @@ the plugin
     fn test_comment_write_boundary() -> Result<()> {
         let str = r#"<!--some comment here-->"#;
         let bytes = str.as_bytes();
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Comment] = true;
 
         for i in 1..bytes.len() {
@@ -2111,7 +3219,7 @@ the plugin
         for i in 1..bytes.len() {
             let event_handler = TextEventHandler::new();
             let mut sax = SAXParser::new(&event_handler);
-            let mut events = [false; 10];
+            let mut events = [false; Event::COUNT];
             events[Event::Attribute] = true;
             sax.events = events;
 
@@ -2148,7 +3256,7 @@ the plugin
     fn test_script_tag_unquoted_attribute() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Attribute] = true;
         events[Event::CloseTag] = true;
         sax.events = events;
@@ -2189,7 +3297,7 @@ the plugin
     fn test_attribute_no_whitespace_between() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Attribute] = true;
         events[Event::CloseTag] = true;
         sax.events = events;
@@ -2217,7 +3325,7 @@ the plugin
     fn test_attribute_position_no_value_attr() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Attribute] = true;
         events[Event::CloseTag] = true;
         sax.events = events;
@@ -2260,7 +3368,7 @@ the plugin
     fn test_attribute_position_no_value_attr1() -> Result<()> {
         let event_handler = TextEventHandler::new();
         let mut sax = SAXParser::new(&event_handler);
-        let mut events = [false; 10];
+        let mut events = [false; Event::COUNT];
         events[Event::Attribute] = true;
         events[Event::CloseTag] = true;
         sax.events = events;
@@ -2285,4 +3393,710 @@ the plugin
 
         Ok(())
     }
+
+    #[test]
+    fn test_decode_entities() -> Result<()> {
+        let str = r#"<p title="a &amp; b &#x1F600;">1 &lt; 2 &#38;&#38; AT&T &unknown;<![CDATA[&amp;]]>&gt;</p>"#;
+        let bytes = str.as_bytes();
+
+        for i in 1..bytes.len() {
+            let event_handler = TextEventHandler::new();
+            let mut sax = SAXParser::new(&event_handler);
+            let mut events = [false; Event::COUNT];
+            events[Event::Attribute] = true;
+            events[Event::Text] = true;
+            events[Event::Cdata] = true;
+            sax.events = events;
+            sax.decode_entities = true;
+
+            sax.write(&bytes[..i]);
+            sax.write(&bytes[i..]);
+            sax.identity();
+
+            let attrs = event_handler.attributes.borrow();
+            assert_eq!(attrs[0].value.value, "a & b 😀".as_bytes(), "At iteration i={}", i);
+            assert_eq!(attrs[0].value.raw, b"a &amp; b &#x1F600;", "At iteration i={}", i);
+            assert_eq!(attrs[0].value.byte_range, (10, 29), "At iteration i={}", i);
+
+            let texts = event_handler.texts.borrow();
+            assert_eq!(texts.len(), 3, "At iteration i={}", i);
+            assert_eq!(texts[0].value, b"1 < 2 && AT&T &unknown;", "At iteration i={}", i);
+            assert_eq!(texts[0].raw_value(), b"1 &lt; 2 &#38;&#38; AT&T &unknown;", "At iteration i={}", i);
+            assert_eq!(texts[1].value, b"&amp;", "At iteration i={}", i);
+            assert_eq!(texts[2].value, b">", "At iteration i={}", i);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_preserve_whitespace() -> Result<()> {
+        let str = "<a>\n  <b/>\n  two\r\n  lines \n</a>";
+        let bytes = str.as_bytes();
+
+        for i in 1..bytes.len() {
+            let event_handler = TextEventHandler::new();
+            let mut sax = SAXParser::new(&event_handler);
+            let mut events = [false; Event::COUNT];
+            events[Event::Text] = true;
+            sax.events = events;
+            sax.preserve_whitespace = true;
+
+            sax.write(&bytes[..i]);
+            sax.write(&bytes[i..]);
+            sax.identity();
+
+            let texts = event_handler.texts.borrow();
+            assert_eq!(texts.len(), 2, "At iteration i={}", i);
+            assert_eq!(texts[0].value, b"\n  ", "At iteration i={}", i);
+            assert_eq!(texts[0].byte_range, (3, 6), "At iteration i={}", i);
+            assert_eq!(texts[0].start, [0, 3], "At iteration i={}", i);
+            assert_eq!(texts[1].value, b"\n  two\r\n  lines \n", "At iteration i={}", i);
+            assert_eq!(texts[1].start, [1, 6], "At iteration i={}", i);
+            assert_eq!(texts[1].end, [4, 0], "At iteration i={}", i);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_entities_skips_jsx() -> Result<()> {
+        let event_handler = TextEventHandler::new();
+        let mut sax = SAXParser::new(&event_handler);
+        let mut events = [false; Event::COUNT];
+        events[Event::Attribute] = true;
+        events[Event::CloseTag] = true;
+        sax.events = events;
+        sax.decode_entities = true;
+
+        sax.write(br#"<Foo a='&lt;' b={x &amp;&amp; y}>&#65;</Foo>"#);
+        sax.identity();
+
+        let attrs = event_handler.attributes.borrow();
+        assert_eq!(attrs[0].value.value, b"<");
+        assert_eq!(attrs[1].value.value, b"x &amp;&amp; y");
+        assert!(attrs[1].value.raw.is_empty());
+
+        let tags = event_handler.tags.borrow();
+        assert_eq!(tags[0].attributes[0].value.value, b"<");
+        assert_eq!(tags[0].text_nodes[0].value, b"A");
+        assert_eq!(tags[0].text_nodes[0].raw, b"&#65;");
+        Ok(())
+    }
+
+    #[test]
+    fn test_doctype_entity_expansion() -> Result<()> {
+        let str = r#"<!DOCTYPE note [
+  <!ENTITY % who "World">
+  <!ENTITY greeting "Hello, %who;!">
+  <!ENTITY arrow '-->'>
+  <!ENTITY loop "&loop;">
+]>
+<note title="&greeting;">&greeting; &arrow; &loop; &missing;</note>"#;
+        let bytes = str.as_bytes();
+
+        for i in 1..bytes.len() {
+            let event_handler = TextEventHandler::new();
+            let mut sax = SAXParser::new(&event_handler);
+            let mut events = [false; Event::COUNT];
+            events[Event::Declaration] = true;
+            events[Event::Attribute] = true;
+            events[Event::Text] = true;
+            sax.events = events;
+            sax.decode_entities = true;
+
+            sax.write(&bytes[..i]);
+            sax.write(&bytes[i..]);
+            sax.identity();
+
+            let declarations = event_handler.declarations.borrow();
+            assert_eq!(declarations.len(), 4, "At iteration i={}", i);
+            assert_eq!(declarations[2].text.value, br#"ENTITY arrow '-->'"#, "At iteration i={}", i);
+            assert_eq!(declarations[2].content.value, b"-->", "At iteration i={}", i);
+
+            let texts = event_handler.texts.borrow();
+            assert_eq!(texts.len(), 1, "At iteration i={}", i);
+            assert_eq!(texts[0].value, b"Hello, World! --> &loop; &missing;", "At iteration i={}", i);
+            assert_eq!(texts[0].raw, b"&greeting; &arrow; &loop; &missing;", "At iteration i={}", i);
+
+            let attrs = event_handler.attributes.borrow();
+            assert_eq!(attrs[0].value.value, b"Hello, World!", "At iteration i={}", i);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_declarations() -> Result<()> {
+        let str = r#"<!DOCTYPE doc [
+  <!ELEMENT doc (head, body?)>
+  <!ATTLIST doc lang NMTOKEN #FIXED "en" id ID #REQUIRED>
+  <!ENTITY % shared SYSTEM "shared.ent">
+  <!NOTATION gif PUBLIC "image/gif">
+]>
+<!ELEMENT x (a|b)*>"#;
+        let bytes = str.as_bytes();
+
+        for i in 1..bytes.len() {
+            let event_handler = TextEventHandler::new();
+            let mut sax = SAXParser::new(&event_handler);
+            let mut events = [false; Event::COUNT];
+            events[Event::Declaration] = true;
+            sax.events = events;
+
+            sax.write(&bytes[..i]);
+            sax.write(&bytes[i..]);
+            sax.identity();
+
+            let declarations = event_handler.declarations.borrow();
+            assert_eq!(declarations.len(), 5, "At iteration i={}", i);
+
+            let element = &declarations[0];
+            assert_eq!(element.kind, DeclarationKind::Element, "At iteration i={}", i);
+            assert_eq!(element.text.start, [1, 4], "At iteration i={}", i);
+            assert_eq!(element.name.value, b"doc", "At iteration i={}", i);
+            assert_eq!(element.content.value, b"(head, body?)", "At iteration i={}", i);
+            assert_eq!(element.content.start, [1, 16], "At iteration i={}", i);
+            assert_eq!(element.content.byte_range, (32, 45), "At iteration i={}", i);
+
+            let attlist = &declarations[1];
+            assert_eq!(attlist.kind, DeclarationKind::Attlist, "At iteration i={}", i);
+            assert_eq!(attlist.attributes.len(), 2, "At iteration i={}", i);
+            assert_eq!(attlist.attributes[0].default_kind, DefaultKind::Fixed, "At iteration i={}", i);
+            assert_eq!(attlist.attributes[0].default_value.value, b"en", "At iteration i={}", i);
+            assert_eq!(attlist.attributes[1].att_type.value, b"ID", "At iteration i={}", i);
+            assert_eq!(attlist.attributes[1].default_kind, DefaultKind::Required, "At iteration i={}", i);
+
+            let entity = &declarations[2];
+            assert!(entity.parameter, "At iteration i={}", i);
+            assert_eq!(entity.name.value, b"shared", "At iteration i={}", i);
+            assert_eq!(entity.system_id.value, b"shared.ent", "At iteration i={}", i);
+
+            let notation = &declarations[3];
+            assert_eq!(notation.kind, DeclarationKind::Notation, "At iteration i={}", i);
+            assert_eq!(notation.public_id.value, b"image/gif", "At iteration i={}", i);
+
+            let outside = &declarations[4];
+            assert_eq!(outside.text.value, b"ELEMENT x (a|b)*", "At iteration i={}", i);
+            assert_eq!(outside.text.start, [6, 2], "At iteration i={}", i);
+            assert_eq!(outside.text.end, [6, 18], "At iteration i={}", i);
+            assert_eq!(outside.name.start, [6, 10], "At iteration i={}", i);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_namespaces() -> Result<()> {
+        let str = r#"<root xmlns="urn:default" xmlns:svg="http://www.w3.org/2000/svg"><svg:rect svg:x="1" y="2" xml:lang="en"/><plain xmlns=""><x:a x:b="c" xmlns:x="urn:x"/></plain><y:bad/></root>"#;
+        let bytes = str.as_bytes();
+
+        for i in 1..bytes.len() {
+            let event_handler = TextEventHandler::new();
+            let mut sax = SAXParser::new(&event_handler);
+            let mut events = [false; Event::COUNT];
+            events[Event::OpenTag] = true;
+            events[Event::Error] = true;
+            sax.events = events;
+            sax.namespaces = true;
+
+            sax.write(&bytes[..i]);
+            sax.write(&bytes[i..]);
+            sax.identity();
+
+            let tags = event_handler.tags.borrow();
+            assert_eq!(tags.len(), 5, "At iteration i={}", i);
+            assert_eq!(tags[0].uri, b"urn:default", "At iteration i={}", i);
+            assert_eq!(tags[0].ns_bindings.len(), 2, "At iteration i={}", i);
+            assert_eq!(tags[0].attributes[0].uri, XMLNS_NAMESPACE, "At iteration i={}", i);
+
+            let rect = &tags[1];
+            assert_eq!(rect.prefix(), b"svg", "At iteration i={}", i);
+            assert_eq!(rect.local_name(), b"rect", "At iteration i={}", i);
+            assert_eq!(rect.uri, b"http://www.w3.org/2000/svg", "At iteration i={}", i);
+            assert_eq!(rect.attributes[0].local_name(), b"x", "At iteration i={}", i);
+            assert_eq!(rect.attributes[0].uri, b"http://www.w3.org/2000/svg", "At iteration i={}", i);
+            // Unprefixed attributes are not in the default namespace
+            assert!(rect.attributes[1].uri.is_empty(), "At iteration i={}", i);
+            assert_eq!(rect.attributes[2].uri, XML_NAMESPACE, "At iteration i={}", i);
+
+            // xmlns="" undeclares the default namespace
+            assert!(tags[2].uri.is_empty(), "At iteration i={}", i);
+            // Declared after use on the same element
+            assert_eq!(tags[3].uri, b"urn:x", "At iteration i={}", i);
+            assert_eq!(tags[3].attributes[0].uri, b"urn:x", "At iteration i={}", i);
+
+            assert!(tags[4].uri.is_empty(), "At iteration i={}", i);
+            let errors = event_handler.errors.borrow();
+            assert_eq!(errors.len(), 1, "At iteration i={}", i);
+            assert_eq!(errors[0].code, ErrorCode::UnboundPrefix, "At iteration i={}", i);
+            assert_eq!(errors[0].message, b"Unbound namespace prefix 'y'", "At iteration i={}", i);
+            let offset = str.find("<y:bad").unwrap() as u64;
+            assert_eq!(errors[0].start, [0, offset], "At iteration i={}", i);
+            assert_eq!(errors[0].end, [0, offset + 6], "At iteration i={}", i);
+            assert_eq!(errors[0].byte_range, (offset, offset + 6), "At iteration i={}", i);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_grapheme_byte_range() -> Result<()> {
+        let str = "<a>é🐉</a><b x=\"ü\"/>";
+        let bytes = str.as_bytes();
+        let at = |s: &str| str.find(s).unwrap() as u64;
+
+        // Every split, including those inside a multi-byte grapheme
+        for i in 1..bytes.len() {
+            let event_handler = TextEventHandler::new();
+            let mut sax = SAXParser::new(&event_handler);
+            let mut events = [false; Event::COUNT];
+            events[Event::Attribute] = true;
+            events[Event::CloseTag] = true;
+            sax.events = events;
+
+            sax.write(&bytes[..i]);
+            sax.write(&bytes[i..]);
+            sax.identity();
+
+            let attrs = event_handler.attributes.borrow();
+            assert_eq!(attrs[0].value.byte_range, (at("ü"), at("\"/>")), "At iteration i={}", i);
+            assert_eq!(attrs[0].byte_range, (at("x="), at("/>")), "At iteration i={}", i);
+
+            let tags = event_handler.tags.borrow();
+            assert_eq!(tags[0].byte_range, (0, at("<b")), "At iteration i={}", i);
+            assert_eq!(tags[1].byte_range, (at("<b"), bytes.len() as u64), "At iteration i={}", i);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_xml_decl() -> Result<()> {
+        let str = "\u{feff}<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<root><?xml version=\"1.1\"?></root>";
+        let bytes = str.as_bytes();
+
+        for i in 1..bytes.len() {
+            let event_handler = TextEventHandler::new();
+            let mut sax = SAXParser::new(&event_handler);
+            let mut events = [false; Event::COUNT];
+            events[Event::XmlDecl] = true;
+            events[Event::ProcessingInstruction] = true;
+            events[Event::Error] = true;
+            sax.events = events;
+
+            sax.write(&bytes[..i]);
+            sax.write(&bytes[i..]);
+            sax.identity();
+
+            let xml_decls = event_handler.xml_decls.borrow();
+            assert_eq!(xml_decls.len(), 1, "At iteration i={}", i);
+            assert_eq!(xml_decls[0].version.value, b"1.0", "At iteration i={}", i);
+            assert_eq!(xml_decls[0].version.byte_range, (18, 21), "At iteration i={}", i);
+            assert_eq!(xml_decls[0].encoding.value, b"UTF-8", "At iteration i={}", i);
+            assert_eq!(xml_decls[0].encoding.byte_range, (33, 38), "At iteration i={}", i);
+            assert_eq!(xml_decls[0].standalone.value, b"yes", "At iteration i={}", i);
+            assert_eq!(xml_decls[0].standalone.byte_range, (52, 55), "At iteration i={}", i);
+            assert_eq!(xml_decls[0].byte_range, (3, 58), "At iteration i={}", i);
+
+            // the misplaced declaration is reported and
+            // delivered as a plain processing instruction
+            let errors = event_handler.errors.borrow();
+            assert_eq!(errors.len(), 1, "At iteration i={}", i);
+            assert_eq!(errors[0].code, ErrorCode::MisplacedXmlDecl, "At iteration i={}", i);
+            assert_eq!(errors[0].start, [1, 6], "At iteration i={}", i);
+            assert_eq!(errors[0].end, [1, 27], "At iteration i={}", i);
+
+            let proc_insts = event_handler.proc_insts.borrow();
+            assert_eq!(proc_insts.len(), 1, "At iteration i={}", i);
+            assert_eq!(proc_insts[0].content.value, br#"version="1.1""#, "At iteration i={}", i);
+        }
+
+        let event_handler = TextEventHandler::new();
+        let mut sax = SAXParser::new(&event_handler);
+        sax.events[Event::XmlDecl] = true;
+        sax.write(br#"<?xml version = '1.0'?><root/>"#);
+        sax.identity();
+        let xml_decls = event_handler.xml_decls.borrow();
+        assert_eq!(xml_decls[0].start, [0, 0]);
+        assert_eq!(xml_decls[0].version.value, b"1.0");
+        assert_eq!(xml_decls[0].version.start, [0, 17]);
+        assert_eq!(xml_decls[0].version.end, [0, 20]);
+        assert!(xml_decls[0].encoding.value.is_empty());
+
+        // Without an XmlDecl subscriber the declaration
+        // is delivered as a processing instruction
+        let event_handler = TextEventHandler::new();
+        let mut sax = SAXParser::new(&event_handler);
+        sax.events[Event::ProcessingInstruction] = true;
+        sax.events[Event::Error] = true;
+        sax.write(br#"<?xml version="1.0" encoding="utf-8"?><root/>"#);
+        sax.identity();
+        let proc_insts = event_handler.proc_insts.borrow();
+        assert_eq!(proc_insts.len(), 1);
+        assert_eq!(proc_insts[0].target.value, b"xml");
+        assert_eq!(proc_insts[0].content.value, br#"version="1.0" encoding="utf-8""#);
+        assert_eq!(proc_insts[0].byte_range, (0, 38));
+        assert!(event_handler.errors.borrow().is_empty());
+
+        // Only a misplaced declaration is of interest to error subscribers
+        let event_handler = TextEventHandler::new();
+        let mut sax = SAXParser::new(&event_handler);
+        sax.events[Event::Error] = true;
+        sax.write(br#"<?xml version="1.0"?><root><?xml version="1.0"?></root>"#);
+        sax.identity();
+        let errors = event_handler.errors.borrow();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ErrorCode::MisplacedXmlDecl);
+        assert_eq!(errors[0].byte_range, (27, 48));
+        Ok(())
+    }
+
+    #[test]
+    fn test_transcoding() -> Result<()> {
+        let mut cp1252 = br#"<?xml version="1.0" encoding="windows-1252"?><a b="caf"#.to_vec();
+        cp1252.extend_from_slice(b"\xE9\">\x80 &amp;</a>");
+        let str = "<root a=\"\u{e9}\u{1F600}\">x</root>";
+        let mut utf16 = vec![0xFF, 0xFE];
+        utf16.extend(str.encode_utf16().flat_map(u16::to_le_bytes));
+
+        let cases = [
+            (cp1252.as_slice(), "caf\u{e9}", (51, 55), (57, 64), (45, 68)),
+            (utf16.as_slice(), "\u{e9}\u{1F600}", (20, 26), (30, 32), (2, 46)),
+        ];
+        for (bytes, value, value_range, text_range, tag_range) in cases {
+            for i in 1..bytes.len() {
+                let event_handler = TextEventHandler::new();
+                let mut sax = SAXParser::new(&event_handler);
+                let mut events = [false; Event::COUNT];
+                events[Event::Attribute] = true;
+                events[Event::Text] = true;
+                events[Event::CloseTag] = true;
+                sax.events = events;
+
+                sax.write(&bytes[..i]);
+                sax.write(&bytes[i..]);
+                sax.identity();
+
+                let attrs = event_handler.attributes.borrow();
+                assert_eq!(attrs[0].value.value, value.as_bytes(), "At iteration i={}", i);
+                assert_eq!(attrs[0].value.byte_range, value_range, "At iteration i={}", i);
+
+                let texts = event_handler.texts.borrow();
+                assert_eq!(texts[0].byte_range, text_range, "At iteration i={}", i);
+
+                let tags = event_handler.tags.borrow();
+                assert_eq!(tags[0].byte_range, tag_range, "At iteration i={}", i);
+            }
+        }
+
+        // A code unit cut off at the EOF becomes U+FFFD
+        let mut truncated = vec![0xFF, 0xFE];
+        truncated.extend("<a>xy".encode_utf16().flat_map(u16::to_le_bytes));
+        truncated.pop();
+        let event_handler = TextEventHandler::new();
+        let mut sax = SAXParser::new(&event_handler);
+        sax.events[Event::Text] = true;
+        sax.write(&truncated);
+        sax.identity();
+        let texts = event_handler.texts.borrow();
+        assert_eq!(texts[0].value, "x\u{FFFD}".as_bytes());
+        assert_eq!(texts[0].byte_range, (8, 11));
+        Ok(())
+    }
+
+    #[test]
+    fn test_transcoding_discards_runs() -> Result<()> {
+        // 'é' and '€' each start a run of their own UTF-8 width
+        let item = "<item a=\"é\">x€y</item>";
+        let str = format!("<root>{}</root>", item.repeat(500));
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(str.encode_utf16().flat_map(u16::to_le_bytes));
+
+        let event_handler = TextEventHandler::new();
+        let mut sax = SAXParser::new(&event_handler);
+        let mut events = [false; Event::COUNT];
+        events[Event::Attribute] = true;
+        events[Event::Text] = true;
+        events[Event::CloseTag] = true;
+        sax.events = events;
+
+        let mut max_runs = 0;
+        for chunk in bytes.chunks(7) {
+            sax.write(chunk);
+            if let Some(decoder) = &sax.decoder {
+                max_runs = max_runs.max(decoder.runs.len());
+            }
+        }
+        sax.identity();
+        assert!(max_runs < 10, "{} runs kept", max_runs);
+
+        // Offsets are still mapped to the UTF-16 input
+        let item_len = 2 * item.encode_utf16().count() as u64;
+        let tags = event_handler.tags.borrow();
+        assert_eq!(tags.len(), 501);
+        assert_eq!(tags[499].byte_range, (14 + 499 * item_len, 14 + 500 * item_len));
+        assert_eq!(tags[500].byte_range, (2, bytes.len() as u64));
+        let texts = event_handler.texts.borrow();
+        assert_eq!(texts[499].byte_range, (14 + 499 * item_len + 24, 14 + 499 * item_len + 30));
+        let attrs = event_handler.attributes.borrow();
+        assert_eq!(attrs[499].value.byte_range, (14 + 499 * item_len + 18, 14 + 499 * item_len + 20));
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() -> Result<()> {
+        let str = "<root><a><b></a></c>1 < 2<!-- open";
+        let bytes = str.as_bytes();
+        let at = |s: &str| str.find(s).unwrap() as u64;
+
+        for i in 1..bytes.len() {
+            let event_handler = TextEventHandler::new();
+            let mut sax = SAXParser::new(&event_handler);
+            let mut events = [false; Event::COUNT];
+            events[Event::Error] = true;
+            sax.events = events;
+
+            sax.write(&bytes[..i]);
+            sax.write(&bytes[i..]);
+            sax.identity();
+
+            let errors = event_handler.errors.borrow();
+            let codes: Vec<ErrorCode> = errors.iter().map(|e| e.code).collect();
+            assert_eq!(
+                codes,
+                [
+                    ErrorCode::UnclosedTag,
+                    ErrorCode::UnmatchedCloseTag,
+                    ErrorCode::InvalidTagStart,
+                    ErrorCode::UnclosedTag,
+                    ErrorCode::UnexpectedEof
+                ],
+                "At iteration i={}",
+                i
+            );
+
+            assert_eq!(errors[0].message, b"Tag 'b' was not closed", "At iteration i={}", i);
+            assert_eq!(errors[0].start, [0, at("<b>")], "At iteration i={}", i);
+            assert_eq!(errors[0].byte_range, (at("<b>"), at("</a>")), "At iteration i={}", i);
+
+            assert_eq!(errors[1].message, b"Close tag 'c' has no matching open tag", "At iteration i={}", i);
+            assert_eq!(errors[1].start, [0, at("</c>")], "At iteration i={}", i);
+            assert_eq!(errors[1].end, [0, at("1 <")], "At iteration i={}", i);
+            assert_eq!(errors[1].byte_range, (at("</c>"), at("1 <")), "At iteration i={}", i);
+
+            assert_eq!(errors[2].start, [0, at("< 2")], "At iteration i={}", i);
+            assert_eq!(errors[2].byte_range, (at("< 2"), at("< 2") + 1), "At iteration i={}", i);
+
+            assert_eq!(errors[3].message, b"Tag 'root' was not closed", "At iteration i={}", i);
+            assert_eq!(errors[3].byte_range, (0, 6), "At iteration i={}", i);
+
+            assert_eq!(errors[4].message, b"Unexpected end of input in comment", "At iteration i={}", i);
+            assert_eq!(errors[4].start, [0, at("<!--")], "At iteration i={}", i);
+            assert_eq!(errors[4].end, [0, bytes.len() as u64], "At iteration i={}", i);
+            assert_eq!(errors[4].byte_range, (at("<!--"), bytes.len() as u64), "At iteration i={}", i);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict() -> Result<()> {
+        let str = "hello<root a=\"1\" a=\"2\" b=c d 1x=\"y\"><!-- a -- b --><x></root><root2/>bye";
+        let bytes = str.as_bytes();
+        let at = |s: &str| str.find(s).unwrap() as u64;
+
+        for i in 1..bytes.len() {
+            let event_handler = TextEventHandler::new();
+            let mut sax = SAXParser::new(&event_handler);
+            let mut events = [false; Event::COUNT];
+            events[Event::Error] = true;
+            sax.events = events;
+            sax.strict = true;
+
+            sax.write(&bytes[..i]);
+            sax.write(&bytes[i..]);
+            sax.identity();
+
+            let errors = event_handler.errors.borrow();
+            let codes: Vec<ErrorCode> = errors.iter().map(|e| e.code).collect();
+            assert_eq!(
+                codes,
+                [
+                    ErrorCode::TextOutsideRoot,
+                    ErrorCode::DuplicateAttribute,
+                    ErrorCode::UnquotedAttributeValue,
+                    ErrorCode::MissingAttributeValue,
+                    ErrorCode::InvalidName,
+                    ErrorCode::DoubleHyphenInComment,
+                    ErrorCode::MismatchedCloseTag,
+                    ErrorCode::UnclosedTag,
+                    ErrorCode::MultipleRoots,
+                    ErrorCode::TextOutsideRoot,
+                ],
+                "At iteration i={}",
+                i
+            );
+
+            assert_eq!(errors[1].start, [0, at("a=\"2\"")], "At iteration i={}", i);
+            assert_eq!(errors[2].byte_range, (at("c d"), at("c d") + 1), "At iteration i={}", i);
+            assert_eq!(errors[4].message, b"Invalid attribute name '1x'", "At iteration i={}", i);
+            assert_eq!(errors[5].start, [0, at("-- b")], "At iteration i={}", i);
+            assert_eq!(errors[5].byte_range, (at("-- b"), at("-- b") + 2), "At iteration i={}", i);
+            assert_eq!(errors[6].message, b"Expected '</x>' but found '</root>'", "At iteration i={}", i);
+            assert_eq!(errors[6].start, [0, at("</root>")], "At iteration i={}", i);
+            assert_eq!(errors[8].byte_range, (at("<root2"), at("<root2") + 6), "At iteration i={}", i);
+            assert_eq!(errors[9].start, [0, at("bye")], "At iteration i={}", i);
+        }
+
+        // The same document only reports the recovered close tag when lenient
+        let event_handler = TextEventHandler::new();
+        let mut sax = SAXParser::new(&event_handler);
+        let mut events = [false; Event::COUNT];
+        events[Event::Error] = true;
+        sax.events = events;
+        sax.write(bytes);
+        sax.identity();
+        let errors = event_handler.errors.borrow();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ErrorCode::UnclosedTag);
+        Ok(())
+    }
+
+    #[test]
+    fn test_identity_flushes_unterminated() -> Result<()> {
+        let cases: [(&str, Event); 3] = [
+            ("<root><a>text<!-- partial", Event::Comment),
+            ("<root><a>text<?pi some", Event::ProcessingInstruction),
+            ("<root><a>text<b x=\"1\" y=\"2", Event::OpenTag),
+        ];
+        for (str, event) in cases {
+            let bytes = str.as_bytes();
+            for i in 1..bytes.len() {
+                let event_handler = TextEventHandler::new();
+                let mut sax = SAXParser::new(&event_handler);
+                let mut events = [false; Event::COUNT];
+                events[event] = true;
+                events[Event::CloseTag] = true;
+                sax.events = events;
+
+                sax.write(&bytes[..i]);
+                sax.write(&bytes[i..]);
+                sax.identity();
+
+                let tags = event_handler.tags.borrow();
+                let names: Vec<&[u8]> = tags.iter().map(|t| t.name.as_slice()).collect();
+                let end = [0, bytes.len() as u64];
+                match event {
+                    Event::Comment => {
+                        let texts = event_handler.texts.borrow();
+                        assert_eq!(texts.len(), 1, "At iteration i={}", i);
+                        assert_eq!(texts[0].value, b" partial", "At iteration i={}", i);
+                        assert_eq!(texts[0].end, end, "At iteration i={}", i);
+                        assert_eq!(names, [b"a".as_slice(), b"root"], "At iteration i={}", i);
+                    }
+                    Event::ProcessingInstruction => {
+                        let proc_insts = event_handler.proc_insts.borrow();
+                        assert_eq!(proc_insts.len(), 1, "At iteration i={}", i);
+                        assert_eq!(proc_insts[0].target.value, b"pi", "At iteration i={}", i);
+                        assert_eq!(proc_insts[0].content.value, b"some", "At iteration i={}", i);
+                        assert_eq!(names, [b"a".as_slice(), b"root"], "At iteration i={}", i);
+                    }
+                    _ => {
+                        // OpenTag for the partial <b> then CloseTags innermost first
+                        assert_eq!(names, [b"root".as_slice(), b"a", b"b", b"b", b"a", b"root"], "At iteration i={}", i);
+                        assert_eq!(tags[2].attributes.len(), 2, "At iteration i={}", i);
+                        assert_eq!(tags[2].attributes[1].value.value, b"2", "At iteration i={}", i);
+                        assert!(!tags[2].implicitly_closed, "At iteration i={}", i);
+                    }
+                }
+                let closed = &tags[tags.len() - 2..];
+                assert!(closed.iter().all(|t| t.implicitly_closed), "At iteration i={}", i);
+                assert_eq!(closed[0].close_end, end, "At iteration i={}", i);
+                assert_eq!(closed[0].byte_range.1, bytes.len() as u64, "At iteration i={}", i);
+                assert_eq!(closed[0].text_nodes[0].value, b"text", "At iteration i={}", i);
+            }
+        }
+
+        // Elements closed by an ancestor's close tag are flagged too
+        let event_handler = TextEventHandler::new();
+        let mut sax = SAXParser::new(&event_handler);
+        let mut events = [false; Event::COUNT];
+        events[Event::CloseTag] = true;
+        sax.events = events;
+        sax.write(b"<a><b></a>");
+        sax.identity();
+        let tags = event_handler.tags.borrow();
+        assert_eq!(tags.len(), 2);
+        assert!(tags[0].implicitly_closed);
+        assert_eq!(tags[0].close_start, [0, 6]);
+        assert!(!tags[1].implicitly_closed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mutable_handlers() {
+        let doc = b"<!DOCTYPE r><r><!--c-->text<e a=\"1\"/><![CDATA[data]]></r>";
+
+        let shared = TextEventHandler::new();
+        let mut sax = SAXParser::new(&shared);
+        sax.events = [true; Event::COUNT];
+        sax.write(doc);
+        sax.identity();
+
+        let mut events = Vec::new();
+        let mut closure = |event: Event, _data: Entity| events.push(event);
+        let mut sax = SAXParser::new_mut(&mut closure);
+        sax.events = [true; Event::COUNT];
+        sax.write(doc);
+        sax.identity();
+        drop(sax);
+        assert_eq!(
+            events,
+            [
+                Event::Doctype,
+                Event::OpenTagStart,
+                Event::OpenTag,
+                Event::Comment,
+                Event::Text,
+                Event::OpenTagStart,
+                Event::Attribute,
+                Event::OpenTag,
+                Event::CloseTag,
+                Event::Cdata,
+                Event::CloseTag
+            ]
+        );
+
+        #[derive(Default)]
+        struct Collector {
+            texts: Vec<Vec<u8>>,
+            tags: Vec<Vec<u8>>,
+            attributes: usize,
+        }
+
+        impl TypedEventHandler for Collector {
+            fn text(&mut self, text: &Text) {
+                self.texts.push(text.value.clone());
+            }
+            fn doctype(&mut self, doctype: &Text) {
+                self.texts.push(doctype.value.clone());
+            }
+            fn comment(&mut self, comment: &Text) {
+                self.texts.push(comment.value.clone());
+            }
+            fn cdata(&mut self, cdata: &Text) {
+                self.texts.push(cdata.value.clone());
+            }
+            fn attribute(&mut self, _attribute: &Attribute) {
+                self.attributes += 1;
+            }
+            fn close_tag(&mut self, tag: &Tag) {
+                self.tags.push(tag.name.clone());
+            }
+        }
+
+        let mut collector = Collector::default();
+        let mut sax = SAXParser::new_typed(&mut collector);
+        sax.events = [true; Event::COUNT];
+        sax.write(doc);
+        sax.identity();
+        drop(sax);
+        assert_eq!(collector.texts, *shared.texts.borrow().iter().map(|t| t.value.clone()).collect::<Vec<_>>());
+        assert_eq!(collector.tags, [b"e".as_slice(), b"r"]);
+        assert_eq!(collector.attributes, 1);
+    }
 }
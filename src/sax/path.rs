@@ -0,0 +1,299 @@
+//! Element path patterns restricting the events a parser generates.
+//!
+//! A pattern is a sequence of steps each preceded by `/` (a child of the
+//! element matched so far) or `//` (any descendant of it). A step is an
+//! element name or `*` for any element. When a [`PathFilter`] is set on the
+//! parser, only events within elements matching one of its patterns (the
+//! element included) are generated. Errors are always reported.
+//!
+//! # Examples
+//!
+//! ```
+//! use sax_wasm::sax::parser::{Event, SAXParser};
+//! use sax_wasm::sax::path::PathFilter;
+//! use sax_wasm::sax::tag::Entity;
+//!
+//! let mut prices = Vec::new();
+//! let mut handler = |_event: Event, data: Entity| {
+//!     if let Entity::Text(text) = data {
+//!         prices.push(text.value.clone());
+//!     }
+//! };
+//! let mut parser = SAXParser::new_mut(&mut handler);
+//! parser.events[Event::Text] = true;
+//! parser.path_filter = Some(PathFilter::new(&["/catalog/*/price"]).unwrap());
+//! parser.write(b"<catalog><name>Shop</name><product><price>9.99</price></product></catalog>");
+//! parser.identity();
+//! drop(parser);
+//! assert_eq!(prices, [b"9.99"]);
+//! ```
+
+use std::error::Error;
+use std::fmt;
+
+/// Steps are matched using a bit each so patterns, and XPath expressions
+/// evaluated the same way, are limited in length.
+pub const MAX_STEPS: usize = 63;
+
+/// An invalid path pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathError {
+    pub pattern: String,
+    pub message: String,
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid path '{}': {}", self.pattern, self.message)
+    }
+}
+
+impl Error for PathError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    // Preceded by `//`
+    descendant: bool,
+    // `None` for `*`
+    name: Option<Vec<u8>>,
+}
+
+/// A parsed path pattern such as `/catalog/product/price`, `//price` or `/catalog/*//price`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathPattern {
+    steps: Vec<Step>,
+}
+
+impl PathPattern {
+    pub fn parse(pattern: &str) -> Result<PathPattern, PathError> {
+        let error = |message: &str| PathError {
+            pattern: pattern.to_string(),
+            message: message.to_string(),
+        };
+        let Some(mut rest) = pattern.strip_prefix('/') else {
+            return Err(error("a pattern starts with '/' or '//'"));
+        };
+        let mut steps = Vec::new();
+        loop {
+            let descendant = match rest.strip_prefix('/') {
+                Some(after) => {
+                    rest = after;
+                    true
+                }
+                None => false,
+            };
+            let end = rest.find('/').unwrap_or(rest.len());
+            let name = &rest[..end];
+            if name.is_empty() {
+                return Err(error("a step has no name"));
+            }
+            steps.push(Step {
+                descendant,
+                name: (name != "*").then(|| name.as_bytes().to_vec()),
+            });
+            if end == rest.len() {
+                break;
+            }
+            rest = &rest[end + 1..];
+        }
+        if steps.len() > MAX_STEPS {
+            return Err(error(&format!("more than {} steps", MAX_STEPS)));
+        }
+        Ok(PathPattern {
+            steps,
+        })
+    }
+
+    /// The steps matched within a child named `name` of an element
+    /// within which the steps of `states` were matched. Bit `i` of a state
+    /// is set when the first `i` steps were matched. Bit 0 is set at the
+    /// document level.
+    pub fn step(&self, states: u64, name: &[u8]) -> u64 {
+        let mut child = 0;
+        let mut pending = states & !(1 << self.steps.len());
+        while pending != 0 {
+            let i = pending.trailing_zeros() as usize;
+            pending &= pending - 1;
+            let step = &self.steps[i];
+            // The step may still match further down
+            if step.descendant {
+                child |= 1 << i;
+            }
+            if step.name.as_deref().is_none_or(|n| n == name) {
+                child |= 1 << (i + 1);
+            }
+        }
+        child
+    }
+
+    /// Whether every step was matched.
+    pub fn is_match(&self, states: u64) -> bool {
+        states & (1 << self.steps.len()) != 0
+    }
+}
+
+/// Tracks which open elements match one of a set of [`PathPattern`]s.
+/// Set it on `SAXParser::path_filter` before writing a document.
+#[derive(Debug, Clone)]
+pub struct PathFilter {
+    patterns: Vec<PathPattern>,
+    // The states of each pattern for the document
+    // followed by those within each open element
+    states: Vec<u64>,
+    // Whether the document and each open element
+    // is within an element matching a pattern
+    inside: Vec<bool>,
+    // Whether events at the current position are generated
+    selected: bool,
+}
+
+impl PathFilter {
+    pub fn new(patterns: &[&str]) -> Result<PathFilter, PathError> {
+        let patterns = patterns.iter().map(|p| PathPattern::parse(p)).collect::<Result<Vec<_>, _>>()?;
+        let mut filter = PathFilter {
+            patterns,
+            states: Vec::new(),
+            inside: Vec::new(),
+            selected: false,
+        };
+        filter.clear();
+        Ok(filter)
+    }
+
+    /// Whether events at the current position are generated.
+    pub fn selected(&self) -> bool {
+        self.selected
+    }
+
+    /// The name of an element was read. The events of its
+    /// start tag are generated when the element matches.
+    pub(crate) fn start_element(&mut self, name: &[u8]) {
+        let level = self.inside.len() - 1;
+        let n = self.patterns.len();
+        let states = &self.states[level * n..];
+        self.selected = self.inside[level] || self.patterns.iter().zip(states).any(|(p, &s)| p.is_match(p.step(s, name)));
+    }
+
+    /// An element was opened.
+    pub(crate) fn push(&mut self, name: &[u8]) {
+        let level = self.inside.len() - 1;
+        let n = self.patterns.len();
+        let mut inside = self.inside[level];
+        for i in 0..n {
+            let pattern = &self.patterns[i];
+            let states = pattern.step(self.states[level * n + i], name);
+            inside |= pattern.is_match(states);
+            self.states.push(states);
+        }
+        self.inside.push(inside);
+        self.selected = inside;
+    }
+
+    /// The innermost open element was closed.
+    pub(crate) fn pop(&mut self) {
+        self.truncate(self.inside.len() - 2);
+    }
+
+    /// Closes the elements after the first `len`.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.inside.truncate(len + 1);
+        self.states.truncate((len + 1) * self.patterns.len());
+        self.reselect();
+    }
+
+    /// Back to the innermost open element after a start tag, e.g. of a self-closing element.
+    pub(crate) fn reselect(&mut self) {
+        self.selected = self.inside[self.inside.len() - 1];
+    }
+
+    /// Readies the filter for the next document.
+    pub(crate) fn clear(&mut self) {
+        self.states.clear();
+        self.states.resize(self.patterns.len(), 1);
+        self.inside.clear();
+        self.inside.push(false);
+        self.selected = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sax::parser::{Event, SAXParser};
+    use crate::sax::tag::Entity;
+
+    fn matches(pattern: &str, path: &[&str]) -> bool {
+        let pattern = PathPattern::parse(pattern).unwrap();
+        let states = path.iter().fold(1, |states, name| pattern.step(states, name.as_bytes()));
+        pattern.is_match(states)
+    }
+
+    #[test]
+    fn test_patterns() {
+        assert!(matches("/catalog/product/price", &["catalog", "product", "price"]));
+        assert!(!matches("/catalog/product/price", &["catalog", "price"]));
+        assert!(!matches("/catalog/product", &["shop", "catalog", "product"]));
+        assert!(matches("/catalog/*/price", &["catalog", "book", "price"]));
+        assert!(!matches("/catalog/*/price", &["catalog", "price"]));
+        assert!(matches("//price", &["price"]));
+        assert!(matches("//price", &["catalog", "a", "b", "price"]));
+        assert!(matches("/catalog//b/price", &["catalog", "b", "a", "b", "price"]));
+        assert!(!matches("/catalog//b/price", &["catalog", "b", "a", "price"]));
+        assert!(matches("//*", &["any"]));
+    }
+
+    #[test]
+    fn test_filters_events() {
+        let doc = b"<?xml version=\"1.0\"?><catalog><!-- c --><product id=\"1\"><name>Ab</name><price cur=\"EUR\">1.5<!-- p --><b/></price></product><price>2.5</price><sale><product><price>3.5</price></product></sale></catalog>";
+        for chunk_size in [3, 5, 7, doc.len()] {
+            let mut events = Vec::new();
+            let mut handler = |event: Event, data: Entity| {
+                let description = match data {
+                    Entity::Tag(tag) => tag.name.clone(),
+                    Entity::Attribute(attribute) => attribute.name.value.clone(),
+                    Entity::Text(text) => text.value.clone(),
+                    _ => Vec::new(),
+                };
+                events.push(format!("{:?} {}", event, String::from_utf8_lossy(&description)));
+            };
+            let mut parser = SAXParser::new_mut(&mut handler);
+            parser.events = [true; Event::COUNT];
+            parser.path_filter = Some(PathFilter::new(&["/catalog/product/price", "//sale//price"]).unwrap());
+            for chunk in doc.chunks(chunk_size) {
+                parser.write(chunk);
+            }
+            parser.identity();
+            drop(parser);
+            assert_eq!(
+                events,
+                [
+                    "OpenTagStart price",
+                    "Attribute cur",
+                    "OpenTag price",
+                    "Text 1.5",
+                    "Comment  p ",
+                    "OpenTagStart b",
+                    "OpenTag b",
+                    "CloseTag b",
+                    "CloseTag price",
+                    "OpenTagStart price",
+                    "OpenTag price",
+                    "Text 3.5",
+                    "CloseTag price",
+                ],
+                "chunk size {}",
+                chunk_size
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for pattern in ["", "catalog", "/", "/a//", "/a///b"] {
+            assert!(PathPattern::parse(pattern).is_err(), "{}", pattern);
+        }
+        let long = "/a".repeat(MAX_STEPS + 1);
+        assert!(PathPattern::parse(&long).is_err());
+        assert!(PathPattern::parse(&"/a".repeat(MAX_STEPS)).is_ok());
+    }
+}
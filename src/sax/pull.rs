@@ -0,0 +1,226 @@
+//! A pull reader over [`SAXParser`].
+//!
+//! Instead of implementing [`EventHandler`], feed chunks to a [`PullParser`] and
+//! ask for events one at a time. The events are produced by the same state
+//! machine and carry the same data as the ones passed to an event handler.
+//!
+//! # Examples
+//!
+//! ```
+//! use sax_wasm::sax::parser::Event;
+//! use sax_wasm::sax::pull::{PullEvent, PullParser};
+//! use sax_wasm::sax::tag::Entity;
+//!
+//! let mut reader = PullParser::new(&[Event::OpenTag, Event::Text]);
+//! reader.feed(b"<greeting>Hel");
+//!
+//! let PullEvent::Event(Event::OpenTag, Entity::Tag(tag)) = reader.next_event() else { panic!() };
+//! assert_eq!(tag.name, b"greeting");
+//! // The text is cut off at the end of the chunk
+//! assert!(matches!(reader.next_event(), PullEvent::NeedMoreData));
+//!
+//! reader.feed(b"lo</greeting>");
+//! reader.finish();
+//! let PullEvent::Event(Event::Text, Entity::Text(text)) = reader.next_event() else { panic!() };
+//! assert_eq!(text.value, b"Hello");
+//! assert!(matches!(reader.next_event(), PullEvent::End));
+//! ```
+
+use super::parser::{Event, EventHandler, SAXParser};
+use super::tag::{Dispatched, Entity};
+
+/// The handler of the underlying parser. It is never called since
+/// the parser queues its events for the reader.
+struct Queued;
+
+impl EventHandler for Queued {
+    fn handle_event(&self, _event: Event, _data: Entity) {}
+}
+
+/// The result of [`PullParser::next_event`].
+pub enum PullEvent<'a> {
+    /// The next event and its data, borrowed until the following call.
+    Event(Event, Entity<'a>),
+    /// Every event of the chunks fed so far was returned. The last chunk
+    /// may end in the middle of a token which the next chunk completes.
+    NeedMoreData,
+    /// The document was finished and every event was returned.
+    End,
+}
+
+/// Parses chunks of a document and hands out the events on request.
+///
+/// Events can be borrowed one at a time using [`next_event`](Self::next_event)
+/// or taken as owned values by iterating the reader. Iteration stops when
+/// more data is needed or the document is finished.
+pub struct PullParser {
+    parser: SAXParser<'static>,
+    // The event most recently returned by `next_event`
+    current: Option<Dispatched>,
+    finished: bool,
+}
+
+impl PullParser {
+    /// Creates a reader producing the given events.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sax_wasm::sax::parser::Event;
+    /// use sax_wasm::sax::pull::PullParser;
+    /// use sax_wasm::sax::tag::Dispatched;
+    ///
+    /// let mut reader = PullParser::new(&[Event::OpenTag]);
+    /// reader.feed(b"<a><b/></a>");
+    ///
+    /// let names: Vec<_> = reader
+    ///     .map(|(_, dispatched)| match dispatched {
+    ///         Dispatched::Tag(tag) => tag.name,
+    ///         _ => unreachable!(),
+    ///     })
+    ///     .collect();
+    /// assert_eq!(names, [b"a", b"b"]);
+    /// ```
+    pub fn new(events: &[Event]) -> PullParser {
+        let mut parser = SAXParser::new(&Queued);
+        parser.queue_events();
+        for &event in events {
+            parser.events[event] = true;
+        }
+        PullParser {
+            parser,
+            current: None,
+            finished: false,
+        }
+    }
+
+    /// The underlying parser, e.g. to set `decode_entities`, `namespaces` or `strict`.
+    pub fn parser_mut(&mut self) -> &mut SAXParser<'static> {
+        &mut self.parser
+    }
+
+    /// Parses the next chunk of the document. Feeding a chunk after
+    /// [`finish`](Self::finish) starts a new document.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.finished = false;
+        self.parser.write(chunk);
+    }
+
+    /// Ends the document. Text at the end of the document, unterminated
+    /// constructs and elements left open are reported as they are when
+    /// an event handler is used.
+    pub fn finish(&mut self) {
+        self.parser.identity();
+        self.finished = true;
+    }
+
+    /// Returns the next event.
+    pub fn next_event(&mut self) -> PullEvent<'_> {
+        match self.parser.next_queued() {
+            Some((event, dispatched)) => PullEvent::Event(event, self.current.insert(dispatched).as_entity()),
+            None if self.finished => PullEvent::End,
+            None => PullEvent::NeedMoreData,
+        }
+    }
+}
+
+impl Iterator for PullParser {
+    type Item = (Event, Dispatched);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.parser.next_queued()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    struct Recorder {
+        events: RefCell<Vec<(Event, Vec<u8>)>>,
+    }
+
+    impl EventHandler for Recorder {
+        fn handle_event(&self, event: Event, data: Entity) {
+            self.events.borrow_mut().push((event, describe(data)));
+        }
+    }
+
+    fn describe(data: Entity) -> Vec<u8> {
+        match data {
+            Entity::Tag(tag) => tag.name.clone(),
+            Entity::Attribute(attribute) => attribute.name.value.clone(),
+            Entity::Text(text) => text.value.clone(),
+            Entity::ProcInst(proc_inst) => proc_inst.target.value.clone(),
+            Entity::Error(error) => error.message.clone(),
+            Entity::Declaration(declaration) => declaration.name.value.clone(),
+            Entity::XmlDecl(xml_decl) => xml_decl.version.value.clone(),
+        }
+    }
+
+    const ALL: [Event; Event::COUNT] = [
+        Event::Text,
+        Event::ProcessingInstruction,
+        Event::Declaration,
+        Event::Doctype,
+        Event::Comment,
+        Event::OpenTagStart,
+        Event::Attribute,
+        Event::OpenTag,
+        Event::CloseTag,
+        Event::Cdata,
+        Event::Error,
+        Event::XmlDecl,
+    ];
+
+    #[test]
+    fn test_matches_event_handler() {
+        let doc =
+            "<?xml version=\"1.0\"?>\n<!DOCTYPE r [<!ELEMENT r ANY>]>\n<r a=\"1\"><!-- c --><![CDATA[x]]>🚀 text<?pi d?><e/></r><oops>";
+        let recorder = Recorder {
+            events: RefCell::new(Vec::new()),
+        };
+        let mut parser = SAXParser::new(&recorder);
+        parser.events = [true; Event::COUNT];
+        let mut reader = PullParser::new(&ALL);
+        let mut pulled = Vec::new();
+        // Split everywhere including inside of multi-byte characters
+        for chunk in doc.as_bytes().chunks(3) {
+            parser.write(chunk);
+            reader.feed(chunk);
+            while let PullEvent::Event(event, data) = reader.next_event() {
+                pulled.push((event, describe(data)));
+            }
+        }
+        parser.identity();
+        reader.finish();
+        pulled.extend(reader.by_ref().map(|(event, dispatched)| (event, describe(dispatched.as_entity()))));
+
+        assert!(pulled.len() > 10);
+        assert_eq!(pulled, recorder.events.into_inner());
+        assert!(matches!(reader.next_event(), PullEvent::End));
+    }
+
+    #[test]
+    fn test_need_more_data() {
+        let mut reader = PullParser::new(&[Event::OpenTag, Event::CloseTag]);
+        assert!(matches!(reader.next_event(), PullEvent::NeedMoreData));
+
+        reader.feed(b"<root><chi");
+        assert!(matches!(reader.next_event(), PullEvent::Event(Event::OpenTag, Entity::Tag(tag)) if tag.name == b"root"));
+        assert!(matches!(reader.next_event(), PullEvent::NeedMoreData));
+
+        reader.feed(b"ld/></root>");
+        let events: Vec<_> = reader.by_ref().map(|(event, _)| event).collect();
+        assert_eq!(events, [Event::OpenTag, Event::CloseTag, Event::CloseTag]);
+        assert!(matches!(reader.next_event(), PullEvent::NeedMoreData));
+
+        reader.finish();
+        assert!(matches!(reader.next_event(), PullEvent::End));
+
+        // A new document
+        reader.feed(b"<next/>");
+        assert!(matches!(reader.next_event(), PullEvent::Event(Event::OpenTag, Entity::Tag(tag)) if tag.name == b"next"));
+    }
+}
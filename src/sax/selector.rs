@@ -0,0 +1,483 @@
+//! CSS selector matching against the elements of a parsed document.
+//!
+//! Supported are type (`a`) and universal (`*`) selectors, `#id`,
+//! `.class`, attribute selectors with the `=`, `~=`, `|=`, `^=`, `$=` and
+//! `*=` operators, the descendant (` `) and child (`>`) combinators, and the
+//! `:first-child` and `:nth-child()` pseudo-classes. Selectors are separated
+//! by `,`. Element and attribute names are compared ignoring ASCII case as
+//! in HTML, attribute values are compared as is.
+//!
+//! Pseudo-classes depending on the elements after an element, such as
+//! `:last-child`, and the sibling combinators are not supported since
+//! matches are reported at the start tag. Void HTML elements such as `<br>`
+//! are open until their parent is closed unless written as `<br/>`, which
+//! affects the child combinator and positions of the elements after them.
+//!
+//! The [`SelectorMatcher`] is an event handler reading the open elements from
+//! the [`Context`] passed with each event, so the parser must be subscribed to
+//! [`EVENTS`].
+//!
+//! # Examples
+//!
+//! ```
+//! use sax_wasm::sax::parser::{subscribe, SAXParser};
+//! use sax_wasm::sax::selector::{SelectorMatcher, EVENTS};
+//!
+//! let mut links = Vec::new();
+//! let mut matcher = SelectorMatcher::new(&["nav a.external[href]"], |_selector, tag| {
+//!     links.push((tag.attributes[1].value.value.clone(), tag.open_start));
+//! })
+//! .unwrap();
+//! let mut parser = SAXParser::new_with_context(&mut matcher);
+//! subscribe(&mut parser.events, &EVENTS);
+//! parser.write(b"<body><a class=\"external\" href=\"/a\">A</a>\n<nav><p><a class=\"link external\" href=\"/b\">B</a></p></nav></body>");
+//! parser.identity();
+//! drop(parser);
+//! drop(matcher);
+//! assert_eq!(links, [(b"/b".to_vec(), [1, 8])]);
+//! ```
+
+use std::error::Error;
+use std::fmt;
+
+use super::borrowed::TagRef;
+use super::context::Context;
+use super::expression::{ExpressionError, Reader};
+use super::parser::{ContextEventHandler, Event};
+use super::tag::{Entity, Tag};
+
+/// An invalid selector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorError {
+    pub selector: String,
+    pub message: String,
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid selector '{}': {}", self.selector, self.message)
+    }
+}
+
+impl Error for SelectorError {}
+
+impl ExpressionError for SelectorError {
+    fn new(selector: &str, message: &str) -> SelectorError {
+        SelectorError {
+            selector: selector.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    // [name=value]
+    Equals,
+    // [name~=value], a whitespace separated word
+    Includes,
+    // [name|=value], the value or a value followed by `-`
+    DashMatch,
+    // [name^=value]
+    Prefix,
+    // [name$=value]
+    Suffix,
+    // [name*=value]
+    Substring,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Condition {
+    // [name]
+    HasAttribute(Vec<u8>),
+    // #id, .class and [name<op>value]
+    Attribute(Vec<u8>, Operator, Vec<u8>),
+    // :nth-child(an+b), :first-child being :nth-child(1)
+    NthChild(i64, i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+/// The conditions on a single element such as `a.external[href]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Compound {
+    // How the element relates to the element
+    // of the compound before it
+    combinator: Combinator,
+    // `None` for `*`
+    name: Option<Vec<u8>>,
+    conditions: Vec<Condition>,
+}
+
+/// A parsed selector without `,` such as `nav > ul li:nth-child(2n+1)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    compounds: Vec<Compound>,
+}
+
+impl Reader<'_, SelectorError> {
+    fn ident(&mut self) -> Result<Vec<u8>, SelectorError> {
+        self.name_while(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b >= 0x80)
+    }
+
+    fn value(&mut self) -> Result<Vec<u8>, SelectorError> {
+        match self.quoted()? {
+            Some(value) => Ok(value),
+            None => self.ident(),
+        }
+    }
+
+    fn attribute(&mut self) -> Result<Condition, SelectorError> {
+        self.whitespace();
+        let name = self.ident()?;
+        self.whitespace();
+        let operator = [
+            (&b"="[..], Operator::Equals),
+            (b"~=", Operator::Includes),
+            (b"|=", Operator::DashMatch),
+            (b"^=", Operator::Prefix),
+            (b"$=", Operator::Suffix),
+            (b"*=", Operator::Substring),
+        ]
+        .into_iter()
+        .find(|(token, _)| self.eat(token));
+        let condition = match operator {
+            Some((_, operator)) => {
+                self.whitespace();
+                let value = self.value()?;
+                self.whitespace();
+                Condition::Attribute(name, operator, value)
+            }
+            None => Condition::HasAttribute(name),
+        };
+        if !self.eat(b"]") {
+            return self.error("an attribute selector is not closed");
+        }
+        Ok(condition)
+    }
+
+    fn pseudo_class(&mut self) -> Result<Condition, SelectorError> {
+        let name = self.ident()?.to_ascii_lowercase();
+        match &name[..] {
+            b"first-child" => Ok(Condition::NthChild(0, 1)),
+            b"nth-child" => {
+                if !self.eat(b"(") {
+                    return self.error(":nth-child has an argument");
+                }
+                let end = self.rest.iter().position(|&b| b == b')').unwrap_or(self.rest.len());
+                let Some((a, b)) = std::str::from_utf8(&self.rest[..end]).ok().and_then(nth) else {
+                    return self.error("the argument of :nth-child is an+b, odd or even");
+                };
+                self.rest = &self.rest[end..];
+                if !self.eat(b")") {
+                    return self.error("the argument of :nth-child is not closed");
+                }
+                Ok(Condition::NthChild(a, b))
+            }
+            b"last-child" | b"nth-last-child" | b"only-child" | b"last-of-type" | b"only-of-type" | b"empty" => {
+                self.error("pseudo-classes depending on later content cannot be matched at the start tag")
+            }
+            _ => self.error("unsupported pseudo-class"),
+        }
+    }
+
+    fn compound(&mut self, combinator: Combinator) -> Result<Compound, SelectorError> {
+        // The type selector may be left out before other conditions
+        let name = if self.eat(b"*") || self.rest.first().is_some_and(|b| b"#.[:".contains(b)) {
+            None
+        } else {
+            Some(self.ident()?)
+        };
+        let mut conditions = Vec::new();
+        loop {
+            if self.eat(b"#") {
+                conditions.push(Condition::Attribute(b"id".to_vec(), Operator::Equals, self.ident()?));
+            } else if self.eat(b".") {
+                conditions.push(Condition::Attribute(b"class".to_vec(), Operator::Includes, self.ident()?));
+            } else if self.eat(b"[") {
+                conditions.push(self.attribute()?);
+            } else if self.eat(b":") {
+                conditions.push(self.pseudo_class()?);
+            } else {
+                break;
+            }
+        }
+        Ok(Compound {
+            combinator,
+            name,
+            conditions,
+        })
+    }
+}
+
+/// Parses the argument of `:nth-child()`.
+fn nth(argument: &str) -> Option<(i64, i64)> {
+    let argument = argument.trim().to_ascii_lowercase();
+    match argument.as_str() {
+        "odd" => return Some((2, 1)),
+        "even" => return Some((2, 0)),
+        _ => {}
+    }
+    let Some((a, b)) = argument.split_once('n') else {
+        return argument.parse().ok().map(|b| (0, b));
+    };
+    let a = match a.trim_end() {
+        "" | "+" => 1,
+        "-" => -1,
+        a => a.parse().ok()?,
+    };
+    let b = b.trim_start();
+    let b = match b.strip_prefix('+').or_else(|| b.strip_prefix('-')) {
+        _ if b.is_empty() => 0,
+        Some(digits) if !digits.starts_with(['+', '-']) => {
+            let digits: i64 = digits.trim_start().parse().ok()?;
+            if b.starts_with('-') {
+                -digits
+            } else {
+                digits
+            }
+        }
+        _ => return None,
+    };
+    Some((a, b))
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Result<Selector, SelectorError> {
+        let mut selectors = Selector::parse_list(selector)?;
+        if selectors.len() > 1 {
+            return Err(SelectorError::new(selector, "a single selector is expected, use Selector::parse_list for a list"));
+        }
+        Ok(selectors.remove(0))
+    }
+
+    /// Parses selectors separated by `,`.
+    pub fn parse_list(selectors: &str) -> Result<Vec<Selector>, SelectorError> {
+        let mut reader = Reader::new(selectors);
+        let mut list = Vec::new();
+        let mut compounds = Vec::new();
+        let mut combinator = Combinator::Descendant;
+        reader.whitespace();
+        loop {
+            compounds.push(reader.compound(combinator)?);
+            let spaced = reader.whitespace();
+            if reader.rest.is_empty() {
+                list.push(Selector {
+                    compounds,
+                });
+                break;
+            } else if reader.eat(b",") {
+                list.push(Selector {
+                    compounds: std::mem::take(&mut compounds),
+                });
+                reader.whitespace();
+                combinator = Combinator::Descendant;
+            } else if reader.eat(b">") {
+                reader.whitespace();
+                combinator = Combinator::Child;
+            } else if reader.rest.starts_with(b"+") || reader.rest.starts_with(b"~") {
+                return reader.error("sibling combinators are not supported");
+            } else if spaced {
+                combinator = Combinator::Descendant;
+            } else {
+                return reader.error("unexpected character");
+            }
+        }
+        Ok(list)
+    }
+
+    /// Whether the innermost open element of `context` matches.
+    pub fn matches(&self, context: &Context) -> bool {
+        let elements: Vec<_> = context.open_elements().collect();
+        let indexes: Vec<_> = context.indexes().collect();
+        !elements.is_empty() && self.matches_at(self.compounds.len() - 1, &elements, &indexes, elements.len() - 1)
+    }
+
+    /// Whether the compounds up to `compound` match with the
+    /// last one matching the open element at `element`.
+    fn matches_at(&self, compound: usize, elements: &[TagRef], indexes: &[usize], element: usize) -> bool {
+        let current = &self.compounds[compound];
+        if !current.matches(&elements[element], indexes[element]) {
+            return false;
+        }
+        if compound == 0 {
+            return true;
+        }
+        match current.combinator {
+            Combinator::Child => element > 0 && self.matches_at(compound - 1, elements, indexes, element - 1),
+            Combinator::Descendant => (0..element).rev().any(|parent| self.matches_at(compound - 1, elements, indexes, parent)),
+        }
+    }
+}
+
+impl Compound {
+    fn matches(&self, tag: &TagRef, index: usize) -> bool {
+        if self.name.as_ref().is_some_and(|name| !name.eq_ignore_ascii_case(tag.name)) {
+            return false;
+        }
+        self.conditions.iter().all(|condition| match condition {
+            Condition::HasAttribute(name) => tag.attributes().any(|a| a.name.value.eq_ignore_ascii_case(name)),
+            Condition::Attribute(name, operator, value) => {
+                tag.attributes().filter(|a| a.name.value.eq_ignore_ascii_case(name)).any(|a| compare(*operator, a.value.value, value))
+            }
+            Condition::NthChild(a, b) => {
+                // The position from 1 is a * n + b for some n >= 0
+                let offset = index as i64 + 1 - b;
+                match a {
+                    0 => offset == 0,
+                    a => offset % a == 0 && offset / a >= 0,
+                }
+            }
+        })
+    }
+}
+
+fn compare(operator: Operator, actual: &[u8], expected: &[u8]) -> bool {
+    match operator {
+        Operator::Equals => actual == expected,
+        Operator::Includes => !expected.is_empty() && actual.split(|b| b.is_ascii_whitespace()).any(|word| word == expected),
+        Operator::DashMatch => actual.strip_prefix(expected).is_some_and(|rest| rest.is_empty() || rest[0] == b'-'),
+        Operator::Prefix => !expected.is_empty() && actual.starts_with(expected),
+        Operator::Suffix => !expected.is_empty() && actual.ends_with(expected),
+        Operator::Substring => !expected.is_empty() && actual.windows(expected.len()).any(|window| window == expected),
+    }
+}
+
+/// The events the parser must be subscribed to.
+pub const EVENTS: [Event; 1] = [Event::OpenTag];
+
+/// Matches selectors against the events of a parser. See the [module documentation](self).
+pub struct SelectorMatcher<F: FnMut(usize, &Tag)> {
+    selectors: Vec<Selector>,
+    // The selector list each selector was parsed from
+    lists: Vec<usize>,
+    on_match: F,
+}
+
+impl<F: FnMut(usize, &Tag)> SelectorMatcher<F> {
+    /// Parses the selectors, each of which may be a list separated by `,`.
+    /// `on_match` is called with the index of the selector and the tag of
+    /// every matching element. The positions of its start tag are set.
+    pub fn new(selectors: &[&str], on_match: F) -> Result<SelectorMatcher<F>, SelectorError> {
+        let mut matcher = SelectorMatcher {
+            selectors: Vec::new(),
+            lists: Vec::new(),
+            on_match,
+        };
+        for (i, selector) in selectors.iter().enumerate() {
+            for selector in Selector::parse_list(selector)? {
+                matcher.selectors.push(selector);
+                matcher.lists.push(i);
+            }
+        }
+        Ok(matcher)
+    }
+}
+
+impl<F: FnMut(usize, &Tag)> ContextEventHandler for SelectorMatcher<F> {
+    fn handle_event(&mut self, event: Event, data: Entity, context: &Context) {
+        let (Event::OpenTag, Entity::Tag(tag)) = (event, data) else {
+            return;
+        };
+        let mut last = None;
+        for (selector, &list) in self.selectors.iter().zip(&self.lists) {
+            // An element matching several selectors of a list is reported once
+            if last != Some(list) && selector.matches(context) {
+                (self.on_match)(list, tag);
+                last = Some(list);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sax::parser::parse_in_chunks;
+
+    fn select(selectors: &[&str], doc: &[u8]) -> Vec<(usize, String)> {
+        let mut found = Vec::new();
+        let matcher = SelectorMatcher::new(selectors, |i, tag| {
+            let id = tag.attributes.iter().find(|a| a.name.value == b"id").map(|a| a.value.value.clone()).unwrap_or_default();
+            found.push((i, format!("{}#{} {:?}", String::from_utf8_lossy(&tag.name), String::from_utf8_lossy(&id), tag.open_start)));
+        })
+        .unwrap();
+        parse_in_chunks(matcher, &EVENTS, doc, 5);
+        found
+    }
+
+    const DOC: &[u8] = b"<html><body id=\"b\">\n<nav id=\"n\"><ul id=\"u\"><li id=\"l1\"><a id=\"a1\" class=\"external link\" href=\"https://x.org\">X</a></li>\n<li id=\"l2\" lang=\"en-GB\"><a id=\"a2\" href=\"/local.pdf\">Y</a></li><li id=\"l3\"></li></ul></nav>\n<P id=\"p\"><A id=\"a3\" class=\"external\">Z</A></P></body></html>";
+
+    #[test]
+    fn test_combinators() {
+        let found = select(&["nav a", "ul > li", "body > a", "body a.external", "#n .link"], DOC);
+        assert_eq!(
+            found,
+            [
+                (1, "li#l1 [1, 23]".to_string()),
+                (0, "a#a1 [1, 35]".to_string()),
+                (3, "a#a1 [1, 35]".to_string()),
+                (4, "a#a1 [1, 35]".to_string()),
+                (1, "li#l2 [2, 0]".to_string()),
+                (0, "a#a2 [2, 25]".to_string()),
+                (1, "li#l3 [2, 64]".to_string()),
+                (3, "A#a3 [3, 10]".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conditions() {
+        let found = select(
+            &[
+                "[lang|=en]",
+                "a[href^='https:']",
+                "a[href$=\".pdf\"]",
+                "[class*=tern]:first-child",
+                "li:nth-child(2n+1)",
+                "li:nth-child(even), p",
+                "*:nth-child(-n+1)[id=u]",
+            ],
+            DOC,
+        );
+        assert_eq!(
+            found,
+            [
+                (6, "ul#u [1, 12]".to_string()),
+                (4, "li#l1 [1, 23]".to_string()),
+                (1, "a#a1 [1, 35]".to_string()),
+                (3, "a#a1 [1, 35]".to_string()),
+                (0, "li#l2 [2, 0]".to_string()),
+                (5, "li#l2 [2, 0]".to_string()),
+                (2, "a#a2 [2, 25]".to_string()),
+                (4, "li#l3 [2, 64]".to_string()),
+                (5, "P#p [3, 0]".to_string()),
+                (3, "A#a3 [3, 10]".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nth() {
+        assert_eq!(nth("odd"), Some((2, 1)));
+        assert_eq!(nth(" 3 "), Some((0, 3)));
+        assert_eq!(nth("-n + 3"), Some((-1, 3)));
+        assert_eq!(nth("2n-1"), Some((2, -1)));
+        assert_eq!(nth("n"), Some((1, 0)));
+        assert_eq!(nth("2n+-1"), None);
+        assert_eq!(nth("x"), None);
+    }
+
+    #[test]
+    fn test_invalid_selectors() {
+        for selector in ["", "a >", "> a", "a,", "a + b", "a ~ b", "a:last-child", "a:hover", "[href", "a[x='1]", ":nth-child(x)", "a b)"] {
+            assert!(Selector::parse_list(selector).is_err(), "{}", selector);
+        }
+        assert!(Selector::parse("a, b").is_err());
+        assert!(Selector::parse("*").is_ok());
+    }
+}
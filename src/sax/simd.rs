@@ -0,0 +1,278 @@
+//! Byte classification over fixed size blocks.
+//!
+//! The scanners in this module examine [`BLOCK`] bytes at a time and report the
+//! matching positions as a bit mask where bit `n` corresponds to `block[n]`.
+//! The implementation is selected by target: `simd128` on wasm32, SSE2 on
+//! x86/x86_64, NEON on aarch64 and a portable scalar loop everywhere else.
+//! Every backend produces the same masks as [`scalar`], which keeps line and
+//! character tracking identical regardless of the platform the parser runs on.
+//!
+//! [`whitespace_masks`] drives [`GraphemeClusters::skip_whitespace`] and
+//! [`delimiter_masks`] drives the delimiter searches in
+//! [`GraphemeClusters::take_until_one_found`] and [`GraphemeClusters::take_until`].
+//!
+//! [`GraphemeClusters::skip_whitespace`]: super::grapheme_iterator::GraphemeClusters::skip_whitespace
+//! [`GraphemeClusters::take_until_one_found`]: super::grapheme_iterator::GraphemeClusters::take_until_one_found
+//! [`GraphemeClusters::take_until`]: super::grapheme_iterator::GraphemeClusters::take_until
+
+/// The number of bytes examined by a single scan.
+pub const BLOCK: usize = 16;
+
+/// Returns `true` for the XML whitespace characters: space, tab, CR and NL.
+#[inline(always)]
+pub fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+/// Classifies a block of bytes, returning `(whitespace_mask, newline_mask)`.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::simd::whitespace_masks;
+///
+/// let (ws, nl) = whitespace_masks(b"  \n\tabc         ");
+/// assert_eq!(ws & 0b1_1111, 0b0_1111);
+/// assert_eq!(nl, 0b100);
+/// ```
+#[inline(always)]
+pub fn whitespace_masks(block: &[u8; BLOCK]) -> (u32, u32) {
+    backend::whitespace_masks(block)
+}
+
+/// The bit masks produced by [`delimiter_masks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DelimiterMasks {
+    /// Bytes equal to one of the delimiters.
+    pub delimiters: u32,
+    /// `\n` bytes.
+    pub newlines: u32,
+    /// Bytes with the high bit set, i.e. the parts of multi-byte UTF-8 sequences.
+    pub non_ascii: u32,
+}
+
+/// Classifies a block of bytes against a small set of delimiters.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::simd::delimiter_masks;
+///
+/// let masks = delimiter_masks(b"a=\"\xC3\xA9\"\n/>       ", b"/>");
+/// assert_eq!(masks.delimiters, 0b1_1000_0000);
+/// assert_eq!(masks.newlines, 0b100_0000);
+/// assert_eq!(masks.non_ascii, 0b1_1000);
+/// ```
+#[inline(always)]
+pub fn delimiter_masks(block: &[u8; BLOCK], delimiters: &[u8]) -> DelimiterMasks {
+    backend::delimiter_masks(block, delimiters)
+}
+
+/// The portable implementation used where no vector backend is available.
+pub mod scalar {
+    use super::{is_whitespace, DelimiterMasks, BLOCK};
+
+    #[inline(always)]
+    pub fn whitespace_masks(block: &[u8; BLOCK]) -> (u32, u32) {
+        let mut ws = 0;
+        let mut nl = 0;
+        for (i, &byte) in block.iter().enumerate() {
+            ws |= (is_whitespace(byte) as u32) << i;
+            nl |= ((byte == b'\n') as u32) << i;
+        }
+        (ws, nl)
+    }
+
+    #[inline(always)]
+    pub fn delimiter_masks(block: &[u8; BLOCK], delimiters: &[u8]) -> DelimiterMasks {
+        let mut masks = DelimiterMasks {
+            delimiters: 0,
+            newlines: 0,
+            non_ascii: 0,
+        };
+        for (i, &byte) in block.iter().enumerate() {
+            masks.delimiters |= (delimiters.contains(&byte) as u32) << i;
+            masks.newlines |= ((byte == b'\n') as u32) << i;
+            masks.non_ascii |= ((byte >= 0x80) as u32) << i;
+        }
+        masks
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod backend {
+    use super::{DelimiterMasks, BLOCK};
+    use core::arch::wasm32::{i8x16_bitmask, i8x16_eq, i8x16_splat, v128, v128_load, v128_or};
+
+    #[inline(always)]
+    pub fn whitespace_masks(block: &[u8; BLOCK]) -> (u32, u32) {
+        // SAFETY: `block` is exactly 16 readable bytes and `v128_load` permits unaligned reads.
+        let chunk = unsafe { v128_load(block.as_ptr() as *const v128) };
+        let is_space = i8x16_eq(chunk, i8x16_splat(b' ' as i8));
+        let is_tab = i8x16_eq(chunk, i8x16_splat(b'\t' as i8));
+        let is_nl = i8x16_eq(chunk, i8x16_splat(b'\n' as i8));
+        let is_cr = i8x16_eq(chunk, i8x16_splat(b'\r' as i8));
+        let ws = v128_or(v128_or(is_space, is_tab), v128_or(is_nl, is_cr));
+        (i8x16_bitmask(ws) as u32, i8x16_bitmask(is_nl) as u32)
+    }
+
+    #[inline(always)]
+    pub fn delimiter_masks(block: &[u8; BLOCK], delimiters: &[u8]) -> DelimiterMasks {
+        // SAFETY: `block` is exactly 16 readable bytes and `v128_load` permits unaligned reads.
+        let chunk = unsafe { v128_load(block.as_ptr() as *const v128) };
+        let mut matches = i8x16_splat(0);
+        for &delimiter in delimiters {
+            matches = v128_or(matches, i8x16_eq(chunk, i8x16_splat(delimiter as i8)));
+        }
+        DelimiterMasks {
+            delimiters: i8x16_bitmask(matches) as u32,
+            newlines: i8x16_bitmask(i8x16_eq(chunk, i8x16_splat(b'\n' as i8))) as u32,
+            non_ascii: i8x16_bitmask(chunk) as u32,
+        }
+    }
+}
+
+#[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse2"))]
+mod backend {
+    use super::{DelimiterMasks, BLOCK};
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::{__m128i, _mm_cmpeq_epi8, _mm_loadu_si128, _mm_movemask_epi8, _mm_or_si128, _mm_set1_epi8, _mm_setzero_si128};
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::{__m128i, _mm_cmpeq_epi8, _mm_loadu_si128, _mm_movemask_epi8, _mm_or_si128, _mm_set1_epi8, _mm_setzero_si128};
+
+    #[inline(always)]
+    pub fn whitespace_masks(block: &[u8; BLOCK]) -> (u32, u32) {
+        // SAFETY: SSE2 is enabled for this target, `block` is exactly 16 readable bytes
+        // and `_mm_loadu_si128` permits unaligned reads.
+        unsafe {
+            let chunk = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+            let is_space = _mm_cmpeq_epi8(chunk, _mm_set1_epi8(b' ' as i8));
+            let is_tab = _mm_cmpeq_epi8(chunk, _mm_set1_epi8(b'\t' as i8));
+            let is_nl = _mm_cmpeq_epi8(chunk, _mm_set1_epi8(b'\n' as i8));
+            let is_cr = _mm_cmpeq_epi8(chunk, _mm_set1_epi8(b'\r' as i8));
+            let ws = _mm_or_si128(_mm_or_si128(is_space, is_tab), _mm_or_si128(is_nl, is_cr));
+            (_mm_movemask_epi8(ws) as u32, _mm_movemask_epi8(is_nl) as u32)
+        }
+    }
+
+    #[inline(always)]
+    pub fn delimiter_masks(block: &[u8; BLOCK], delimiters: &[u8]) -> DelimiterMasks {
+        // SAFETY: SSE2 is enabled for this target, `block` is exactly 16 readable bytes
+        // and `_mm_loadu_si128` permits unaligned reads.
+        unsafe {
+            let chunk = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+            let mut matches = _mm_setzero_si128();
+            for &delimiter in delimiters {
+                matches = _mm_or_si128(matches, _mm_cmpeq_epi8(chunk, _mm_set1_epi8(delimiter as i8)));
+            }
+            DelimiterMasks {
+                delimiters: _mm_movemask_epi8(matches) as u32,
+                newlines: _mm_movemask_epi8(_mm_cmpeq_epi8(chunk, _mm_set1_epi8(b'\n' as i8))) as u32,
+                non_ascii: _mm_movemask_epi8(chunk) as u32,
+            }
+        }
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+mod backend {
+    use super::{DelimiterMasks, BLOCK};
+    use core::arch::aarch64::{
+        uint8x16_t, vaddv_u8, vandq_u8, vceqq_u8, vcgeq_u8, vdupq_n_u8, vget_high_u8, vget_low_u8, vld1q_u8, vorrq_u8,
+    };
+
+    /// Per-lane bit weights used to emulate a movemask: each half sums to one byte.
+    const LANE_BITS: [u8; BLOCK] = [1, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128];
+
+    #[inline(always)]
+    fn bitmask(lanes: uint8x16_t) -> u32 {
+        // SAFETY: NEON is enabled for this target and `LANE_BITS` is exactly 16 readable bytes.
+        unsafe {
+            let weighted = vandq_u8(lanes, vld1q_u8(LANE_BITS.as_ptr()));
+            vaddv_u8(vget_low_u8(weighted)) as u32 | (vaddv_u8(vget_high_u8(weighted)) as u32) << 8
+        }
+    }
+
+    #[inline(always)]
+    pub fn whitespace_masks(block: &[u8; BLOCK]) -> (u32, u32) {
+        // SAFETY: NEON is enabled for this target, `block` is exactly 16 readable bytes
+        // and `vld1q_u8` permits unaligned reads.
+        unsafe {
+            let chunk = vld1q_u8(block.as_ptr());
+            let is_space = vceqq_u8(chunk, vdupq_n_u8(b' '));
+            let is_tab = vceqq_u8(chunk, vdupq_n_u8(b'\t'));
+            let is_nl = vceqq_u8(chunk, vdupq_n_u8(b'\n'));
+            let is_cr = vceqq_u8(chunk, vdupq_n_u8(b'\r'));
+            let ws = vorrq_u8(vorrq_u8(is_space, is_tab), vorrq_u8(is_nl, is_cr));
+            (bitmask(ws), bitmask(is_nl))
+        }
+    }
+
+    #[inline(always)]
+    pub fn delimiter_masks(block: &[u8; BLOCK], delimiters: &[u8]) -> DelimiterMasks {
+        // SAFETY: NEON is enabled for this target, `block` is exactly 16 readable bytes
+        // and `vld1q_u8` permits unaligned reads.
+        unsafe {
+            let chunk = vld1q_u8(block.as_ptr());
+            let mut matches = vdupq_n_u8(0);
+            for &delimiter in delimiters {
+                matches = vorrq_u8(matches, vceqq_u8(chunk, vdupq_n_u8(delimiter)));
+            }
+            DelimiterMasks {
+                delimiters: bitmask(matches),
+                newlines: bitmask(vceqq_u8(chunk, vdupq_n_u8(b'\n'))),
+                non_ascii: bitmask(vcgeq_u8(chunk, vdupq_n_u8(0x80))),
+            }
+        }
+    }
+}
+
+#[cfg(not(any(
+    all(target_arch = "wasm32", target_feature = "simd128"),
+    all(any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse2"),
+    all(target_arch = "aarch64", target_feature = "neon"),
+)))]
+mod backend {
+    pub use super::scalar::{delimiter_masks, whitespace_masks};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend_matches_scalar_for_every_byte_and_lane() {
+        for byte in 0..=255u8 {
+            for lane in 0..BLOCK {
+                let mut block = [b'a'; BLOCK];
+                block[lane] = byte;
+                assert_eq!(whitespace_masks(&block), scalar::whitespace_masks(&block), "byte {byte:#04x} in lane {lane}");
+            }
+        }
+    }
+
+    #[test]
+    fn delimiter_backend_matches_scalar_for_every_byte_and_lane() {
+        for delimiters in [&b""[..], b"<", b"<\n", b"=> \t\n", b">/ \n\t\r", b"\xC3\xA9"] {
+            for byte in 0..=255u8 {
+                for lane in 0..BLOCK {
+                    let mut block = [b'a'; BLOCK];
+                    block[lane] = byte;
+                    assert_eq!(
+                        delimiter_masks(&block, delimiters),
+                        scalar::delimiter_masks(&block, delimiters),
+                        "byte {byte:#04x} in lane {lane} with delimiters {delimiters:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn masks_cover_all_lanes() {
+        assert_eq!(whitespace_masks(&[b' '; BLOCK]), (0xFFFF, 0));
+        assert_eq!(whitespace_masks(&[b'\n'; BLOCK]), (0xFFFF, 0xFFFF));
+        assert_eq!(whitespace_masks(&[0x0C; BLOCK]), (0, 0));
+        assert_eq!(whitespace_masks(b"\r\n\t <a>\n\n\xF0\x9F\x9A\x80 \n "), (0b1110_0001_1000_1111, 0b0100_0001_1000_0010));
+    }
+}
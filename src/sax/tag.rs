@@ -1,5 +1,13 @@
+use std::error::Error;
+use std::fmt;
+use std::mem;
 use std::slice;
 
+use super::borrowed::{AttributeRef, TagRef, TextRef};
+use super::dtd::Declaration;
+use super::entities::EntityTable;
+use super::utils::advance_position;
+
 #[repr(C)]
 #[derive(Clone)]
 pub struct Tag {
@@ -13,6 +21,13 @@ pub struct Tag {
     pub close_end: [u64; 2],
     pub header: (usize, usize),
     pub byte_range: (u64, u64),
+    // Resolved namespace URI (namespace mode only)
+    pub uri: Vec<u8>,
+    // xmlns declarations made on this element
+    // as (prefix, uri) pairs (namespace mode only)
+    pub ns_bindings: Vec<(Vec<u8>, Vec<u8>)>,
+    // Closed by an ancestor's close tag or the end of the input
+    pub implicitly_closed: bool,
 }
 
 impl Tag {
@@ -29,9 +44,25 @@ impl Tag {
             close_start: [0; 2],
             close_end: [0; 2],
             byte_range: (0, 0),
+            uri: Vec::new(),
+            ns_bindings: Vec::new(),
+            implicitly_closed: false,
         }
     }
 
+    /// Returns the namespace prefix of the tag name
+    /// e.g. `svg` for `<svg:rect>` or an empty slice
+    /// when the name is not prefixed.
+    pub fn prefix(&self) -> &[u8] {
+        split_qname(&self.name).0
+    }
+
+    /// Returns the local part of the tag name
+    /// e.g. `rect` for `<svg:rect>`.
+    pub fn local_name(&self) -> &[u8] {
+        split_qname(&self.name).1
+    }
+
     pub fn get_name_slice(&mut self, ptr: *const u8) -> &[u8] {
         if !self.name.is_empty() {
             return self.get_name(ptr);
@@ -59,6 +90,44 @@ impl Tag {
         true
     }
 
+    /// Same as [`Text::hydrate_spanning`] for the name, attributes and text nodes.
+    pub fn hydrate_spanning(&mut self, ptr: *const u8) {
+        if !self.name.is_empty() {
+            self.hydrate(ptr);
+            return;
+        }
+        for a in &mut self.attributes {
+            a.hydrate_spanning(ptr);
+        }
+        for t in &mut self.text_nodes {
+            t.hydrate_spanning(ptr);
+        }
+    }
+
+    /// A view of the tag, see [`Text::view`].
+    pub fn view<'a>(&'a self, source: &'a [u8]) -> TagRef<'a> {
+        let pending = pending(self.header, source);
+        TagRef {
+            name: if pending.is_empty() {
+                &self.name
+            } else {
+                pending
+            },
+            uri: &self.uri,
+            self_closing: self.self_closing,
+            implicitly_closed: self.implicitly_closed,
+            open_start: self.open_start,
+            open_end: self.open_end,
+            close_start: self.close_start,
+            close_end: self.close_end,
+            byte_range: self.byte_range,
+            ns_bindings: &self.ns_bindings,
+            attributes: &self.attributes,
+            text_nodes: &self.text_nodes,
+            source,
+        }
+    }
+
     fn get_name(&mut self, ptr: *const u8) -> &Vec<u8> {
         let (start, end) = self.header;
         if start > end {
@@ -76,6 +145,17 @@ impl Tag {
         self.header.1 = 0;
         &self.name
     }
+
+    /// Maps every byte offset in the tag, its attributes and text nodes.
+    pub fn map_byte_ranges(&mut self, map: &dyn Fn(u64) -> u64) {
+        self.byte_range = (map(self.byte_range.0), map(self.byte_range.1));
+        for a in &mut self.attributes {
+            a.map_byte_ranges(map);
+        }
+        for t in &mut self.text_nodes {
+            t.map_byte_ranges(map);
+        }
+    }
 }
 
 #[repr(C)]
@@ -86,6 +166,9 @@ pub struct Text {
     pub start: [u64; 2],
     pub end: [u64; 2],
     pub byte_range: (u64, u64),
+    // The undecoded source bytes when entity
+    // decoding replaced references in `value`
+    pub raw: Vec<u8>,
 }
 
 impl Text {
@@ -96,9 +179,28 @@ impl Text {
             end: [0; 2],
             header: (0, 0),
             byte_range: (0, 0),
+            raw: Vec::new(),
         };
     }
 
+    /// Resolves the predefined entities, numeric character references
+    /// and the entities declared in `entities` in the (hydrated) value.
+    /// When anything was decoded, the original bytes are kept in `raw`.
+    pub fn decode(&mut self, entities: &EntityTable) {
+        if let Some(decoded) = entities.decode(&self.value) {
+            self.raw = mem::replace(&mut self.value, decoded);
+        }
+    }
+
+    /// Returns the value exactly as it appeared in the source.
+    pub fn raw_value(&self) -> &[u8] {
+        if self.raw.is_empty() {
+            &self.value
+        } else {
+            &self.raw
+        }
+    }
+
     pub fn get_value_slice(&mut self, ptr: *const u8, ptr_len: usize) -> &[u8] {
         let mut sl = &[] as &[u8];
 
@@ -135,6 +237,40 @@ impl Text {
         self.value.extend_from_slice(sl);
         true
     }
+
+    /// Hydrates the value only when the text spans chunks i.e. part of it
+    /// was hydrated at the end of an earlier chunk. Otherwise the pending
+    /// bytes are left in the chunk for [`view`](Self::view) to borrow.
+    /// Returns what [`hydrate`](Self::hydrate) would.
+    pub fn hydrate_spanning(&mut self, ptr: *const u8) -> bool {
+        if !self.value.is_empty() {
+            return self.hydrate(ptr);
+        }
+        self.header.0 <= self.header.1
+    }
+
+    /// A view of the text. The value is borrowed from `source` (the chunk
+    /// being parsed) unless it was hydrated. A text spanning chunks must be
+    /// hydrated first, see [`hydrate_spanning`](Self::hydrate_spanning).
+    pub fn view<'a>(&'a self, source: &'a [u8]) -> TextRef<'a> {
+        let pending = pending(self.header, source);
+        debug_assert!(pending.is_empty() || self.value.is_empty());
+        TextRef {
+            value: if pending.is_empty() {
+                &self.value
+            } else {
+                pending
+            },
+            raw: &self.raw,
+            start: self.start,
+            end: self.end,
+            byte_range: self.byte_range,
+        }
+    }
+
+    pub fn map_byte_ranges(&mut self, map: &dyn Fn(u64) -> u64) {
+        self.byte_range = (map(self.byte_range.0), map(self.byte_range.1));
+    }
 }
 
 #[repr(C)]
@@ -144,6 +280,8 @@ pub struct Attribute {
     pub value: Text,
     pub attr_type: AttrType,
     pub byte_range: (u64, u64),
+    // Resolved namespace URI (namespace mode only)
+    pub uri: Vec<u8>,
 }
 
 impl Attribute {
@@ -153,12 +291,78 @@ impl Attribute {
             value: Text::new([0; 2]),
             attr_type: AttrType::NoValue,
             byte_range: (0, 0),
+            uri: Vec::new(),
         };
     }
 
     pub fn hydrate(&mut self, ptr: *const u8) -> bool {
         self.name.hydrate(ptr) | self.value.hydrate(ptr)
     }
+
+    /// Same as [`Text::hydrate_spanning`] for the name and value.
+    pub fn hydrate_spanning(&mut self, ptr: *const u8) -> bool {
+        self.name.hydrate_spanning(ptr) | self.value.hydrate_spanning(ptr)
+    }
+
+    /// A view of the attribute, see [`Text::view`].
+    pub fn view<'a>(&'a self, source: &'a [u8]) -> AttributeRef<'a> {
+        AttributeRef {
+            name: self.name.view(source),
+            value: self.value.view(source),
+            attr_type: self.attr_type,
+            byte_range: self.byte_range,
+            uri: &self.uri,
+        }
+    }
+
+    pub fn map_byte_ranges(&mut self, map: &dyn Fn(u64) -> u64) {
+        self.name.map_byte_ranges(map);
+        self.value.map_byte_ranges(map);
+        self.byte_range = (map(self.byte_range.0), map(self.byte_range.1));
+    }
+
+    /// Returns the namespace prefix of the attribute name
+    /// e.g. `xlink` for `xlink:href` or an empty slice
+    /// when the name is not prefixed.
+    pub fn prefix(&self) -> &[u8] {
+        split_qname(&self.name.value).0
+    }
+
+    /// Returns the local part of the attribute name
+    /// e.g. `href` for `xlink:href`.
+    pub fn local_name(&self) -> &[u8] {
+        split_qname(&self.name.value).1
+    }
+}
+
+/// Splits a qualified name into its prefix and local part.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::tag::split_qname;
+///
+/// assert_eq!(split_qname(b"xlink:href"), (&b"xlink"[..], &b"href"[..]));
+/// assert_eq!(split_qname(b"href"), (&b""[..], &b"href"[..]));
+/// ```
+pub fn split_qname(name: &[u8]) -> (&[u8], &[u8]) {
+    match name.iter().position(|&b| b == b':') {
+        Some(idx) => (&name[..idx], &name[idx + 1..]),
+        None => (&[], name),
+    }
+}
+
+/// The bytes `header` refers to in `source` which are not hydrated yet.
+/// Mirrors `Text::hydrate` and `Tag::get_name`.
+fn pending(header: (usize, usize), source: &[u8]) -> &[u8] {
+    let (start, end) = header;
+    if start < end {
+        &source[start..end]
+    } else if start > 0 && start == end {
+        &source[start..start + 1]
+    } else {
+        &[]
+    }
 }
 
 #[repr(C)]
@@ -185,6 +389,94 @@ impl ProcInst {
     pub fn hydrate(&mut self, ptr: *const u8) -> bool {
         self.target.hydrate(ptr) | self.content.hydrate(ptr)
     }
+
+    pub fn map_byte_ranges(&mut self, map: &dyn Fn(u64) -> u64) {
+        self.target.map_byte_ranges(map);
+        self.content.map_byte_ranges(map);
+        self.byte_range = (map(self.byte_range.0), map(self.byte_range.1));
+    }
+}
+
+/// The XML declaration e.g. `<?xml version="1.0" encoding="UTF-8"?>`.
+///
+/// Each pseudo-attribute value is a `Text` with its own positions.
+/// Pseudo-attributes that are not present are left empty.
+#[repr(C)]
+#[derive(Clone)]
+pub struct XmlDecl {
+    pub start: [u64; 2],
+    pub end: [u64; 2],
+    pub version: Text,
+    pub encoding: Text,
+    pub standalone: Text,
+    pub byte_range: (u64, u64),
+}
+
+impl XmlDecl {
+    /// Builds the XML declaration from a (hydrated) processing
+    /// instruction whose target is `xml`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sax_wasm::sax::tag::{ProcInst, XmlDecl};
+    ///
+    /// let mut proc_inst = ProcInst::new();
+    /// proc_inst.content.start = [0, 6];
+    /// proc_inst.content.byte_range = (6, 38);
+    /// proc_inst.content.value = br#"version="1.0" standalone='yes'"#.to_vec();
+    /// let xml_decl = XmlDecl::from_proc_inst(&proc_inst);
+    ///
+    /// assert_eq!(xml_decl.version.value, b"1.0");
+    /// assert_eq!(xml_decl.version.start, [0, 15]);
+    /// assert_eq!(xml_decl.standalone.value, b"yes");
+    /// assert_eq!(xml_decl.standalone.byte_range, (32, 35));
+    /// assert!(xml_decl.encoding.value.is_empty());
+    /// ```
+    pub fn from_proc_inst(proc_inst: &ProcInst) -> XmlDecl {
+        let mut xml_decl = XmlDecl {
+            start: proc_inst.start,
+            end: proc_inst.end,
+            version: Text::new([0; 2]),
+            encoding: Text::new([0; 2]),
+            standalone: Text::new([0; 2]),
+            byte_range: proc_inst.byte_range,
+        };
+        let content = &proc_inst.content;
+        let bytes = content.value.as_slice();
+        let mut idx = 0;
+        while let Some(eq) = bytes[idx..].iter().position(|&b| b == b'=') {
+            let name = bytes[idx..idx + eq].trim_ascii();
+            idx += eq + 1;
+            idx += bytes[idx..].iter().take_while(|b| b.is_ascii_whitespace()).count();
+            let Some(&quote) = bytes.get(idx).filter(|&&b| b == b'"' || b == b'\'') else {
+                break;
+            };
+            let Some(len) = bytes[idx + 1..].iter().position(|&b| b == quote) else {
+                break;
+            };
+            let value_start = idx + 1;
+            idx = value_start + len + 1;
+            let text = match name {
+                b"version" => &mut xml_decl.version,
+                b"encoding" => &mut xml_decl.encoding,
+                b"standalone" => &mut xml_decl.standalone,
+                _ => continue,
+            };
+            text.start = advance_position(content.start, &bytes[..value_start]);
+            text.end = advance_position(text.start, &bytes[value_start..value_start + len]);
+            text.byte_range = (content.byte_range.0 + value_start as u64, content.byte_range.0 + (value_start + len) as u64);
+            text.value = bytes[value_start..value_start + len].to_vec();
+        }
+        xml_decl
+    }
+
+    pub fn map_byte_ranges(&mut self, map: &dyn Fn(u64) -> u64) {
+        self.version.map_byte_ranges(map);
+        self.encoding.map_byte_ranges(map);
+        self.standalone.map_byte_ranges(map);
+        self.byte_range = (map(self.byte_range.0), map(self.byte_range.1));
+    }
 }
 
 #[repr(C)]
@@ -194,6 +486,9 @@ pub enum Entity<'a> {
     ProcInst(&'a ProcInst),
     Tag(&'a Tag),
     Text(&'a Text),
+    Error(&'a ParseError),
+    Declaration(&'a Declaration),
+    XmlDecl(&'a XmlDecl),
 }
 
 pub enum Dispatched {
@@ -201,6 +496,106 @@ pub enum Dispatched {
     ProcInst(Box<ProcInst>),
     Tag(Box<Tag>),
     Text(Box<Text>),
+    Error(Box<ParseError>),
+    Declaration(Box<Declaration>),
+    XmlDecl(Box<XmlDecl>),
+}
+
+impl Dispatched {
+    pub fn as_entity(&self) -> Entity<'_> {
+        match self {
+            Dispatched::Attribute(attribute) => Entity::Attribute(attribute),
+            Dispatched::ProcInst(proc_inst) => Entity::ProcInst(proc_inst),
+            Dispatched::Tag(tag) => Entity::Tag(tag),
+            Dispatched::Text(text) => Entity::Text(text),
+            Dispatched::Error(error) => Entity::Error(error),
+            Dispatched::Declaration(declaration) => Entity::Declaration(declaration),
+            Dispatched::XmlDecl(xml_decl) => Entity::XmlDecl(xml_decl),
+        }
+    }
+
+    /// Maps every byte offset in the dispatched entity
+    /// e.g. from transcoded UTF-8 back to the original input.
+    pub fn map_byte_ranges(&mut self, map: &dyn Fn(u64) -> u64) {
+        match self {
+            Dispatched::Attribute(attribute) => attribute.map_byte_ranges(map),
+            Dispatched::ProcInst(proc_inst) => proc_inst.map_byte_ranges(map),
+            Dispatched::Tag(tag) => tag.map_byte_ranges(map),
+            Dispatched::Text(text) => text.map_byte_ranges(map),
+            Dispatched::Error(error) => error.byte_range = (map(error.byte_range.0), map(error.byte_range.1)),
+            Dispatched::Declaration(declaration) => declaration.map_byte_ranges(map),
+            Dispatched::XmlDecl(xml_decl) => xml_decl.map_byte_ranges(map),
+        }
+    }
+}
+
+/// A problem found in the document.
+///
+/// The parser never stops on malformed input. Problems are
+/// reported using the `Error` event and parsing continues.
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct ParseError {
+    pub code: ErrorCode,
+    pub message: Vec<u8>,
+    pub start: [u64; 2],
+    pub end: [u64; 2],
+    pub byte_range: (u64, u64),
+}
+
+impl ParseError {
+    pub fn new(code: ErrorCode, message: String) -> ParseError {
+        ParseError {
+            code,
+            message: message.into_bytes(),
+            start: [0; 2],
+            end: [0; 2],
+            byte_range: (0, 0),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Positions are zero based
+        let [line, character] = self.start;
+        write!(f, "{} at line {}, character {}", String::from_utf8_lossy(&self.message), line + 1, character + 1)
+    }
+}
+
+impl Error for ParseError {}
+
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ErrorCode {
+    // <x:a> without xmlns:x in scope
+    UnboundPrefix = 1,
+    // </a> without an open <a>
+    UnmatchedCloseTag = 2,
+    // <a> implicitly closed by a parent or still open at EOF
+    UnclosedTag = 3,
+    // Comment, CDATA, PI, DOCTYPE or tag cut off at EOF
+    UnexpectedEof = 4,
+    // '<' not followed by a name e.g. '1 < 2'
+    InvalidTagStart = 5,
+    // <1a> or <a b$c="d"> (strict mode only)
+    InvalidName = 6,
+    // <!-- a -- b --> (strict mode only)
+    DoubleHyphenInComment = 7,
+    // <a b="1" b="2"> (strict mode only)
+    DuplicateAttribute = 8,
+    // <a b=c> or <a b={c}> (strict mode only)
+    UnquotedAttributeValue = 9,
+    // <a b> (strict mode only)
+    MissingAttributeValue = 10,
+    // <a/><b/> (strict mode only)
+    MultipleRoots = 11,
+    // text before or after the root element (strict mode only)
+    TextOutsideRoot = 12,
+    // <a><b></a> (strict mode only)
+    MismatchedCloseTag = 13,
+    // <?xml ...?> anywhere but the very start of the document
+    MisplacedXmlDecl = 14,
 }
 
 #[derive(Clone, Copy)]
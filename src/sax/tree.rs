@@ -0,0 +1,528 @@
+//! An in-memory tree of a parsed document.
+//!
+//! The nodes are kept in a single arena in document order and refer to each
+//! other by [`NodeId`]. Every node keeps the data of the event it was built
+//! from, including its positions and byte range, so a node can be found by
+//! an offset into the input using [`Document::node_at`].
+//!
+//! Whitespace only text is not generated by the parser so it is not part of
+//! the tree. The `text_nodes` of the tag of an element are not collected;
+//! the text of an element is found among its children.
+//!
+//! # Examples
+//!
+//! ```
+//! use sax_wasm::sax::tree::{Document, NodeKind};
+//!
+//! let doc = b"<catalog><product id=\"1\">Tea<!-- new --></product></catalog>";
+//! let document = Document::parse(doc);
+//! let catalog = document.document_element().unwrap();
+//! let product = document.first_child(catalog).unwrap();
+//! let NodeKind::Element(tag) = &document[product].kind else { panic!() };
+//! assert_eq!(tag.byte_range, (9, 50));
+//! let text = document.children(product).next().unwrap();
+//! assert_eq!(document.node_at(25), text);
+//! assert!(matches!(&document[text].kind, NodeKind::Text(text) if text.value == b"Tea"));
+//! assert_eq!(document.parent(text), Some(product));
+//! ```
+
+use std::ops::Index;
+
+use super::parser::{subscribe, Event, SAXParser, TypedEventHandler};
+use super::tag::{Attribute, ParseError, ProcInst, Tag, Text, XmlDecl};
+
+/// Refers to a node of a [`Document`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    /// The position of the node in document order, the document being 0.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// The data of a node.
+#[derive(Clone)]
+pub enum NodeKind {
+    /// The document node containing every other node.
+    Document {
+        end: [u64; 2],
+        byte_end: u64,
+    },
+    /// An element with its attributes. The positions of its
+    /// close tag are set when the element is closed.
+    Element(Tag),
+    Text(Text),
+    Cdata(Text),
+    Comment(Text),
+    Doctype(Text),
+    ProcessingInstruction(ProcInst),
+    XmlDecl(XmlDecl),
+}
+
+/// A node of a [`Document`].
+#[derive(Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    parent: Option<NodeId>,
+    first_child: Option<NodeId>,
+    last_child: Option<NodeId>,
+    previous_sibling: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+}
+
+impl Node {
+    fn new(kind: NodeKind) -> Node {
+        Node {
+            kind,
+            parent: None,
+            first_child: None,
+            last_child: None,
+            previous_sibling: None,
+            next_sibling: None,
+        }
+    }
+
+    /// Where the node starts, the start tag for an element.
+    pub fn start(&self) -> [u64; 2] {
+        match &self.kind {
+            NodeKind::Document {
+                ..
+            } => [0; 2],
+            NodeKind::Element(tag) => tag.open_start,
+            NodeKind::Text(text) | NodeKind::Cdata(text) | NodeKind::Comment(text) | NodeKind::Doctype(text) => text.start,
+            NodeKind::ProcessingInstruction(proc_inst) => proc_inst.start,
+            NodeKind::XmlDecl(xml_decl) => xml_decl.start,
+        }
+    }
+
+    /// Where the node ends, the end of the close tag for an element
+    /// or of the start tag for a self-closing one.
+    pub fn end(&self) -> [u64; 2] {
+        match &self.kind {
+            NodeKind::Document {
+                end,
+                ..
+            } => *end,
+            NodeKind::Element(tag) if tag.self_closing => tag.open_end,
+            NodeKind::Element(tag) => tag.close_end,
+            NodeKind::Text(text) | NodeKind::Cdata(text) | NodeKind::Comment(text) | NodeKind::Doctype(text) => text.end,
+            NodeKind::ProcessingInstruction(proc_inst) => proc_inst.end,
+            NodeKind::XmlDecl(xml_decl) => xml_decl.end,
+        }
+    }
+
+    /// The bytes of the input the node was read from.
+    pub fn byte_range(&self) -> (u64, u64) {
+        match &self.kind {
+            NodeKind::Document {
+                byte_end,
+                ..
+            } => (0, *byte_end),
+            NodeKind::Element(tag) => tag.byte_range,
+            NodeKind::Text(text) | NodeKind::Cdata(text) | NodeKind::Comment(text) | NodeKind::Doctype(text) => text.byte_range,
+            NodeKind::ProcessingInstruction(proc_inst) => proc_inst.byte_range,
+            NodeKind::XmlDecl(xml_decl) => xml_decl.byte_range,
+        }
+    }
+
+    /// The tag of an element.
+    pub fn tag(&self) -> Option<&Tag> {
+        match &self.kind {
+            NodeKind::Element(tag) => Some(tag),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed document. Index it with a [`NodeId`] to get a [`Node`].
+#[derive(Clone)]
+pub struct Document {
+    nodes: Vec<Node>,
+    errors: Vec<ParseError>,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Document::new()
+    }
+}
+
+impl Document {
+    /// A document without nodes besides the document node.
+    pub fn new() -> Document {
+        Document {
+            nodes: vec![Node::new(NodeKind::Document {
+                end: [0; 2],
+                byte_end: 0,
+            })],
+            errors: Vec::new(),
+        }
+    }
+
+    /// Parses a complete document using the default options of the parser.
+    /// Use a [`TreeBuilder`] to configure the parser or write the document
+    /// in chunks.
+    pub fn parse(source: &[u8]) -> Document {
+        let mut builder = TreeBuilder::new();
+        let mut parser = SAXParser::new_typed(&mut builder);
+        subscribe(&mut parser.events, &EVENTS);
+        parser.write(source);
+        parser.identity();
+        drop(parser);
+        builder.finish()
+    }
+
+    /// The document node.
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// The first element at the top level.
+    pub fn document_element(&self) -> Option<NodeId> {
+        self.children(self.root()).find(|&id| matches!(self[id].kind, NodeKind::Element(_)))
+    }
+
+    /// The number of nodes including the document node.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the document has no nodes besides the document node.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+
+    /// Every node in document order.
+    pub fn nodes(&self) -> impl ExactSizeIterator<Item = (NodeId, &Node)> {
+        self.nodes.iter().enumerate().map(|(i, node)| (NodeId(i), node))
+    }
+
+    /// The problems reported while parsing.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self[id].parent
+    }
+
+    pub fn first_child(&self, id: NodeId) -> Option<NodeId> {
+        self[id].first_child
+    }
+
+    pub fn last_child(&self, id: NodeId) -> Option<NodeId> {
+        self[id].last_child
+    }
+
+    pub fn previous_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self[id].previous_sibling
+    }
+
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self[id].next_sibling
+    }
+
+    /// The children of a node in document order.
+    pub fn children(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self[id].first_child, move |&child| self[child].next_sibling)
+    }
+
+    /// The parent of a node, its parent and so on up to the document node.
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self[id].parent, move |&parent| self[parent].parent)
+    }
+
+    /// The nodes within a node in document order.
+    pub fn descendants(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        // Nodes are stored in document order, so the descendants
+        // are the nodes up to the next node outside of it
+        let end = std::iter::once(id)
+            .chain(self.ancestors(id))
+            .find_map(|ancestor| self[ancestor].next_sibling)
+            .map_or(self.nodes.len(), NodeId::index);
+        (id.0 + 1..end).map(NodeId)
+    }
+
+    /// The innermost node whose bytes contain `byte_offset`,
+    /// the document node when no other node does.
+    pub fn node_at(&self, byte_offset: u64) -> NodeId {
+        let mut id = self.root();
+        while let Some(child) = self.children(id).find(|&child| {
+            let (start, end) = self[child].byte_range();
+            start <= byte_offset && byte_offset < end
+        }) {
+            id = child;
+        }
+        id
+    }
+
+    /// The element and attribute whose bytes contain `byte_offset`.
+    pub fn attribute_at(&self, byte_offset: u64) -> Option<(NodeId, &Attribute)> {
+        let id = self.node_at(byte_offset);
+        let attribute = self[id].tag()?.attributes.iter().find(|a| a.byte_range.0 <= byte_offset && byte_offset < a.byte_range.1)?;
+        Some((id, attribute))
+    }
+
+    /// Adds a node as the last child of `parent`.
+    fn append(&mut self, parent: NodeId, kind: NodeKind) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        let mut node = Node::new(kind);
+        node.parent = Some(parent);
+        node.previous_sibling = self[parent].last_child;
+        match node.previous_sibling {
+            Some(previous) => self.nodes[previous.0].next_sibling = Some(id),
+            None => self.nodes[parent.0].first_child = Some(id),
+        }
+        self.nodes[parent.0].last_child = Some(id);
+        self.nodes.push(node);
+        id
+    }
+
+    /// Extends the document node to the end of `id`.
+    fn extend(&mut self, id: NodeId) {
+        let (node_end, node_byte_end) = (self[id].end(), self[id].byte_range().1);
+        if let NodeKind::Document {
+            end,
+            byte_end,
+        } = &mut self.nodes[0].kind
+        {
+            if node_byte_end >= *byte_end {
+                *end = node_end;
+                *byte_end = node_byte_end;
+            }
+        }
+    }
+}
+
+impl Index<NodeId> for Document {
+    type Output = Node;
+
+    fn index(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+}
+
+/// The events the parser must be subscribed to.
+pub const EVENTS: [Event; 9] = [
+    Event::Text,
+    Event::ProcessingInstruction,
+    Event::Doctype,
+    Event::Comment,
+    Event::OpenTag,
+    Event::CloseTag,
+    Event::Cdata,
+    Event::Error,
+    Event::XmlDecl,
+];
+
+/// Builds a [`Document`] from the events of a parser.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::parser::{subscribe, SAXParser};
+/// use sax_wasm::sax::tree::{TreeBuilder, EVENTS};
+///
+/// let mut builder = TreeBuilder::new();
+/// let mut parser = SAXParser::new_typed(&mut builder);
+/// subscribe(&mut parser.events, &EVENTS);
+/// parser.namespaces = true;
+/// parser.write(b"<a xmlns=\"urn:a\"><b/>");
+/// parser.write(b"</a>");
+/// parser.identity();
+/// drop(parser);
+/// let document = builder.finish();
+/// let b = document.descendants(document.root()).last().unwrap();
+/// assert_eq!(document[b].tag().unwrap().uri, b"urn:a");
+/// ```
+pub struct TreeBuilder {
+    document: Document,
+    // The document node followed by the open elements
+    open: Vec<NodeId>,
+}
+
+impl Default for TreeBuilder {
+    fn default() -> Self {
+        TreeBuilder::new()
+    }
+}
+
+impl TreeBuilder {
+    pub fn new() -> TreeBuilder {
+        TreeBuilder {
+            document: Document::new(),
+            open: vec![NodeId(0)],
+        }
+    }
+
+    /// The document built so far. Elements
+    /// still open have no close tag positions.
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// The document after the parser was given all of it.
+    pub fn finish(self) -> Document {
+        self.document
+    }
+
+    fn append(&mut self, kind: NodeKind) -> NodeId {
+        let parent = self.open[self.open.len() - 1];
+        let id = self.document.append(parent, kind);
+        if parent == self.document.root() {
+            self.document.extend(id);
+        }
+        id
+    }
+}
+
+impl TypedEventHandler for TreeBuilder {
+    fn text(&mut self, text: &Text) {
+        self.append(NodeKind::Text(text.clone()));
+    }
+
+    fn processing_instruction(&mut self, proc_inst: &ProcInst) {
+        self.append(NodeKind::ProcessingInstruction(proc_inst.clone()));
+    }
+
+    fn doctype(&mut self, doctype: &Text) {
+        self.append(NodeKind::Doctype(doctype.clone()));
+    }
+
+    fn comment(&mut self, comment: &Text) {
+        self.append(NodeKind::Comment(comment.clone()));
+    }
+
+    fn open_tag(&mut self, tag: &Tag) {
+        let id = self.append(NodeKind::Element(tag.clone()));
+        self.open.push(id);
+    }
+
+    fn close_tag(&mut self, tag: &Tag) {
+        // Elements are closed innermost first, including
+        // the ones closed by an ancestor's close tag
+        let Some(position) =
+            self.open.iter().rposition(|&id| self.document[id].tag().is_some_and(|open| open.byte_range.0 == tag.byte_range.0))
+        else {
+            return;
+        };
+        let id = self.open[position];
+        self.open.truncate(position);
+        if let NodeKind::Element(element) = &mut self.document.nodes[id.0].kind {
+            element.close_start = tag.close_start;
+            element.close_end = tag.close_end;
+            element.byte_range = tag.byte_range;
+            element.self_closing = tag.self_closing;
+            element.implicitly_closed = tag.implicitly_closed;
+        }
+        if position == 1 {
+            self.document.extend(id);
+        }
+    }
+
+    fn cdata(&mut self, cdata: &Text) {
+        self.append(NodeKind::Cdata(cdata.clone()));
+    }
+
+    fn error(&mut self, error: &ParseError) {
+        self.document.errors.push(error.clone());
+    }
+
+    fn xml_decl(&mut self, xml_decl: &XmlDecl) {
+        self.append(NodeKind::XmlDecl(xml_decl.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe(document: &Document, id: NodeId) -> String {
+        let node = &document[id];
+        let description = match &node.kind {
+            NodeKind::Document {
+                ..
+            } => "document".to_string(),
+            NodeKind::Element(tag) => format!("<{}>", String::from_utf8_lossy(&tag.name)),
+            NodeKind::Text(text) => format!("text {}", String::from_utf8_lossy(&text.value)),
+            NodeKind::Cdata(text) => format!("cdata {}", String::from_utf8_lossy(&text.value)),
+            NodeKind::Comment(text) => format!("comment {}", String::from_utf8_lossy(&text.value)),
+            NodeKind::Doctype(text) => format!("doctype {}", String::from_utf8_lossy(&text.value)),
+            NodeKind::ProcessingInstruction(proc_inst) => format!("pi {}", String::from_utf8_lossy(&proc_inst.target.value)),
+            NodeKind::XmlDecl(xml_decl) => format!("xml {}", String::from_utf8_lossy(&xml_decl.version.value)),
+        };
+        format!("{} {:?} {:?} {:?}", description, node.start(), node.end(), node.byte_range())
+    }
+
+    const DOC: &[u8] =
+        b"<?xml version=\"1.0\"?>\n<!DOCTYPE r>\n<r a=\"1\">\n  <p>one<b>two</b></p>\n  <![CDATA[x<y]]><?pi data?><e/>\n</r>\n<!-- end -->";
+
+    #[test]
+    fn test_builds_tree() {
+        for chunk_size in [5, 7, DOC.len()] {
+            let mut builder = TreeBuilder::new();
+            let mut parser = SAXParser::new_typed(&mut builder);
+            subscribe(&mut parser.events, &EVENTS);
+            for chunk in DOC.chunks(chunk_size) {
+                parser.write(chunk);
+            }
+            parser.identity();
+            drop(parser);
+            let document = builder.finish();
+            let mut lines = Vec::new();
+            for (id, _) in document.nodes() {
+                let depth = document.ancestors(id).count();
+                lines.push(format!("{}{}", "  ".repeat(depth), describe(&document, id)));
+            }
+            assert_eq!(
+                lines,
+                [
+                    "document [0, 0] [6, 12] (0, 118)",
+                    "  xml 1.0 [0, 0] [0, 21] (0, 21)",
+                    "  doctype r [1, 0] [1, 12] (22, 34)",
+                    "  <r> [2, 0] [5, 4] (35, 105)",
+                    "    <p> [3, 2] [3, 22] (47, 67)",
+                    "      text one [3, 5] [3, 8] (50, 53)",
+                    "      <b> [3, 8] [3, 18] (53, 63)",
+                    "        text two [3, 11] [3, 14] (56, 59)",
+                    "    cdata x<y [4, 2] [4, 17] (70, 85)",
+                    "    pi pi [4, 17] [4, 28] (85, 96)",
+                    "    <e> [4, 28] [4, 32] (96, 100)",
+                    "  comment  end  [6, 0] [6, 12] (106, 118)",
+                ],
+                "chunk size {}",
+                chunk_size
+            );
+        }
+    }
+
+    #[test]
+    fn test_navigation_and_lookup() {
+        let document = Document::parse(DOC);
+        let r = document.document_element().unwrap();
+        let children: Vec<_> = document.children(r).map(|id| describe(&document, id)).collect();
+        assert_eq!(children.len(), 4);
+        let p = document.first_child(r).unwrap();
+        let e = document.last_child(r).unwrap();
+        assert_eq!(document.next_sibling(p).and_then(|id| document.next_sibling(id)), document.previous_sibling(e));
+        assert_eq!(document.previous_sibling(p), None);
+        assert_eq!(document.next_sibling(e), None);
+        assert_eq!(document.descendants(p).count(), 3);
+        assert_eq!(document.descendants(r).count(), 7);
+        assert_eq!(document.descendants(document.root()).count(), document.len() - 1);
+
+        let b = document.last_child(p).unwrap();
+        assert_eq!(document.ancestors(b).collect::<Vec<_>>(), [p, r, document.root()]);
+        assert_eq!(document.node_at(57), document.first_child(b).unwrap());
+        assert_eq!(document.node_at(54), b);
+        assert_eq!(document.node_at(46), r);
+        assert_eq!(document.node_at(22), document.first_child(document.root()).and_then(|id| document.next_sibling(id)).unwrap());
+        assert_eq!(document.node_at(1000), document.root());
+        let (element, attribute) = document.attribute_at(39).unwrap();
+        assert_eq!((element, &attribute.name.value[..]), (r, &b"a"[..]));
+        assert!(document.attribute_at(45).is_none());
+        assert!(document.errors().is_empty());
+    }
+}
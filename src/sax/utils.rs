@@ -124,6 +124,65 @@ pub fn grapheme_len(byte: u8) -> usize {
     }
 }
 
+/// Counts the UTF-16 code units needed to represent UTF-8 bytes.
+///
+/// Positions reported by the parser are in UTF-16 code units so they
+/// line up with JavaScript strings. 4-byte sequences count as 2.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::utils::utf16_len;
+///
+/// assert_eq!(utf16_len("abc".as_bytes()), 3);
+/// assert_eq!(utf16_len("é🐉".as_bytes()), 3);
+/// ```
+pub fn utf16_len(bytes: &[u8]) -> u64 {
+    let mut len = 0;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let grapheme_len = grapheme_len(bytes[idx]);
+        len += if grapheme_len == 4 {
+            2
+        } else {
+            1
+        };
+        idx += grapheme_len;
+    }
+    len
+}
+
+/// Advances a `[line, character]` position over UTF-8 bytes
+/// the same way the parser counts lines and UTF-16 characters.
+///
+/// # Examples
+///
+/// ```
+/// use sax_wasm::sax::utils::advance_position;
+///
+/// assert_eq!(advance_position([0, 2], b"ab"), [0, 4]);
+/// assert_eq!(advance_position([0, 2], b"a\nbc"), [1, 2]);
+/// ```
+pub fn advance_position(position: [u64; 2], bytes: &[u8]) -> [u64; 2] {
+    let [mut line, mut character] = position;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let grapheme_len = grapheme_len(bytes[idx]);
+        if bytes[idx] == b'\n' {
+            line += 1;
+            character = 0;
+        } else {
+            character += if grapheme_len == 4 {
+                2
+            } else {
+                1
+            };
+        }
+        idx += grapheme_len;
+    }
+    [line, character]
+}
+
 #[inline(always)]
 pub fn ascii_contains(haystack: &[u8], byte: u8) -> bool {
     match haystack {
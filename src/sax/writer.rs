@@ -0,0 +1,504 @@
+//! Writes XML to any byte sink.
+//!
+//! An [`XmlWriter`] escapes text and attribute values and keeps track of the
+//! open elements so its output is well-formed: elements are closed in order,
+//! there is a single root element, and names, comments, processing
+//! instructions and CDATA sections are checked before they are written.
+//! Anything that would make the output malformed is refused with an
+//! [`io::ErrorKind::InvalidInput`] error and nothing is written for it.
+//!
+//! Documents are written either with the builder methods or from the
+//! structures of the parser, one event at a time with [`XmlWriter::write_event`].
+//! Enable `decode_entities` on the parser when writing what it reads: the
+//! values are escaped by the writer, so references left in them would be
+//! escaped again.
+//!
+//! # Examples
+//!
+//! ```
+//! use sax_wasm::sax::writer::XmlWriter;
+//!
+//! let mut writer = XmlWriter::new(Vec::new());
+//! writer.start_element(b"note").unwrap();
+//! writer.attribute(b"title", b"Fish & \"Chips\"").unwrap();
+//! writer.text(b"1 < 2").unwrap();
+//! writer.start_element(b"br").unwrap();
+//! writer.end_element().unwrap();
+//! let xml = writer.finish().unwrap();
+//! assert_eq!(xml, b"<note title=\"Fish &amp; &quot;Chips&quot;\">1 &lt; 2<br/></note>");
+//! ```
+//!
+//! Parsing and writing a document again:
+//!
+//! ```
+//! use sax_wasm::sax::parser::{Event, SAXParser};
+//! use sax_wasm::sax::tag::{Entity, Tag};
+//! use sax_wasm::sax::writer::XmlWriter;
+//!
+//! let mut writer = XmlWriter::new(Vec::new());
+//! let mut result = Ok(());
+//! let mut handler = |event: Event, data: Entity| {
+//!     // Rename the <old> elements
+//!     let renamed;
+//!     let data = match data {
+//!         Entity::Tag(tag) if tag.name == b"old" => {
+//!             renamed = Tag { name: b"new".to_vec(), ..tag.clone() };
+//!             Entity::Tag(&renamed)
+//!         }
+//!         data => data,
+//!     };
+//!     if result.is_ok() {
+//!         result = writer.write_event(event, data);
+//!     }
+//! };
+//! let mut parser = SAXParser::new_mut(&mut handler);
+//! parser.events = [true; Event::COUNT];
+//! parser.decode_entities = true;
+//! parser.write(b"<doc><old a=\"&lt;\">x &amp; y</old><!-- c --></doc>");
+//! parser.identity();
+//! drop(parser);
+//! result.unwrap();
+//! assert_eq!(writer.finish().unwrap(), b"<doc><new a=\"&lt;\">x &amp; y</new><!-- c --></doc>");
+//! ```
+
+use std::io::{self, Write};
+
+use super::names::is_name;
+use super::parser::Event;
+use super::tag::{Attribute, Entity, ProcInst, Tag, Text, XmlDecl};
+use super::utils::ascii_compare;
+
+/// Where the writer is relative to the root element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Root {
+    Before,
+    Inside,
+    After,
+}
+
+/// Writes XML to a sink. See the [module documentation](self).
+pub struct XmlWriter<W: Write> {
+    sink: W,
+    // The names of the open elements
+    open: Vec<Vec<u8>>,
+    // The `>` of the last start tag is not written
+    // yet so attributes can be added to it
+    in_start_tag: bool,
+    // The names of the attributes of that start tag
+    attribute_names: Vec<Vec<u8>>,
+    root: Root,
+    // Anything was written. The XML declaration comes first
+    started: bool,
+}
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidInput, message.to_string()))
+}
+
+fn check_name(name: &[u8]) -> io::Result<()> {
+    if !is_name(name) {
+        return invalid(&format!("'{}' is not a valid name", String::from_utf8_lossy(name)));
+    }
+    Ok(())
+}
+
+/// Writes `value` replacing the bytes for which `escape` has a replacement.
+fn write_escaped(sink: &mut impl Write, value: &[u8], escape: fn(u8) -> Option<&'static [u8]>) -> io::Result<()> {
+    let mut start = 0;
+    for (i, &byte) in value.iter().enumerate() {
+        if let Some(replacement) = escape(byte) {
+            sink.write_all(&value[start..i])?;
+            sink.write_all(replacement)?;
+            start = i + 1;
+        }
+    }
+    sink.write_all(&value[start..])
+}
+
+/// Escapes text content. `>` is escaped so `]]>` never appears
+/// and a carriage return is kept from being normalized away.
+pub(super) fn escape_text(byte: u8) -> Option<&'static [u8]> {
+    match byte {
+        b'&' => Some(b"&amp;"),
+        b'<' => Some(b"&lt;"),
+        b'>' => Some(b"&gt;"),
+        b'\r' => Some(b"&#xD;"),
+        _ => None,
+    }
+}
+
+/// Escapes a double quoted attribute value. Whitespace other
+/// than spaces is kept from being normalized to spaces.
+pub(super) fn escape_attribute(byte: u8) -> Option<&'static [u8]> {
+    match byte {
+        b'&' => Some(b"&amp;"),
+        b'<' => Some(b"&lt;"),
+        b'"' => Some(b"&quot;"),
+        b'\t' => Some(b"&#x9;"),
+        b'\n' => Some(b"&#xA;"),
+        b'\r' => Some(b"&#xD;"),
+        _ => None,
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+impl<W: Write> XmlWriter<W> {
+    pub fn new(sink: W) -> XmlWriter<W> {
+        XmlWriter {
+            sink,
+            open: Vec::new(),
+            in_start_tag: false,
+            attribute_names: Vec::new(),
+            root: Root::Before,
+            started: false,
+        }
+    }
+
+    /// The number of open elements.
+    pub fn depth(&self) -> usize {
+        self.open.len()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.sink
+    }
+
+    /// Closes the open elements and returns the sink. Fails
+    /// when no root element was written.
+    pub fn finish(mut self) -> io::Result<W> {
+        while !self.open.is_empty() {
+            self.end_element()?;
+        }
+        if self.root == Root::Before {
+            return invalid("a document has a root element");
+        }
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+
+    /// Writes the `>` of a start tag written before.
+    fn close_start_tag(&mut self) -> io::Result<()> {
+        if self.in_start_tag {
+            self.in_start_tag = false;
+            self.sink.write_all(b">")?;
+        }
+        Ok(())
+    }
+
+    /// Readies the writer for content other than attributes.
+    fn content(&mut self) -> io::Result<()> {
+        self.started = true;
+        self.close_start_tag()
+    }
+
+    /// Writes the XML declaration. It is the first thing written.
+    pub fn xml_decl(&mut self, version: &[u8], encoding: Option<&[u8]>, standalone: Option<bool>) -> io::Result<()> {
+        if self.started {
+            return invalid("the XML declaration is only allowed at the very start of the document");
+        }
+        if version.is_empty() || !version.iter().all(|b| b.is_ascii_digit() || *b == b'.') {
+            return invalid("the version of the XML declaration is a number such as 1.0");
+        }
+        if encoding.is_some_and(|e| e.is_empty() || !e.iter().all(|b| b.is_ascii_alphanumeric() || b"._-".contains(b))) {
+            return invalid("an encoding name is made of ASCII letters, digits, '.', '_' and '-'");
+        }
+        self.started = true;
+        self.sink.write_all(b"<?xml version=\"")?;
+        self.sink.write_all(version)?;
+        if let Some(encoding) = encoding {
+            self.sink.write_all(b"\" encoding=\"")?;
+            self.sink.write_all(encoding)?;
+        }
+        if let Some(standalone) = standalone {
+            self.sink.write_all(b"\" standalone=\"")?;
+            self.sink.write_all(if standalone {
+                b"yes"
+            } else {
+                b"no"
+            })?;
+        }
+        self.sink.write_all(b"\"?>")
+    }
+
+    /// Writes a document type declaration such as `html` or
+    /// `note SYSTEM "note.dtd"`, before the root element.
+    pub fn doctype(&mut self, doctype: &[u8]) -> io::Result<()> {
+        if self.root != Root::Before {
+            return invalid("the document type declaration comes before the root element");
+        }
+        let name_end = doctype.iter().position(u8::is_ascii_whitespace).unwrap_or(doctype.len());
+        check_name(&doctype[..name_end])?;
+        if doctype.contains(&b'>') {
+            return invalid("a document type declaration does not contain '>'");
+        }
+        self.content()?;
+        self.sink.write_all(b"<!DOCTYPE ")?;
+        self.sink.write_all(doctype)?;
+        self.sink.write_all(b">")
+    }
+
+    /// Opens an element. Attributes may be added until anything else is written.
+    pub fn start_element(&mut self, name: &[u8]) -> io::Result<()> {
+        check_name(name)?;
+        if self.root == Root::After {
+            return invalid("a document has a single root element");
+        }
+        self.content()?;
+        self.root = Root::Inside;
+        self.sink.write_all(b"<")?;
+        self.sink.write_all(name)?;
+        self.open.push(name.to_vec());
+        self.in_start_tag = true;
+        self.attribute_names.clear();
+        Ok(())
+    }
+
+    /// Adds an attribute to the element opened last.
+    pub fn attribute(&mut self, name: &[u8], value: &[u8]) -> io::Result<()> {
+        if !self.in_start_tag {
+            return invalid("attributes are written right after their start tag");
+        }
+        check_name(name)?;
+        if self.attribute_names.iter().any(|n| n == name) {
+            return invalid(&format!("the attribute '{}' is written twice", String::from_utf8_lossy(name)));
+        }
+        self.attribute_names.push(name.to_vec());
+        self.sink.write_all(b" ")?;
+        self.sink.write_all(name)?;
+        self.sink.write_all(b"=\"")?;
+        write_escaped(&mut self.sink, value, escape_attribute)?;
+        self.sink.write_all(b"\"")
+    }
+
+    /// Closes the innermost open element, using an
+    /// empty element tag when it has no content.
+    pub fn end_element(&mut self) -> io::Result<()> {
+        let Some(name) = self.open.pop() else {
+            return invalid("there is no open element to close");
+        };
+        if self.in_start_tag {
+            self.in_start_tag = false;
+            self.sink.write_all(b"/>")?;
+        } else {
+            self.sink.write_all(b"</")?;
+            self.sink.write_all(&name)?;
+            self.sink.write_all(b">")?;
+        }
+        if self.open.is_empty() {
+            self.root = Root::After;
+        }
+        Ok(())
+    }
+
+    /// Writes character data. Outside of the root element only whitespace is allowed.
+    pub fn text(&mut self, text: &[u8]) -> io::Result<()> {
+        if self.root != Root::Inside && !text.iter().all(u8::is_ascii_whitespace) {
+            return invalid("text is only allowed within the root element");
+        }
+        self.content()?;
+        write_escaped(&mut self.sink, text, escape_text)
+    }
+
+    /// Writes a CDATA section. Occurrences of `]]>` are
+    /// split across two sections.
+    pub fn cdata(&mut self, cdata: &[u8]) -> io::Result<()> {
+        if self.root != Root::Inside {
+            return invalid("CDATA sections are only allowed within the root element");
+        }
+        self.content()?;
+        self.sink.write_all(b"<![CDATA[")?;
+        let mut rest = cdata;
+        while let Some(i) = rest.windows(3).position(|window| window == b"]]>") {
+            self.sink.write_all(&rest[..i + 2])?;
+            self.sink.write_all(b"]]><![CDATA[")?;
+            rest = &rest[i + 2..];
+        }
+        self.sink.write_all(rest)?;
+        self.sink.write_all(b"]]>")
+    }
+
+    pub fn comment(&mut self, comment: &[u8]) -> io::Result<()> {
+        if contains(comment, b"--") || comment.ends_with(b"-") {
+            return invalid("a comment does not contain '--' or end with '-'");
+        }
+        self.content()?;
+        self.sink.write_all(b"<!--")?;
+        self.sink.write_all(comment)?;
+        self.sink.write_all(b"-->")
+    }
+
+    pub fn processing_instruction(&mut self, target: &[u8], content: &[u8]) -> io::Result<()> {
+        check_name(target)?;
+        if ascii_compare(b"xml", target) {
+            return invalid("the target 'xml' is reserved for the XML declaration");
+        }
+        if contains(content, b"?>") {
+            return invalid("a processing instruction does not contain '?>'");
+        }
+        self.content()?;
+        self.sink.write_all(b"<?")?;
+        self.sink.write_all(target)?;
+        if !content.is_empty() {
+            self.sink.write_all(b" ")?;
+            self.sink.write_all(content)?;
+        }
+        self.sink.write_all(b"?>")
+    }
+
+    /// Opens the element of a tag with its attributes and closes it
+    /// as well when the tag is self-closing. Otherwise it is closed
+    /// by [`XmlWriter::write_close_tag`] after its content.
+    pub fn write_tag(&mut self, tag: &Tag) -> io::Result<()> {
+        self.start_element(&tag.name)?;
+        for attribute in &tag.attributes {
+            self.write_attribute(attribute)?;
+        }
+        if tag.self_closing {
+            self.end_element()
+        } else {
+            self.close_start_tag()
+        }
+    }
+
+    pub fn write_attribute(&mut self, attribute: &Attribute) -> io::Result<()> {
+        self.attribute(&attribute.name.value, &attribute.value.value)
+    }
+
+    /// Closes the element of a tag. It must be the innermost open element.
+    pub fn write_close_tag(&mut self, tag: &Tag) -> io::Result<()> {
+        match self.open.last() {
+            Some(name) if *name == tag.name => self.end_element(),
+            _ => invalid(&format!("the element '{}' is not the innermost open element", String::from_utf8_lossy(&tag.name))),
+        }
+    }
+
+    pub fn write_text(&mut self, text: &Text) -> io::Result<()> {
+        self.text(&text.value)
+    }
+
+    pub fn write_proc_inst(&mut self, proc_inst: &ProcInst) -> io::Result<()> {
+        // The target keeps the whitespace after it
+        self.processing_instruction(proc_inst.target.value.trim_ascii_end(), proc_inst.content.value.trim_ascii_start())
+    }
+
+    pub fn write_xml_decl(&mut self, xml_decl: &XmlDecl) -> io::Result<()> {
+        let encoding = Some(&xml_decl.encoding.value[..]).filter(|e| !e.is_empty());
+        let standalone = match &xml_decl.standalone.value[..] {
+            b"" => None,
+            standalone => Some(standalone == b"yes"),
+        };
+        self.xml_decl(&xml_decl.version.value, encoding, standalone)
+    }
+
+    /// Writes the data of a parser event. The `OpenTag` event writes the whole
+    /// start tag so the `OpenTagStart` and `Attribute` events are ignored, as
+    /// are events describing the document type.
+    pub fn write_event(&mut self, event: Event, data: Entity) -> io::Result<()> {
+        match (event, data) {
+            (Event::Text, Entity::Text(text)) => self.write_text(text),
+            (Event::ProcessingInstruction, Entity::ProcInst(proc_inst)) => self.write_proc_inst(proc_inst),
+            (Event::Doctype, Entity::Text(doctype)) => self.doctype(doctype.value.trim_ascii()),
+            (Event::Comment, Entity::Text(comment)) => self.comment(&comment.value),
+            (Event::OpenTag, Entity::Tag(tag)) => self.write_tag(tag),
+            // Self-closing elements were closed with their start tag
+            (Event::CloseTag, Entity::Tag(tag)) if !tag.self_closing => self.write_close_tag(tag),
+            (Event::Cdata, Entity::Text(cdata)) => self.cdata(&cdata.value),
+            (Event::XmlDecl, Entity::XmlDecl(xml_decl)) => self.write_xml_decl(xml_decl),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sax::parser::SAXParser;
+
+    fn rewrite(doc: &[u8]) -> Vec<u8> {
+        let mut writer = XmlWriter::new(Vec::new());
+        let mut result = Ok(());
+        let mut handler = |event: Event, data: Entity| {
+            if result.is_ok() {
+                result = writer.write_event(event, data);
+            }
+        };
+        let mut parser = SAXParser::new_mut(&mut handler);
+        parser.events = [true; Event::COUNT];
+        parser.decode_entities = true;
+        parser.write(doc);
+        parser.identity();
+        drop(parser);
+        result.unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_escaping() {
+        let mut writer = XmlWriter::new(Vec::new());
+        writer.start_element(b"a").unwrap();
+        writer.attribute(b"v", b"<&>\"'\t\n\r").unwrap();
+        writer.text(b"<&>\"'\t\n\r]]>").unwrap();
+        writer.cdata(b"x]]>y]]>").unwrap();
+        writer.comment(b" - ").unwrap();
+        writer.processing_instruction(b"pi", b"a > b").unwrap();
+        assert_eq!(
+            writer.finish().unwrap(),
+            &b"<a v=\"&lt;&amp;>&quot;'&#x9;&#xA;&#xD;\">&lt;&amp;&gt;\"'\t\n&#xD;]]&gt;<![CDATA[x]]]]><![CDATA[>y]]]]><![CDATA[>]]><!-- - --><?pi a > b?></a>"[..]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let doc = b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE catalog>\n<catalog xmlns:x=\"urn:x\">\n  <x:item id=\"1\" name=\"a &amp; b\">Tea &lt;green&gt;</x:item>\n  <empty></empty><br/>\n  <![CDATA[<raw>]]><?style a?>\n</catalog>\n<!-- end -->";
+        let written = rewrite(doc);
+        assert_eq!(
+            String::from_utf8(written.clone()).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><!DOCTYPE catalog><catalog xmlns:x=\"urn:x\"><x:item id=\"1\" name=\"a &amp; b\">Tea &lt;green&gt;</x:item><empty></empty><br/><![CDATA[<raw>]]><?style a?></catalog><!-- end -->"
+        );
+        assert_eq!(rewrite(&written), written);
+    }
+
+    #[test]
+    fn test_well_formedness() {
+        let mut writer = XmlWriter::new(Vec::new());
+        assert!(writer.attribute(b"a", b"").is_err());
+        assert!(writer.end_element().is_err());
+        assert!(writer.text(b"text").is_err());
+        assert!(writer.cdata(b"").is_err());
+        assert!(writer.start_element(b"1a").is_err());
+        writer.text(b"\n").unwrap();
+        writer.start_element(b"a").unwrap();
+        assert!(writer.xml_decl(b"1.0", None, None).is_err());
+        assert!(writer.doctype(b"a").is_err());
+        writer.attribute(b"x", b"1").unwrap();
+        assert!(writer.attribute(b"x", b"2").is_err());
+        assert!(writer.attribute(b"y z", b"2").is_err());
+        assert!(writer.comment(b"a--b").is_err());
+        assert!(writer.comment(b"a-").is_err());
+        assert!(writer.processing_instruction(b"XML", b"").is_err());
+        assert!(writer.processing_instruction(b"pi", b"?>").is_err());
+        writer.start_element(b"b").unwrap();
+        writer.text(b"t").unwrap();
+        assert!(writer.attribute(b"y", b"2").is_err());
+        let mut b = Tag::new([0; 2]);
+        b.name = b"a".to_vec();
+        assert!(writer.write_close_tag(&b).is_err());
+        b.name = b"b".to_vec();
+        writer.write_close_tag(&b).unwrap();
+        writer.end_element().unwrap();
+        assert!(writer.start_element(b"c").is_err());
+        assert!(writer.text(b"after").is_err());
+        writer.comment(b"after").unwrap();
+        assert_eq!(writer.finish().unwrap(), b"\n<a x=\"1\"><b>t</b></a><!--after-->");
+
+        assert!(XmlWriter::new(Vec::new()).finish().is_err());
+        let mut writer = XmlWriter::new(Vec::new());
+        writer.start_element(b"a").unwrap();
+        writer.start_element(b"b").unwrap();
+        assert_eq!(writer.depth(), 2);
+        assert_eq!(writer.finish().unwrap(), b"<a><b/></a>");
+    }
+}
@@ -0,0 +1,441 @@
+//! A streaming evaluator for a forward-axis subset of XPath.
+//!
+//! Supported are absolute location paths with the child (`/`) and
+//! descendant (`//`) axes, name tests (`name`, `*`), predicates on
+//! attributes (`[@id]`, `[@id='1']`, `[@id!='1']`) and on the position
+//! among the matching siblings (`[2]`), and a final `@name`, `@*` or
+//! `text()` step. Matches are reported while the document is parsed; no
+//! tree is built.
+//!
+//! The [`XPathEvaluator`] is an event handler. It follows the open elements
+//! of the parser through the [`Context`] passed with each event, so the
+//! parser must be subscribed to [`EVENTS`].
+//!
+//! # Examples
+//!
+//! ```
+//! use sax_wasm::sax::parser::{subscribe, SAXParser};
+//! use sax_wasm::sax::xpath::{XPathEvaluator, XPathMatch, EVENTS};
+//!
+//! let mut prices = Vec::new();
+//! let mut evaluator = XPathEvaluator::new(&["//product[@sale]/price/text()"], |_expression, found| {
+//!     if let XPathMatch::Text(text) = found {
+//!         prices.push(text.value.clone());
+//!     }
+//! })
+//! .unwrap();
+//! let mut parser = SAXParser::new_with_context(&mut evaluator);
+//! subscribe(&mut parser.events, &EVENTS);
+//! parser.write(b"<catalog><product><price>10</price></product><product sale=\"\"><price>5</price></product></catalog>");
+//! parser.identity();
+//! drop(parser);
+//! drop(evaluator);
+//! assert_eq!(prices, [b"5"]);
+//! ```
+
+use std::error::Error;
+use std::fmt;
+
+use super::context::Context;
+use super::expression::{ExpressionError, Reader};
+use super::parser::{ContextEventHandler, Event};
+use super::path::MAX_STEPS;
+use super::tag::{Attribute, Entity, Tag, Text};
+
+/// An invalid expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XPathError {
+    pub expression: String,
+    pub message: String,
+}
+
+impl fmt::Display for XPathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid XPath expression '{}': {}", self.expression, self.message)
+    }
+}
+
+impl Error for XPathError {}
+
+impl ExpressionError for XPathError {
+    fn new(expression: &str, message: &str) -> XPathError {
+        XPathError {
+            expression: expression.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Predicate {
+    // [@name]
+    HasAttribute(Vec<u8>),
+    // [@name='value'] and [@name!='value']
+    Attribute(Vec<u8>, Vec<u8>, bool),
+    // [n] counting the siblings passing the
+    // name test and the predicates before it
+    Position(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    // Preceded by `//`
+    descendant: bool,
+    // `None` for `*`
+    name: Option<Vec<u8>>,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Element,
+    // text() and whether it was preceded by `//`
+    Text(bool),
+    // @name, `None` for `@*`
+    Attribute(bool, Option<Vec<u8>>),
+}
+
+/// A compiled expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XPath {
+    steps: Vec<Step>,
+    target: Target,
+}
+
+impl Reader<'_, XPathError> {
+    fn name(&mut self) -> Result<Vec<u8>, XPathError> {
+        self.name_while(|b| !b"/[]@='\"!() \t".contains(&b))
+    }
+
+    fn literal(&mut self) -> Result<Vec<u8>, XPathError> {
+        match self.quoted()? {
+            Some(value) => Ok(value),
+            None => self.error("a quoted value is missing"),
+        }
+    }
+
+    fn predicate(&mut self) -> Result<Predicate, XPathError> {
+        let predicate = if self.eat(b"@") {
+            let name = self.name()?;
+            if self.eat(b"=") {
+                Predicate::Attribute(name, self.literal()?, true)
+            } else if self.eat(b"!=") {
+                Predicate::Attribute(name, self.literal()?, false)
+            } else {
+                Predicate::HasAttribute(name)
+            }
+        } else {
+            let end = self.rest.iter().position(|b| !b.is_ascii_digit()).unwrap_or(self.rest.len());
+            let position = std::str::from_utf8(&self.rest[..end]).ok().and_then(|digits| digits.parse().ok());
+            self.rest = &self.rest[end..];
+            match position {
+                Some(position) if position > 0 => Predicate::Position(position),
+                _ => return self.error("a predicate is either on an attribute or a position from 1"),
+            }
+        };
+        if !self.eat(b"]") {
+            return self.error("a predicate is not closed");
+        }
+        Ok(predicate)
+    }
+}
+
+impl XPath {
+    pub fn parse(expression: &str) -> Result<XPath, XPathError> {
+        let mut reader = Reader::new(expression);
+        if !reader.rest.starts_with(b"/") {
+            return reader.error("an expression starts with '/' or '//'");
+        }
+        let mut steps = Vec::new();
+        let mut target = Target::Element;
+        while !reader.rest.is_empty() {
+            if target != Target::Element {
+                return reader.error("text() and attributes end an expression");
+            }
+            if !reader.eat(b"/") {
+                return reader.error("steps are separated by '/' or '//'");
+            }
+            let descendant = reader.eat(b"/");
+            if reader.eat(b"text()") {
+                target = Target::Text(descendant);
+            } else if reader.eat(b"@") {
+                let name = if reader.eat(b"*") {
+                    None
+                } else {
+                    Some(reader.name()?)
+                };
+                target = Target::Attribute(descendant, name);
+            } else {
+                let name = if reader.eat(b"*") {
+                    None
+                } else {
+                    Some(reader.name()?)
+                };
+                let mut predicates = Vec::new();
+                while reader.eat(b"[") {
+                    predicates.push(reader.predicate()?);
+                }
+                if predicates.iter().filter(|p| matches!(p, Predicate::Position(_))).count() > 1 {
+                    return reader.error("a step has more than one position predicate");
+                }
+                steps.push(Step {
+                    descendant,
+                    name,
+                    predicates,
+                });
+            }
+        }
+        if steps.is_empty() && target == Target::Element {
+            return reader.error("an expression has a step");
+        }
+        if steps.len() > MAX_STEPS {
+            return reader.error(&format!("more than {} steps", MAX_STEPS));
+        }
+        Ok(XPath {
+            steps,
+            target,
+        })
+    }
+
+    /// Whether the text and attributes of the descendants of
+    /// an element matching every step are matched as well.
+    fn target_descends(&self) -> bool {
+        matches!(self.target, Target::Text(true) | Target::Attribute(true, _))
+    }
+}
+
+/// The events the parser must be subscribed to.
+pub const EVENTS: [Event; 3] = [Event::OpenTag, Event::Text, Event::Cdata];
+
+/// A node matching an expression.
+pub enum XPathMatch<'a> {
+    Element(&'a Tag),
+    Attribute(&'a Tag, &'a Attribute),
+    Text(&'a Text),
+}
+
+/// The state within the document or an open element.
+#[derive(Default)]
+struct Level {
+    // Bit `i` is set for each expression when its
+    // first `i` steps were matched by the element
+    states: Vec<u64>,
+    // The children passing the test of each step before its position
+    // predicate, for each step of each expression one after the other
+    counts: Vec<u32>,
+}
+
+/// Evaluates expressions against the events of a parser. See the [module documentation](self).
+pub struct XPathEvaluator<F: FnMut(usize, XPathMatch)> {
+    expressions: Vec<XPath>,
+    // The document followed by the open elements. Levels
+    // past `depth` are kept to reuse their allocations
+    levels: Vec<Level>,
+    depth: usize,
+    on_match: F,
+}
+
+impl<F: FnMut(usize, XPathMatch)> XPathEvaluator<F> {
+    /// Compiles the expressions. `on_match` is called with the index of
+    /// the expression and the node for every match.
+    pub fn new(expressions: &[&str], on_match: F) -> Result<XPathEvaluator<F>, XPathError> {
+        let expressions = expressions.iter().map(|e| XPath::parse(e)).collect::<Result<Vec<_>, _>>()?;
+        let mut evaluator = XPathEvaluator {
+            expressions,
+            levels: vec![Level::default()],
+            depth: 0,
+            on_match,
+        };
+        evaluator.reset_level(0);
+        for state in &mut evaluator.levels[0].states {
+            *state = 1;
+        }
+        Ok(evaluator)
+    }
+
+    fn reset_level(&mut self, depth: usize) {
+        if self.levels.len() == depth {
+            self.levels.push(Level::default());
+        }
+        let level = &mut self.levels[depth];
+        level.states.clear();
+        level.states.resize(self.expressions.len(), 0);
+        level.counts.clear();
+        level.counts.resize(self.expressions.iter().map(|e| e.steps.len()).sum(), 0);
+    }
+
+    fn open_element(&mut self, tag: &Tag, depth: usize) {
+        // Elements may have been closed since without the evaluator
+        // knowing. Their parent is always known unless the parser
+        // is not subscribed to `EVENTS`
+        if depth == 0 || depth > self.depth + 1 {
+            return;
+        }
+        self.reset_level(depth);
+        let (parents, children) = self.levels.split_at_mut(depth);
+        let (parent, level) = (&mut parents[depth - 1], &mut children[0]);
+
+        let mut counts = &mut parent.counts[..];
+        for (i, expression) in self.expressions.iter().enumerate() {
+            let (step_counts, rest) = counts.split_at_mut(expression.steps.len());
+            counts = rest;
+            let n = expression.steps.len();
+            let states = parent.states[i];
+            let mut child = 0;
+            if states & (1 << n) != 0 && expression.target_descends() {
+                child |= 1 << n;
+            }
+            let mut pending = states & !(1 << n);
+            while pending != 0 {
+                let s = pending.trailing_zeros() as usize;
+                pending &= pending - 1;
+                let step = &expression.steps[s];
+                if step.descendant {
+                    child |= 1 << s;
+                }
+                if step.name.as_deref().is_none_or(|name| name == tag.name) && passes(step, tag, &mut step_counts[s]) {
+                    child |= 1 << (s + 1);
+                }
+            }
+            level.states[i] = child;
+        }
+        self.depth = depth;
+
+        let level = &self.levels[depth];
+        for (i, expression) in self.expressions.iter().enumerate() {
+            if level.states[i] & (1 << expression.steps.len()) == 0 {
+                continue;
+            }
+            match &expression.target {
+                Target::Element => (self.on_match)(i, XPathMatch::Element(tag)),
+                Target::Attribute(_, name) => {
+                    for attribute in &tag.attributes {
+                        if name.as_ref().is_none_or(|name| *name == attribute.name.value) {
+                            (self.on_match)(i, XPathMatch::Attribute(tag, attribute));
+                        }
+                    }
+                }
+                Target::Text(_) => {}
+            }
+        }
+    }
+
+    fn text(&mut self, text: &Text, depth: usize) {
+        if depth > self.depth {
+            return;
+        }
+        self.depth = depth;
+        let level = &self.levels[depth];
+        for (i, expression) in self.expressions.iter().enumerate() {
+            if matches!(expression.target, Target::Text(_)) && level.states[i] & (1 << expression.steps.len()) != 0 {
+                (self.on_match)(i, XPathMatch::Text(text));
+            }
+        }
+    }
+}
+
+/// Whether the element passes the predicates of a step whose name test
+/// it passed. `count` is the number of its siblings counted for the position.
+fn passes(step: &Step, tag: &Tag, count: &mut u32) -> bool {
+    let attribute = |name: &[u8]| tag.attributes.iter().find(|a| a.name.value == name);
+    step.predicates.iter().all(|predicate| match predicate {
+        Predicate::HasAttribute(name) => attribute(name).is_some(),
+        Predicate::Attribute(name, value, equal) => attribute(name).is_some_and(|a| (a.value.value == *value) == *equal),
+        Predicate::Position(position) => {
+            *count += 1;
+            *count == *position
+        }
+    })
+}
+
+impl<F: FnMut(usize, XPathMatch)> ContextEventHandler for XPathEvaluator<F> {
+    fn handle_event(&mut self, event: Event, data: Entity, context: &Context) {
+        match (event, data) {
+            (Event::OpenTag, Entity::Tag(tag)) => self.open_element(tag, context.depth()),
+            (Event::Text | Event::Cdata, Entity::Text(text)) => self.text(text, context.depth()),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sax::parser::parse_in_chunks;
+
+    fn evaluate(expressions: &[&str], doc: &[u8]) -> Vec<(usize, String)> {
+        let mut found = Vec::new();
+        let evaluator = XPathEvaluator::new(expressions, |i, node| {
+            let description = match node {
+                XPathMatch::Element(tag) => format!("<{}> {:?}", String::from_utf8_lossy(&tag.name), tag.open_start),
+                XPathMatch::Attribute(tag, attribute) => {
+                    let name = String::from_utf8_lossy(&tag.name);
+                    format!(
+                        "{}@{}={}",
+                        name,
+                        String::from_utf8_lossy(&attribute.name.value),
+                        String::from_utf8_lossy(&attribute.value.value)
+                    )
+                }
+                XPathMatch::Text(text) => String::from_utf8_lossy(&text.value).into_owned(),
+            };
+            found.push((i, description));
+        })
+        .unwrap();
+        parse_in_chunks(evaluator, &EVENTS, doc, 4);
+        found
+    }
+
+    const DOC: &[u8] = b"<lib>\n<book id=\"1\" lang=\"en\"><title>One</title><note>n1</note></book>\n<book id=\"2\"><title>Two</title><part><title>Sub</title></part></book>\n<shelf><book id=\"3\"><title>Three</title></book></shelf>\n</lib>";
+
+    #[test]
+    fn test_axes() {
+        let found = evaluate(&["/lib/book", "//title/text()", "/lib/book//title/text()", "/lib/*/book/@id"], DOC);
+        assert_eq!(
+            found,
+            [
+                (0, "<book> [1, 0]".to_string()),
+                (1, "One".to_string()),
+                (2, "One".to_string()),
+                (0, "<book> [2, 0]".to_string()),
+                (1, "Two".to_string()),
+                (2, "Two".to_string()),
+                (1, "Sub".to_string()),
+                (2, "Sub".to_string()),
+                (3, "book@id=3".to_string()),
+                (1, "Three".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_predicates() {
+        let found = evaluate(
+            &["//book[@lang]/title/text()", "//book[@id!='1']/@*", "/lib/book[2]/title", "//book[@id='2']//text()", "/lib/*[3]"],
+            DOC,
+        );
+        assert_eq!(
+            found,
+            [
+                (0, "One".to_string()),
+                (1, "book@id=2".to_string()),
+                (2, "<title> [2, 13]".to_string()),
+                (3, "Two".to_string()),
+                (3, "Sub".to_string()),
+                (4, "<shelf> [3, 0]".to_string()),
+                (1, "book@id=3".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        for expression in ["", "lib", "/", "/lib/", "/a/text()/b", "/a[@x='1]", "/a[0]", "/a[1][2]", "/a[x]", "/a b"] {
+            assert!(XPath::parse(expression).is_err(), "{}", expression);
+        }
+        assert!(XPath::parse("//text()").is_ok());
+        assert!(XPath::parse(&"/a".repeat(MAX_STEPS)).is_ok());
+        let error = XPath::parse(&"/a".repeat(MAX_STEPS + 1)).unwrap_err();
+        assert_eq!(error.message, format!("more than {} steps", MAX_STEPS));
+    }
+}
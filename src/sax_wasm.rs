@@ -1,16 +1,30 @@
-use std::mem;
 use std::ptr;
 use std::slice;
 
 use crate::sax::parser::*;
 use crate::sax::tag::*;
 
-static mut SAX: *mut SAXParser = 0 as *mut SAXParser;
-pub struct SaxEventHandler;
+/// Parsers created through `create_parser`. A handle is the slot index plus
+/// one so that `0` never names a parser.
+static mut PARSERS: Vec<Option<Box<Instance>>> = Vec::new();
+/// The handle used by the single-parser `parser`, `write` and `end` exports.
+static mut DEFAULT_HANDLE: u32 = 0;
+
+/// Bits of the `set_parser_options` bitmask.
+const DECODE_ENTITIES: u32 = 0b1;
+const NAMESPACES: u32 = 0b10;
+const STRICT: u32 = 0b100;
+const PRESERVE_WHITESPACE: u32 = 0b1000;
+
+pub struct SaxEventHandler {
+    handle: u32,
+}
 
 impl SaxEventHandler {
-    pub fn new() -> Self {
-        SaxEventHandler
+    pub fn new(handle: u32) -> Self {
+        SaxEventHandler {
+            handle,
+        }
     }
 }
 
@@ -21,40 +35,168 @@ impl EventHandler for SaxEventHandler {
             Entity::ProcInst(proc_inst) => ptr::from_ref(proc_inst) as *const u8,
             Entity::Tag(tag) => ptr::from_ref(tag) as *const u8,
             Entity::Text(text) => ptr::from_ref(text) as *const u8,
+            Entity::Error(error) => ptr::from_ref(error) as *const u8,
+            Entity::Declaration(declaration) => ptr::from_ref(declaration) as *const u8,
+            Entity::XmlDecl(xml_decl) => ptr::from_ref(xml_decl) as *const u8,
         };
-        unsafe { event_listener(1 << event as u32, ptr) };
+        unsafe { event_listener(1 << event as u32, ptr, self.handle) };
     }
 }
 
-fn generate_event_lookup(events: u32) -> [bool; 10] {
-    let mut event_lookup = [false; 10];
-    for i in 0..10 {
+/// A parser and the event handler it reports to.
+struct Instance {
+    // Declared first so it is dropped before the handler it borrows
+    parser: SAXParser<'static>,
+    _event_handler: Box<SaxEventHandler>,
+}
+
+fn generate_event_lookup(events: u32) -> Events {
+    let mut event_lookup = [false; Event::COUNT];
+    for i in 0..Event::COUNT {
         event_lookup[i] = events & (1 << i) != 0;
     }
     event_lookup
 }
 
+/// Looks up a live parser. The instance is boxed so the pointer stays valid
+/// when parsers are created from within an event callback.
+unsafe fn instance(handle: u32) -> Option<*mut Instance> {
+    let parsers = &mut *ptr::addr_of_mut!(PARSERS);
+    let slot = parsers.get_mut((handle as usize).checked_sub(1)?)?;
+    slot.as_deref_mut().map(ptr::from_mut)
+}
+
+/// Creates a parser subscribed to the `events` bitmask and returns its handle.
+/// Events from this parser are reported to `event_listener` with the handle.
+///
+/// # Safety
+///
+/// Must not be called concurrently with any other export.
+#[no_mangle]
+pub unsafe extern "C" fn create_parser(events: u32) -> u32 {
+    let parsers = &mut *ptr::addr_of_mut!(PARSERS);
+    let index = parsers.iter().position(Option::is_none).unwrap_or_else(|| {
+        parsers.push(None);
+        parsers.len() - 1
+    });
+    let handle = index as u32 + 1;
+
+    let event_handler = Box::new(SaxEventHandler::new(handle));
+    // The handler lives on the heap next to the parser and outlives it, see `Instance`
+    let handler_ref: &'static SaxEventHandler = &*ptr::from_ref(event_handler.as_ref());
+    let mut parser = SAXParser::new(handler_ref);
+    parser.events = generate_event_lookup(events);
+    parsers[index] = Some(Box::new(Instance {
+        parser,
+        _event_handler: event_handler,
+    }));
+    handle
+}
+
+/// Changes the events bitmask of a parser. This can be done at any time during processing.
+///
+/// # Safety
+///
+/// Must not be called concurrently with any other export.
+#[no_mangle]
+pub unsafe extern "C" fn set_parser_events(handle: u32, events: u32) {
+    if let Some(instance) = instance(handle) {
+        (*instance).parser.events = generate_event_lookup(events);
+    }
+}
+
+/// Changes the options of a parser from a bitmask: `0b1` decodes entities,
+/// `0b10` resolves namespaces, `0b100` reports well-formedness violations and
+/// `0b1000` preserves whitespace.
+/// All options are off when a parser is created.
+///
+/// # Safety
+///
+/// Must not be called concurrently with any other export.
+#[no_mangle]
+pub unsafe extern "C" fn set_parser_options(handle: u32, options: u32) {
+    if let Some(instance) = instance(handle) {
+        let parser = &mut (*instance).parser;
+        parser.decode_entities = options & DECODE_ENTITIES != 0;
+        parser.namespaces = options & NAMESPACES != 0;
+        parser.strict = options & STRICT != 0;
+        parser.preserve_whitespace = options & PRESERVE_WHITESPACE != 0;
+    }
+}
+
+/// Supplies a parser with the next chunk of the document.
+///
+/// # Safety
+///
+/// `ptr` must point to `length` readable bytes. Must not be called concurrently
+/// with any other export.
+#[no_mangle]
+pub unsafe extern "C" fn write_parser(handle: u32, ptr: *const u8, length: usize) {
+    if let Some(instance) = instance(handle) {
+        let document = slice::from_raw_parts(ptr, length);
+        (*instance).parser.write(document);
+    }
+}
+
+/// Ends the document on a parser and readies it for the next one.
+///
+/// # Safety
+///
+/// Must not be called concurrently with any other export.
+#[no_mangle]
+pub unsafe extern "C" fn end_parser(handle: u32) {
+    if let Some(instance) = instance(handle) {
+        (*instance).parser.identity();
+    }
+}
+
+/// Frees a parser. The handle may be handed out again by `create_parser`.
+///
+/// # Safety
+///
+/// A parser must not be destroyed from within one of its own events. Must not
+/// be called concurrently with any other export.
+#[no_mangle]
+pub unsafe extern "C" fn destroy_parser(handle: u32) {
+    let parsers = &mut *ptr::addr_of_mut!(PARSERS);
+    if let Some(slot) = (handle as usize).checked_sub(1).and_then(|index| parsers.get_mut(index)) {
+        *slot = None;
+    }
+    if DEFAULT_HANDLE == handle {
+        DEFAULT_HANDLE = 0;
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn parser(events: u32) {
-    if SAX == 0 as *mut SAXParser {
-        let event_handler = Box::leak(Box::new(SaxEventHandler::new()));
-        let sax_parse = SAXParser::new(event_handler);
-        SAX = mem::transmute(Box::new(sax_parse));
+    if DEFAULT_HANDLE == 0 {
+        DEFAULT_HANDLE = create_parser(events);
+    } else {
+        set_parser_events(DEFAULT_HANDLE, events);
     }
-    (*SAX).events = generate_event_lookup(events);
+}
+
+/// Changes the options of the parser used by `parser`, `write` and `end`,
+/// see `set_parser_options`.
+///
+/// # Safety
+///
+/// Must not be called concurrently with any other export.
+#[no_mangle]
+pub unsafe extern "C" fn set_options(options: u32) {
+    set_parser_options(DEFAULT_HANDLE, options);
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn write(ptr: *const u8, length: usize) {
-    let document = slice::from_raw_parts(ptr, length);
-    (*SAX).write(document);
+    write_parser(DEFAULT_HANDLE, ptr, length);
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn end() {
-    (*SAX).identity();
+    end_parser(DEFAULT_HANDLE);
 }
 
 extern "C" {
-    fn event_listener(event: u32, ptr: *const u8);
+    fn event_listener(event: u32, ptr: *const u8, handle: u32);
 }